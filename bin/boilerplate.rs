@@ -1,11 +1,16 @@
 #![allow(clippy::single_match)]
 use vangers::{
-    config::{settings::Terrain, Settings},
+    config::{
+        layers::Issue,
+        settings::{Overrides, Terrain},
+        Settings,
+    },
     render::{ScreenTargets, DEPTH_FORMAT},
 };
 
 use futures::executor::{LocalPool, LocalSpawner};
 use log::info;
+use std::path::PathBuf;
 use winit::{
     event,
     event_loop::{ControlFlow, EventLoop},
@@ -52,13 +57,98 @@ pub struct HarnessOptions {
     pub uses_level: bool,
 }
 
+/// Adds the command line options that control the settings.
+pub fn add_settings_options(options: &mut getopts::Options) {
+    options
+        .optopt(
+            "",
+            "config",
+            "settings file applied over `config/settings.ron`",
+            "PATH",
+        )
+        .optmulti(
+            "",
+            "set",
+            "override a setting, e.g. `game.level=Fostral`",
+            "KEY=VALUE",
+        )
+        .optflag(
+            "",
+            "print-config",
+            "print the resolved settings with their sources, and exit",
+        )
+        .optflag(
+            "",
+            "check-config",
+            "check the settings against the game data and the adapter, and exit",
+        );
+}
+
+/// Initializes the logging and loads the settings according to the command line.
+/// Returns nothing if the settings only had to be printed or checked.
+pub fn load_settings(matches: &getopts::Matches) -> Option<Settings> {
+    env_logger::init();
+    info!("Loading the settings");
+    let overrides = Overrides {
+        config: matches.opt_str("config").map(PathBuf::from),
+        assignments: matches.opt_strs("set"),
+    };
+    let layers = Settings::resolve(&overrides)
+        .unwrap_or_else(|e| panic!("Unable to load the settings: {}", e));
+    if matches.opt_present("print-config") {
+        print!("{}", layers);
+        return None;
+    }
+    if matches.opt_present("check-config") {
+        let issues = match Settings::check_layers(&layers) {
+            Ok(settings) => check_adapter(&settings),
+            Err(issues) => issues,
+        };
+        if issues.is_empty() {
+            println!("The settings are valid");
+            return None;
+        }
+        for issue in issues.iter() {
+            println!("{}", issue);
+        }
+        std::process::exit(1);
+    }
+    match Settings::from_layers(&layers) {
+        Ok(settings) => Some(settings),
+        Err(issues) => panic!("Invalid settings:\n{}", join_issues(&issues)),
+    }
+}
+
+fn join_issues(issues: &[Issue]) -> String {
+    issues
+        .iter()
+        .map(|issue| issue.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Checks the settings against an adapter of the selected backend, without a window.
+fn check_adapter(settings: &Settings) -> Vec<Issue> {
+    let instance = wgpu::Instance::new(settings.backend.to_wgpu());
+    let adapter =
+        futures::executor::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            compatible_surface: None,
+            force_fallback_adapter: false,
+        }));
+    match adapter {
+        Some(adapter) => settings.validate_adapter(&adapter.get_downlevel_properties()),
+        None => vec![Issue {
+            path: "backend".to_string(),
+            message: "no adapter is available".to_string(),
+        }],
+    }
+}
+
 impl Harness {
-    pub fn init(options: HarnessOptions) -> (Self, Settings) {
-        env_logger::init();
+    pub fn init(options: HarnessOptions, settings: &Settings) -> Self {
         let mut task_pool = LocalPool::new();
 
-        info!("Loading the settings");
-        let settings = Settings::load("config/settings.ron");
         let extent = wgpu::Extent3d {
             width: settings.window.size[0],
             height: settings.window.size[1],
@@ -86,6 +176,13 @@ impl Harness {
             .expect("Unable to initialize GPU via the selected backend.");
 
         let downlevel_caps = adapter.get_downlevel_properties();
+        let issues = settings.validate_adapter(&downlevel_caps);
+        if !issues.is_empty() {
+            panic!(
+                "The settings don't fit the adapter:\n{}",
+                join_issues(&issues)
+            );
+        }
         let adapter_limits = adapter.limits();
 
         let mut limits = match settings.render.terrain {
@@ -147,7 +244,7 @@ impl Harness {
             })
             .create_view(&wgpu::TextureViewDescriptor::default());
 
-        let harness = Harness {
+        Harness {
             task_pool,
             event_loop,
             window,
@@ -159,9 +256,7 @@ impl Harness {
             extent,
             reload_on_focus: settings.window.reload_on_focus,
             depth_target,
-        };
-
-        (harness, settings)
+        }
     }
 
     pub fn main_loop<A: 'static + Application>(self, mut app: A) {
@@ -1,5 +1,4 @@
 use crate::boilerplate::Application;
-use m3d::Mesh;
 use vangers::{config, level, model, render, space};
 
 use futures::executor::LocalSpawner;
@@ -63,8 +62,9 @@ impl CarView {
         );
 
         info!("Loading car registry");
-        let game_reg = config::game::Registry::load(settings);
-        let car_reg = config::car::load_registry(settings, &game_reg, device, &object);
+        let game_reg = config::game::Registry::load(settings).unwrap_or_else(|e| panic!("{}", e));
+        let car_reg = config::car::load_registry(settings, &game_reg, device, &object)
+            .unwrap_or_else(|e| panic!("{}", e));
         let cinfo = match car_reg.get(&settings.car.id) {
             Some(ci) => ci,
             None => {
@@ -73,12 +73,13 @@ impl CarView {
             }
         };
         let mut model = cinfo.model.clone();
-        for (ms, sid) in model.slots.iter_mut().zip(settings.car.slots.iter()) {
-            let info = &game_reg.model_infos[sid];
-            let raw = Mesh::load(&mut settings.open_relative(&info.path));
-            ms.mesh = Some(model::load_c3d(raw, device));
-            ms.scale = info.scale;
-        }
+        model::SlotMeshes::default().attach(
+            &mut model,
+            &settings.car.slots,
+            &game_reg,
+            settings,
+            device,
+        );
 
         CarView {
             model,
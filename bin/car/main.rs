@@ -5,11 +5,6 @@ mod boilerplate;
 fn main() {
     use std::env;
 
-    let (harness, settings) = boilerplate::Harness::init(boilerplate::HarnessOptions {
-        title: "car",
-        uses_level: false,
-    });
-
     let args: Vec<_> = env::args().collect();
     let mut options = getopts::Options::new();
     //TODO: normals on/off
@@ -18,6 +13,7 @@ fn main() {
     options
         .parsing_style(getopts::ParsingStyle::StopAtFirstFree)
         .optflag("h", "help", "print this help menu");
+    boilerplate::add_settings_options(&mut options);
 
     let matches = options.parse(&args[1..]).unwrap();
     if matches.opt_present("h") || !matches.free.is_empty() {
@@ -27,6 +23,18 @@ fn main() {
         return;
     }
 
+    let settings = match boilerplate::load_settings(&matches) {
+        Some(settings) => settings,
+        None => return,
+    };
+    let harness = boilerplate::Harness::init(
+        boilerplate::HarnessOptions {
+            title: "car",
+            uses_level: false,
+        },
+        &settings,
+    );
+
     let app = app::CarView::new(
         &settings,
         &harness.device,
@@ -1,54 +1,139 @@
 mod layers;
 mod level_png;
+mod model_gltf;
 mod model_obj;
+mod physics;
+mod prm;
+mod shape;
 
 use std::{
     fs::{read as fs_read, File},
-    io::BufWriter,
+    io::{BufReader, BufWriter},
     path::{Path, PathBuf},
 };
 
-pub fn save_tiff(path: &Path, layers: layers::LevelLayers) {
+/// Swaps the nibbles of the material layers, which keep the first texel in the lower one,
+/// unlike TIFF.
+fn swap_nibbles(data: &[u8]) -> Vec<u8> {
+    data.iter().map(|&m| m.rotate_left(4)).collect()
+}
+
+pub fn save_tiff(
+    path: &Path,
+    layers: layers::LevelLayers,
+    terrain_colors: &[[u8; 3]],
+    options: &tiff::Options,
+) {
+    let (mat0, mat1) = (swap_nibbles(&layers.mat0), swap_nibbles(&layers.mat1));
+    let layer = |bpp, name, data| tiff::Image {
+        width: layers.size.0,
+        height: layers.size.1,
+        bpp,
+        name,
+        data,
+        palette: if bpp == 4 { Some(terrain_colors) } else { None },
+    };
     let images = [
-        tiff::Image {
-            width: layers.size.0 as u32,
-            height: layers.size.1 as u32,
-            bpp: 8,
-            name: "h0",
-            data: &layers.het0,
-        },
-        tiff::Image {
-            width: layers.size.0 as u32,
-            height: layers.size.1 as u32,
-            bpp: 8,
-            name: "h1",
-            data: &layers.het1,
-        },
-        tiff::Image {
-            width: layers.size.0 as u32,
-            height: layers.size.1 as u32,
-            bpp: 8,
-            name: "del",
-            data: &layers.delta,
-        },
-        tiff::Image {
-            width: layers.size.0 as u32,
-            height: layers.size.1 as u32,
-            bpp: 4,
-            name: "m0",
-            data: &layers.mat0,
-        },
-        tiff::Image {
-            width: layers.size.0 as u32,
-            height: layers.size.1 as u32,
-            bpp: 4,
-            name: "m1",
-            data: &layers.mat1,
-        },
+        layer(8, "h0", &layers.het0[..]),
+        layer(8, "h1", &layers.het1[..]),
+        layer(8, "del", &layers.delta[..]),
+        layer(4, "m0", &mat0[..]),
+        layer(4, "m1", &mat1[..]),
     ];
 
     let file = BufWriter::new(File::create(path).unwrap());
-    tiff::save(file, &images).unwrap();
+    tiff::save_with(file, &images, options).unwrap();
+}
+
+/// Loads the layers written by `save_tiff`, possibly re-saved by an image editor.
+/// Heights may come in 16 bits, and materials in 8 bits per texel.
+///
+/// TIFF doesn't store the number of terrains, so it comes from the level INI.
+pub fn load_tiff(path: &Path, num_terrains: u8) -> layers::LevelLayers {
+    let file = BufReader::new(File::open(path).unwrap());
+    let mut images = tiff::load(file).unwrap();
+    let mut take = |name: &str| {
+        let image = images
+            .remove(name)
+            .unwrap_or_else(|| panic!("Layer '{}' is missing", name));
+        println!(
+            "\t\t{} {}x{} {}bpp",
+            name, image.width, image.height, image.bpp
+        );
+        image
+    };
+
+    let mut heights = Vec::new();
+    for name in &["h0", "h1", "del"] {
+        let image = take(name);
+        let data = match image.bpp {
+            8 => image.data,
+            16 => image.data.chunks(2).map(|pair| pair[1]).collect(),
+            other => panic!("Unexpected {} bits per height", other),
+        };
+        heights.push(((image.width, image.height), data));
+    }
+    let mut materials = Vec::new();
+    for name in &["m0", "m1"] {
+        let image = take(name);
+        let data = match image.bpp {
+            4 => swap_nibbles(&image.data),
+            8 => image
+                .data
+                .chunks(2)
+                .map(|pair| (pair[0] & 0xF) | (pair[1] << 4))
+                .collect(),
+            other => panic!("Unexpected {} bits per material", other),
+        };
+        materials.push(((image.width, image.height), data));
+    }
+
+    let size = heights[0].0;
+    for &(layer_size, _) in heights.iter().chain(materials.iter()) {
+        assert_eq!(layer_size, size, "Layers have different sizes");
+    }
+    let max_terrain = materials
+        .iter()
+        .flat_map(|(_, data)| data.iter().map(|&m| (m & 0xF).max(m >> 4)))
+        .max()
+        .unwrap_or(0);
+    assert!(
+        max_terrain < num_terrains,
+        "Terrain {} is out of the {} terrains of the level",
+        max_terrain,
+        num_terrains
+    );
+    let mut layers = layers::LevelLayers::new(size, num_terrains);
+    layers.delta = heights.pop().unwrap().1;
+    layers.het1 = heights.pop().unwrap().1;
+    layers.het0 = heights.pop().unwrap().1;
+    layers.mat1 = materials.pop().unwrap().1;
+    layers.mat0 = materials.pop().unwrap().1;
+    layers
+}
+
+/// Level description for a brand new world, to be tweaked by hand afterwards.
+fn new_level_config(ini_path: &Path, num_terrains: u8) -> vangers::level::LevelConfig {
+    use vangers::level::{Power, TerrainConfig};
+
+    let colors_per_terrain = 0x100 / num_terrains as u32;
+    vangers::level::LevelConfig {
+        path_palette: ini_path.with_extension("pal"),
+        path_data: ini_path.with_extension(""),
+        is_compressed: false,
+        size: (Power(0), Power(0)),
+        geo: Power(5),
+        section: Power(7),
+        min_square: Power(2),
+        terrains: (0..num_terrains)
+            .map(|i| TerrainConfig {
+                shadow_offset: 0,
+                height_shift: 0,
+                colors: (i as u32 * colors_per_terrain) as u8
+                    ..((i as u32 + 1) * colors_per_terrain - 1) as u8,
+            })
+            .collect(),
+    }
 }
 
 fn main() {
@@ -59,9 +144,79 @@ fn main() {
     let mut options = getopts::Options::new();
     options
         .parsing_style(getopts::ParsingStyle::StopAtFirstFree)
-        .optflag("h", "help", "print this help menu");
+        .optflag("h", "help", "print this help menu")
+        .optopt(
+            "g",
+            "generate",
+            "generate a level with the given seed into the output INI",
+            "SEED",
+        )
+        .optopt(
+            "c",
+            "compress",
+            "compression of the TIFF layers: lzw or deflate",
+            "METHOD",
+        )
+        .optflag("", "big-tiff", "write the TIFF layers with 64-bit offsets")
+        .optopt(
+            "",
+            "level",
+            "level INI to take the number of terrains from, when loading TIFF layers",
+            "INI",
+        )
+        .optflag(
+            "",
+            "update-physics",
+            "recompute the physics of the imported meshes from their geometry, \
+             instead of keeping the stored one",
+        )
+        .optflag(
+            "",
+            "check-physics",
+            "compare the physics of the input M3D with the one computed from the geometry",
+        )
+        .optopt(
+            "",
+            "collision-hull",
+            "replace the collision shapes of the imported model with the convex hulls \
+             of its draw meshes, simplified to about the given number of polygons",
+            "POLYGONS",
+        );
 
     let matches = options.parse(&args[1..]).unwrap();
+    if let Some(seed) = matches.opt_str("g") {
+        use vangers::level::generate;
+
+        let dst_path = match matches.free.as_slice() {
+            [dst] => PathBuf::from(dst),
+            _ => {
+                println!("Expected a single output INI path");
+                return;
+            }
+        };
+        let params = generate::GeneratorParams {
+            seed: seed.parse().expect("Seed has to be a number"),
+            ..Default::default()
+        };
+        println!("\tGenerating the level...");
+        let generated = generate::generate(&params, &dst_path);
+        println!("\tSaving...");
+        generated.save(&dst_path).unwrap();
+        return;
+    }
+    if matches.opt_present("check-physics") {
+        let src_path = match matches.free.as_slice() {
+            [src] => PathBuf::from(src),
+            _ => {
+                println!("Expected a single input M3D path");
+                return;
+            }
+        };
+        println!("\tLoading M3D...");
+        let mut model = m3d::FullModel::load(File::open(&src_path).unwrap()).unwrap();
+        physics::check_model(&mut model);
+        return;
+    }
     if matches.opt_present("h") || matches.free.len() != 2 {
         println!("Vangers resource converter");
         let brief = format!("Usage: {} [options] <input> <output>", args[0]);
@@ -69,6 +224,21 @@ fn main() {
         return;
     }
 
+    let tiff_options = tiff::Options {
+        compression: match matches.opt_str("c").as_deref() {
+            None => tiff::Compression::None,
+            Some("lzw") => tiff::Compression::Lzw,
+            Some("deflate") => tiff::Compression::Deflate,
+            Some(other) => panic!("Unknown compression: {}", other),
+        },
+        big_tiff: matches.opt_present("big-tiff"),
+    };
+    let hull_polygons = matches.opt_str("collision-hull").map(|count| {
+        count
+            .parse::<usize>()
+            .expect("Number of hull polygons has to be a number")
+    });
+    let update_physics = matches.opt_present("update-physics");
     let src_path = PathBuf::from(matches.free[0].as_str());
     let dst_path = PathBuf::from(matches.free[1].as_str());
 
@@ -85,26 +255,84 @@ fn main() {
         ("m3d", "ron") => {
             let file = File::open(&src_path).unwrap();
             println!("\tLoading M3D...");
-            let raw = m3d::FullModel::load(file);
+            let raw = m3d::FullModel::load(file).unwrap();
             println!("\tExporting OBJ data...");
             model_obj::export_m3d(raw, &dst_path);
         }
         ("ron", "md3") => {
             println!("\tImporting OBJ data...");
-            let model = model_obj::import_m3d(&src_path);
+            let mut model = model_obj::import_m3d(&src_path);
+            shape::update_model(&mut model, hull_polygons);
+            if update_physics {
+                physics::update_model(&mut model);
+            }
+            if let Err(e) = model.validate() {
+                panic!("Imported model is invalid: {}", e);
+            }
             println!("\tSaving M3D...");
             model.save(File::create(&dst_path).unwrap());
         }
         ("a3d", "ron") => {
             let file = File::open(&src_path).unwrap();
             println!("\tLoading A3D...");
-            let raw = m3d::AnimatedMesh::load(file);
+            let raw = m3d::AnimatedMesh::load(file).unwrap();
             println!("\tExporting OBJ data...");
             model_obj::export_a3d(raw, &dst_path);
         }
         ("ron", "a3d") => {
             println!("\tImporting OBJ data...");
-            let amesh = model_obj::import_a3d(&src_path);
+            let mut amesh = model_obj::import_a3d(&src_path);
+            if update_physics {
+                for (i, mesh) in amesh.meshes.iter_mut().enumerate() {
+                    physics::update(&format!("frame{}", i), mesh);
+                }
+            }
+            if let Err(e) = amesh.validate() {
+                panic!("Imported model is invalid: {}", e);
+            }
+            println!("\tSaving A3D...");
+            amesh.save(File::create(&dst_path).unwrap());
+        }
+        ("m3d", "gltf") => {
+            let file = File::open(&src_path).unwrap();
+            println!("\tLoading M3D...");
+            let raw = m3d::FullModel::load(file).unwrap();
+            println!("\tExporting glTF...");
+            model_gltf::export_m3d(raw, &dst_path);
+        }
+        ("gltf", "m3d") | ("glb", "m3d") => {
+            println!("\tImporting glTF...");
+            let mut model = model_gltf::import_m3d(&src_path)
+                .unwrap_or_else(|e| panic!("Unable to import {}: {}", src_path.display(), e));
+            shape::update_model(&mut model, hull_polygons);
+            if update_physics {
+                physics::update_model(&mut model);
+            }
+            if let Err(e) = model.validate() {
+                panic!("Imported model is invalid: {}", e);
+            }
+            println!("\tSaving M3D...");
+            model.save(File::create(&dst_path).unwrap());
+        }
+        ("a3d", "gltf") => {
+            let file = File::open(&src_path).unwrap();
+            println!("\tLoading A3D...");
+            let raw = m3d::AnimatedMesh::load(file).unwrap();
+            println!("\tExporting glTF...");
+            model_gltf::export_a3d(raw, &dst_path);
+        }
+        ("gltf", "a3d") | ("glb", "a3d") => {
+            println!("\tImporting glTF...");
+            let mut amesh = model_gltf::import_a3d(&src_path)
+                .unwrap_or_else(|e| panic!("Unable to import {}: {}", src_path.display(), e));
+            if update_physics {
+                for (i, mesh) in amesh.meshes.iter_mut().enumerate() {
+                    physics::update(&format!("frame{}", i), mesh);
+                }
+            }
+            if let Err(e) = amesh.validate() {
+                panic!("Imported model is invalid: {}", e);
+            }
             println!("\tSaving A3D...");
             amesh.save(File::create(&dst_path).unwrap());
         }
@@ -120,16 +348,28 @@ fn main() {
             println!("\tSaving multiple PNGs...");
             level_png::save(&dst_path, layers, &palette);
         }
+        ("prm", "ron") => {
+            println!("\tExporting the parameters...");
+            prm::export(&src_path, &dst_path, prm::Format::Ron);
+        }
+        ("prm", "json") => {
+            println!("\tExporting the parameters...");
+            prm::export(&src_path, &dst_path, prm::Format::Json);
+        }
         ("ini", "tiff") => {
             println!("\tLoading the level...");
             let config = vangers::level::LevelConfig::load(&src_path);
             let level = vangers::level::load(&config);
+            let terrain_colors = layers::extract_palette(&level)
+                .chunks(3)
+                .map(|c| [c[0], c[1], c[2]])
+                .collect::<Vec<_>>();
             let layers = layers::LevelLayers::from_level_data(
                 &vangers::level::LevelData::from(level),
                 config.terrains.len() as u8,
             );
             println!("\tSaving TIFF layers...");
-            save_tiff(&dst_path, layers);
+            save_tiff(&dst_path, layers, &terrain_colors, &tiff_options);
         }
         ("ini", "vmp") => {
             println!("\tLoading the VMC...");
@@ -138,6 +378,34 @@ fn main() {
             println!("\tSaving VMP...");
             vangers::level::LevelData::from(level).save_vmp(&dst_path);
         }
+        ("ini", "vpr") => {
+            println!("\tLoading the level...");
+            let config = vangers::level::LevelConfig::load(&src_path);
+            let level = vangers::level::load(&config);
+            println!("\tBuilding VPR...");
+            let base = vangers::level::vpr::Vpr::load_existing(&config).unwrap();
+            let vpr = vangers::level::vpr::Vpr::from_level(
+                &vangers::level::LevelData::from(level),
+                &config,
+                base,
+            );
+            vpr.save(&dst_path).unwrap();
+        }
+        ("tiff", "vmp") => {
+            let num_terrains = match matches.opt_str("level") {
+                Some(ini) => vangers::level::LevelConfig::load(Path::new(&ini))
+                    .terrains
+                    .len() as u8,
+                None => {
+                    println!("Expected the level INI to be given with --level");
+                    return;
+                }
+            };
+            println!("\tLoading TIFF layers...");
+            let layers = load_tiff(&src_path, num_terrains);
+            println!("\tSaving VMP...");
+            layers.export().save_vmp(&dst_path);
+        }
         ("ron", "vmp") => {
             println!("\tLoading multiple PNGs...");
             let layers = level_png::load(&src_path);
@@ -145,6 +413,37 @@ fn main() {
             let level_data = layers.export();
             level_data.save_vmp(&dst_path);
         }
+        ("ron", "ini") => {
+            use vangers::level::{vpr::Vpr, LevelConfig, Power};
+
+            println!("\tLoading multiple PNGs...");
+            let layers = level_png::load(&src_path);
+            let size = layers.size;
+            let num_terrains = layers.num_terrains;
+            let level_data = layers.export();
+            let (mut config, base) = if dst_path.exists() {
+                println!("\tUpdating the existing INI...");
+                let config = LevelConfig::load(&dst_path);
+                let base = Vpr::load_existing(&config).unwrap();
+                (config, base)
+            } else {
+                (new_level_config(&dst_path, num_terrains), None)
+            };
+            config.size = (
+                Power(size.0.trailing_zeros() as i32),
+                Power(size.1.trailing_zeros() as i32),
+            );
+            config.is_compressed = false;
+            assert_eq!(config.terrains.len(), num_terrains as usize);
+            println!("\tSaving VMP...");
+            level_data.save_vmp(&config.path_data.with_extension("vmp"));
+            println!("\tSaving VPR...");
+            Vpr::from_level(&level_data, &config, base)
+                .save(&config.path_data.with_extension("vpr"))
+                .unwrap();
+            println!("\tSaving INI...");
+            config.save(&dst_path).unwrap();
+        }
         ("pal", "png") => {
             println!("Converting palette to PNG...");
             let data = fs_read(&src_path).unwrap();
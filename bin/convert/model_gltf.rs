@@ -0,0 +1,1059 @@
+//! glTF 2.0 export and import of the models.
+//!
+//! Every mesh becomes a node under a single root, which also rotates
+//! the Z-up model space into the Y-up space of glTF. The data that glTF
+//! has no place for is kept in the node extras, so that the conversion
+//! is lossless, while the node translations stay editable.
+//! The node rotations are only there for display, the exact angles
+//! are read back from the extras.
+
+use m3d::{
+    AnimatedMesh, BodyColor, Bounds, CollisionMesh, CollisionQuad, Debrie, DrawMesh, DrawTriangle,
+    FullModel, Geometry, Mesh, Physics, Polygon, Slot, UpperBound, Vertex, Wheel, NORMALIZER,
+    NUM_COLOR_IDS,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    f32::consts::FRAC_1_SQRT_2,
+    fs,
+    hash::Hash,
+    path::Path,
+};
+
+type DrawAnimatedMesh = AnimatedMesh<Geometry<DrawTriangle>>;
+
+/// Rotation of -90 degrees around X, turning Z-up into Y-up.
+const ROOT_ROTATION: [f32; 4] = [-FRAC_1_SQRT_2, 0.0, 0.0, FRAC_1_SQRT_2];
+const MODE_TRIANGLES: u64 = 4;
+const COMPONENT_U8: u64 = 5121;
+const COMPONENT_U16: u64 = 5123;
+const COMPONENT_U32: u64 = 5125;
+const COMPONENT_F32: u64 = 5126;
+const TARGET_ARRAY_BUFFER: u32 = 34962;
+const TARGET_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const GLB_CHUNK_JSON: u32 = 0x4E4F_534A;
+const GLB_CHUNK_BIN: u32 = 0x004E_4942;
+
+#[derive(Serialize, Deserialize)]
+struct ModelExtras {
+    bound: UpperBound,
+    color: BodyColor,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MeshExtras {
+    bounds: Bounds,
+    max_radius: u32,
+    parent_rot: [i32; 3],
+    physics: Physics,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WheelExtras {
+    steer: u32,
+    pos: [f32; 3],
+    width: u32,
+    radius: u32,
+    bound_index: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SlotExtras {
+    scale: f32,
+    angle: i32,
+}
+
+fn color_names() -> Vec<String> {
+    (0..NUM_COLOR_IDS)
+        .map(|id| format!("{:?}", crate::model_obj::map_color_id(id)))
+        .collect()
+}
+
+fn to_f32(v: [i32; 3]) -> [f32; 3] {
+    [v[0] as f32, v[1] as f32, v[2] as f32]
+}
+
+fn to_i32(v: [f32; 3]) -> [i32; 3] {
+    [
+        v[0].round() as i32,
+        v[1].round() as i32,
+        v[2].round() as i32,
+    ]
+}
+
+fn quantize(v: [f32; 3], scale: f32) -> [i8; 3] {
+    let q = |x: f32| (x * scale).round().clamp(-NORMALIZER, NORMALIZER) as i8;
+    [q(v[0]), q(v[1]), q(v[2])]
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let m2 = v[0] * v[0] + v[1] * v[1] + v[2] * v[2];
+    if m2 == 0.0 {
+        [0.0, 0.0, 1.0]
+    } else {
+        let scale = m2.sqrt().recip();
+        [v[0] * scale, v[1] * scale, v[2] * scale]
+    }
+}
+
+/// Normal of the triangle, scaled to the M3D range.
+fn face_normal(a: [i8; 3], b: [i8; 3], c: [i8; 3]) -> [i8; 3] {
+    let sub = |u: [i8; 3], v: [i8; 3]| {
+        [
+            u[0] as f32 - v[0] as f32,
+            u[1] as f32 - v[1] as f32,
+            u[2] as f32 - v[2] as f32,
+        ]
+    };
+    let (e1, e2) = (sub(b, a), sub(c, a));
+    let n = [
+        e1[1] * e2[2] - e1[2] * e2[1],
+        e1[2] * e2[0] - e1[0] * e2[2],
+        e1[0] * e2[1] - e1[1] * e2[0],
+    ];
+    quantize(normalize(n), NORMALIZER)
+}
+
+/// Rotation by the angles, in degrees, around X, then Y, then Z.
+/// This is how `parent_rot` is shown, the renderer doesn't apply it.
+fn euler_rotation(angles: [i32; 3]) -> [f32; 4] {
+    let half = angles.map(|a| (a as f32).to_radians() * 0.5);
+    let (sx, cx) = half[0].sin_cos();
+    let (sy, cy) = half[1].sin_cos();
+    let (sz, cz) = half[2].sin_cos();
+    [
+        sx * cy * cz - cx * sy * sz,
+        cx * sy * cz + sx * cy * sz,
+        cx * cy * sz - sx * sy * cz,
+        cx * cy * cz + sx * sy * sz,
+    ]
+}
+
+fn mesh_extras<G>(mesh: &Mesh<G>) -> Value {
+    serde_json::to_value(MeshExtras {
+        bounds: mesh.bounds,
+        max_radius: mesh.max_radius,
+        parent_rot: mesh.parent_rot,
+        physics: mesh.physics,
+    })
+    .unwrap()
+}
+
+/// Accumulates the JSON objects and the binary buffer of a glTF file.
+struct Builder {
+    buffer: Vec<u8>,
+    views: Vec<Value>,
+    accessors: Vec<Value>,
+    meshes: Vec<Value>,
+    nodes: Vec<Value>,
+}
+
+impl Builder {
+    fn new() -> Self {
+        Builder {
+            buffer: Vec::new(),
+            views: Vec::new(),
+            accessors: Vec::new(),
+            meshes: Vec::new(),
+            nodes: Vec::new(),
+        }
+    }
+
+    fn push_view(&mut self, data: &[u8], target: u32) -> usize {
+        let aligned = (self.buffer.len() + 3) & !3;
+        self.buffer.resize(aligned, 0);
+        self.views.push(json!({
+            "buffer": 0,
+            "byteOffset": self.buffer.len(),
+            "byteLength": data.len(),
+            "target": target,
+        }));
+        self.buffer.extend_from_slice(data);
+        self.views.len() - 1
+    }
+
+    fn push_vectors(&mut self, vectors: &[[f32; 3]]) -> usize {
+        let data = vectors
+            .iter()
+            .flatten()
+            .flat_map(|v| v.to_le_bytes())
+            .collect::<Vec<_>>();
+        let view = self.push_view(&data, TARGET_ARRAY_BUFFER);
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for v in vectors {
+            for i in 0..3 {
+                min[i] = min[i].min(v[i]);
+                max[i] = max[i].max(v[i]);
+            }
+        }
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": COMPONENT_F32,
+            "count": vectors.len(),
+            "type": "VEC3",
+            "min": min,
+            "max": max,
+        }));
+        self.accessors.len() - 1
+    }
+
+    fn push_indices(&mut self, indices: &[u32]) -> usize {
+        let data = indices
+            .iter()
+            .flat_map(|i| i.to_le_bytes())
+            .collect::<Vec<_>>();
+        let view = self.push_view(&data, TARGET_ELEMENT_ARRAY_BUFFER);
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": COMPONENT_U32,
+            "count": indices.len(),
+            "type": "SCALAR",
+        }));
+        self.accessors.len() - 1
+    }
+
+    /// Splits the vertices by their normals, and the triangles by materials.
+    fn add_draw_mesh(&mut self, name: &str, geometry: &Geometry<DrawTriangle>) -> Option<usize> {
+        if geometry.polygons.is_empty() {
+            return None;
+        }
+        let mut vertex_map = HashMap::new();
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut groups = BTreeMap::<[u32; 2], Vec<u32>>::new();
+        for tri in geometry.polygons.iter() {
+            let indices = groups.entry(tri.material).or_default();
+            for v in tri.vertices.iter() {
+                let index = *vertex_map.entry((v.pos, v.normal)).or_insert_with(|| {
+                    let p = geometry.positions[v.pos as usize];
+                    let n = geometry.normals[v.normal as usize];
+                    positions.push([p[0] as f32, p[1] as f32, p[2] as f32]);
+                    normals.push(normalize([n[0] as f32, n[1] as f32, n[2] as f32]));
+                    positions.len() as u32 - 1
+                });
+                indices.push(index);
+            }
+        }
+
+        let position_accessor = self.push_vectors(&positions);
+        let normal_accessor = self.push_vectors(&normals);
+        let primitives = groups
+            .into_iter()
+            .map(|(material, indices)| {
+                let mut primitive = json!({
+                    "attributes": {
+                        "POSITION": position_accessor,
+                        "NORMAL": normal_accessor,
+                    },
+                    "indices": self.push_indices(&indices),
+                    "material": material[0],
+                    "mode": MODE_TRIANGLES,
+                });
+                if material[1] != 0 {
+                    primitive["extras"] = json!({ "material1": material[1] });
+                }
+                primitive
+            })
+            .collect::<Vec<_>>();
+
+        self.meshes.push(json!({
+            "name": name,
+            "primitives": primitives,
+        }));
+        Some(self.meshes.len() - 1)
+    }
+
+    /// Splits each quad into a pair of triangles.
+    fn add_collision_mesh(
+        &mut self,
+        name: &str,
+        geometry: &Geometry<CollisionQuad>,
+    ) -> Option<usize> {
+        if geometry.polygons.is_empty() {
+            return None;
+        }
+        let positions = geometry
+            .positions
+            .iter()
+            .map(|p| [p[0] as f32, p[1] as f32, p[2] as f32])
+            .collect::<Vec<_>>();
+        let indices = geometry
+            .polygons
+            .iter()
+            .flat_map(|quad| {
+                let v = quad.vertices;
+                [v[0], v[1], v[2], v[0], v[2], v[3]]
+            })
+            .map(u32::from)
+            .collect::<Vec<_>>();
+
+        let position_accessor = self.push_vectors(&positions);
+        let primitive = json!({
+            "attributes": {
+                "POSITION": position_accessor,
+            },
+            "indices": self.push_indices(&indices),
+            "mode": MODE_TRIANGLES,
+        });
+        self.meshes.push(json!({
+            "name": name,
+            "primitives": [primitive],
+            "extras": { "collision": true },
+        }));
+        Some(self.meshes.len() - 1)
+    }
+
+    fn add_node(
+        &mut self,
+        name: &str,
+        mesh: Option<usize>,
+        translation: [f32; 3],
+        extras: Value,
+    ) -> usize {
+        let mut node = json!({
+            "name": name,
+            "translation": translation,
+            "extras": extras,
+        });
+        if let Some(mesh) = mesh {
+            node["mesh"] = json!(mesh);
+        }
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    /// Places the node of a mesh relative to its parent.
+    fn add_mesh_node<G>(&mut self, name: &str, mesh_index: Option<usize>, mesh: &Mesh<G>) -> usize {
+        let extras = json!({ "mesh": mesh_extras(mesh) });
+        let node = self.add_node(name, mesh_index, to_f32(mesh.parent_off), extras);
+        if mesh.parent_rot != [0; 3] {
+            self.nodes[node]["rotation"] = json!(euler_rotation(mesh.parent_rot));
+        }
+        node
+    }
+
+    fn add_draw_node(&mut self, name: &str, mesh: &DrawMesh) -> usize {
+        let mesh_index = self.add_draw_mesh(name, &mesh.geometry);
+        self.add_mesh_node(name, mesh_index, mesh)
+    }
+
+    fn add_collision_node(&mut self, name: &str, mesh: &CollisionMesh) -> usize {
+        let mesh_index = self.add_collision_mesh(name, &mesh.geometry);
+        self.add_mesh_node(name, mesh_index, mesh)
+    }
+
+    /// Writes the JSON part, and the buffer next to it.
+    fn save(self, root_name: &str, children: Vec<usize>, extras: Value, path: &Path) {
+        let mut nodes = self.nodes;
+        nodes.push(json!({
+            "name": root_name,
+            "rotation": ROOT_ROTATION,
+            "children": children,
+            "extras": extras,
+        }));
+        let root = nodes.len() - 1;
+
+        let bin_path = path.with_extension("bin");
+        let bin_name = bin_path.file_name().unwrap().to_str().unwrap();
+        let materials = color_names()
+            .into_iter()
+            .map(|name| json!({ "name": name }))
+            .collect::<Vec<_>>();
+        let document = json!({
+            "asset": {
+                "version": "2.0",
+                "generator": "vangers convert",
+            },
+            "scene": 0,
+            "scenes": [{ "nodes": [root] }],
+            "nodes": nodes,
+            "meshes": self.meshes,
+            "materials": materials,
+            "accessors": self.accessors,
+            "bufferViews": self.views,
+            "buffers": [{
+                "uri": bin_name,
+                "byteLength": self.buffer.len(),
+            }],
+        });
+
+        let string = serde_json::to_string_pretty(&document).unwrap();
+        fs::write(path, string).unwrap();
+        fs::write(&bin_path, &self.buffer).unwrap();
+    }
+}
+
+pub fn export_m3d(full: FullModel, path: &Path) {
+    let mut builder = Builder::new();
+    let mut children = vec![
+        builder.add_draw_node("body", &full.body),
+        builder.add_collision_node("shape", &full.shape),
+    ];
+
+    for (i, wheel) in full.wheels.iter().enumerate() {
+        let name = format!("wheel{}", i);
+        let node = match wheel.mesh {
+            Some(ref mesh) => builder.add_draw_node(&name, mesh),
+            None => builder.add_node(&name, None, wheel.pos, json!({})),
+        };
+        builder.nodes[node]["extras"]["wheel"] = serde_json::to_value(WheelExtras {
+            steer: wheel.steer,
+            pos: wheel.pos,
+            width: wheel.width,
+            radius: wheel.radius,
+            bound_index: wheel.bound_index,
+        })
+        .unwrap();
+        children.push(node);
+    }
+
+    for (i, debrie) in full.debris.iter().enumerate() {
+        let name = format!("debrie{}", i);
+        children.push(builder.add_draw_node(&name, &debrie.mesh));
+        children.push(builder.add_collision_node(&format!("{}-shape", name), &debrie.shape));
+    }
+
+    for (i, slot) in full.slots.iter().enumerate() {
+        let name = format!("slot{}", i);
+        let extras = json!({
+            "slot": SlotExtras {
+                scale: slot.scale,
+                angle: slot.angle,
+            },
+        });
+        let node = builder.add_node(&name, None, to_f32(slot.pos), extras);
+        // same placement as the renderer, see `Batcher::add_model`
+        let half_angle = (slot.angle as f32).to_radians() * 0.5;
+        builder.nodes[node]["rotation"] = json!([0.0, half_angle.sin(), 0.0, half_angle.cos()]);
+        if let Some(ref mesh) = slot.mesh {
+            let child = builder.add_draw_node(&format!("{}-mesh", name), mesh);
+            let off = to_f32(mesh.parent_off);
+            builder.nodes[child]["translation"] = json!([-off[0], -off[1], -off[2]]);
+            builder.nodes[node]["children"] = json!([child]);
+        }
+        children.push(node);
+    }
+
+    let extras = json!({
+        "model": ModelExtras {
+            bound: full.bound,
+            color: full.color,
+        },
+    });
+    builder.save("model", children, extras, path);
+}
+
+pub fn export_a3d(a3d: DrawAnimatedMesh, path: &Path) {
+    let mut builder = Builder::new();
+    let children = a3d
+        .meshes
+        .iter()
+        .enumerate()
+        .map(|(i, mesh)| builder.add_draw_node(&format!("frame{}", i), mesh))
+        .collect();
+    let extras = json!({
+        "model": ModelExtras {
+            bound: a3d.bound,
+            color: a3d.color,
+        },
+    });
+    builder.save("animation", children, extras, path);
+}
+
+#[derive(Default)]
+struct Dedup<T> {
+    items: Vec<T>,
+    map: HashMap<T, u16>,
+}
+
+impl<T: Copy + Eq + Hash> Dedup<T> {
+    fn add(&mut self, item: T) -> u16 {
+        let items = &mut self.items;
+        *self.map.entry(item).or_insert_with(|| {
+            items.push(item);
+            items.len() as u16 - 1
+        })
+    }
+}
+
+fn decode_base64(text: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for c in text.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            _ => continue,
+        };
+        acc = (acc << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    out
+}
+
+struct Triangle {
+    positions: [[f32; 3]; 3],
+    normals: Option<[[f32; 3]; 3]>,
+    /// Color, if the primitive has a material, and the second material.
+    material: (Option<u32>, u32),
+}
+
+/// Parsed glTF or GLB file with the buffers loaded.
+struct Document {
+    json: Value,
+    buffers: Vec<Vec<u8>>,
+    color_names: Vec<String>,
+    nodes: HashMap<String, usize>,
+}
+
+/// Reads an index into one of the top-level arrays of the document.
+fn array_index(value: &Value, what: &str) -> Result<usize, String> {
+    value
+        .as_u64()
+        .map(|i| i as usize)
+        .ok_or_else(|| format!("missing or bad {} index", what))
+}
+
+fn read_translation(node: &Value) -> Result<[f32; 3], String> {
+    let t = match node["translation"].as_array() {
+        Some(t) => t,
+        None => return Ok([0.0; 3]),
+    };
+    let component = |i: usize| {
+        t.get(i)
+            .and_then(Value::as_f64)
+            .map(|v| v as f32)
+            .ok_or_else(|| format!("bad translation {:?}", t))
+    };
+    Ok([component(0)?, component(1)?, component(2)?])
+}
+
+/// Fills the mesh data that glTF tools may not preserve from the geometry.
+/// The bounds and the radius are taken around the node origin.
+fn derive_mesh_extras<P: Polygon>(mesh: &mut Mesh<Geometry<P>>) {
+    let positions = &mesh.geometry.positions;
+    for axis in 0..3 {
+        let values = positions.iter().map(|p| p[axis] as i32);
+        mesh.bounds.coord_min[axis] = values.clone().min().unwrap_or(0);
+        mesh.bounds.coord_max[axis] = values.max().unwrap_or(0);
+    }
+    let max_radius = positions
+        .iter()
+        .map(|p| p.iter().map(|&v| v as f32 * v as f32).sum::<f32>().sqrt())
+        .fold(0.0, f32::max);
+    mesh.max_radius = max_radius.ceil() as u32;
+    mesh.physics = mesh.compute_physics();
+}
+
+impl Document {
+    fn load(path: &Path) -> Result<Self, String> {
+        let data =
+            fs::read(path).map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+        let (json, mut glb_buffer) = if data.starts_with(GLB_MAGIC) {
+            Self::split_glb(&data)?
+        } else {
+            let json = serde_json::from_slice::<Value>(&data)
+                .map_err(|e| format!("unable to parse {}: {}", path.display(), e))?;
+            (json, None)
+        };
+
+        let buffers = json["buffers"]
+            .as_array()
+            .map_or(&[][..], |b| &b[..])
+            .iter()
+            .enumerate()
+            .map(|(i, buffer)| match buffer["uri"].as_str() {
+                Some(uri) if uri.starts_with("data:") => match uri.split_once(',') {
+                    Some((_, encoded)) => Ok(decode_base64(encoded)),
+                    None => Err(format!("buffer {} has a malformed data URI", i)),
+                },
+                Some(uri) => {
+                    let buffer_path = path.with_file_name(uri);
+                    fs::read(&buffer_path).map_err(|e| {
+                        format!("unable to read buffer {}: {}", buffer_path.display(), e)
+                    })
+                }
+                None => glb_buffer
+                    .take()
+                    .ok_or_else(|| format!("buffer {} has no URI and no GLB chunk", i)),
+            })
+            .collect::<Result<_, _>>()?;
+
+        let nodes = json["nodes"]
+            .as_array()
+            .ok_or("no nodes found")?
+            .iter()
+            .enumerate()
+            .filter_map(|(i, node)| Some((node["name"].as_str()?.to_string(), i)))
+            .collect();
+
+        Ok(Document {
+            json,
+            buffers,
+            color_names: color_names(),
+            nodes,
+        })
+    }
+
+    fn split_glb(data: &[u8]) -> Result<(Value, Option<Vec<u8>>), String> {
+        let read_u32 = |offset: usize| {
+            u32::from_le_bytes([
+                data[offset],
+                data[offset + 1],
+                data[offset + 2],
+                data[offset + 3],
+            ])
+        };
+        let mut json = None;
+        let mut bin = None;
+        let mut offset = 12;
+        while offset + 8 <= data.len() {
+            let length = read_u32(offset) as usize;
+            let chunk = data
+                .get(offset + 8..offset + 8 + length)
+                .ok_or_else(|| format!("GLB chunk at {} is truncated", offset))?;
+            match read_u32(offset + 4) {
+                GLB_CHUNK_JSON => {
+                    let value = serde_json::from_slice(chunk)
+                        .map_err(|e| format!("unable to parse the GLB JSON: {}", e))?;
+                    json = Some(value);
+                }
+                GLB_CHUNK_BIN => bin = Some(chunk.to_vec()),
+                _ => {}
+            }
+            offset += 8 + length;
+        }
+        Ok((json.ok_or("GLB has no JSON chunk")?, bin))
+    }
+
+    fn node(&self, name: &str) -> Option<&Value> {
+        let index = *self.nodes.get(name)?;
+        Some(&self.json["nodes"][index])
+    }
+
+    fn accessor_data(&self, index: &Value) -> Result<(&Value, Vec<&[u8]>), String> {
+        let accessor_index = array_index(index, "accessor")?;
+        let accessor = self.json["accessors"]
+            .get(accessor_index)
+            .ok_or_else(|| format!("accessor {} is not found", accessor_index))?;
+        if accessor.get("sparse").is_some() {
+            return Err(format!(
+                "accessor {} is sparse, which is not supported",
+                accessor_index
+            ));
+        }
+        let view_index = array_index(&accessor["bufferView"], "buffer view")?;
+        let view = self.json["bufferViews"]
+            .get(view_index)
+            .ok_or_else(|| format!("buffer view {} is not found", view_index))?;
+        let buffer_index = array_index(&view["buffer"], "buffer")?;
+        let buffer = self
+            .buffers
+            .get(buffer_index)
+            .ok_or_else(|| format!("buffer {} is not found", buffer_index))?;
+        let component_size = match accessor["componentType"].as_u64() {
+            Some(COMPONENT_U8) => 1,
+            Some(COMPONENT_U16) => 2,
+            Some(COMPONENT_U32) | Some(COMPONENT_F32) => 4,
+            other => {
+                return Err(format!(
+                    "accessor {} has unsupported component type {:?}",
+                    accessor_index, other
+                ))
+            }
+        };
+        let element_size = component_size
+            * match accessor["type"].as_str() {
+                Some("VEC3") => 3,
+                Some("SCALAR") => 1,
+                other => {
+                    return Err(format!(
+                        "accessor {} has unsupported type {:?}",
+                        accessor_index, other
+                    ))
+                }
+            };
+        let stride = view["byteStride"]
+            .as_u64()
+            .map_or(element_size, |s| s as usize);
+        let start = view["byteOffset"].as_u64().unwrap_or(0) as usize
+            + accessor["byteOffset"].as_u64().unwrap_or(0) as usize;
+        let count = accessor["count"]
+            .as_u64()
+            .ok_or_else(|| format!("accessor {} has no count", accessor_index))?
+            as usize;
+        if count != 0 && start + (count - 1) * stride + element_size > buffer.len() {
+            return Err(format!(
+                "accessor {} reads past the end of buffer {}",
+                accessor_index, buffer_index
+            ));
+        }
+        let elements = (0..count)
+            .map(|i| &buffer[start + i * stride..start + i * stride + element_size])
+            .collect();
+        Ok((accessor, elements))
+    }
+
+    fn read_vectors(&self, index: &Value) -> Result<Vec<[f32; 3]>, String> {
+        let (accessor, elements) = self.accessor_data(index)?;
+        if accessor["componentType"] != COMPONENT_F32 || accessor["type"] != "VEC3" {
+            return Err(format!("accessor {} is not a float vector", index));
+        }
+        let component = |e: &[u8], i: usize| {
+            f32::from_le_bytes([e[i * 4], e[i * 4 + 1], e[i * 4 + 2], e[i * 4 + 3]])
+        };
+        Ok(elements
+            .into_iter()
+            .map(|e| [component(e, 0), component(e, 1), component(e, 2)])
+            .collect())
+    }
+
+    fn read_indices(&self, primitive: &Value, count: usize) -> Result<Vec<u32>, String> {
+        if primitive["indices"].is_null() {
+            return Ok((0..count as u32).collect());
+        }
+        let (accessor, elements) = self.accessor_data(&primitive["indices"])?;
+        let ty = accessor["componentType"].as_u64();
+        let indices = elements
+            .into_iter()
+            .map(|e| match ty {
+                Some(COMPONENT_U8) => Ok(e[0] as u32),
+                Some(COMPONENT_U16) => Ok(u16::from_le_bytes([e[0], e[1]]) as u32),
+                Some(COMPONENT_U32) => Ok(u32::from_le_bytes([e[0], e[1], e[2], e[3]])),
+                _ => Err(format!("indices can't be of component type {:?}", ty)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        match indices.iter().find(|&&i| i as usize >= count) {
+            Some(i) => Err(format!("index {} is out of the {} vertices", i, count)),
+            None => Ok(indices),
+        }
+    }
+
+    /// Finds the color of a material by its name.
+    fn read_color_id(&self, material: &Value) -> Result<u32, String> {
+        let material_index = array_index(material, "material")?;
+        let name = self.json["materials"][material_index]["name"]
+            .as_str()
+            .ok_or_else(|| format!("material {} has no name", material_index))?;
+        // Blender adds numeric suffixes to the duplicate names
+        let base = name.split('.').next().unwrap();
+        match self.color_names.iter().position(|c| c == base) {
+            Some(color_id) => Ok(color_id as u32),
+            None => Err(format!(
+                "material {} is not a known color, expected one of {:?}",
+                name, self.color_names
+            )),
+        }
+    }
+
+    /// Returns the triangles of all primitives, and their materials.
+    fn read_triangles(&self, mesh: &Value) -> Result<Vec<Triangle>, String> {
+        let mut triangles = Vec::new();
+        let primitives = mesh["primitives"].as_array().ok_or("no primitives found")?;
+        for primitive in primitives {
+            let mode = primitive["mode"].as_u64().unwrap_or(MODE_TRIANGLES);
+            if mode != MODE_TRIANGLES {
+                return Err(format!(
+                    "primitive mode {} is not supported, only triangle lists are",
+                    mode
+                ));
+            }
+            let attributes = &primitive["attributes"];
+            let positions = self.read_vectors(&attributes["POSITION"])?;
+            let normals = if attributes["NORMAL"].is_null() {
+                None
+            } else {
+                let normals = self.read_vectors(&attributes["NORMAL"])?;
+                if normals.len() != positions.len() {
+                    return Err("normals don't match the positions".to_string());
+                }
+                Some(normals)
+            };
+            let color_id = if primitive["material"].is_null() {
+                None
+            } else {
+                Some(self.read_color_id(&primitive["material"])?)
+            };
+            let material1 = primitive["extras"]["material1"].as_u64().unwrap_or(0) as u32;
+
+            let indices = self.read_indices(primitive, positions.len())?;
+            for tri in indices.chunks_exact(3) {
+                let pick = |data: &[[f32; 3]]| {
+                    [
+                        data[tri[0] as usize],
+                        data[tri[1] as usize],
+                        data[tri[2] as usize],
+                    ]
+                };
+                triangles.push(Triangle {
+                    positions: pick(&positions),
+                    normals: normals.as_ref().map(|n| pick(n)),
+                    material: (color_id, material1),
+                });
+            }
+        }
+        Ok(triangles)
+    }
+
+    fn read_draw_geometry(&self, mesh: &Value) -> Result<Geometry<DrawTriangle>, String> {
+        let mut positions = Dedup::default();
+        let mut normals = Dedup::default();
+        let polygons = self
+            .read_triangles(mesh)?
+            .into_iter()
+            .map(|tri| {
+                let color_id = tri.material.0.ok_or("primitive has no material")?;
+                let pos = tri.positions;
+                let p = [
+                    quantize(pos[0], 1.0),
+                    quantize(pos[1], 1.0),
+                    quantize(pos[2], 1.0),
+                ];
+                let flat_normal = face_normal(p[0], p[1], p[2]);
+                let mut vertices = [Vertex::DUMMY; 3];
+                for (i, v) in vertices.iter_mut().enumerate() {
+                    let normal = match tri.normals {
+                        Some(ref n) => quantize(normalize(n[i]), NORMALIZER),
+                        None => flat_normal,
+                    };
+                    *v = Vertex {
+                        pos: positions.add(p[i]),
+                        normal: normals.add(normal),
+                    };
+                }
+                Ok(DrawTriangle {
+                    vertices,
+                    flat_normal,
+                    material: [color_id, tri.material.1],
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Geometry {
+            positions: positions.items,
+            normals: normals.items,
+            polygons,
+        })
+    }
+
+    /// Pairs the triangles back into quads, where possible.
+    fn read_collision_geometry(&self, mesh: &Value) -> Result<Geometry<CollisionQuad>, String> {
+        let mut positions = Dedup::default();
+        let triangles = self
+            .read_triangles(mesh)?
+            .into_iter()
+            .map(|tri| tri.positions.map(|p| positions.add(quantize(p, 1.0))))
+            .collect::<Vec<_>>();
+
+        let mut polygons = Vec::with_capacity(triangles.len() / 2 + 1);
+        let mut rest = &triangles[..];
+        while let Some((a, tail)) = rest.split_first() {
+            let vertices = match tail.first() {
+                Some(b) if b[0] == a[0] && b[1] == a[2] => {
+                    rest = &tail[1..];
+                    [a[0], a[1], a[2], b[2]]
+                }
+                _ => {
+                    rest = tail;
+                    [a[0], a[1], a[2], a[2]]
+                }
+            };
+            let p = vertices.map(|v| positions.items[v as usize]);
+            let sum = (0..3).map(|i| p.iter().map(|v| v[i] as i32).sum::<i32>() / 4);
+            let mut middle = [0i8; 3];
+            for (m, s) in middle.iter_mut().zip(sum) {
+                *m = s as i8;
+            }
+            polygons.push(CollisionQuad {
+                vertices,
+                middle,
+                flat_normal: face_normal(p[0], p[1], p[2]),
+            });
+        }
+        Ok(Geometry {
+            positions: positions.items,
+            normals: Vec::new(),
+            polygons,
+        })
+    }
+
+    /// Reads the mesh of a node, if there is one with this name.
+    ///
+    /// The nodes saved by tools that drop the extras get the bounds,
+    /// the radius, and the physics computed from the geometry.
+    fn read_mesh<P: Polygon>(
+        &self,
+        name: &str,
+        read_geometry: impl FnOnce(&Value) -> Result<Geometry<P>, String>,
+    ) -> Result<Option<Mesh<Geometry<P>>>, String> {
+        let node = match self.node(name) {
+            Some(node) => node,
+            None => return Ok(None),
+        };
+        let in_node = |e: String| format!("node {}: {}", name, e);
+        let geometry = match node["mesh"].as_u64() {
+            Some(index) => {
+                let mesh = self.json["meshes"]
+                    .get(index as usize)
+                    .ok_or_else(|| in_node(format!("mesh {} is not found", index)))?;
+                read_geometry(mesh).map_err(in_node)?
+            }
+            None => Geometry {
+                positions: Vec::new(),
+                normals: Vec::new(),
+                polygons: Vec::new(),
+            },
+        };
+        let translation = read_translation(node).map_err(in_node)?;
+        let extras = &node["extras"]["mesh"];
+        if extras.is_null() {
+            println!(
+                "\t\t{} has no mesh extras, computing them from the geometry",
+                name
+            );
+            let mut mesh = Mesh {
+                geometry,
+                bounds: Bounds {
+                    coord_min: [0; 3],
+                    coord_max: [0; 3],
+                },
+                parent_off: to_i32(translation),
+                parent_rot: [0; 3],
+                max_radius: 0,
+                physics: Physics {
+                    volume: 0.0,
+                    rcm: [0.0; 3],
+                    jacobi: [[0.0; 3]; 3],
+                },
+            };
+            derive_mesh_extras(&mut mesh);
+            return Ok(Some(mesh));
+        }
+        let extras = MeshExtras::deserialize(extras)
+            .map_err(|e| in_node(format!("bad mesh extras: {}", e)))?;
+        Ok(Some(Mesh {
+            geometry,
+            bounds: extras.bounds,
+            parent_off: to_i32(translation),
+            parent_rot: extras.parent_rot,
+            max_radius: extras.max_radius,
+            physics: extras.physics,
+        }))
+    }
+
+    fn read_draw_mesh(&self, name: &str) -> Result<Option<DrawMesh>, String> {
+        self.read_mesh(name, |mesh| self.read_draw_geometry(mesh))
+    }
+
+    fn read_collision_mesh(&self, name: &str) -> Result<Option<CollisionMesh>, String> {
+        self.read_mesh(name, |mesh| self.read_collision_geometry(mesh))
+    }
+
+    fn read_model_extras(&self, root_name: &str) -> Result<ModelExtras, String> {
+        let root = self
+            .node(root_name)
+            .ok_or_else(|| format!("root node {} is not found", root_name))?;
+        ModelExtras::deserialize(&root["extras"]["model"])
+            .map_err(|e| format!("root node {} has no model extras: {}", root_name, e))
+    }
+}
+
+pub fn import_m3d(path: &Path) -> Result<FullModel, String> {
+    let doc = Document::load(path)?;
+    let extras = doc.read_model_extras("model")?;
+
+    let mut wheels = Vec::new();
+    while let Some(node) = doc.node(&format!("wheel{}", wheels.len())) {
+        let name = format!("wheel{}", wheels.len());
+        let w = WheelExtras::deserialize(&node["extras"]["wheel"])
+            .map_err(|e| format!("node {} has no wheel extras: {}", name, e))?;
+        wheels.push(Wheel {
+            mesh: if node["mesh"].is_null() && node["extras"]["mesh"].is_null() {
+                None
+            } else {
+                doc.read_draw_mesh(&name)?
+            },
+            steer: w.steer,
+            pos: w.pos,
+            width: w.width,
+            radius: w.radius,
+            bound_index: w.bound_index,
+        });
+    }
+
+    let mut debris = Vec::new();
+    while let Some(mesh) = doc.read_draw_mesh(&format!("debrie{}", debris.len()))? {
+        let shape_name = format!("debrie{}-shape", debris.len());
+        debris.push(Debrie {
+            mesh,
+            shape: doc
+                .read_collision_mesh(&shape_name)?
+                .ok_or_else(|| format!("node {} is not found", shape_name))?,
+        });
+    }
+
+    let mut slots = [Slot::EMPTY, Slot::EMPTY, Slot::EMPTY];
+    for (i, slot) in slots.iter_mut().enumerate() {
+        let name = format!("slot{}", i);
+        let node = match doc.node(&name) {
+            Some(node) => node,
+            None => continue,
+        };
+        let s = SlotExtras::deserialize(&node["extras"]["slot"])
+            .map_err(|e| format!("node {} has no slot extras: {}", name, e))?;
+        let pos = read_translation(node).map_err(|e| format!("node {}: {}", name, e))?;
+        *slot = Slot {
+            mesh: doc
+                .read_draw_mesh(&format!("{}-mesh", name))?
+                .map(|mut mesh| {
+                    // the node is placed at the negated offset
+                    mesh.parent_off = mesh.parent_off.map(|v| -v);
+                    mesh
+                }),
+            scale: s.scale,
+            pos: to_i32(pos),
+            angle: s.angle,
+        };
+    }
+
+    Ok(FullModel {
+        body: doc
+            .read_draw_mesh("body")?
+            .ok_or("body node is not found")?,
+        shape: doc
+            .read_collision_mesh("shape")?
+            .ok_or("shape node is not found")?,
+        bound: extras.bound,
+        color: extras.color,
+        wheels,
+        debris,
+        slots,
+    })
+}
+
+pub fn import_a3d(path: &Path) -> Result<DrawAnimatedMesh, String> {
+    let doc = Document::load(path)?;
+    let extras = doc.read_model_extras("animation")?;
+    let mut meshes = Vec::new();
+    while let Some(mesh) = doc.read_draw_mesh(&format!("frame{}", meshes.len()))? {
+        meshes.push(mesh);
+    }
+    Ok(AnimatedMesh {
+        meshes,
+        bound: extras.bound,
+        color: extras.color,
+    })
+}
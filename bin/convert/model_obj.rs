@@ -148,7 +148,7 @@ pub fn import_a3d(mesh_path: &Path) -> DrawAnimatedMesh {
     }
 }
 
-fn map_color_id(id: u32) -> ColorId {
+pub fn map_color_id(id: u32) -> ColorId {
     use std::mem;
     if id < NUM_COLOR_IDS {
         unsafe { mem::transmute(id) }
@@ -0,0 +1,69 @@
+use m3d::{FullModel, Geometry, Mesh, Polygon};
+
+/// Visits the draw and collision meshes of the model, together with their names.
+fn visit_meshes(model: &mut FullModel, mut fun: impl FnMut(&str, &mut dyn MeshPhysics)) {
+    fun("body", &mut model.body);
+    fun("shape", &mut model.shape);
+    for (i, wheel) in model.wheels.iter_mut().enumerate() {
+        if let Some(ref mut mesh) = wheel.mesh {
+            fun(&format!("wheel{}", i), mesh);
+        }
+    }
+    for (i, debrie) in model.debris.iter_mut().enumerate() {
+        fun(&format!("debrie{}", i), &mut debrie.mesh);
+        fun(&format!("debrie{}-shape", i), &mut debrie.shape);
+    }
+    for (i, slot) in model.slots.iter_mut().enumerate() {
+        if let Some(ref mut mesh) = slot.mesh {
+            fun(&format!("slot{}", i), mesh);
+        }
+    }
+}
+
+pub trait MeshPhysics {
+    fn stored(&mut self) -> &mut m3d::Physics;
+    fn compute(&self) -> m3d::Physics;
+}
+
+impl<P: Polygon> MeshPhysics for Mesh<Geometry<P>> {
+    fn stored(&mut self) -> &mut m3d::Physics {
+        &mut self.physics
+    }
+    fn compute(&self) -> m3d::Physics {
+        self.compute_physics()
+    }
+}
+
+/// Replaces the stored physics with the one integrated from the geometry.
+pub fn update(name: &str, mesh: &mut dyn MeshPhysics) {
+    let physics = mesh.compute();
+    if physics.volume > 0.0 {
+        *mesh.stored() = physics;
+    } else {
+        println!(
+            "\t\t{} doesn't enclose any volume, keeping the physics",
+            name
+        );
+    }
+}
+
+pub fn update_model(model: &mut FullModel) {
+    visit_meshes(model, update);
+}
+
+/// Reports how far the stored physics is from the one integrated from the geometry.
+pub fn check_model(model: &mut FullModel) {
+    visit_meshes(model, |name, mesh| {
+        let computed = mesh.compute();
+        let stored = mesh.stored();
+        println!(
+            "\t{}: volume {} vs {}, center {:?} vs {:?}, difference {:.1}%",
+            name,
+            stored.volume,
+            computed.volume,
+            stored.rcm,
+            computed.rcm,
+            stored.difference(&computed) * 100.0,
+        );
+    });
+}
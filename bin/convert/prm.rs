@@ -0,0 +1,38 @@
+//! Mirrors of the text parameter files, mostly for diffing them.
+
+use serde::Serialize;
+use vangers::config::{car, common, ParseError};
+
+use std::{fs, path::Path};
+
+#[derive(Clone, Copy, Debug)]
+pub enum Format {
+    Ron,
+    Json,
+}
+
+fn save<T: Serialize>(result: Result<T, ParseError>, format: Format, path: &Path) {
+    let value = result.unwrap_or_else(|e| panic!("{}", e));
+    let string = match format {
+        Format::Ron => {
+            ron::ser::to_string_pretty(&value, ron::ser::PrettyConfig::default()).unwrap()
+        }
+        Format::Json => serde_json::to_string_pretty(&value).unwrap(),
+    };
+    fs::write(path, string).unwrap();
+}
+
+/// Exports a parameter file, picking the layout by its name:
+/// `common.prm`, `car.prm`, or the parameters of a single vehicle otherwise.
+pub fn export(src_path: &Path, dst_path: &Path, format: Format) {
+    let name = src_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    match name.as_str() {
+        "common.prm" => save(common::load(src_path), format, dst_path),
+        "car.prm" => save(car::load_list(src_path), format, dst_path),
+        _ => save(car::CarPhysics::load(src_path), format, dst_path),
+    }
+}
@@ -0,0 +1,85 @@
+use m3d::{CollisionMesh, CollisionQuad, DrawMesh, FullModel, Mesh};
+
+/// Polygons in the shapes generated for the models that have none.
+const DEFAULT_POLYGONS: usize = 32;
+
+fn hull_mesh(mesh: &DrawMesh, max_polygons: usize) -> CollisionMesh {
+    Mesh {
+        geometry: m3d::convex_hull(&mesh.geometry, max_polygons),
+        bounds: mesh.bounds,
+        parent_off: mesh.parent_off,
+        parent_rot: mesh.parent_rot,
+        max_radius: mesh.max_radius,
+        physics: mesh.physics,
+    }
+}
+
+/// Returns true if the shape is replaced.
+fn update(
+    name: &str,
+    shape: &mut CollisionMesh,
+    mesh: &DrawMesh,
+    max_polygons: Option<usize>,
+) -> bool {
+    let max_polygons = match max_polygons {
+        Some(count) => count,
+        None if shape.geometry.polygons.is_empty() => {
+            println!("\t\t{} has no collision polygons, generating them", name);
+            DEFAULT_POLYGONS
+        }
+        None => return false,
+    };
+    *shape = hull_mesh(mesh, max_polygons);
+    println!(
+        "\t\t{} hull has {} polygons",
+        name,
+        shape.geometry.polygons.len()
+    );
+    true
+}
+
+/// Index of the polygon with the middle closest to the point.
+fn nearest_polygon(shape: &CollisionMesh, point: [f32; 3]) -> u32 {
+    let distance = |quad: &CollisionQuad| {
+        (0..3)
+            .map(|i| (quad.middle[i] as f32 - point[i]).powi(2))
+            .sum::<f32>()
+    };
+    shape
+        .geometry
+        .polygons
+        .iter()
+        .enumerate()
+        .min_by(|a, b| distance(a.1).total_cmp(&distance(b.1)))
+        .map_or(0, |(i, _)| i as u32)
+}
+
+/// Replaces the collision shapes with the convex hulls of the draw meshes,
+/// of about `max_polygons` quads each. Without it, only the empty shapes are replaced.
+///
+/// The wheels are bound to the new polygons closest to their old ones,
+/// or to their own positions if the old shape had no such polygon.
+pub fn update_model(model: &mut FullModel, max_polygons: Option<usize>) {
+    let old_polygons = &model.shape.geometry.polygons;
+    let anchors = model
+        .wheels
+        .iter()
+        .map(|wheel| match old_polygons.get(wheel.bound_index as usize) {
+            Some(quad) => quad.middle.map(|v| v as f32),
+            None => wheel.pos,
+        })
+        .collect::<Vec<_>>();
+    if update("shape", &mut model.shape, &model.body, max_polygons) {
+        for (wheel, anchor) in model.wheels.iter_mut().zip(anchors) {
+            wheel.bound_index = nearest_polygon(&model.shape, anchor);
+        }
+    }
+    for (i, debrie) in model.debris.iter_mut().enumerate() {
+        update(
+            &format!("debrie{}-shape", i),
+            &mut debrie.shape,
+            &debrie.mesh,
+            max_polygons,
+        );
+    }
+}
@@ -50,8 +50,10 @@ impl LevelView {
             let level_config = level::LevelConfig::load(&full_path);
             level::load(&level_config)
         } else {
-            let escaves = config::escaves::load(settings.open_relative("escaves.prm"));
-            let worlds = config::worlds::load(settings.open_relative("wrlds.dat"));
+            let escaves = config::escaves::load(&settings.data_path.join("escaves.prm"))
+                .unwrap_or_else(|e| panic!("{}", e));
+            let worlds = config::worlds::load(&settings.data_path.join("wrlds.dat"))
+                .unwrap_or_else(|e| panic!("{}", e));
 
             let ini_name = worlds.get(&settings.game.level).unwrap_or_else(|| {
                 panic!(
@@ -76,8 +78,9 @@ impl LevelView {
                         )
                     });
                 let bunch = {
-                    let file = settings.open_relative("bunches.prm");
-                    let mut bunches = config::bunches::load(file);
+                    let mut bunches =
+                        config::bunches::load(&settings.data_path.join("bunches.prm"))
+                            .unwrap_or_else(|e| panic!("{}", e));
                     let index = bunches
                         .iter()
                         .position(|b| b.escave == escave.name)
@@ -133,7 +136,12 @@ impl LevelView {
             },
         };
 
-        let objects_palette = level::read_palette(settings.open_palette(), None);
+        // generated levels may be viewed without the game data
+        let objects_palette = if settings.check_path("resource/pal/objects.pal") {
+            level::read_palette(settings.open_palette(), None)
+        } else {
+            level.palette
+        };
         #[cfg(feature = "glsl")]
         let store_init = vangers::render::body::GpuStoreInit::new_dummy(device);
         let render = Render::new(
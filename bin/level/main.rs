@@ -5,16 +5,12 @@ mod boilerplate;
 fn main() {
     use std::env;
 
-    let (harness, settings) = boilerplate::Harness::init(boilerplate::HarnessOptions {
-        title: "level",
-        uses_level: true,
-    });
-
     let args: Vec<_> = env::args().collect();
     let mut options = getopts::Options::new();
     options
         .parsing_style(getopts::ParsingStyle::StopAtFirstFree)
         .optflag("h", "help", "print this help menu");
+    boilerplate::add_settings_options(&mut options);
 
     let matches = options.parse(&args[1..]).unwrap();
     if matches.opt_present("h") || matches.free.len() > 1 {
@@ -24,6 +20,18 @@ fn main() {
         return;
     }
 
+    let settings = match boilerplate::load_settings(&matches) {
+        Some(settings) => settings,
+        None => return,
+    };
+    let harness = boilerplate::Harness::init(
+        boilerplate::HarnessOptions {
+            title: "level",
+            uses_level: true,
+        },
+        &settings,
+    );
+
     let path = matches.free.first();
     let app = app::LevelView::new(
         &settings,
@@ -5,10 +5,15 @@ use futures::executor::LocalSpawner;
 use log::info;
 use wgpu::util::DeviceExt as _;
 
-use std::mem;
+use std::{fs::File, mem};
+
+enum Resource {
+    Model(model::VisualModel),
+    Animation(model::AnimatedModel),
+}
 
 pub struct ResourceView {
-    model: model::VisualModel,
+    resource: Resource,
     global: render::global::Context,
     object: render::object::Context,
     transform: space::Transform,
@@ -25,7 +30,7 @@ impl ResourceView {
         queue: &wgpu::Queue,
         downlevel_caps: &wgpu::DownlevelCapabilities,
         color_format: wgpu::TextureFormat,
-    ) -> Self {
+    ) -> Result<Self, m3d::Error> {
         let cam = space::Camera {
             loc: cgmath::vec3(0.0, -200.0, 100.0),
             rot: cgmath::Rotation3::from_angle_x::<cgmath::Rad<_>>(cgmath::Angle::turn_div_6()),
@@ -60,11 +65,22 @@ impl ResourceView {
         );
 
         info!("Loading model {}", path);
-        let file = settings.open_relative(path);
-        let model = model::load_m3d(file, device, &object, settings.game.physics.shape_sampling);
-
-        ResourceView {
-            model,
+        let file = File::open(settings.data_path.join(path))?;
+        let resource = if path.ends_with(".a3d") {
+            model::load_a3d(file, device).map(Resource::Animation)
+        } else {
+            model::load_m3d(
+                file,
+                device,
+                &object,
+                settings.game.physics.shape_sampling,
+                &settings.render.lod,
+            )
+            .map(Resource::Model)
+        }?;
+
+        Ok(ResourceView {
+            resource,
             global,
             object,
             transform: cgmath::Decomposed {
@@ -75,7 +91,7 @@ impl ResourceView {
             cam,
             rotation: cgmath::Rad(0.),
             light_config: settings.render.light,
-        }
+        })
     }
 }
 
@@ -93,6 +109,18 @@ impl Application for ResourceView {
                 Key::Escape => return false,
                 Key::A => self.rotation = -angle,
                 Key::D => self.rotation = angle,
+                Key::P => {
+                    if let Resource::Animation(ref mut animation) = self.resource {
+                        use model::Playback as Pb;
+                        animation.playback = match animation.playback {
+                            Pb::Once => Pb::Loop,
+                            Pb::Loop => Pb::PingPong,
+                            Pb::PingPong => Pb::Once,
+                        };
+                        animation.restart();
+                        info!("Playback {:?}", animation.playback);
+                    }
+                }
                 _ => (),
             },
             KeyboardInput {
@@ -111,12 +139,16 @@ impl Application for ResourceView {
 
     fn update(
         &mut self,
-        _device: &wgpu::Device,
+        device: &wgpu::Device,
         delta: f32,
         _spawner: &LocalSpawner,
     ) -> Vec<wgpu::CommandBuffer> {
         use cgmath::Transform;
 
+        if let Resource::Animation(ref mut animation) = self.resource {
+            animation.advance(delta);
+            animation.prepare(device);
+        }
         if self.rotation != cgmath::Rad(0.) {
             let angle = self.rotation * delta;
             let other = cgmath::Decomposed {
@@ -147,13 +179,21 @@ impl Application for ResourceView {
         _spawner: &LocalSpawner,
     ) -> wgpu::CommandBuffer {
         let mut batcher = render::Batcher::new();
-        batcher.add_model(
-            &self.model,
-            &self.transform,
-            None,
-            &render::body::GpuBody::ZERO,
-            render::object::BodyColor::Dummy,
-        );
+        match self.resource {
+            Resource::Model(ref model) => batcher.add_model(
+                model,
+                &self.transform,
+                None,
+                &render::body::GpuBody::ZERO,
+                render::object::BodyColor::Dummy,
+            ),
+            Resource::Animation(ref animation) => batcher.add_animated_model(
+                animation,
+                &self.transform,
+                &render::body::GpuBody::ZERO,
+                render::object::BodyColor::Dummy,
+            ),
+        }
         batcher.prepare(device);
 
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
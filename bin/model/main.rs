@@ -5,16 +5,12 @@ mod boilerplate;
 fn main() {
     use std::env;
 
-    let (harness, settings) = boilerplate::Harness::init(boilerplate::HarnessOptions {
-        title: "model",
-        uses_level: false,
-    });
-
     let args: Vec<_> = env::args().collect();
     let mut options = getopts::Options::new();
     options
         .parsing_style(getopts::ParsingStyle::StopAtFirstFree)
         .optflag("h", "help", "print this help menu");
+    boilerplate::add_settings_options(&mut options);
 
     let matches = options.parse(&args[1..]).unwrap();
     if matches.opt_present("h") || matches.free.len() != 1 {
@@ -24,15 +20,33 @@ fn main() {
         return;
     }
 
+    let settings = match boilerplate::load_settings(&matches) {
+        Some(settings) => settings,
+        None => return,
+    };
+    let harness = boilerplate::Harness::init(
+        boilerplate::HarnessOptions {
+            title: "model",
+            uses_level: false,
+        },
+        &settings,
+    );
+
     let path = &matches.free[0];
-    let app = app::ResourceView::new(
+    let app = match app::ResourceView::new(
         path,
         &settings,
         &harness.device,
         &harness.queue,
         &harness.downlevel_caps,
         harness.color_format,
-    );
+    ) {
+        Ok(app) => app,
+        Err(e) => {
+            eprintln!("Unable to load {}: {}", path, e);
+            return;
+        }
+    };
 
     harness.main_loop(app);
 }
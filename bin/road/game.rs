@@ -1,5 +1,4 @@
 use crate::{boilerplate::Application, physics};
-use m3d::Mesh;
 #[cfg(feature = "glsl")]
 use vangers::render::{
     body::{GpuStore, GpuStoreInit},
@@ -14,7 +13,12 @@ use vangers::{
 use cgmath::prelude::*;
 use futures::executor::LocalSpawner;
 
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
 #[derive(Debug, PartialEq)]
 struct Ai {
@@ -61,6 +65,7 @@ enum SimulationStep<'a> {
 pub struct Agent {
     _name: String,
     spirit: Spirit,
+    car_id: String,
     car: config::car::CarInfo,
     color: BodyColor,
     control: Control,
@@ -71,6 +76,7 @@ pub struct Agent {
 impl Agent {
     fn spawn(
         name: String,
+        car_id: &str,
         car: &config::car::CarInfo,
         color: BodyColor,
         coords: (i32, i32),
@@ -78,7 +84,7 @@ impl Agent {
         level: &level::Level,
         #[cfg(feature = "glsl")] gpu_store: Option<&mut GpuStore>,
     ) -> Self {
-        let height = physics::get_height(level.get(coords).top()) + 5.; //center offset
+        let height = level::query::get_height(level.get(coords).top()) + 5.; //center offset
         let transform = cgmath::Decomposed {
             scale: car.scale,
             disp: cgmath::vec3(coords.0 as f32, coords.1 as f32, height),
@@ -91,6 +97,7 @@ impl Agent {
                 last_transform: transform,
                 roll_time: 0.0,
             }),
+            car_id: car_id.to_string(),
             car: car.clone(),
             color,
             control: Control::default(),
@@ -238,6 +245,25 @@ struct DataBase {
     game: config::game::Registry,
 }
 
+/// Modification times of the parameter files, so that only the changed ones get reparsed.
+#[derive(Default)]
+struct FileStamps {
+    times: HashMap<PathBuf, SystemTime>,
+}
+
+impl FileStamps {
+    /// Returns true if the file has changed since the last check.
+    fn check(&mut self, path: &Path) -> bool {
+        match fs::metadata(path).and_then(|meta| meta.modified()) {
+            Ok(time) => self.times.insert(path.to_path_buf(), time) != Some(time),
+            Err(e) => {
+                log::warn!("Unable to check {}: {}", path.display(), e);
+                false
+            }
+        }
+    }
+}
+
 #[cfg(feature = "glsl")]
 struct Gpu {
     store: GpuStore,
@@ -301,6 +327,8 @@ struct Roll {
 
 pub struct Game {
     db: DataBase,
+    data_path: PathBuf,
+    file_stamps: FileStamps,
     render: Render,
     batcher: Batcher,
     #[cfg(feature = "glsl")]
@@ -319,6 +347,7 @@ pub struct Game {
     roll: Option<Roll>,
     is_paused: bool,
     tick: Option<f32>,
+    lod: config::settings::Lod,
 }
 
 impl Game {
@@ -332,8 +361,12 @@ impl Game {
     ) -> Self {
         let mut rng = rand::thread_rng();
         log::info!("Loading world parameters");
-        let mut escaves = config::escaves::load(settings.open_relative("escaves.prm"));
-        let mut escaves_secondary = config::escaves::load(settings.open_relative("spots.prm"));
+        let load_escaves = |name: &str| {
+            config::escaves::load(&settings.data_path.join(name))
+                .unwrap_or_else(|e| panic!("{}", e))
+        };
+        let mut escaves = load_escaves("escaves.prm");
+        let mut escaves_secondary = load_escaves("spots.prm");
         escaves.append(&mut escaves_secondary);
 
         let (level, default_coords) = if settings.game.level.is_empty() {
@@ -352,17 +385,22 @@ impl Game {
                 None => (0, 0),
             };
 
-            let worlds = config::worlds::load(settings.open_relative("wrlds.dat"));
-            let ini_name = match worlds.get(&settings.game.level) {
-                Some(name) => name,
-                None => panic!(
-                    "Unknown level '{}', valid names are: {:?}",
-                    settings.game.level,
-                    worlds.keys().collect::<Vec<_>>()
-                ),
+            let ini_path = if settings.game.level.ends_with(".ini") {
+                // a level outside of the world list, e.g. a generated one
+                settings.data_path.join(&settings.game.level)
+            } else {
+                let worlds = config::worlds::load(&settings.data_path.join("wrlds.dat"))
+                    .unwrap_or_else(|e| panic!("{}", e));
+                match worlds.get(&settings.game.level) {
+                    Some(name) => settings.data_path.join(name),
+                    None => panic!(
+                        "Unknown level '{}', valid names are: {:?}",
+                        settings.game.level,
+                        worlds.keys().collect::<Vec<_>>()
+                    ),
+                }
             };
-            let ini_path = settings.data_path.join(ini_name);
-            log::info!("Using level {}", ini_name);
+            log::info!("Using level {}", ini_path.display());
 
             let config = level::LevelConfig::load(&ini_path);
             let level = level::load(&config);
@@ -417,11 +455,14 @@ impl Game {
 
         log::info!("Loading world database");
         let db = {
-            let game = config::game::Registry::load(settings);
+            let game = config::game::Registry::load(settings).unwrap_or_else(|e| panic!("{}", e));
             DataBase {
-                _bunches: config::bunches::load(settings.open_relative("bunches.prm")),
-                cars: config::car::load_registry(settings, &game, device, &render.object),
-                common: config::common::load(settings.open_relative("common.prm")),
+                _bunches: config::bunches::load(&settings.data_path.join("bunches.prm"))
+                    .unwrap_or_else(|e| panic!("{}", e)),
+                cars: config::car::load_registry(settings, &game, device, &render.object)
+                    .unwrap_or_else(|e| panic!("{}", e)),
+                common: config::common::load(&settings.data_path.join("common.prm"))
+                    .unwrap_or_else(|e| panic!("{}", e)),
                 _escaves: escaves,
                 game,
             }
@@ -447,6 +488,7 @@ impl Game {
         let car_names = db.cars.keys().cloned().collect::<Vec<_>>();
         let mut player_agent = Agent::spawn(
             "Player".to_string(),
+            &settings.car.id,
             match db.cars.get(&settings.car.id) {
                 Some(name) => name,
                 None => panic!(
@@ -462,18 +504,14 @@ impl Game {
             gpu.as_mut().map(|Gpu { ref mut store, .. }| store),
         );
         player_agent.spirit = Spirit::Player;
-        for (ms, sid) in player_agent
-            .car
-            .model
-            .slots
-            .iter_mut()
-            .zip(settings.car.slots.iter())
-        {
-            let info = &db.game.model_infos[sid];
-            let raw = Mesh::load(&mut settings.open_relative(&info.path));
-            ms.mesh = Some(model::load_c3d(raw, device));
-            ms.scale = info.scale;
-        }
+        let mut slot_meshes = model::SlotMeshes::default();
+        slot_meshes.attach(
+            &mut player_agent.car.model,
+            &settings.car.slots,
+            &db.game,
+            settings,
+            device,
+        );
 
         let mut agents = vec![player_agent];
         // populate with random agents
@@ -493,8 +531,9 @@ impl Game {
                     rng.gen_range(0..level.size.1),
                 ),
             };
-            let agent = Agent::spawn(
+            let mut agent = Agent::spawn(
                 format!("Other-{}", i),
+                car_id,
                 &db.cars[car_id],
                 color,
                 (x, y),
@@ -503,11 +542,28 @@ impl Game {
                 #[cfg(feature = "glsl")]
                 gpu.as_mut().map(|Gpu { ref mut store, .. }| store),
             );
+            slot_meshes.attach(
+                &mut agent.car.model,
+                &settings.game.other.slots,
+                &db.game,
+                settings,
+                device,
+            );
             agents.push(agent);
         }
 
+        let data_path = settings.data_path.clone();
+        let mut file_stamps = FileStamps::default();
+        file_stamps.check(&data_path.join("common.prm"));
+        file_stamps.check(&data_path.join("car.prm"));
+        for car in db.cars.values() {
+            file_stamps.check(&car.physics_path);
+        }
+
         Game {
             db,
+            data_path,
+            file_stamps,
             render,
             batcher: Batcher::new(),
             #[cfg(feature = "glsl")]
@@ -526,6 +582,97 @@ impl Game {
             roll: None,
             is_paused: false,
             tick: None,
+            lod: settings.render.lod,
+        }
+    }
+
+    /// Reparses the changed parameter files and pushes them into the live agents,
+    /// including the vehicle scales.
+    /// A file that fails to parse is reported and keeps the old parameters.
+    fn reload_parameters(&mut self) {
+        let mut changed = false;
+        let common_path = self.data_path.join("common.prm");
+        if self.file_stamps.check(&common_path) {
+            log::info!("Reloading {}", common_path.display());
+            match config::common::load(&common_path) {
+                Ok(common) => {
+                    changed = true;
+                    self.db.common = common;
+                    #[cfg(feature = "glsl")]
+                    if let Some(ref mut gpu) = self.gpu {
+                        gpu.store.update_common(&self.db.common);
+                    }
+                }
+                Err(e) => log::error!("{}", e),
+            }
+        }
+
+        let list_path = self.data_path.join("car.prm");
+        if self.file_stamps.check(&list_path) {
+            log::info!("Reloading {}", list_path.display());
+            match config::car::load_list(&list_path) {
+                Ok(list) => {
+                    changed = true;
+                    for entry in list {
+                        if let Some(car) = self.db.cars.get_mut(&entry.name) {
+                            car.kind = entry.kind;
+                            car.stats = entry.stats;
+                        }
+                    }
+                }
+                Err(e) => log::error!("{}", e),
+            }
+        }
+
+        let mut physics_paths = self
+            .db
+            .cars
+            .values()
+            .map(|car| car.physics_path.clone())
+            .collect::<Vec<_>>();
+        // vehicles without their own parameters share the defaults
+        physics_paths.sort();
+        physics_paths.dedup();
+        for path in physics_paths {
+            if !self.file_stamps.check(&path) {
+                continue;
+            }
+            log::info!("Reloading {}", path.display());
+            let physics = match config::car::CarPhysics::load(&path) {
+                Ok(physics) => physics,
+                Err(e) => {
+                    log::error!("{}", e);
+                    continue;
+                }
+            };
+            changed = true;
+            for car in self.db.cars.values_mut() {
+                if car.physics_path == path {
+                    car.set_physics(physics.clone());
+                }
+            }
+        }
+        if !changed {
+            return;
+        }
+
+        for agent in self.agents.iter_mut() {
+            let car = &self.db.cars[&agent.car_id];
+            agent.car.kind = car.kind;
+            agent.car.stats = car.stats;
+            agent.car.physics = car.physics.clone();
+            agent.car.scale = car.scale;
+            match agent.physics {
+                Physics::Cpu {
+                    ref mut transform, ..
+                } => transform.scale = car.scale,
+                #[cfg(feature = "glsl")]
+                Physics::Gpu { ref body, .. } => {
+                    let gpu = self.gpu.as_mut().unwrap();
+                    gpu.store.update_physics(body, &agent.car.physics);
+                    gpu.store.update_scale(body, car.scale);
+                }
+            }
         }
     }
 
@@ -871,6 +1018,7 @@ impl Application for Game {
 
     fn reload(&mut self, device: &wgpu::Device) {
         self.render.reload(device);
+        self.reload_parameters();
         #[cfg(feature = "glsl")]
         if let Some(Gpu {
             ref mut store,
@@ -901,6 +1049,15 @@ impl Application for Game {
         let identity_transform = space::Transform::one();
         let clipper = Clipper::new(&self.cam);
         self.batcher.clear();
+        // GPU bodies are only positioned on the GPU, so they get the full meshes
+        #[cfg(feature = "glsl")]
+        let lod_origin = match self.gpu {
+            Some(_) => None,
+            None => Some(self.cam.loc),
+        };
+        #[cfg(not(feature = "glsl"))]
+        let lod_origin = Some(self.cam.loc);
+        self.batcher.set_lod_view(lod_origin, &self.lod);
 
         for agent in self.agents.iter() {
             let (gpu_body, transform) = match agent.physics {
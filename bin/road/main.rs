@@ -1,7 +1,5 @@
 #![allow(irrefutable_let_patterns)]
 
-use log::info;
-
 #[path = "../boilerplate.rs"]
 mod boilerplate;
 mod game;
@@ -10,17 +8,12 @@ mod physics;
 fn main() {
     use std::env;
 
-    let (harness, settings) = boilerplate::Harness::init(boilerplate::HarnessOptions {
-        title: "road",
-        uses_level: true,
-    });
-
-    info!("Parsing command line");
     let args: Vec<_> = env::args().collect();
     let mut options = getopts::Options::new();
     options
         .parsing_style(getopts::ParsingStyle::StopAtFirstFree)
         .optflag("h", "help", "print this help menu");
+    boilerplate::add_settings_options(&mut options);
 
     let matches = options.parse(&args[1..]).unwrap();
     if matches.opt_present("h") || !matches.free.is_empty() {
@@ -30,6 +23,18 @@ fn main() {
         return;
     }
 
+    let settings = match boilerplate::load_settings(&matches) {
+        Some(settings) => settings,
+        None => return,
+    };
+    let harness = boilerplate::Harness::init(
+        boilerplate::HarnessOptions {
+            title: "road",
+            uses_level: true,
+        },
+        &settings,
+    );
+
     let game = game::Game::new(
         &settings,
         harness.color_format,
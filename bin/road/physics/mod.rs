@@ -10,8 +10,6 @@ use std::f32::EPSILON;
 mod rigid;
 mod terrain;
 
-pub use terrain::get_height;
-
 const MAX_TRACTION: config::common::Traction = 4.0;
 
 #[derive(Debug)]
@@ -296,7 +294,7 @@ pub fn step(
             let pw = transform.transform_point(cgmath::Point3::from(wheel.pos));
             let detect_wheel_hits = false;
             if detect_wheel_hits {
-                let dist = terrain::get_distance_to_terrain(level, pw);
+                let dist = level::query::get_distance_to_terrain(level, pw);
                 if dist > 0.0 {
                     continue;
                 }
@@ -1,4 +1,11 @@
-use vangers::{config, level, model, space};
+use vangers::{
+    config,
+    level::{
+        self,
+        query::{get_height, get_middle},
+    },
+    model, space,
+};
 
 use cgmath::prelude::*;
 
@@ -46,35 +53,6 @@ impl HitAccumulator {
     }
 }
 
-pub fn get_height(altitude: u8) -> f32 {
-    altitude as f32 * (level::HEIGHT_SCALE as f32) / 255.0
-}
-
-// see `GET_MIDDLE_HIGHT` macro
-fn get_middle(low: u8, high: u8) -> f32 {
-    let extra_room = if high.saturating_sub(low) > 130 {
-        110
-    } else {
-        48
-    };
-    get_height(low.saturating_add(extra_room))
-}
-
-pub fn get_distance_to_terrain(level: &level::Level, point: cgmath::Point3<f32>) -> f32 {
-    let altitude = match level.get((point.x as i32, point.y as i32)) {
-        level::Texel::Single(p) => p.0,
-        level::Texel::Dual { high, low, .. } => {
-            let middle = get_middle(low.0, high.0);
-            if point.z > middle {
-                high.0
-            } else {
-                low.0
-            }
-        }
-    };
-    point.z - get_height(altitude)
-}
-
 impl CollisionData {
     pub fn collide_low(
         poly: &model::Polygon,
@@ -0,0 +1,379 @@
+//! Collision shapes built from the convex hull of the draw geometry.
+//!
+//! The hull is computed exactly on the integer positions, optionally simplified,
+//! and then the adjacent triangles that are nearly coplanar get paired into quads.
+//! The simplification cuts the corners of the hull, so the faces of the simplified
+//! hull are pushed back out until it encloses all the points.
+
+use crate::{simplify, CollisionQuad, DrawTriangle, Geometry, Vertex, NORMALIZER};
+
+use std::collections::{HashMap, HashSet};
+
+/// Smallest cosine between the halves of a quad.
+const MIN_QUAD_FLATNESS: f64 = 0.98;
+/// Largest error of the collapses that are considered to keep the surface.
+const FLAT_ERROR: f32 = 1.0e-3;
+
+type Point = [i64; 3];
+
+fn sub(a: Point, b: Point) -> Point {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: Point, b: Point) -> Point {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn dot(a: Point, b: Point) -> i64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// Positive if `d` is in front of the counter-clockwise triangle `abc`.
+fn orient(a: Point, b: Point, c: Point, d: Point) -> i64 {
+    dot(cross(sub(b, a), sub(c, a)), sub(d, a))
+}
+
+fn triangle_normal(p: &[Point], t: [usize; 3]) -> Point {
+    cross(sub(p[t[1]], p[t[0]]), sub(p[t[2]], p[t[0]]))
+}
+
+fn unit(n: Point) -> [f64; 3] {
+    let v = n.map(|c| c as f64);
+    let len = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if len > 0.0 {
+        v.map(|c| c / len)
+    } else {
+        v
+    }
+}
+
+/// Finds the corners of a tetrahedron, facing outwards with the last corner
+/// being the apex. Returns nothing if all the points are coplanar.
+fn tetrahedron(points: &[Point]) -> Option<[usize; 4]> {
+    let p0 = 0;
+    let p1 = (1..points.len()).find(|&i| points[i] != points[p0])?;
+    let p2 = (1..points.len())
+        .find(|&i| cross(sub(points[p1], points[p0]), sub(points[i], points[p0])) != [0; 3])?;
+    let p3 =
+        (1..points.len()).find(|&i| orient(points[p0], points[p1], points[p2], points[i]) != 0)?;
+    Some(
+        if orient(points[p0], points[p1], points[p2], points[p3]) > 0 {
+            [p0, p2, p1, p3]
+        } else {
+            [p0, p1, p2, p3]
+        },
+    )
+}
+
+/// Builds the hull triangles, facing outwards, with the indices into `points`.
+/// Returns nothing if all the points are coplanar.
+fn hull_triangles(points: &[Point]) -> Option<Vec<[usize; 3]>> {
+    let [p0, b, c, p3] = tetrahedron(points)?;
+    let mut faces = vec![[p0, b, c], [p0, p3, b], [b, p3, c], [c, p3, p0]];
+
+    for i in 0..points.len() {
+        let p = points[i];
+        let (visible, kept): (Vec<_>, Vec<_>) = faces
+            .into_iter()
+            .partition(|f| orient(points[f[0]], points[f[1]], points[f[2]], p) > 0);
+        faces = kept;
+        if visible.is_empty() {
+            continue;
+        }
+        let edges = visible
+            .iter()
+            .flat_map(|f| (0..3).map(move |j| (f[j], f[(j + 1) % 3])))
+            .collect::<HashSet<_>>();
+        for &(u, v) in edges.iter() {
+            if !edges.contains(&(v, u)) {
+                faces.push([u, v, i]);
+            }
+        }
+    }
+    Some(faces)
+}
+
+/// Largest number of the rounds pushing the faces out in `enclose`.
+const MAX_PUSHES: usize = 64;
+
+/// Checks that all the points are behind the faces, or in them.
+fn encloses(points: &[Point], hull: &[Point], triangles: &[[usize; 3]]) -> bool {
+    triangles.iter().all(|t| {
+        points
+            .iter()
+            .all(|&p| orient(hull[t[0]], hull[t[1]], hull[t[2]], p) <= 0)
+    })
+}
+
+/// Pushes the faces of the hull out until all the points are behind them.
+/// Each round moves the corners away from the center of the hull by half of the largest
+/// factor that their faces need to reach the farthest points, and takes the hull again.
+/// The half steps keep the corners shared by several faces from overshooting.
+/// Returns nothing if the result doesn't fit into the coordinate range.
+fn enclose(points: &[Point], mut hull: Vec<Point>) -> Option<(Vec<Point>, Vec<[usize; 3]>)> {
+    for _ in 0..MAX_PUSHES {
+        let triangles = hull_triangles(&hull)?;
+        let center =
+            [0, 1, 2].map(|i| hull.iter().map(|p| p[i] as f64).sum::<f64>() / hull.len() as f64);
+        let mut scale = vec![1f64; hull.len()];
+        let mut is_enclosing = true;
+        for &t in triangles.iter() {
+            let excess = points
+                .iter()
+                .map(|&p| orient(hull[t[0]], hull[t[1]], hull[t[2]], p))
+                .max()
+                .unwrap_or(0);
+            if excess > 0 {
+                is_enclosing = false;
+                let normal = triangle_normal(&hull, t);
+                // the face is at this distance from the center, scaled by `|normal|`
+                let depth = (0..3)
+                    .map(|i| normal[i] as f64 * (hull[t[0]][i] as f64 - center[i]))
+                    .sum::<f64>();
+                for &v in t.iter() {
+                    scale[v] = scale[v].max(1.0 + 0.5 * excess as f64 / depth);
+                }
+            }
+        }
+        if is_enclosing {
+            return Some((hull, triangles));
+        }
+        for (p, s) in hull.iter_mut().zip(scale) {
+            for (c, &o) in p.iter_mut().zip(center.iter()) {
+                let d = o + (*c as f64 - o) * s;
+                // rounding outwards, so that every push makes progress
+                *c = if d > o { d.ceil() } else { d.floor() } as i64;
+                if *c < i8::MIN as i64 || *c > i8::MAX as i64 {
+                    return None;
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Picks the corners of a hull with at most `max_triangles` inside the full one,
+/// adding the point that is the farthest outside, one at a time.
+fn reduce_hull(points: &[Point], max_triangles: usize) -> Vec<Point> {
+    // start with the largest tetrahedron that is easy to find
+    let p0 = match points.iter().min() {
+        Some(&p) => p,
+        None => return Vec::new(),
+    };
+    let p1 = *points
+        .iter()
+        .max_by_key(|&&p| dot(sub(p, p0), sub(p, p0)))
+        .unwrap();
+    let p2 = *points
+        .iter()
+        .max_by_key(|&&p| {
+            let n = cross(sub(p1, p0), sub(p, p0));
+            dot(n, n)
+        })
+        .unwrap();
+    let p3 = *points
+        .iter()
+        .max_by_key(|&&p| orient(p0, p1, p2, p).abs())
+        .unwrap();
+    let seed = [p0, p1, p2, p3];
+    let mut corners = match tetrahedron(&seed) {
+        Some(corners) => corners.map(|c| seed[c]).to_vec(),
+        None => return Vec::new(),
+    };
+    // every corner on a hull adds two triangles
+    while 2 * corners.len() + 2 <= max_triangles {
+        let triangles = hull_triangles(&corners).unwrap();
+        let farthest = points
+            .iter()
+            .map(|&p| {
+                let distance = triangles
+                    .iter()
+                    .map(|&t| {
+                        let normal = triangle_normal(&corners, t);
+                        let d = orient(corners[t[0]], corners[t[1]], corners[t[2]], p);
+                        d as f64 / (dot(normal, normal) as f64).sqrt()
+                    })
+                    .fold(0.0, f64::max);
+                (distance, p)
+            })
+            .max_by(|a, b| a.0.total_cmp(&b.0));
+        match farthest {
+            Some((distance, p)) if distance > 0.0 => corners.push(p),
+            _ => break,
+        }
+    }
+    corners
+}
+
+/// Reduces the hull to about `max_triangles`, if it has more, and pushes
+/// the result out to enclose the points. Otherwise, only removes
+/// the vertices lying on the faces and edges.
+fn simplify_hull(
+    points: &[Point],
+    triangles: &[[usize; 3]],
+    max_triangles: usize,
+) -> (Vec<Point>, Vec<[usize; 3]>) {
+    // the reduced hulls that can't be pushed out are refined further
+    let mut budget = max_triangles;
+    while budget < triangles.len() {
+        if let Some(result) = enclose(points, reduce_hull(points, budget)) {
+            return result;
+        }
+        budget *= 2;
+    }
+
+    let geometry = Geometry {
+        positions: points.iter().map(|p| p.map(|c| c as i8)).collect(),
+        normals: vec![[0; 3]],
+        polygons: triangles
+            .iter()
+            .map(|t| DrawTriangle {
+                vertices: t.map(|pos| Vertex {
+                    pos: pos as u16,
+                    normal: 0,
+                }),
+                flat_normal: [0; 3],
+                material: [0; 2],
+            })
+            .collect(),
+    };
+    let geometry = simplify(&geometry, 0, FLAT_ERROR).geometry;
+    let hull = geometry
+        .positions
+        .iter()
+        .map(|p| p.map(|c| c as i64))
+        .collect::<Vec<_>>();
+    let flat = geometry
+        .polygons
+        .iter()
+        .map(|tri| tri.vertices.map(|v| v.pos as usize))
+        .collect::<Vec<_>>();
+    if encloses(points, &hull, &flat) {
+        (hull, flat)
+    } else {
+        (points.to_vec(), triangles.to_vec())
+    }
+}
+
+/// Pairs the adjacent triangles into quads, flattest pairs first.
+/// The unpaired triangles repeat their last corner.
+fn pair_triangles(points: &[Point], triangles: &[[usize; 3]]) -> Vec<[usize; 4]> {
+    let edges = triangles
+        .iter()
+        .enumerate()
+        .flat_map(|(t, tri)| (0..3).map(move |j| ((tri[j], tri[(j + 1) % 3]), (t, j))))
+        .collect::<HashMap<_, _>>();
+    let normals = triangles
+        .iter()
+        .map(|&t| unit(triangle_normal(points, t)))
+        .collect::<Vec<_>>();
+
+    let mut pairs = Vec::new();
+    for (&(u, v), &(t1, j1)) in edges.iter() {
+        let (t2, j2) = match edges.get(&(v, u)) {
+            Some(&other) if other.0 > t1 => other,
+            _ => continue,
+        };
+        let (n1, n2) = (normals[t1], normals[t2]);
+        let flatness = n1[0] * n2[0] + n1[1] * n2[1] + n1[2] * n2[2];
+        if flatness < MIN_QUAD_FLATNESS {
+            continue;
+        }
+        // the corners opposite to the shared edge
+        let x = triangles[t1][(j1 + 2) % 3];
+        let y = triangles[t2][(j2 + 2) % 3];
+        let quad = [u, y, v, x];
+        // convex if the other diagonal keeps both halves facing the same way
+        let n = triangle_normal(points, [u, v, x]);
+        let convex = dot(triangle_normal(points, [y, v, x]), n) > 0
+            && dot(triangle_normal(points, [x, u, y]), n) > 0;
+        if convex {
+            pairs.push((flatness, t1, t2, quad));
+        }
+    }
+    pairs.sort_by(|a, b| b.0.total_cmp(&a.0).then((a.1, a.2).cmp(&(b.1, b.2))));
+
+    let mut used = vec![false; triangles.len()];
+    let mut quads = Vec::with_capacity(triangles.len());
+    for (_, t1, t2, quad) in pairs {
+        if !used[t1] && !used[t2] {
+            used[t1] = true;
+            used[t2] = true;
+            quads.push(quad);
+        }
+    }
+    for (tri, _) in triangles.iter().zip(used).filter(|&(_, used)| !used) {
+        quads.push([tri[0], tri[1], tri[2], tri[2]]);
+    }
+    quads
+}
+
+/// Builds a collision shape enclosing the draw geometry: its convex hull,
+/// simplified down to about `max_polygons` quads. More quads are kept when
+/// the simplified shape can't enclose the geometry within the coordinate range.
+/// The shape is empty if the geometry is flat.
+pub fn convex_hull(
+    geometry: &Geometry<DrawTriangle>,
+    max_polygons: usize,
+) -> Geometry<CollisionQuad> {
+    let mut used = vec![false; geometry.positions.len()];
+    for tri in geometry.polygons.iter() {
+        for v in tri.vertices.iter() {
+            used[v.pos as usize] = true;
+        }
+    }
+    let mut points = geometry
+        .positions
+        .iter()
+        .zip(used)
+        .filter(|&(_, used)| used)
+        .map(|(p, _)| p.map(|c| c as i64))
+        .collect::<Vec<_>>();
+    points.sort_unstable();
+    points.dedup();
+
+    let mut result = Geometry {
+        positions: Vec::new(),
+        normals: Vec::new(),
+        polygons: Vec::new(),
+    };
+    let triangles = match hull_triangles(&points) {
+        Some(triangles) => triangles,
+        None => return result,
+    };
+    let (points, triangles) = simplify_hull(&points, &triangles, 2 * max_polygons.max(2));
+
+    let mut position_map = vec![None; points.len()];
+    for quad in pair_triangles(&points, &triangles) {
+        let corners = if quad[2] == quad[3] {
+            &quad[..3]
+        } else {
+            &quad[..]
+        };
+        let mut sum = [0i64; 3];
+        for &c in corners {
+            for (s, v) in sum.iter_mut().zip(points[c]) {
+                *s += v;
+            }
+        }
+        let count = corners.len() as f64;
+        let n1 = triangle_normal(&points, [quad[0], quad[1], quad[2]]);
+        let n2 = triangle_normal(&points, [quad[0], quad[2], quad[3]]);
+        let normal = unit([n1[0] + n2[0], n1[1] + n2[1], n1[2] + n2[2]]);
+        result.polygons.push(CollisionQuad {
+            vertices: quad.map(|c| {
+                *position_map[c].get_or_insert_with(|| {
+                    result.positions.push(points[c].map(|v| v as i8));
+                    result.positions.len() as u16 - 1
+                })
+            }),
+            middle: sum.map(|s| (s as f64 / count).round() as i8),
+            flat_normal: normal.map(|c| (c * NORMALIZER as f64).round() as i8),
+        });
+    }
+    result
+}
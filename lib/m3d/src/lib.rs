@@ -12,32 +12,105 @@
 #![allow(missing_debug_implementations, clippy::new_without_default)]
 
 mod geometry;
+mod hull;
+mod simplify;
 
 pub use self::geometry::{
     CollisionQuad, ColorId, DrawTriangle, Geometry, Vertex, NORMALIZER, NUM_COLOR_IDS,
 };
+pub use self::hull::convex_hull;
+pub use self::simplify::{simplify, Simplified};
 
 use byteorder::{LittleEndian as E, ReadBytesExt, WriteBytesExt};
 use serde::{Deserialize, Serialize};
-use std::{fs::File, io::Write};
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Write},
+};
 
 const MAX_SLOTS: usize = 3;
 const MAGIC_VERSION: u32 = 8;
+/// Vertices are referenced by 16-bit indices.
+const MAX_VERTICES: u32 = 0x10000;
+/// Upper bound on the reserved capacity, so that garbage counts
+/// fail on reading instead of exhausting the memory.
+const MAX_RESERVE: u32 = 0x1000;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Version(u32),
+    TooManyVertices(u32),
+    /// Polygon with an unexpected number of corners.
+    Corners(u32),
+    /// Vertex, normal, or polygon index out of range.
+    Index {
+        what: &'static str,
+        index: u32,
+        count: usize,
+    },
+    SlotMask(u32),
+    NanPhysics,
+    Color(u32),
+    /// Animation without any meshes.
+    NoFrames,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "{}", e),
+            Error::Version(version) => write!(f, "unknown version {}", version),
+            Error::TooManyVertices(count) => write!(f, "too many vertices: {}", count),
+            Error::Corners(count) => write!(f, "polygon with {} corners", count),
+            Error::Index { what, index, count } => {
+                write!(f, "{} index {} is out of {}", what, index, count)
+            }
+            Error::SlotMask(mask) => write!(f, "invalid slot mask 0x{:X}", mask),
+            Error::NanPhysics => write!(f, "physics parameters are not finite"),
+            Error::Color(color) => write!(f, "unknown color id {}", color),
+            Error::NoFrames => write!(f, "animation has no frames"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
 
-fn read_vec_i32<I: ReadBytesExt>(source: &mut I) -> [i32; 3] {
-    [
-        source.read_i32::<E>().unwrap(),
-        source.read_i32::<E>().unwrap(),
-        source.read_i32::<E>().unwrap(),
-    ]
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
 }
 
-fn read_vec_i8<I: ReadBytesExt>(source: &mut I) -> [i8; 3] {
-    [
-        source.read_i8().unwrap(),
-        source.read_i8().unwrap(),
-        source.read_i8().unwrap(),
-    ]
+fn check_index(what: &'static str, index: u32, count: usize) -> Result<(), Error> {
+    if (index as usize) < count {
+        Ok(())
+    } else {
+        Err(Error::Index { what, index, count })
+    }
+}
+
+/// Reads a 32-bit index, which has to fit the 16 bits it's stored in.
+fn read_index<I: ReadBytesExt>(
+    source: &mut I,
+    what: &'static str,
+    count: usize,
+) -> Result<u16, Error> {
+    let index = source.read_u32::<E>()?;
+    u16::try_from(index).map_err(|_| Error::Index { what, index, count })
+}
+
+fn read_vec_i32<I: ReadBytesExt>(source: &mut I) -> io::Result<[i32; 3]> {
+    Ok([
+        source.read_i32::<E>()?,
+        source.read_i32::<E>()?,
+        source.read_i32::<E>()?,
+    ])
+}
+
+fn read_vec_i8<I: ReadBytesExt>(source: &mut I) -> io::Result<[i8; 3]> {
+    Ok([source.read_i8()?, source.read_i8()?, source.read_i8()?])
 }
 
 fn write_vec_i32<W: WriteBytesExt>(dest: &mut W, v: [i32; 3]) {
@@ -60,13 +133,13 @@ pub struct Physics {
 }
 
 impl Physics {
-    fn load<I: ReadBytesExt>(source: &mut I) -> Self {
+    fn load<I: ReadBytesExt>(source: &mut I) -> io::Result<Self> {
         let mut q = [0.0f32; 1 + 3 + 9];
         for qel in q.iter_mut() {
-            *qel = source.read_f64::<E>().unwrap() as f32;
+            *qel = source.read_f64::<E>()? as f32;
         }
 
-        Physics {
+        Ok(Physics {
             volume: q[0],
             rcm: [q[1], q[2], q[3]],
             jacobi: [
@@ -74,9 +147,50 @@ impl Physics {
                 [q[5], q[8], q[11]],
                 [q[6], q[9], q[12]],
             ],
+        })
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        let all_finite = self.volume.is_finite()
+            && self.rcm.iter().all(|v| v.is_finite())
+            && self.jacobi.iter().flatten().all(|v| v.is_finite());
+        if all_finite {
+            Ok(())
+        } else {
+            Err(Error::NanPhysics)
         }
     }
 
+    /// Largest difference from the other values, relative to the magnitudes of these:
+    /// the volume, the center of mass (relative to the size), and the inertia.
+    pub fn difference(&self, other: &Self) -> f32 {
+        let relative = |a: f32, b: f32, scale: f32| {
+            if scale > 0.0 {
+                (a - b).abs() / scale
+            } else {
+                (a - b).abs()
+            }
+        };
+        let size = self.volume.abs().cbrt();
+        let inertia = self
+            .jacobi
+            .iter()
+            .flatten()
+            .fold(0.0f32, |m, v| m.max(v.abs()));
+        let volume = relative(self.volume, other.volume, size * size * size);
+        let rcm = (0..3)
+            .map(|i| relative(self.rcm[i], other.rcm[i], size))
+            .fold(0.0, f32::max);
+        let jacobi = self
+            .jacobi
+            .iter()
+            .flatten()
+            .zip(other.jacobi.iter().flatten())
+            .map(|(&a, &b)| relative(a, b, inertia))
+            .fold(0.0, f32::max);
+        volume.max(rcm).max(jacobi)
+    }
+
     fn write<W: WriteBytesExt>(&self, dest: &mut W) {
         let q = [
             self.volume,
@@ -181,15 +295,15 @@ pub struct UpperBound {
 }
 
 impl UpperBound {
-    fn read<I: ReadBytesExt>(source: &mut I) -> Self {
-        UpperBound {
+    fn read<I: ReadBytesExt>(source: &mut I) -> io::Result<Self> {
+        Ok(UpperBound {
             dimensions: [
-                source.read_u32::<E>().unwrap(),
-                source.read_u32::<E>().unwrap(),
-                source.read_u32::<E>().unwrap(),
+                source.read_u32::<E>()?,
+                source.read_u32::<E>()?,
+                source.read_u32::<E>()?,
             ],
-            radius: source.read_u32::<E>().unwrap(),
-        }
+            radius: source.read_u32::<E>()?,
+        })
     }
 
     fn write<W: WriteBytesExt>(&self, dest: &mut W) {
@@ -207,11 +321,11 @@ pub struct BodyColor {
 }
 
 impl BodyColor {
-    fn read<I: ReadBytesExt>(source: &mut I) -> Self {
-        BodyColor {
-            offset: source.read_u32::<E>().unwrap(),
-            shift: source.read_u32::<E>().unwrap(),
-        }
+    fn read<I: ReadBytesExt>(source: &mut I) -> io::Result<Self> {
+        Ok(BodyColor {
+            offset: source.read_u32::<E>()?,
+            shift: source.read_u32::<E>()?,
+        })
     }
 
     fn write<W: WriteBytesExt>(&self, dest: &mut W) {
@@ -244,11 +358,11 @@ pub struct Bounds {
 }
 
 impl Bounds {
-    fn read<I: ReadBytesExt>(source: &mut I) -> Self {
-        Bounds {
-            coord_max: read_vec_i32(source),
-            coord_min: read_vec_i32(source),
-        }
+    fn read<I: ReadBytesExt>(source: &mut I) -> io::Result<Self> {
+        Ok(Bounds {
+            coord_max: read_vec_i32(source)?,
+            coord_min: read_vec_i32(source)?,
+        })
     }
 
     fn write<W: WriteBytesExt>(&self, dest: &mut W) {
@@ -261,6 +375,8 @@ pub trait Polygon: Sized {
     fn new(middle: [i8; 3], flat_normal: [i8; 3], material: [u32; 2], vertices: &[Vertex]) -> Self;
     fn dump(&self, vertices: &mut Vec<Vertex>) -> ([i8; 3], [i8; 3], [u32; 2]);
     fn num_vertices() -> u32;
+    /// Checks the references into the geometry, and the material.
+    fn validate(&self, num_positions: usize, num_normals: usize) -> Result<(), Error>;
 }
 impl Polygon for DrawTriangle {
     fn new(_middle: [i8; 3], flat_normal: [i8; 3], material: [u32; 2], v: &[Vertex]) -> Self {
@@ -278,6 +394,17 @@ impl Polygon for DrawTriangle {
     fn num_vertices() -> u32 {
         3
     }
+    fn validate(&self, num_positions: usize, num_normals: usize) -> Result<(), Error> {
+        for v in &self.vertices {
+            check_index("position", v.pos as u32, num_positions)?;
+            check_index("normal", v.normal as u32, num_normals)?;
+        }
+        if self.material[0] < NUM_COLOR_IDS {
+            Ok(())
+        } else {
+            Err(Error::Color(self.material[0]))
+        }
+    }
 }
 impl Polygon for CollisionQuad {
     fn new(middle: [i8; 3], flat_normal: [i8; 3], _material: [u32; 2], v: &[Vertex]) -> Self {
@@ -295,6 +422,12 @@ impl Polygon for CollisionQuad {
     fn num_vertices() -> u32 {
         4
     }
+    fn validate(&self, num_positions: usize, _num_normals: usize) -> Result<(), Error> {
+        for &v in &self.vertices {
+            check_index("position", v as u32, num_positions)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -321,26 +454,32 @@ impl<G> Mesh<G> {
 }
 
 impl<P: Polygon> Mesh<Geometry<P>> {
-    pub fn load<I: ReadBytesExt>(source: &mut I) -> Self {
+    /// Reads and validates a mesh.
+    pub fn load<I: ReadBytesExt>(source: &mut I) -> Result<Self, Error> {
         profiling::scope!("Load Mesh");
-        let version = source.read_u32::<E>().unwrap();
-        assert_eq!(version, MAGIC_VERSION);
-        let num_positions = source.read_u32::<E>().unwrap();
-        let num_normals = source.read_u32::<E>().unwrap();
-        let num_polygons = source.read_u32::<E>().unwrap();
-        let _total_verts = source.read_u32::<E>().unwrap();
+        let version = source.read_u32::<E>()?;
+        if version != MAGIC_VERSION {
+            return Err(Error::Version(version));
+        }
+        let num_positions = source.read_u32::<E>()?;
+        let num_normals = source.read_u32::<E>()?;
+        let num_polygons = source.read_u32::<E>()?;
+        let _total_verts = source.read_u32::<E>()?;
+        if num_positions > MAX_VERTICES || num_normals > MAX_VERTICES {
+            return Err(Error::TooManyVertices(num_positions.max(num_normals)));
+        }
 
         let mut result = Mesh {
             geometry: Geometry {
                 positions: Vec::with_capacity(num_positions as usize),
                 normals: Vec::with_capacity(num_normals as usize),
-                polygons: Vec::with_capacity(num_polygons as usize),
+                polygons: Vec::with_capacity(num_polygons.min(MAX_RESERVE) as usize),
             },
-            bounds: Bounds::read(source),
-            parent_off: read_vec_i32(source),
-            max_radius: source.read_u32::<E>().unwrap(),
-            parent_rot: read_vec_i32(source),
-            physics: Physics::load(source),
+            bounds: Bounds::read(source)?,
+            parent_off: read_vec_i32(source)?,
+            max_radius: source.read_u32::<E>()?,
+            parent_rot: read_vec_i32(source)?,
+            physics: Physics::load(source)?,
         };
         log::debug!(
             "\tBounds {:?} with offset {:?}",
@@ -350,38 +489,38 @@ impl<P: Polygon> Mesh<Geometry<P>> {
 
         log::debug!("\tReading {} positions...", num_positions);
         for _ in 0..num_positions {
-            read_vec_i32(source); //unknown
-            let pos = read_vec_i8(source);
-            let _sort_info = source.read_u32::<E>().unwrap();
+            read_vec_i32(source)?; //unknown
+            let pos = read_vec_i8(source)?;
+            let _sort_info = source.read_u32::<E>()?;
             result.geometry.positions.push(pos);
         }
 
         log::debug!("\tReading {} normals...", num_normals);
         for _ in 0..num_normals {
-            let norm = read_vec_i8(source);
-            let _something = source.read_i8().unwrap();
-            let _sort_info = source.read_u32::<E>().unwrap();
+            let norm = read_vec_i8(source)?;
+            let _something = source.read_i8()?;
+            let _sort_info = source.read_u32::<E>()?;
             result.geometry.normals.push(norm);
         }
 
         log::debug!("\tReading {} polygons...", num_polygons);
         let mut vertices = Vec::with_capacity(4);
         for _ in 0..num_polygons {
-            let num_corners = source.read_u32::<E>().unwrap();
-            let _sort_info = source.read_u32::<E>().unwrap();
-            let material = [
-                source.read_u32::<E>().unwrap(),
-                source.read_u32::<E>().unwrap(),
-            ];
-            let flat_normal = read_vec_i8(source);
-            let _something = source.read_i8().unwrap();
-            let middle = read_vec_i8(source);
+            let num_corners = source.read_u32::<E>()?;
+            if num_corners != P::num_vertices() {
+                return Err(Error::Corners(num_corners));
+            }
+            let _sort_info = source.read_u32::<E>()?;
+            let material = [source.read_u32::<E>()?, source.read_u32::<E>()?];
+            let flat_normal = read_vec_i8(source)?;
+            let _something = source.read_i8()?;
+            let middle = read_vec_i8(source)?;
 
             vertices.clear();
             for _ in 0..num_corners {
                 vertices.push(Vertex {
-                    pos: source.read_u32::<E>().unwrap() as u16,
-                    normal: source.read_u32::<E>().unwrap() as u16,
+                    pos: read_index(source, "position", num_positions as usize)?,
+                    normal: read_index(source, "normal", num_normals as usize)?,
                 });
             }
 
@@ -394,11 +533,104 @@ impl<P: Polygon> Mesh<Geometry<P>> {
         // sorted variable polygons
         for _ in 0..3 {
             for _ in 0..num_polygons {
-                let _poly_ind = source.read_u32::<E>().unwrap();
+                let _poly_ind = source.read_u32::<E>()?;
+            }
+        }
+
+        result.validate()?;
+        Ok(result)
+    }
+
+    /// Checks the polygon references, materials, and physics.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.physics.validate()?;
+        let (num_positions, num_normals) =
+            (self.geometry.positions.len(), self.geometry.normals.len());
+        for polygon in &self.geometry.polygons {
+            polygon.validate(num_positions, num_normals)?;
+        }
+        Ok(())
+    }
+
+    /// Integrates the volume, the center of mass, and the inertia tensor
+    /// around it, assuming the polygons form a closed surface of unit density.
+    ///
+    /// Each polygon is fanned into triangles, which form tetrahedrons with the origin.
+    /// Either winding is accepted, as long as it's consistent.
+    pub fn compute_physics(&self) -> Physics {
+        // covariance of the canonical tetrahedron
+        const CANONICAL: [[f64; 3]; 3] = [
+            [1.0 / 60.0, 1.0 / 120.0, 1.0 / 120.0],
+            [1.0 / 120.0, 1.0 / 60.0, 1.0 / 120.0],
+            [1.0 / 120.0, 1.0 / 120.0, 1.0 / 60.0],
+        ];
+
+        let position = |v: Vertex| {
+            let p = self.geometry.positions[v.pos as usize];
+            [p[0] as f64, p[1] as f64, p[2] as f64]
+        };
+        let mut volume = 0.0;
+        let mut moment = [0.0f64; 3];
+        let mut covariance = [[0.0f64; 3]; 3];
+        let mut vertices = Vec::new();
+        for polygon in &self.geometry.polygons {
+            vertices.clear();
+            polygon.dump(&mut vertices);
+            let a = position(vertices[0]);
+            for pair in vertices[1..].windows(2) {
+                let (b, c) = (position(pair[0]), position(pair[1]));
+                let det = a[0] * (b[1] * c[2] - b[2] * c[1]) - a[1] * (b[0] * c[2] - b[2] * c[0])
+                    + a[2] * (b[0] * c[1] - b[1] * c[0]);
+                volume += det / 6.0;
+                for i in 0..3 {
+                    moment[i] += det / 24.0 * (a[i] + b[i] + c[i]);
+                }
+                // det * A * CANONICAL * A^T, where A has the corners as columns
+                let columns = [a, b, c];
+                for (i, row) in covariance.iter_mut().enumerate() {
+                    for (j, cov) in row.iter_mut().enumerate() {
+                        let mut sum = 0.0;
+                        for (k, ck) in columns.iter().enumerate() {
+                            for (l, cl) in columns.iter().enumerate() {
+                                sum += ck[i] * CANONICAL[k][l] * cl[j];
+                            }
+                        }
+                        *cov += det * sum;
+                    }
+                }
+            }
+        }
+
+        if volume < 0.0 {
+            volume = -volume;
+            moment = moment.map(|m| -m);
+            covariance = covariance.map(|row| row.map(|c| -c));
+        }
+        let rcm = if volume > 0.0 {
+            moment.map(|m| m / volume)
+        } else {
+            [0.0; 3]
+        };
+        // move the covariance to the center of mass, and turn it into the inertia
+        for (i, row) in covariance.iter_mut().enumerate() {
+            for (j, cov) in row.iter_mut().enumerate() {
+                *cov -= volume * rcm[i] * rcm[j];
+            }
+        }
+        let trace = covariance[0][0] + covariance[1][1] + covariance[2][2];
+        let mut jacobi = [[0.0f32; 3]; 3];
+        for (i, row) in jacobi.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                let diagonal = if i == j { trace } else { 0.0 };
+                *value = (diagonal - covariance[j][i]) as f32;
             }
         }
 
-        result
+        Physics {
+            volume: volume as f32,
+            rcm: rcm.map(|r| r as f32),
+            jacobi,
+        }
     }
 
     pub fn save<W: Write>(&self, dest: &mut W) {
@@ -473,13 +705,25 @@ pub struct AnimatedMesh<G> {
 }
 
 impl<P: Polygon> AnimatedMesh<Geometry<P>> {
-    pub fn load(mut input: File) -> Self {
-        let count = input.read_u32::<E>().unwrap();
-        AnimatedMesh {
-            bound: UpperBound::read(&mut input),
-            color: BodyColor::read(&mut input),
-            meshes: (0..count).map(|_| Mesh::load(&mut input)).collect(),
+    pub fn load(mut input: File) -> Result<Self, Error> {
+        let count = input.read_u32::<E>()?;
+        Ok(AnimatedMesh {
+            bound: UpperBound::read(&mut input)?,
+            color: BodyColor::read(&mut input)?,
+            meshes: (0..count)
+                .map(|_| Mesh::load(&mut input))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.meshes.is_empty() {
+            return Err(Error::NoFrames);
         }
+        for mesh in self.meshes.iter() {
+            mesh.validate()?;
+        }
+        Ok(())
     }
 
     pub fn save(&self, mut output: File) {
@@ -495,31 +739,32 @@ impl<P: Polygon> AnimatedMesh<Geometry<P>> {
 pub type FullModel = Model<DrawMesh, CollisionMesh>;
 
 impl FullModel {
-    pub fn load(mut input: File) -> Self {
+    /// Reads and validates a full model.
+    pub fn load(mut input: File) -> Result<Self, Error> {
         profiling::scope!("Load Model");
 
         log::debug!("\tReading the body...");
-        let body: DrawMesh = Mesh::load(&mut input);
+        let body: DrawMesh = Mesh::load(&mut input)?;
 
-        let bound = UpperBound::read(&mut input);
-        let num_wheels = input.read_u32::<E>().unwrap();
-        let num_debris = input.read_u32::<E>().unwrap();
-        let color = BodyColor::read(&mut input);
+        let bound = UpperBound::read(&mut input)?;
+        let num_wheels = input.read_u32::<E>()?;
+        let num_debris = input.read_u32::<E>()?;
+        let color = BodyColor::read(&mut input)?;
 
-        let mut wheels = Vec::with_capacity(num_wheels as usize);
+        let mut wheels = Vec::with_capacity(num_wheels.min(MAX_RESERVE) as usize);
         log::debug!("\tReading {} wheels...", num_wheels);
         for _ in 0..num_wheels {
-            let steer = input.read_u32::<E>().unwrap();
+            let steer = input.read_u32::<E>()?;
             let pos = [
-                input.read_f64::<E>().unwrap() as f32,
-                input.read_f64::<E>().unwrap() as f32,
-                input.read_f64::<E>().unwrap() as f32,
+                input.read_f64::<E>()? as f32,
+                input.read_f64::<E>()? as f32,
+                input.read_f64::<E>()? as f32,
             ];
-            let width = input.read_u32::<E>().unwrap();
-            let radius = input.read_u32::<E>().unwrap();
-            let bound_index = input.read_u32::<E>().unwrap();
+            let width = input.read_u32::<E>()?;
+            let radius = input.read_u32::<E>()?;
+            let bound_index = input.read_u32::<E>()?;
             let mesh: Option<DrawMesh> = if steer != 0 {
-                Some(Mesh::load(&mut input))
+                Some(Mesh::load(&mut input)?)
             } else {
                 None
             };
@@ -534,30 +779,33 @@ impl FullModel {
             });
         }
 
-        let mut debris = Vec::with_capacity(num_debris as usize);
+        let mut debris = Vec::with_capacity(num_debris.min(MAX_RESERVE) as usize);
         log::debug!("\tReading {} debris...", num_debris);
         for _ in 0..num_debris {
             debris.push(Debrie {
-                mesh: Mesh::load(&mut input),
-                shape: Mesh::load(&mut input),
+                mesh: Mesh::load(&mut input)?,
+                shape: Mesh::load(&mut input)?,
             });
         }
 
         log::debug!("\tReading the shape...");
-        let shape: CollisionMesh = Mesh::load(&mut input);
+        let shape: CollisionMesh = Mesh::load(&mut input)?;
 
         let mut slots = [Slot::EMPTY, Slot::EMPTY, Slot::EMPTY];
-        let slot_mask = input.read_u32::<E>().unwrap();
+        let slot_mask = input.read_u32::<E>()?;
         log::debug!("\tReading {} slot mask...", slot_mask);
+        if slot_mask >> MAX_SLOTS != 0 {
+            return Err(Error::SlotMask(slot_mask));
+        }
         for slot in &mut slots {
             for p in &mut slot.pos {
-                *p = input.read_i32::<E>().unwrap();
+                *p = input.read_i32::<E>()?;
             }
-            slot.angle = input.read_i32::<E>().unwrap();
+            slot.angle = input.read_i32::<E>()?;
             slot.scale = 1.0;
         }
 
-        FullModel {
+        let model = FullModel {
             body,
             shape,
             bound,
@@ -565,7 +813,35 @@ impl FullModel {
             wheels,
             debris,
             slots,
+        };
+        model.validate()?;
+        Ok(model)
+    }
+
+    /// Checks all the meshes, and the references between them.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.body.validate()?;
+        self.shape.validate()?;
+        for wheel in &self.wheels {
+            if let Some(ref mesh) = wheel.mesh {
+                mesh.validate()?;
+            }
+            check_index(
+                "bound",
+                wheel.bound_index,
+                self.shape.geometry.polygons.len(),
+            )?;
+        }
+        for debrie in &self.debris {
+            debrie.mesh.validate()?;
+            debrie.shape.validate()?;
+        }
+        for slot in &self.slots {
+            if let Some(ref mesh) = slot.mesh {
+                mesh.validate()?;
+            }
         }
+        Ok(())
     }
 
     pub fn save(&self, mut output: File) {
@@ -0,0 +1,381 @@
+//! Mesh simplification by the quadric edge collapse.
+//!
+//! Vertices are only ever moved onto their neighbors, so the positions
+//! stay exactly representable. Edges between different materials, as well as
+//! the open borders, can only be collapsed along themselves.
+
+use crate::{DrawTriangle, Geometry, NORMALIZER};
+
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+type Vector = [f64; 3];
+
+fn sub(a: Vector, b: Vector) -> Vector {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: Vector, b: Vector) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: Vector, b: Vector) -> Vector {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(v: Vector) -> Option<Vector> {
+    let len = dot(v, v).sqrt();
+    if len > 1.0e-9 {
+        Some([v[0] / len, v[1] / len, v[2] / len])
+    } else {
+        None
+    }
+}
+
+/// Sum of the squared distances to a set of planes, stored as
+/// the upper triangle of a symmetric 4x4 matrix.
+#[derive(Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn plane(normal: Vector, point: Vector) -> Self {
+        let [a, b, c] = normal;
+        let d = -dot(normal, point);
+        Quadric([
+            a * a,
+            a * b,
+            a * c,
+            a * d,
+            b * b,
+            b * c,
+            b * d,
+            c * c,
+            c * d,
+            d * d,
+        ])
+    }
+
+    fn add(&mut self, other: &Self) {
+        for (a, b) in self.0.iter_mut().zip(other.0.iter()) {
+            *a += b;
+        }
+    }
+
+    fn evaluate(&self, p: Vector) -> f64 {
+        let q = &self.0;
+        let [x, y, z] = p;
+        q[0] * x * x
+            + 2.0 * q[1] * x * y
+            + 2.0 * q[2] * x * z
+            + 2.0 * q[3] * x
+            + q[4] * y * y
+            + 2.0 * q[5] * y * z
+            + 2.0 * q[6] * y
+            + q[7] * z * z
+            + 2.0 * q[8] * z
+            + q[9]
+    }
+}
+
+/// Collapse of one vertex onto another.
+struct Candidate {
+    cost: f64,
+    from: usize,
+    to: usize,
+    versions: (u32, u32),
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Candidate {}
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Candidate {
+    // cheapest first in the max-heap
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+pub struct Simplified {
+    pub geometry: Geometry<DrawTriangle>,
+    /// Square root of the largest quadric error of the performed collapses,
+    /// in the units of the positions. It's zero for the collapses that keep the surface.
+    pub error: f32,
+}
+
+struct Simplifier {
+    positions: Vec<Vector>,
+    quadrics: Vec<Quadric>,
+    versions: Vec<u32>,
+    triangles: Vec<DrawTriangle>,
+    alive: Vec<bool>,
+    vertex_triangles: Vec<Vec<usize>>,
+    heap: BinaryHeap<Candidate>,
+}
+
+impl Simplifier {
+    fn corner_positions(&self, tri: &DrawTriangle) -> [Vector; 3] {
+        tri.vertices.map(|v| self.positions[v.pos as usize])
+    }
+
+    fn triangles_around(&self, vertex: usize) -> impl Iterator<Item = usize> + '_ {
+        self.vertex_triangles[vertex]
+            .iter()
+            .cloned()
+            .filter(move |&t| self.alive[t])
+    }
+
+    fn has_vertex(&self, t: usize, vertex: usize) -> bool {
+        self.triangles[t]
+            .vertices
+            .iter()
+            .any(|v| v.pos as usize == vertex)
+    }
+
+    /// Checks if the edge is on the open border, or between the materials.
+    fn is_boundary_edge(&self, a: usize, b: usize) -> bool {
+        let mut shared = self.triangles_around(a).filter(|&t| self.has_vertex(t, b));
+        match (shared.next(), shared.next(), shared.next()) {
+            (Some(t0), Some(t1), None) => {
+                self.triangles[t0].material != self.triangles[t1].material
+            }
+            _ => true,
+        }
+    }
+
+    fn neighbors(&self, vertex: usize) -> Vec<usize> {
+        let mut list = Vec::new();
+        for t in self.triangles_around(vertex) {
+            for v in self.triangles[t].vertices.iter() {
+                let w = v.pos as usize;
+                if w != vertex && !list.contains(&w) {
+                    list.push(w);
+                }
+            }
+        }
+        list
+    }
+
+    fn is_boundary_vertex(&self, vertex: usize) -> bool {
+        self.neighbors(vertex)
+            .into_iter()
+            .any(|w| self.is_boundary_edge(vertex, w))
+    }
+
+    fn push_candidate(&mut self, from: usize, to: usize) {
+        let mut quadric = self.quadrics[from];
+        quadric.add(&self.quadrics[to]);
+        self.heap.push(Candidate {
+            cost: quadric.evaluate(self.positions[to]).max(0.0),
+            from,
+            to,
+            versions: (self.versions[from], self.versions[to]),
+        });
+    }
+
+    fn push_vertex_edges(&mut self, vertex: usize) {
+        for w in self.neighbors(vertex) {
+            self.push_candidate(vertex, w);
+            self.push_candidate(w, vertex);
+        }
+    }
+
+    /// Checks that moving `from` onto `to` keeps the boundaries and doesn't fold triangles.
+    fn can_collapse(&self, from: usize, to: usize) -> bool {
+        if self.is_boundary_vertex(from) && !self.is_boundary_edge(from, to) {
+            return false;
+        }
+        // the link condition: only the corners of the removed triangles are shared
+        let mut opposite = Vec::new();
+        for t in self
+            .triangles_around(from)
+            .filter(|&t| self.has_vertex(t, to))
+        {
+            opposite.extend(
+                self.triangles[t]
+                    .vertices
+                    .iter()
+                    .map(|v| v.pos as usize)
+                    .filter(|&w| w != from && w != to),
+            );
+        }
+        let to_neighbors = self.neighbors(to);
+        if self
+            .neighbors(from)
+            .into_iter()
+            .any(|w| w != to && to_neighbors.contains(&w) && !opposite.contains(&w))
+        {
+            return false;
+        }
+        let target = self.positions[to];
+        self.triangles_around(from)
+            .filter(|&t| !self.has_vertex(t, to))
+            .all(|t| {
+                let tri = &self.triangles[t];
+                let before = self.corner_positions(tri);
+                let mut after = before;
+                for (p, v) in after.iter_mut().zip(tri.vertices.iter()) {
+                    if v.pos as usize == from {
+                        *p = target;
+                    }
+                }
+                let n0 = cross(sub(before[1], before[0]), sub(before[2], before[0]));
+                let n1 = cross(sub(after[1], after[0]), sub(after[2], after[0]));
+                match normalize(n1) {
+                    Some(n1) => dot(n0, n1) > 0.0,
+                    None => false,
+                }
+            })
+    }
+
+    fn collapse(&mut self, from: usize, to: usize) -> usize {
+        let mut removed = 0;
+        let list = self.vertex_triangles[from].clone();
+        for t in list {
+            if !self.alive[t] {
+                continue;
+            }
+            if self.has_vertex(t, to) {
+                self.alive[t] = false;
+                removed += 1;
+                continue;
+            }
+            for v in self.triangles[t].vertices.iter_mut() {
+                if v.pos as usize == from {
+                    v.pos = to as u16;
+                }
+            }
+            self.vertex_triangles[to].push(t);
+        }
+        let quadric = self.quadrics[from];
+        self.quadrics[to].add(&quadric);
+        self.versions[from] += 1;
+        self.versions[to] += 1;
+        removed
+    }
+}
+
+/// Collapses the edges until there are no more than `target` triangles,
+/// or the next collapse would exceed `max_error`,
+/// or nothing can be collapsed without breaking the boundaries.
+pub fn simplify(geometry: &Geometry<DrawTriangle>, target: usize, max_error: f32) -> Simplified {
+    let positions = geometry
+        .positions
+        .iter()
+        .map(|p| [p[0] as f64, p[1] as f64, p[2] as f64])
+        .collect::<Vec<_>>();
+    let mut vertex_triangles = vec![Vec::new(); positions.len()];
+    for (t, tri) in geometry.polygons.iter().enumerate() {
+        for v in tri.vertices.iter() {
+            vertex_triangles[v.pos as usize].push(t);
+        }
+    }
+    let mut s = Simplifier {
+        quadrics: vec![Quadric::default(); positions.len()],
+        versions: vec![0; positions.len()],
+        positions,
+        triangles: geometry.polygons.clone(),
+        alive: vec![true; geometry.polygons.len()],
+        vertex_triangles,
+        heap: BinaryHeap::new(),
+    };
+
+    for t in 0..s.triangles.len() {
+        let tri = s.triangles[t];
+        let p = s.corner_positions(&tri);
+        let normal = match normalize(cross(sub(p[1], p[0]), sub(p[2], p[0]))) {
+            Some(normal) => normal,
+            None => continue,
+        };
+        let quadric = Quadric::plane(normal, p[0]);
+        for v in tri.vertices.iter() {
+            s.quadrics[v.pos as usize].add(&quadric);
+        }
+        // keep the boundaries in place with the planes orthogonal to them
+        for i in 0..3 {
+            let (a, b) = (
+                tri.vertices[i].pos as usize,
+                tri.vertices[(i + 1) % 3].pos as usize,
+            );
+            if s.is_boundary_edge(a, b) {
+                if let Some(side) = normalize(cross(sub(p[(i + 1) % 3], p[i]), normal)) {
+                    let quadric = Quadric::plane(side, p[i]);
+                    s.quadrics[a].add(&quadric);
+                    s.quadrics[b].add(&quadric);
+                }
+            }
+        }
+    }
+    for vertex in 0..s.positions.len() {
+        for w in s.neighbors(vertex) {
+            s.push_candidate(vertex, w);
+        }
+    }
+
+    let mut count = s.triangles.len();
+    let mut max_cost = 0.0f64;
+    let max_cost_limit = max_error as f64 * max_error as f64;
+    while count > target {
+        let candidate = match s.heap.pop() {
+            Some(candidate) => candidate,
+            None => break,
+        };
+        let (from, to) = (candidate.from, candidate.to);
+        if candidate.cost > max_cost_limit {
+            break;
+        }
+        if candidate.versions != (s.versions[from], s.versions[to]) || !s.can_collapse(from, to) {
+            continue;
+        }
+        count -= s.collapse(from, to);
+        max_cost = max_cost.max(candidate.cost);
+        s.push_vertex_edges(to);
+    }
+
+    // compact the geometry, recomputing the flat normals
+    let mut position_map = vec![None; geometry.positions.len()];
+    let mut normal_map = vec![None; geometry.normals.len()];
+    let mut result = Geometry {
+        positions: Vec::new(),
+        normals: Vec::new(),
+        polygons: Vec::with_capacity(count),
+    };
+    for (tri, _) in s
+        .triangles
+        .iter()
+        .zip(&s.alive)
+        .filter(|&(_, &alive)| alive)
+    {
+        let mut tri = *tri;
+        let p = s.corner_positions(&tri);
+        if let Some(n) = normalize(cross(sub(p[1], p[0]), sub(p[2], p[0]))) {
+            tri.flat_normal = n.map(|c| (c * NORMALIZER as f64) as i8);
+        }
+        for v in tri.vertices.iter_mut() {
+            v.pos = *position_map[v.pos as usize].get_or_insert_with(|| {
+                result.positions.push(geometry.positions[v.pos as usize]);
+                result.positions.len() as u16 - 1
+            });
+            v.normal = *normal_map[v.normal as usize].get_or_insert_with(|| {
+                result.normals.push(geometry.normals[v.normal as usize]);
+                result.normals.len() as u16 - 1
+            });
+        }
+        result.polygons.push(tri);
+    }
+
+    Simplified {
+        geometry: result,
+        error: max_cost.sqrt() as f32,
+    }
+}
@@ -0,0 +1,136 @@
+use m3d::{convex_hull, CollisionQuad, DrawTriangle, Geometry, Vertex};
+
+/// Triangles over the consecutive triples of the points.
+fn cloud(positions: Vec<[i8; 3]>) -> Geometry<DrawTriangle> {
+    let polygons = (0..positions.len() / 3)
+        .map(|i| DrawTriangle {
+            vertices: [0, 1, 2].map(|j| Vertex {
+                pos: (i * 3 + j) as u16,
+                normal: 0,
+            }),
+            flat_normal: [0; 3],
+            material: [1, 0],
+        })
+        .collect();
+    Geometry {
+        positions,
+        normals: vec![[0, 0, 124]],
+        polygons,
+    }
+}
+
+fn sphere(radius: f32) -> Vec<[i8; 3]> {
+    let mut positions = Vec::new();
+    for lat in 1..12 {
+        let theta = std::f32::consts::PI * lat as f32 / 12.0;
+        for lon in 0..18 {
+            let phi = std::f32::consts::PI * lon as f32 / 9.0;
+            positions.push([
+                (radius * theta.sin() * phi.cos()).round() as i8,
+                (radius * theta.sin() * phi.sin()).round() as i8,
+                (radius * theta.cos()).round() as i8,
+            ]);
+        }
+    }
+    positions.push([0, 0, radius as i8]);
+    positions.push([0, 0, -radius as i8]);
+    positions.truncate(positions.len() / 3 * 3);
+    positions
+}
+
+fn plane(shape: &Geometry<CollisionQuad>, quad: &CollisionQuad) -> ([i32; 3], [i32; 3]) {
+    let p = quad
+        .vertices
+        .map(|v| shape.positions[v as usize].map(|c| c as i32));
+    let (e1, e2) = (
+        [0, 1, 2].map(|i| p[1][i] - p[0][i]),
+        [0, 1, 2].map(|i| p[2][i] - p[0][i]),
+    );
+    let normal = [
+        e1[1] * e2[2] - e1[2] * e2[1],
+        e1[2] * e2[0] - e1[0] * e2[2],
+        e1[0] * e2[1] - e1[1] * e2[0],
+    ];
+    (p[0], normal)
+}
+
+#[test]
+fn cube() {
+    let mut positions = Vec::new();
+    for &x in &[-40, 40] {
+        for &y in &[-40, 40] {
+            for &z in &[-40, 40] {
+                positions.push([x, y, z]);
+            }
+        }
+    }
+    // points inside and on the faces don't change the hull
+    positions.extend([[0, 0, 0], [10, -20, 5], [40, 0, 0], [0, -40, 10]]);
+    let shape = convex_hull(&cloud(positions), 64);
+
+    assert_eq!(shape.positions.len(), 8);
+    assert_eq!(shape.polygons.len(), 6);
+    for quad in shape.polygons.iter() {
+        let axis = quad.flat_normal.iter().position(|&c| c != 0).unwrap();
+        let sign = quad.flat_normal[axis].signum();
+        let mut normal = [0; 3];
+        normal[axis] = 124 * sign;
+        assert_eq!(quad.flat_normal, normal);
+        let mut middle = [0; 3];
+        middle[axis] = 40 * sign;
+        assert_eq!(quad.middle, middle);
+    }
+}
+
+fn assert_encloses(shape: &Geometry<CollisionQuad>, positions: &[[i8; 3]]) {
+    assert!(!shape.polygons.is_empty());
+    for quad in shape.polygons.iter() {
+        let (origin, normal) = plane(shape, quad);
+        for p in positions.iter() {
+            let d = (0..3)
+                .map(|i| (p[i] as i32 - origin[i]) * normal[i])
+                .sum::<i32>();
+            assert!(d <= 0, "point {:?} is outside of the hull", p);
+        }
+    }
+}
+
+#[test]
+fn sphere_encloses_points() {
+    let positions = sphere(100.0);
+    let shape = convex_hull(&cloud(positions.clone()), 1000);
+    assert_encloses(&shape, &positions);
+}
+
+#[test]
+fn simplified_sphere() {
+    let positions = sphere(100.0);
+    let shape = convex_hull(&cloud(positions.clone()), 16);
+    assert_encloses(&shape, &positions);
+    assert!(
+        shape.polygons.len() <= 32,
+        "got {} polygons",
+        shape.polygons.len()
+    );
+    for quad in shape.polygons.iter() {
+        // the hull is around the origin, so the faces look away from it
+        let d = (0..3)
+            .map(|i| quad.middle[i] as i32 * quad.flat_normal[i] as i32)
+            .sum::<i32>();
+        assert!(d > 0, "polygon at {:?} faces inwards", quad.middle);
+    }
+}
+
+#[test]
+fn flat_geometry() {
+    let positions = vec![
+        [0, 0, 0],
+        [10, 0, 0],
+        [0, 10, 0],
+        [10, 10, 0],
+        [5, 5, 0],
+        [3, 7, 0],
+    ];
+    let shape = convex_hull(&cloud(positions), 16);
+    assert!(shape.polygons.is_empty());
+}
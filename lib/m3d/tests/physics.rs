@@ -0,0 +1,85 @@
+use m3d::{DrawMesh, DrawTriangle, Geometry, Mesh, Vertex};
+
+/// Axis-aligned box with the outward facing triangles.
+fn cuboid(min: [i8; 3], max: [i8; 3]) -> DrawMesh {
+    let positions = (0..8)
+        .map(|i| {
+            [
+                if i & 1 != 0 { max[0] } else { min[0] },
+                if i & 2 != 0 { max[1] } else { min[1] },
+                if i & 4 != 0 { max[2] } else { min[2] },
+            ]
+        })
+        .collect();
+    let quads = [
+        [0, 2, 3, 1],
+        [4, 5, 7, 6],
+        [0, 1, 5, 4],
+        [2, 6, 7, 3],
+        [0, 4, 6, 2],
+        [1, 3, 7, 5],
+    ];
+    let vertex = |pos| Vertex { pos, normal: 0 };
+    let polygons = quads
+        .iter()
+        .flat_map(|q| [[q[0], q[1], q[2]], [q[0], q[2], q[3]]])
+        .map(|t| DrawTriangle {
+            vertices: [vertex(t[0]), vertex(t[1]), vertex(t[2])],
+            flat_normal: [0; 3],
+            material: [0; 2],
+        })
+        .collect();
+    Mesh {
+        geometry: Geometry {
+            positions,
+            normals: vec![[0, 0, 124]],
+            polygons,
+        },
+        bounds: m3d::Bounds {
+            coord_min: min.map(i32::from),
+            coord_max: max.map(i32::from),
+        },
+        parent_off: [0; 3],
+        parent_rot: [0; 3],
+        max_radius: 0,
+        physics: m3d::Physics {
+            volume: 0.0,
+            rcm: [0.0; 3],
+            jacobi: [[0.0; 3]; 3],
+        },
+    }
+}
+
+fn assert_close(a: f32, b: f32) {
+    assert!((a - b).abs() <= 1.0e-3 * b.abs().max(1.0), "{} != {}", a, b);
+}
+
+#[test]
+fn cuboid_physics() {
+    let (sx, sy, sz) = (4.0f32, 6.0f32, 10.0f32);
+    let mut mesh = cuboid([-2, 1, -5], [2, 7, 5]);
+    let physics = mesh.compute_physics();
+
+    let volume = sx * sy * sz;
+    assert_close(physics.volume, volume);
+    assert_close(physics.rcm[0], 0.0);
+    assert_close(physics.rcm[1], 4.0);
+    assert_close(physics.rcm[2], 0.0);
+    assert_close(physics.jacobi[0][0], volume * (sy * sy + sz * sz) / 12.0);
+    assert_close(physics.jacobi[1][1], volume * (sx * sx + sz * sz) / 12.0);
+    assert_close(physics.jacobi[2][2], volume * (sx * sx + sy * sy) / 12.0);
+    assert_close(physics.jacobi[0][1], 0.0);
+    assert_close(physics.jacobi[1][2], 0.0);
+    assert_close(physics.jacobi[0][2], 0.0);
+
+    // the opposite winding yields the same result
+    for tri in mesh.geometry.polygons.iter_mut() {
+        tri.vertices.swap(1, 2);
+    }
+    let flipped = mesh.compute_physics();
+    assert!(flipped.difference(&physics) < 1.0e-5);
+
+    mesh.physics = physics;
+    mesh.physics.volume *= 1.5;
+    assert_close(physics.difference(&mesh.physics), 0.5);
+}
@@ -0,0 +1,194 @@
+use m3d::{simplify, ColorId, DrawTriangle, Geometry, Vertex};
+
+const SIZE: usize = 8;
+const STEP: i8 = 10;
+
+/// Square grid on the XY plane, with the heights and materials given per cell.
+fn grid(
+    height: impl Fn(usize, usize) -> i8,
+    material: impl Fn(usize) -> ColorId,
+) -> Geometry<DrawTriangle> {
+    let index = |x: usize, y: usize| (y * (SIZE + 1) + x) as u16;
+    let mut positions = Vec::new();
+    for y in 0..=SIZE {
+        for x in 0..=SIZE {
+            positions.push([x as i8 * STEP, y as i8 * STEP, height(x, y)]);
+        }
+    }
+    let mut polygons = Vec::new();
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let corners = [
+                index(x, y),
+                index(x + 1, y),
+                index(x + 1, y + 1),
+                index(x, y + 1),
+            ];
+            for tri in [[0, 1, 2], [0, 2, 3]] {
+                polygons.push(DrawTriangle {
+                    vertices: tri.map(|i| Vertex {
+                        pos: corners[i],
+                        normal: 0,
+                    }),
+                    flat_normal: [0, 0, 124],
+                    material: [material(x) as u32, 0],
+                });
+            }
+        }
+    }
+    Geometry {
+        positions,
+        normals: vec![[0, 0, 124]],
+        polygons,
+    }
+}
+
+type Point = [f32; 3];
+
+fn sub(a: Point, b: Point) -> Point {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: Point, b: Point) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn lerp(a: Point, b: Point, t: f32) -> Point {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+    ]
+}
+
+/// Distance from the point to the closest point of the segment.
+fn segment_distance(p: Point, a: Point, b: Point) -> f32 {
+    let ab = sub(b, a);
+    let t = (dot(sub(p, a), ab) / dot(ab, ab).max(f32::MIN_POSITIVE)).clamp(0.0, 1.0);
+    let d = sub(p, lerp(a, b, t));
+    dot(d, d).sqrt()
+}
+
+/// Distance from the point to the closest point of the triangle.
+fn triangle_distance(p: Point, [a, b, c]: [Point; 3]) -> f32 {
+    let (ab, ac) = (sub(b, a), sub(c, a));
+    let n = [
+        ab[1] * ac[2] - ab[2] * ac[1],
+        ab[2] * ac[0] - ab[0] * ac[2],
+        ab[0] * ac[1] - ab[1] * ac[0],
+    ];
+    let n2 = dot(n, n);
+    if n2 > 0.0 {
+        // barycentric coordinates of the projection onto the plane
+        let ap = sub(p, a);
+        let height = dot(ap, n) / n2;
+        let q = sub(ap, [n[0] * height, n[1] * height, n[2] * height]);
+        let (d00, d01, d11) = (dot(ab, ab), dot(ab, ac), dot(ac, ac));
+        let (d20, d21) = (dot(q, ab), dot(q, ac));
+        let denom = d00 * d11 - d01 * d01;
+        let v = (d11 * d20 - d01 * d21) / denom;
+        let w = (d00 * d21 - d01 * d20) / denom;
+        if v >= 0.0 && w >= 0.0 && v + w <= 1.0 {
+            return height.abs() * n2.sqrt();
+        }
+    }
+    segment_distance(p, a, b)
+        .min(segment_distance(p, b, c))
+        .min(segment_distance(p, c, a))
+}
+
+/// Largest distance from the original vertices to the simplified surface.
+fn deviation(original: &Geometry<DrawTriangle>, simplified: &Geometry<DrawTriangle>) -> f32 {
+    let point = |p: [i8; 3]| [p[0] as f32, p[1] as f32, p[2] as f32];
+    let triangles = simplified
+        .polygons
+        .iter()
+        .map(|tri| {
+            tri.vertices
+                .map(|v| point(simplified.positions[v.pos as usize]))
+        })
+        .collect::<Vec<_>>();
+    original
+        .positions
+        .iter()
+        .map(|&p| {
+            triangles
+                .iter()
+                .map(|&tri| triangle_distance(point(p), tri))
+                .fold(f32::INFINITY, f32::min)
+        })
+        .fold(0.0, f32::max)
+}
+
+#[test]
+fn flat_grid() {
+    let geometry = grid(|_, _| 0, |_| ColorId::Body);
+    let result = simplify(&geometry, 2, f32::INFINITY);
+    assert_eq!(result.geometry.polygons.len(), 2);
+    assert!(result.error < 1.0e-3, "error {}", result.error);
+    assert!(deviation(&geometry, &result.geometry) < 1.0e-3);
+    // corners of the grid survive
+    for corner in [[0, 0, 0], [80, 0, 0], [80, 80, 0], [0, 80, 0]] {
+        assert!(result.geometry.positions.contains(&corner));
+    }
+}
+
+#[test]
+fn material_boundary() {
+    let half = SIZE / 2;
+    let geometry = grid(
+        |_, _| 0,
+        |x| {
+            if x < half {
+                ColorId::Body
+            } else {
+                ColorId::Window
+            }
+        },
+    );
+    let result = simplify(&geometry, 0, 0.001);
+    assert!(result.geometry.polygons.len() < geometry.polygons.len() / 4);
+    assert!(result.error < 1.0e-3, "error {}", result.error);
+    let border = half as i8 * STEP;
+    for tri in result.geometry.polygons.iter() {
+        let xs = tri
+            .vertices
+            .map(|v| result.geometry.positions[v.pos as usize][0]);
+        if tri.material[0] == ColorId::Body as u32 {
+            assert!(xs.iter().all(|&x| x <= border));
+        } else {
+            assert!(xs.iter().all(|&x| x >= border));
+        }
+    }
+}
+
+#[test]
+fn error_bounds() {
+    let geometry = grid(|x, y| ((x * 7 + y * 3) % 5) as i8 * 2, |_| ColorId::Body);
+
+    let mut last_count = geometry.polygons.len();
+    for &max_error in &[0.1, 2.0, 10.0, 50.0] {
+        let result = simplify(&geometry, 0, max_error);
+        assert!(result.error <= max_error);
+        // the budget holds for the surface itself, not only for the quadrics
+        let distance = deviation(&geometry, &result.geometry);
+        assert!(
+            distance <= max_error,
+            "distance {} over {}",
+            distance,
+            max_error
+        );
+        assert!(result.geometry.polygons.len() <= last_count);
+        last_count = result.geometry.polygons.len();
+    }
+    assert!(last_count < geometry.polygons.len() / 2);
+
+    let mut last_error = 0.0;
+    for &target in &[100, 50, 20] {
+        let result = simplify(&geometry, target, f32::INFINITY);
+        assert!(result.geometry.polygons.len() <= target);
+        assert!(result.error >= last_error);
+        last_error = result.error;
+    }
+    assert!(last_error > 0.0);
+}
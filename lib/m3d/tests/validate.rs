@@ -0,0 +1,117 @@
+use m3d::{AnimatedMesh, DrawMesh, DrawTriangle, Error, Geometry, Mesh, Vertex};
+
+use std::io::Cursor;
+
+fn triangle() -> DrawMesh {
+    let vertex = |pos| Vertex { pos, normal: 0 };
+    Mesh {
+        geometry: Geometry {
+            positions: vec![[0, 0, 0], [10, 0, 0], [0, 10, 0]],
+            normals: vec![[0, 0, 124]],
+            polygons: vec![DrawTriangle {
+                vertices: [vertex(0), vertex(1), vertex(2)],
+                flat_normal: [0, 0, 124],
+                material: [m3d::ColorId::Body as u32, 0],
+            }],
+        },
+        bounds: m3d::Bounds {
+            coord_min: [0; 3],
+            coord_max: [10, 10, 0],
+        },
+        parent_off: [0; 3],
+        parent_rot: [0; 3],
+        max_radius: 10,
+        physics: m3d::Physics {
+            volume: 1.0,
+            rcm: [0.0; 3],
+            jacobi: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        },
+    }
+}
+
+fn reload(mesh: &DrawMesh) -> Result<DrawMesh, Error> {
+    let mut data = Vec::new();
+    mesh.save(&mut data);
+    Mesh::load(&mut Cursor::new(data))
+}
+
+#[test]
+fn valid_mesh() {
+    let mesh = reload(&triangle()).unwrap();
+    assert_eq!(mesh.geometry.positions.len(), 3);
+    assert_eq!(mesh.geometry.polygons.len(), 1);
+}
+
+#[test]
+fn corrupted_mesh() {
+    let mut mesh = triangle();
+    mesh.geometry.polygons[0].vertices[2].pos = 3;
+    assert!(matches!(
+        reload(&mesh),
+        Err(Error::Index {
+            what: "position",
+            index: 3,
+            count: 3
+        })
+    ));
+
+    let mut mesh = triangle();
+    mesh.geometry.polygons[0].material[0] = m3d::NUM_COLOR_IDS;
+    assert!(matches!(reload(&mesh), Err(Error::Color(_))));
+
+    let mut mesh = triangle();
+    mesh.physics.jacobi[1][2] = f32::NAN;
+    assert!(matches!(mesh.validate(), Err(Error::NanPhysics)));
+
+    let mut data = Vec::new();
+    triangle().save(&mut data);
+    // the last position index would be 2 again if truncated to 16 bits
+    let corners = [0, 0, 1, 0, 2, 0]
+        .iter()
+        .flat_map(|&index: &u32| index.to_le_bytes())
+        .collect::<Vec<_>>();
+    let offset = data
+        .windows(corners.len())
+        .position(|w| w == &corners[..])
+        .unwrap();
+    let mut wide = data.clone();
+    wide[offset + 4 * 4 + 2] = 1;
+    assert!(matches!(
+        DrawMesh::load(&mut Cursor::new(&wide)),
+        Err(Error::Index {
+            what: "position",
+            index: 0x10002,
+            count: 3
+        })
+    ));
+
+    data[0] = 7;
+    assert!(matches!(
+        DrawMesh::load(&mut Cursor::new(&data)),
+        Err(Error::Version(7))
+    ));
+    data[0] = 8;
+    data.truncate(data.len() - 1);
+    assert!(matches!(
+        DrawMesh::load(&mut Cursor::new(&data)),
+        Err(Error::Io(_))
+    ));
+}
+
+#[test]
+fn empty_animation() {
+    let mut animation = AnimatedMesh {
+        meshes: Vec::new(),
+        bound: m3d::UpperBound {
+            dimensions: [10, 10, 0],
+            radius: 10,
+        },
+        color: m3d::BodyColor {
+            offset: 0,
+            shift: 0,
+        },
+    };
+    assert!(matches!(animation.validate(), Err(Error::NoFrames)));
+    animation.meshes.push(triangle());
+    animation.validate().unwrap();
+}
@@ -1,36 +1,104 @@
 #![feature(test)]
 
-use byteorder::{LittleEndian as E, ReadBytesExt};
-use std::{
-    fs::File,
-    io::{Read, Seek},
-};
+extern crate test;
 
-const VMC_PATH: &'static str = "/hub/gog/Vangers/game/thechain/fostral/output.vmc";
-const SIZE: [usize; 2] = [1 << 11, 1 << 14];
+const SIZE: [usize; 2] = [1 << 11, 0x100];
 
-#[bench]
-fn load_level(bench: &mut test::Bencher) {
-    let mut file = File::open(VMC_PATH).unwrap();
-    let table: Vec<_> = (0..SIZE[1])
+struct Level {
+    splay: splay::Splay,
+    rows: Vec<(usize, usize)>,
+    buffer: Vec<u8>,
+}
+
+/// Compresses rows resembling the terrain of the game: slowly varying heights,
+/// and the meta made of terrain runs with some of the shadow bits set.
+fn open_level() -> Level {
+    let mut seed = 0x1234_5678u32;
+    let mut next = move || {
+        seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        (seed >> 24) as u8
+    };
+    let source = (0..SIZE[1])
         .map(|_| {
-            let offset = file.read_i32::<E>().unwrap();
-            let size = file.read_i16::<E>().unwrap();
-            (offset, size)
+            let mut height = 0u8;
+            let heights = (0..SIZE[0])
+                .map(|_| {
+                    height = height.wrapping_add(next() & 0x3);
+                    height
+                })
+                .collect::<Vec<_>>();
+            let metas = (0..SIZE[0])
+                .map(|x| (x / 32) as u8 & 0x7 | (next() & 0x40))
+                .collect::<Vec<_>>();
+            (heights, metas)
+        })
+        .collect::<Vec<_>>();
+
+    let compressor = splay::Compressor::new(source.iter().map(|(h, m)| (&h[..], &m[..])));
+    let mut trees = Vec::new();
+    compressor.splay().write(&mut trees).unwrap();
+    let splay = splay::Splay::new(&mut &trees[..]);
+
+    let mut buffer = Vec::new();
+    let rows = source
+        .iter()
+        .map(|(height, meta)| {
+            let offset = buffer.len();
+            compressor.compress(height, meta, &mut buffer);
+            (offset, buffer.len() - offset)
         })
         .collect();
 
-    let splay = splay::Splay::new(&mut file);
+    Level {
+        splay,
+        rows,
+        buffer,
+    }
+}
+
+#[bench]
+fn load_level(bench: &mut test::Bencher) {
+    let level = open_level();
+    let mut height = vec![0u8; SIZE[0]];
+    let mut meta = vec![0u8; SIZE[0]];
+
+    bench.iter(|| {
+        for &(off, size) in level.rows.iter() {
+            level
+                .splay
+                .expand(&level.buffer[off..off + size], &mut height, &mut meta);
+        }
+    });
+}
+
+#[bench]
+fn load_level_checked(bench: &mut test::Bencher) {
+    let level = open_level();
+    let mut height = vec![0u8; SIZE[0]];
+    let mut meta = vec![0u8; SIZE[0]];
+
+    bench.iter(|| {
+        for &(off, size) in level.rows.iter() {
+            level
+                .splay
+                .try_expand(&level.buffer[off..off + size], &mut height, &mut meta)
+                .unwrap();
+        }
+    });
+}
+
+#[bench]
+fn load_level_streamed(bench: &mut test::Bencher) {
+    let level = open_level();
     let mut height = vec![0u8; SIZE[0]];
     let mut meta = vec![0u8; SIZE[0]];
-    let data_offset = file.seek(std::io::SeekFrom::Current(0)).unwrap();
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer).unwrap();
 
     bench.iter(|| {
-        for &(offset, size) in table[..0x100].iter() {
-            let off = offset as usize - data_offset as usize;
-            splay.expand(&buffer[off..off + size as usize], &mut height, &mut meta);
+        for &(off, size) in level.rows.iter() {
+            level
+                .splay
+                .try_expand_from(&level.buffer[off..off + size], &mut height, &mut meta)
+                .unwrap();
         }
     });
 }
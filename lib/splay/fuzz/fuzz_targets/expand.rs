@@ -0,0 +1,38 @@
+//! Feeds arbitrary trees and rows to the checked decoder.
+//! Run with `cargo fuzz run expand` from `lib/splay`.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+const TREES_SIZE: usize = 512 * 2 * 4;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < TREES_SIZE + 2 {
+        return;
+    }
+    let (trees, rest) = data.split_at(TREES_SIZE);
+    let splay = match splay::Splay::try_new(&mut &trees[..]) {
+        Ok(splay) => splay,
+        Err(_) => return,
+    };
+    let mut height = vec![0; rest[0] as usize];
+    let mut meta = vec![0; rest[1] as usize];
+    let input = &rest[2..];
+
+    let sliced = splay.try_expand(input, &mut height, &mut meta);
+    let (height_copy, meta_copy) = (height.clone(), meta.clone());
+    let streamed = splay.try_expand_from(input, &mut height, &mut meta);
+    // both decoders have to agree on the outcome
+    match (sliced, streamed) {
+        (Ok(()), Ok(count)) => {
+            assert_eq!(count, input.len());
+            assert_eq!((height, meta), (height_copy, meta_copy));
+        }
+        (Err(splay::Error::LengthMismatch { actual, .. }), Ok(count)) => {
+            assert_eq!(actual, count);
+            assert_eq!((height, meta), (height_copy, meta_copy));
+        }
+        (Err(e1), Err(e2)) => assert_eq!(e1, e2),
+        (sliced, streamed) => panic!("Decoders disagree: {:?} vs {:?}", sliced, streamed),
+    }
+});
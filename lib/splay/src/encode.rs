@@ -0,0 +1,214 @@
+use crate::Splay;
+
+use byteorder::{LittleEndian as E, WriteBytesExt};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, VecDeque},
+    io::Result as IoResult,
+};
+
+const NUM_SYMBOLS: usize = 0x100;
+
+#[derive(Copy, Clone, Default)]
+struct Code {
+    bits: u64,
+    length: u8,
+}
+
+/// Huffman coding tree, laid out the way `Splay::decompress` walks it:
+/// node `n` has children at `2n` and `2n + 1`, positive values are
+/// other nodes, non-positive values are negated symbols.
+fn build_tree(frequencies: &[u64; NUM_SYMBOLS]) -> [i32; 512] {
+    enum Node {
+        Leaf(u8),
+        Inner(usize, usize),
+    }
+
+    let mut nodes = Vec::with_capacity(2 * NUM_SYMBOLS);
+    let mut heap = BinaryHeap::new();
+    for (symbol, &freq) in frequencies.iter().enumerate() {
+        if freq != 0 {
+            heap.push(Reverse((freq, nodes.len())));
+            nodes.push(Node::Leaf(symbol as u8));
+        }
+    }
+
+    let mut tree = [0i32; 512];
+    let root = match heap.len() {
+        0 => return tree,
+        1 => {
+            // a single symbol still needs one bit per occurrence
+            let symbol = match nodes[0] {
+                Node::Leaf(symbol) => symbol,
+                Node::Inner(..) => unreachable!(),
+            };
+            tree[2] = -(symbol as i32);
+            tree[3] = -(symbol as i32);
+            return tree;
+        }
+        _ => loop {
+            let Reverse((freq0, id0)) = heap.pop().unwrap();
+            match heap.pop() {
+                Some(Reverse((freq1, id1))) => {
+                    heap.push(Reverse((freq0 + freq1, nodes.len())));
+                    nodes.push(Node::Inner(id0, id1));
+                }
+                None => break id0,
+            }
+        },
+    };
+
+    // assign the inner node indices in breadth-first order, starting with 1
+    let mut queue = VecDeque::new();
+    queue.push_back(root);
+    let mut next_index = 1;
+    let mut indices = vec![0i32; nodes.len()];
+    indices[root] = next_index;
+    while let Some(id) = queue.pop_front() {
+        let (left, right) = match nodes[id] {
+            Node::Inner(left, right) => (left, right),
+            Node::Leaf(_) => continue,
+        };
+        let base = 2 * indices[id] as usize;
+        for (offset, &child) in [left, right].iter().enumerate() {
+            tree[base + offset] = match nodes[child] {
+                Node::Leaf(symbol) => -(symbol as i32),
+                Node::Inner(..) => {
+                    next_index += 1;
+                    indices[child] = next_index;
+                    queue.push_back(child);
+                    next_index
+                }
+            };
+        }
+    }
+
+    tree
+}
+
+fn collect_codes(tree: &[i32; 512], codes: &mut [Code; NUM_SYMBOLS], index: usize, prefix: Code) {
+    for bit in 0..2 {
+        let code = Code {
+            bits: (prefix.bits << 1) | bit as u64,
+            length: prefix.length + 1,
+        };
+        assert!(code.length <= 64, "Huffman code is too long");
+        match tree[2 * index + bit] {
+            child if child > 0 => collect_codes(tree, codes, child as usize, code),
+            leaf => codes[(-leaf) as usize] = code,
+        }
+    }
+}
+
+struct BitWriter<'a> {
+    output: &'a mut Vec<u8>,
+    current: u8,
+    count: u8,
+}
+
+impl BitWriter<'_> {
+    fn write(&mut self, code: Code) {
+        assert_ne!(code.length, 0, "Symbol is missing from the tree");
+        for i in (0..code.length).rev() {
+            self.current = (self.current << 1) | ((code.bits >> i) & 1) as u8;
+            self.count += 1;
+            if self.count == 8 {
+                self.output.push(self.current);
+                self.current = 0;
+                self.count = 0;
+            }
+        }
+    }
+
+    fn finish(self) {
+        if self.count != 0 {
+            self.output.push(self.current << (8 - self.count));
+        }
+    }
+}
+
+/// Encoder with trees tuned for a particular set of rows.
+pub struct Compressor {
+    splay: Splay,
+    codes1: [Code; NUM_SYMBOLS],
+    codes2: [Code; NUM_SYMBOLS],
+}
+
+impl Compressor {
+    /// Builds the optimal trees for the given pairs of height and meta rows.
+    pub fn new<'a, I>(rows: I) -> Self
+    where
+        I: IntoIterator<Item = (&'a [u8], &'a [u8])>,
+    {
+        let mut freq1 = [0u64; NUM_SYMBOLS];
+        let mut freq2 = [0u64; NUM_SYMBOLS];
+        for (input1, input2) in rows {
+            let mut last_char = 0;
+            for &b in input1 {
+                freq1[b.wrapping_sub(last_char) as usize] += 1;
+                last_char = b;
+            }
+            last_char = 0;
+            for &b in input2 {
+                freq2[(b ^ last_char) as usize] += 1;
+                last_char = b;
+            }
+        }
+
+        let splay = Splay {
+            tree1: build_tree(&freq1),
+            tree2: build_tree(&freq2),
+        };
+        let mut codes1 = [Code::default(); NUM_SYMBOLS];
+        let mut codes2 = [Code::default(); NUM_SYMBOLS];
+        collect_codes(&splay.tree1, &mut codes1, 1, Code::default());
+        collect_codes(&splay.tree2, &mut codes2, 1, Code::default());
+
+        Compressor {
+            splay,
+            codes1,
+            codes2,
+        }
+    }
+
+    pub fn splay(&self) -> &Splay {
+        &self.splay
+    }
+
+    /// Compresses a pair of rows into the output.
+    /// Both rows must only contain symbols seen by `new`, it panics otherwise.
+    pub fn compress(&self, input1: &[u8], input2: &[u8], output: &mut Vec<u8>) {
+        let mut writer = BitWriter {
+            output: &mut *output,
+            current: 0,
+            count: 0,
+        };
+        let mut last_char = 0;
+        for &b in input1 {
+            writer.write(self.codes1[b.wrapping_sub(last_char) as usize]);
+            last_char = b;
+        }
+        writer.finish();
+
+        let mut writer = BitWriter {
+            output: &mut *output,
+            current: 0,
+            count: 0,
+        };
+        last_char = 0;
+        for &b in input2 {
+            writer.write(self.codes2[(b ^ last_char) as usize]);
+            last_char = b;
+        }
+        writer.finish();
+    }
+}
+
+impl Splay {
+    pub fn write<O: WriteBytesExt>(&self, output: &mut O) -> IoResult<()> {
+        for &v in self.tree1.iter().chain(self.tree2.iter()) {
+            output.write_i32::<E>(v)?;
+        }
+        Ok(())
+    }
+}
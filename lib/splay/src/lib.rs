@@ -11,8 +11,97 @@
 )]
 #![allow(missing_debug_implementations, clippy::new_without_default)]
 
+mod encode;
+
+pub use self::encode::Compressor;
+
 use byteorder::{LittleEndian as E, ReadBytesExt, WriteBytesExt};
-use std::io::{Read, Write};
+use std::{
+    fmt,
+    io::{self, Read, Result as IoResult, Write},
+};
+
+/// Problems found while expanding a compressed stream.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    /// The input ended before all the output bytes were decoded.
+    InputOverrun,
+    /// The tree refers to a node or a symbol outside of its bounds.
+    BadTreeNode(i32),
+    /// The tree has a cycle, so decoding would never finish.
+    CyclicTree,
+    /// Decoding finished, but didn't consume exactly the given input.
+    LengthMismatch { expected: usize, actual: usize },
+    /// Reading the input stream failed.
+    Io(io::ErrorKind),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::InputOverrun => write!(f, "input overrun"),
+            Error::BadTreeNode(code) => write!(f, "bad tree node {}", code),
+            Error::CyclicTree => write!(f, "cyclic tree"),
+            Error::LengthMismatch { expected, actual } => {
+                write!(f, "consumed {} bytes instead of {}", actual, expected)
+            }
+            Error::Io(kind) => write!(f, "unable to read: {:?}", kind),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for io::Error {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Io(kind) => kind.into(),
+            Error::InputOverrun => io::Error::new(io::ErrorKind::UnexpectedEof, error),
+            _ => io::Error::new(io::ErrorKind::InvalidData, error),
+        }
+    }
+}
+
+/// Source of the compressed bytes for the decoder.
+trait ByteSource {
+    fn next_byte(&mut self) -> Result<u8, Error>;
+}
+
+struct SliceSource<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl ByteSource for SliceSource<'_> {
+    #[inline]
+    fn next_byte(&mut self) -> Result<u8, Error> {
+        let byte = *self.data.get(self.position).ok_or(Error::InputOverrun)?;
+        self.position += 1;
+        Ok(byte)
+    }
+}
+
+struct ReadSource<R> {
+    input: R,
+    count: usize,
+}
+
+impl<R: Read> ByteSource for ReadSource<R> {
+    fn next_byte(&mut self) -> Result<u8, Error> {
+        let mut byte = [0];
+        loop {
+            return match self.input.read(&mut byte) {
+                Ok(0) => Err(Error::InputOverrun),
+                Ok(_) => {
+                    self.count += 1;
+                    Ok(byte[0])
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => Err(Error::Io(e.kind())),
+            };
+        }
+    }
+}
 
 #[derive(Copy, Clone)]
 pub struct Splay {
@@ -24,19 +113,59 @@ pub struct Splay {
 
 impl Splay {
     pub fn new<I: ReadBytesExt>(input: &mut I) -> Self {
+        Self::try_new(input).unwrap()
+    }
+
+    /// Reads the trees, making sure they can't lead the decoder out of bounds
+    /// or into an endless loop.
+    pub fn try_new<I: ReadBytesExt>(input: &mut I) -> IoResult<Self> {
         let mut splay = Splay {
             tree1: [0; 512],
             tree2: [0; 512],
         };
-        for i in 0..512 {
-            let v = input.read_i32::<E>().unwrap();
-            splay.tree1[i] = v;
+        input.read_i32_into::<E>(&mut splay.tree1)?;
+        input.read_i32_into::<E>(&mut splay.tree2)?;
+        Self::validate(&splay.tree1)?;
+        Self::validate(&splay.tree2)?;
+        Ok(splay)
+    }
+
+    /// Checks all the nodes reachable from the root.
+    fn validate(tree: &[i32; 512]) -> Result<(), Error> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum State {
+            Unseen,
+            InProgress,
+            Done,
         }
-        for i in 0..512 {
-            let v = input.read_i32::<E>().unwrap();
-            splay.tree2[i] = v;
+        let mut states = [State::Unseen; 256];
+        // depth-first, with each node visited before and after its children
+        let mut stack = vec![(1usize, false)];
+        while let Some((node, children_done)) = stack.pop() {
+            if children_done {
+                states[node] = State::Done;
+                continue;
+            }
+            match states[node] {
+                State::Done => continue,
+                State::InProgress => return Err(Error::CyclicTree),
+                State::Unseen => {}
+            }
+            states[node] = State::InProgress;
+            stack.push((node, true));
+            for &code in &tree[2 * node..2 * node + 2] {
+                match code {
+                    1..=255 => match states[code as usize] {
+                        State::InProgress => return Err(Error::CyclicTree),
+                        State::Unseen => stack.push((code as usize, false)),
+                        State::Done => {}
+                    },
+                    -255..=0 => {}
+                    _ => return Err(Error::BadTreeNode(code)),
+                }
+            }
         }
-        splay
+        Ok(())
     }
 
     pub fn write_trivial<O: WriteBytesExt>(output: &mut O) {
@@ -83,6 +212,35 @@ impl Splay {
         k_input
     }
 
+    /// Decodes with the input checked for overruns.
+    /// The tree is known to be valid, see `validate`.
+    fn try_decompress<S: ByteSource, F: Fn(u8, u8) -> u8>(
+        tree: &[i32; 512],
+        source: &mut S,
+        output: &mut [u8],
+        fun: F,
+    ) -> Result<(), Error> {
+        let mut last_char = 0u8;
+        let mut bit = 0;
+        let mut cur = 0u8;
+        for out in output.iter_mut() {
+            let mut code = 1i32;
+            while code > 0 {
+                bit = if bit == 0 {
+                    cur = source.next_byte()?;
+                    7
+                } else {
+                    bit - 1
+                };
+                let i = ((code as usize) << 1) + ((cur >> bit) as usize & 1);
+                code = tree[i];
+            }
+            last_char = fun(last_char, -code as u8);
+            *out = last_char;
+        }
+        Ok(())
+    }
+
     #[allow(dead_code)]
     fn decompress_orig<I: Read, F: Fn(u8, u8) -> u8>(
         tree: &[i32],
@@ -113,12 +271,49 @@ impl Splay {
         }
     }
 
+    /// Expands a row without checking the input, see `try_expand` for the checked version.
     pub fn expand(&self, input: &[u8], output1: &mut [u8], output2: &mut [u8]) {
         let off1 = Self::decompress(&self.tree1, input, output1, |b, c| b.wrapping_add(c));
         let off2 = Self::decompress(&self.tree2, &input[off1..], output2, |b, c| b ^ c);
         assert_eq!(off1 + off2, input.len());
     }
 
+    pub fn try_expand(
+        &self,
+        input: &[u8],
+        output1: &mut [u8],
+        output2: &mut [u8],
+    ) -> Result<(), Error> {
+        let mut source = SliceSource {
+            data: input,
+            position: 0,
+        };
+        Self::try_decompress(&self.tree1, &mut source, output1, |b, c| b.wrapping_add(c))?;
+        Self::try_decompress(&self.tree2, &mut source, output2, |b, c| b ^ c)?;
+        if source.position != input.len() {
+            return Err(Error::LengthMismatch {
+                expected: input.len(),
+                actual: source.position,
+            });
+        }
+        Ok(())
+    }
+
+    /// Expands a row from a stream, reading only as much as needed,
+    /// and returns the number of bytes consumed.
+    /// The stream is read byte by byte, so it's better to be buffered.
+    pub fn try_expand_from<R: Read>(
+        &self,
+        input: R,
+        output1: &mut [u8],
+        output2: &mut [u8],
+    ) -> Result<usize, Error> {
+        let mut source = ReadSource { input, count: 0 };
+        Self::try_decompress(&self.tree1, &mut source, output1, |b, c| b.wrapping_add(c))?;
+        Self::try_decompress(&self.tree2, &mut source, output2, |b, c| b ^ c)?;
+        Ok(source.count)
+    }
+
     pub fn compress_trivial<O: Write>(input1: &[u8], input2: &[u8], output: &mut O) {
         let mut last_char = 0;
         for &b in input1 {
@@ -0,0 +1,70 @@
+use splay::{Compressor, Splay};
+
+fn make_rows(count: usize, width: usize) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut seed = 0x1234_5678u32;
+    let mut next = move || {
+        seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        (seed >> 24) as u8
+    };
+    (0..count)
+        .map(|_| {
+            let mut height = 0u8;
+            let heights = (0..width)
+                .map(|_| {
+                    height = height.wrapping_add(next() & 0x3);
+                    height
+                })
+                .collect();
+            let metas = (0..width)
+                .map(|x| (x / 32) as u8 | (next() & 0x40))
+                .collect();
+            (heights, metas)
+        })
+        .collect()
+}
+
+fn roundtrip(rows: &[(Vec<u8>, Vec<u8>)]) -> usize {
+    let compressor = Compressor::new(rows.iter().map(|(h, m)| (&h[..], &m[..])));
+    let mut trees = Vec::new();
+    compressor.splay().write(&mut trees).unwrap();
+    assert_eq!(trees.len() as u64, Splay::tree_size());
+    let splay = Splay::new(&mut &trees[..]);
+
+    let mut total = 0;
+    let mut data = Vec::new();
+    for (height, meta) in rows {
+        data.clear();
+        compressor.compress(height, meta, &mut data);
+        let mut out_height = vec![0; height.len()];
+        let mut out_meta = vec![0; meta.len()];
+        splay
+            .try_expand(&data, &mut out_height, &mut out_meta)
+            .unwrap();
+        assert_eq!(&out_height, height);
+        assert_eq!(&out_meta, meta);
+        total += data.len();
+    }
+    total
+}
+
+#[test]
+fn compress_roundtrip() {
+    let rows = make_rows(64, 256);
+    let compressed = roundtrip(&rows);
+    assert!(compressed < 64 * 256);
+}
+
+#[test]
+fn compress_single_symbol() {
+    let rows = vec![(vec![7u8; 16], vec![0u8; 16])];
+    assert_eq!(roundtrip(&rows), 4);
+}
+
+#[test]
+#[should_panic(expected = "Symbol is missing from the tree")]
+fn compress_unknown_symbol() {
+    let (height, meta) = (vec![7u8; 16], vec![0u8; 16]);
+    let compressor = Compressor::new(vec![(&height[..], &meta[..])]);
+    let mut output = Vec::new();
+    compressor.compress(&[7, 9], &[0, 0], &mut output);
+}
@@ -0,0 +1,158 @@
+//! Property tests of the checked decoder on random and corrupted data.
+
+use splay::{Compressor, Error, Splay};
+
+struct Lcg(u32);
+
+impl Lcg {
+    fn next(&mut self) -> u8 {
+        self.0 = self.0.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        (self.0 >> 24) as u8
+    }
+
+    fn bytes(&mut self, count: usize) -> Vec<u8> {
+        (0..count).map(|_| self.next()).collect()
+    }
+}
+
+fn trees_to_bytes(tree1: &[i32; 512], tree2: &[i32; 512]) -> Vec<u8> {
+    tree1
+        .iter()
+        .chain(tree2.iter())
+        .flat_map(|v| v.to_le_bytes().to_vec())
+        .collect()
+}
+
+/// Height and meta of a row, with the compressed data.
+type Row = (Vec<u8>, Vec<u8>, Vec<u8>);
+
+fn compressed_rows(rng: &mut Lcg, count: usize) -> (Splay, Vec<Row>) {
+    let rows = (0..count)
+        .map(|_| {
+            let width = 2 + rng.next() as usize;
+            let skew = rng.next() | 1;
+            let height = (0..width).map(|_| rng.next() % skew).collect::<Vec<_>>();
+            let meta = (0..width).map(|_| rng.next() & 0x4F).collect::<Vec<_>>();
+            (height, meta)
+        })
+        .collect::<Vec<_>>();
+    let compressor = Compressor::new(rows.iter().map(|(h, m)| (&h[..], &m[..])));
+    let rows = rows
+        .into_iter()
+        .map(|(height, meta)| {
+            let mut data = Vec::new();
+            compressor.compress(&height, &meta, &mut data);
+            (height, meta, data)
+        })
+        .collect();
+    (*compressor.splay(), rows)
+}
+
+#[test]
+fn slice_and_stream_agree() {
+    let mut rng = Lcg(1);
+    for _ in 0..16 {
+        let (splay, rows) = compressed_rows(&mut rng, 8);
+        for (height, meta, data) in rows {
+            let mut h1 = vec![0; height.len()];
+            let mut m1 = vec![0; meta.len()];
+            splay.try_expand(&data, &mut h1, &mut m1).unwrap();
+            assert_eq!((&h1, &m1), (&height, &meta));
+
+            // trailing bytes are left in the stream
+            let mut stream = data.clone();
+            stream.extend_from_slice(&[0xAB; 3]);
+            let mut h2 = vec![0; height.len()];
+            let mut m2 = vec![0; meta.len()];
+            let consumed = splay
+                .try_expand_from(&stream[..], &mut h2, &mut m2)
+                .unwrap();
+            assert_eq!(consumed, data.len());
+            assert_eq!((&h2, &m2), (&height, &meta));
+        }
+    }
+}
+
+#[test]
+fn truncated_and_padded_input() {
+    let mut rng = Lcg(2);
+    let (splay, rows) = compressed_rows(&mut rng, 32);
+    for (height, meta, data) in rows {
+        let mut h = vec![0; height.len()];
+        let mut m = vec![0; meta.len()];
+        let cut = rng.next() as usize % data.len();
+        assert_eq!(
+            splay.try_expand(&data[..cut], &mut h, &mut m),
+            Err(Error::InputOverrun)
+        );
+        assert_eq!(
+            splay.try_expand_from(&data[..cut], &mut h, &mut m),
+            Err(Error::InputOverrun)
+        );
+        let mut padded = data.clone();
+        padded.push(0);
+        assert_eq!(
+            splay.try_expand(&padded, &mut h, &mut m),
+            Err(Error::LengthMismatch {
+                expected: data.len() + 1,
+                actual: data.len(),
+            })
+        );
+    }
+}
+
+#[test]
+fn random_garbage_never_panics() {
+    let mut rng = Lcg(3);
+    let (splay, _) = compressed_rows(&mut rng, 4);
+    for _ in 0..256 {
+        let len = rng.next() as usize;
+        let data = rng.bytes(len);
+        let mut h = vec![0; 1 + rng.next() as usize];
+        let mut m = vec![0; 1 + rng.next() as usize];
+        let _ = splay.try_expand(&data, &mut h, &mut m);
+        let _ = splay.try_expand_from(&data[..], &mut h, &mut m);
+    }
+}
+
+#[test]
+fn random_trees_are_checked() {
+    let mut rng = Lcg(4);
+    let mut accepted = 0;
+    for _ in 0..256 {
+        let mut tree1 = [0i32; 512];
+        let mut tree2 = [0i32; 512];
+        for v in tree1.iter_mut().chain(tree2.iter_mut()) {
+            // mostly in-range nodes and symbols, with some out of range
+            *v = rng.next() as i32 - 0x80 + ((rng.next() as i32 & 0x3) - 1) * 0x100;
+        }
+        let splay = match Splay::try_new(&mut &trees_to_bytes(&tree1, &tree2)[..]) {
+            Ok(splay) => splay,
+            Err(_) => continue,
+        };
+        accepted += 1;
+        let data = rng.bytes(64);
+        let mut h = vec![0; 32];
+        let mut m = vec![0; 32];
+        let _ = splay.try_expand(&data, &mut h, &mut m);
+    }
+    assert!(accepted < 256);
+}
+
+#[test]
+fn cyclic_tree() {
+    let mut tree = [0i32; 512];
+    // node 1 points to node 2, which points back to 1
+    tree[2] = 2;
+    tree[3] = -5;
+    tree[4] = 1;
+    tree[5] = -6;
+    match Splay::try_new(&mut &trees_to_bytes(&tree, &[0; 512])[..]) {
+        Ok(_) => panic!("Cyclic tree is accepted"),
+        Err(e) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidData),
+    }
+
+    let mut tree = [0i32; 512];
+    tree[3] = 300;
+    assert!(Splay::try_new(&mut &trees_to_bytes(&[0; 512], &tree)[..]).is_err());
+}
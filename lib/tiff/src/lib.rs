@@ -11,26 +11,71 @@
 )]
 #![allow(missing_debug_implementations, clippy::new_without_default)]
 
+mod load;
+mod lzw;
+
+pub use load::{load, Error, LoadedImage};
+
 use byteorder::{LittleEndian as E, WriteBytesExt};
 
 use std::io::{Result as IoResult, Seek, SeekFrom};
 
+const TY_BYTE: u16 = 1;
 const TY_ASCII: u16 = 2;
 const TY_SHORT: u16 = 3;
 const TY_LONG: u16 = 4;
+const TY_RATIONAL: u16 = 5;
+const TY_UNDEFINED: u16 = 7;
+const TY_LONG8: u16 = 16;
+
 const TAG_IMAGE_WIDTH: u16 = 0x100;
 const TAG_IMAGE_LENGTH: u16 = 0x101;
 const TAG_BITS_PER_SAMPLE: u16 = 0x102;
+const TAG_COMPRESSION: u16 = 0x103;
+const TAG_PHOTOMETRIC: u16 = 0x106;
 const TAG_IMAGE_DESCRIPTION: u16 = 0x10E;
 const TAG_STRIP_OFFSETS: u16 = 0x111;
+const TAG_SAMPLES_PER_PIXEL: u16 = 0x115;
 const TAG_ROWS_PER_STRIP: u16 = 0x116;
 const TAG_STRIP_BYTE_COUNTS: u16 = 0x117;
+const TAG_X_RESOLUTION: u16 = 0x11A;
+const TAG_Y_RESOLUTION: u16 = 0x11B;
+const TAG_RESOLUTION_UNIT: u16 = 0x128;
+const TAG_COLOR_MAP: u16 = 0x140;
 
-struct Field {
-    tag: u16,
-    ty: u16,
-    count: u32,
-    value: u32,
+const COMPRESSION_NONE: u32 = 1;
+const COMPRESSION_LZW: u32 = 5;
+const COMPRESSION_DEFLATE: u32 = 8;
+const PHOTOMETRIC_WHITE_IS_ZERO: u32 = 0;
+const PHOTOMETRIC_BLACK_IS_ZERO: u32 = 1;
+const PHOTOMETRIC_PALETTE: u32 = 3;
+const RESOLUTION_UNIT_INCH: u32 = 2;
+const DOTS_PER_INCH: u32 = 72;
+/// Uncompressed size of a strip to aim for.
+const STRIP_SIZE: usize = 0x10000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    None,
+    Lzw,
+    Deflate,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Options {
+    pub compression: Compression,
+    /// Forces 64-bit offsets. They are also used when the data
+    /// may not fit into 4GB.
+    pub big_tiff: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            compression: Compression::None,
+            big_tiff: false,
+        }
+    }
 }
 
 pub struct Image<'a> {
@@ -39,97 +84,252 @@ pub struct Image<'a> {
     pub bpp: u16,
     pub name: &'a str,
     pub data: &'a [u8],
+    /// Colors of the indexed images, stored as a color map.
+    pub palette: Option<&'a [[u8; 3]]>,
 }
 
-pub fn save<W: Seek + WriteBytesExt>(mut tiff: W, images: &[Image<'_>]) -> IoResult<()> {
-    // header
-    tiff.write_u16::<E>(0x4949)?; // little endian
-    tiff.write_u16::<E>(42)?; // magic
-    let data_start = (images.len() * 0x80) as u32;
-    let mut data_offset = data_start;
-    let mut cur_offset = 4;
-    // image file directory
-    for im in images {
-        tiff.write_u32::<E>(cur_offset + 4)?; // IFD offset
-        let total_bytes = (im.width * im.height) as usize * im.bpp as usize / 8;
-        assert_eq!(total_bytes, im.data.len());
-        let description: u32 = im
-            .name
-            .chars()
-            .take(3)
-            .enumerate()
-            .map(|(i, c)| (c as u32) << (i * 8))
-            .sum();
-        let fields = [
-            Field {
-                tag: TAG_IMAGE_WIDTH,
-                ty: TY_LONG,
-                count: 1,
-                value: im.width,
-            },
-            Field {
-                tag: TAG_IMAGE_LENGTH,
-                ty: TY_LONG,
-                count: 1,
-                value: im.height,
-            },
-            Field {
-                tag: TAG_BITS_PER_SAMPLE,
-                ty: TY_SHORT,
-                count: 1,
-                value: im.bpp as u32,
-            },
-            Field {
-                tag: TAG_IMAGE_DESCRIPTION,
-                ty: TY_ASCII,
-                count: im.name.len().min(3) as u32 + 1,
-                value: description,
-            },
-            Field {
-                tag: TAG_STRIP_OFFSETS,
-                ty: TY_LONG,
-                count: 1,
-                value: data_offset,
-            },
-            Field {
-                tag: TAG_ROWS_PER_STRIP,
-                ty: TY_SHORT,
-                count: 1,
-                value: im.height,
-            },
-            Field {
-                tag: TAG_STRIP_BYTE_COUNTS,
-                ty: TY_LONG,
-                count: 1,
-                value: total_bytes as u32,
+/// IFD entry with the values serialized.
+struct Field {
+    tag: u16,
+    ty: u16,
+    count: u64,
+    data: Vec<u8>,
+}
+
+impl Field {
+    fn short(tag: u16, values: &[u16]) -> Self {
+        Field {
+            tag,
+            ty: TY_SHORT,
+            count: values.len() as u64,
+            data: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        }
+    }
+
+    fn long(tag: u16, value: u32) -> Self {
+        Field {
+            tag,
+            ty: TY_LONG,
+            count: 1,
+            data: value.to_le_bytes().to_vec(),
+        }
+    }
+
+    /// Offsets and byte counts, 64-bit in BigTIFF.
+    fn offsets(tag: u16, values: &[u64], big_tiff: bool) -> Self {
+        Field {
+            tag,
+            ty: if big_tiff { TY_LONG8 } else { TY_LONG },
+            count: values.len() as u64,
+            data: if big_tiff {
+                values.iter().flat_map(|v| v.to_le_bytes()).collect()
+            } else {
+                values
+                    .iter()
+                    .flat_map(|&v| (v as u32).to_le_bytes())
+                    .collect()
             },
-        ];
-        tiff.write_u16::<E>(fields.len() as u16)?;
-        for &Field {
+        }
+    }
+
+    fn rational(tag: u16, numerator: u32, denominator: u32) -> Self {
+        let mut data = numerator.to_le_bytes().to_vec();
+        data.extend_from_slice(&denominator.to_le_bytes());
+        Field {
             tag,
-            ty,
-            count,
-            value,
-        } in &fields
-        {
-            tiff.write_u16::<E>(tag)?;
-            tiff.write_u16::<E>(ty)?;
-            tiff.write_u32::<E>(count)?;
-            tiff.write_u32::<E>(value)?;
+            ty: TY_RATIONAL,
+            count: 1,
+            data,
         }
-        cur_offset += 4 + 2 + fields.len() as u32 * 12;
-        data_offset += total_bytes as u32;
-        assert_eq!(tiff.seek(SeekFrom::Current(0)).unwrap(), cur_offset as u64);
     }
-    // gap
-    assert!(cur_offset < data_start);
-    tiff.write_u32::<E>(0)?; // next IFD offset
-    for _ in cur_offset + 4..data_start {
-        tiff.write_u8(0)?;
+
+    fn ascii(tag: u16, value: &str) -> Self {
+        let mut data = value.as_bytes().to_vec();
+        data.push(0);
+        Field {
+            tag,
+            ty: TY_ASCII,
+            count: data.len() as u64,
+            data,
+        }
+    }
+}
+
+/// Keeps track of the position, so that the offsets can be computed while writing.
+struct Output<W> {
+    tiff: W,
+    pos: u64,
+    big_tiff: bool,
+}
+
+impl<W: Seek + WriteBytesExt> Output<W> {
+    fn write(&mut self, data: &[u8]) -> IoResult<()> {
+        self.tiff.write_all(data)?;
+        self.pos += data.len() as u64;
+        Ok(())
+    }
+
+    fn write_offset(&mut self, offset: u64) -> IoResult<()> {
+        if self.big_tiff {
+            self.tiff.write_u64::<E>(offset)?;
+            self.pos += 8;
+        } else {
+            self.tiff.write_u32::<E>(offset as u32)?;
+            self.pos += 4;
+        }
+        Ok(())
+    }
+
+    /// Values have to start on a word boundary.
+    fn align(&mut self) -> IoResult<()> {
+        if self.pos & 1 != 0 {
+            self.write(&[0])?;
+        }
+        Ok(())
+    }
+
+    fn patch_offset(&mut self, at: u64, offset: u64) -> IoResult<()> {
+        self.tiff.seek(SeekFrom::Start(at))?;
+        let pos = self.pos;
+        self.write_offset(offset)?;
+        self.pos = pos;
+        self.tiff.seek(SeekFrom::Start(pos))?;
+        Ok(())
+    }
+}
+
+fn compress(data: &[u8], compression: Compression) -> Vec<u8> {
+    match compression {
+        Compression::None => data.to_vec(),
+        Compression::Lzw => lzw::encode(data),
+        Compression::Deflate => miniz_oxide::deflate::compress_to_vec_zlib(data, 6),
     }
-    // image data
+}
+
+/// Writes the images uncompressed.
+pub fn save<W: Seek + WriteBytesExt>(tiff: W, images: &[Image<'_>]) -> IoResult<()> {
+    save_with(tiff, images, &Options::default())
+}
+
+/// Writes the images, expecting the output to be at the start of the file.
+pub fn save_with<W: Seek + WriteBytesExt>(
+    tiff: W,
+    images: &[Image<'_>],
+    options: &Options,
+) -> IoResult<()> {
+    // leave room for LZW expanding the incompressible data
+    let max_size = images
+        .iter()
+        .map(|im| im.data.len() as u64 * 3 / 2 + 0x1000)
+        .sum::<u64>();
+    let mut out = Output {
+        tiff,
+        pos: 0,
+        big_tiff: options.big_tiff || max_size > u32::MAX as u64,
+    };
+
+    // header
+    out.write(b"II")?; // little endian
+    if out.big_tiff {
+        out.write(&43u16.to_le_bytes())?; // magic
+        out.write(&8u16.to_le_bytes())?; // offset size
+        out.write(&0u16.to_le_bytes())?;
+    } else {
+        out.write(&42u16.to_le_bytes())?; // magic
+    }
+    let mut next_ifd_at = out.pos;
+    out.write_offset(0)?;
+
     for im in images {
-        tiff.write_all(im.data)?;
+        let stride = (im.width as usize * im.bpp as usize).div_ceil(8);
+        assert_eq!(stride * im.height as usize, im.data.len());
+        let rows_per_strip = (STRIP_SIZE / stride.max(1)).clamp(1, im.height.max(1) as usize);
+
+        // image data
+        let mut strip_offsets = Vec::new();
+        let mut strip_counts = Vec::new();
+        for strip in im.data.chunks((stride * rows_per_strip).max(1)) {
+            let data = compress(strip, options.compression);
+            strip_offsets.push(out.pos);
+            strip_counts.push(data.len() as u64);
+            out.write(&data)?;
+        }
+
+        let (photometric, color_map) = match im.palette {
+            Some(palette) => {
+                // all the reds first, then greens, then blues
+                let count = 1usize << im.bpp;
+                let color_map = (0..3)
+                    .flat_map(|c| {
+                        (0..count).map(move |i| palette.get(i).map_or(0, |p| p[c] as u16 * 0x101))
+                    })
+                    .collect::<Vec<_>>();
+                (PHOTOMETRIC_PALETTE, Some(color_map))
+            }
+            None => (PHOTOMETRIC_BLACK_IS_ZERO, None),
+        };
+        let compression = match options.compression {
+            Compression::None => COMPRESSION_NONE,
+            Compression::Lzw => COMPRESSION_LZW,
+            Compression::Deflate => COMPRESSION_DEFLATE,
+        };
+        let mut fields = vec![
+            Field::long(TAG_IMAGE_WIDTH, im.width),
+            Field::long(TAG_IMAGE_LENGTH, im.height),
+            Field::short(TAG_BITS_PER_SAMPLE, &[im.bpp]),
+            Field::short(TAG_COMPRESSION, &[compression as u16]),
+            Field::short(TAG_PHOTOMETRIC, &[photometric as u16]),
+            Field::ascii(TAG_IMAGE_DESCRIPTION, im.name),
+            Field::offsets(TAG_STRIP_OFFSETS, &strip_offsets, out.big_tiff),
+            Field::short(TAG_SAMPLES_PER_PIXEL, &[1]),
+            Field::long(TAG_ROWS_PER_STRIP, rows_per_strip as u32),
+            Field::offsets(TAG_STRIP_BYTE_COUNTS, &strip_counts, out.big_tiff),
+            Field::rational(TAG_X_RESOLUTION, DOTS_PER_INCH, 1),
+            Field::rational(TAG_Y_RESOLUTION, DOTS_PER_INCH, 1),
+            Field::short(TAG_RESOLUTION_UNIT, &[RESOLUTION_UNIT_INCH as u16]),
+        ];
+        if let Some(ref color_map) = color_map {
+            fields.push(Field::short(TAG_COLOR_MAP, color_map));
+        }
+
+        // values that don't fit into the entries
+        let inline_size = if out.big_tiff { 8 } else { 4 };
+        let mut value_offsets = Vec::with_capacity(fields.len());
+        for field in &fields {
+            if field.data.len() > inline_size {
+                out.align()?;
+                value_offsets.push(Some(out.pos));
+                out.write(&field.data)?;
+            } else {
+                value_offsets.push(None);
+            }
+        }
+
+        // image file directory
+        out.align()?;
+        let ifd = out.pos;
+        out.patch_offset(next_ifd_at, ifd)?;
+        if out.big_tiff {
+            out.write(&(fields.len() as u64).to_le_bytes())?;
+        } else {
+            out.write(&(fields.len() as u16).to_le_bytes())?;
+        }
+        for (field, value_offset) in fields.iter().zip(value_offsets) {
+            out.write(&field.tag.to_le_bytes())?;
+            out.write(&field.ty.to_le_bytes())?;
+            out.write_offset(field.count)?;
+            match value_offset {
+                Some(offset) => out.write_offset(offset)?,
+                None => {
+                    let mut value = field.data.clone();
+                    value.resize(inline_size, 0);
+                    out.write(&value)?;
+                }
+            }
+        }
+        next_ifd_at = out.pos;
+        out.write_offset(0)?;
     }
+
     Ok(())
 }
@@ -0,0 +1,435 @@
+use std::{collections::HashMap, fmt, io};
+
+use super::{
+    COMPRESSION_DEFLATE, COMPRESSION_LZW, COMPRESSION_NONE, PHOTOMETRIC_PALETTE,
+    PHOTOMETRIC_WHITE_IS_ZERO, TAG_BITS_PER_SAMPLE, TAG_COLOR_MAP, TAG_COMPRESSION,
+    TAG_IMAGE_DESCRIPTION, TAG_IMAGE_LENGTH, TAG_IMAGE_WIDTH, TAG_PHOTOMETRIC,
+    TAG_SAMPLES_PER_PIXEL, TAG_STRIP_BYTE_COUNTS, TAG_STRIP_OFFSETS, TY_ASCII, TY_BYTE, TY_LONG,
+    TY_LONG8, TY_SHORT, TY_UNDEFINED,
+};
+
+const TAG_PLANAR_CONFIGURATION: u16 = 0x11C;
+const TAG_PREDICTOR: u16 = 0x13D;
+const TAG_TILE_WIDTH: u16 = 0x142;
+
+const COMPRESSION_DEFLATE_OLD: u32 = 32946;
+const COMPRESSION_PACK_BITS: u32 = 32773;
+const PREDICTOR_HORIZONTAL: u32 = 2;
+
+/// Upper bound on the number of directories, protecting from offset loops.
+const MAX_IMAGES: usize = 0x400;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The file doesn't start with a TIFF header.
+    BadHeader,
+    /// An offset or a count points outside of the file.
+    OutOfBounds,
+    MissingTag(u16),
+    /// A feature of the format that is valid but not handled.
+    Unsupported(&'static str, u32),
+    /// The LZW stream refers to an unknown code.
+    BadLzwCode(u16),
+    BadDeflate,
+    /// The decompressed strips don't cover the image.
+    ShortData {
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "{}", e),
+            Error::BadHeader => write!(f, "not a TIFF file"),
+            Error::OutOfBounds => write!(f, "offset is out of the file bounds"),
+            Error::MissingTag(tag) => write!(f, "missing tag 0x{:X}", tag),
+            Error::Unsupported(what, value) => write!(f, "unsupported {} {}", what, value),
+            Error::BadLzwCode(code) => write!(f, "bad LZW code {}", code),
+            Error::BadDeflate => write!(f, "bad Deflate stream"),
+            Error::ShortData { expected, actual } => {
+                write!(f, "image data has {} bytes, expected {}", actual, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+/// Image decoded from a file.
+pub struct LoadedImage {
+    pub width: u32,
+    pub height: u32,
+    pub bpp: u16,
+    /// Packed rows of samples. 16-bit samples are little-endian.
+    pub data: Vec<u8>,
+    /// Colors of the palette images, reduced to 8 bits.
+    pub palette: Option<Vec<[u8; 3]>>,
+}
+
+impl LoadedImage {
+    pub fn as_image<'a>(&'a self, name: &'a str) -> super::Image<'a> {
+        super::Image {
+            width: self.width,
+            height: self.height,
+            bpp: self.bpp,
+            name,
+            data: &self.data,
+            palette: self.palette.as_deref(),
+        }
+    }
+}
+
+struct Source<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+    big_tiff: bool,
+}
+
+impl Source<'_> {
+    fn bytes(&self, offset: usize, count: usize) -> Result<&[u8], Error> {
+        offset
+            .checked_add(count)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or(Error::OutOfBounds)
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16, Error> {
+        let b = self.bytes(offset, 2)?;
+        Ok(if self.big_endian {
+            u16::from_be_bytes([b[0], b[1]])
+        } else {
+            u16::from_le_bytes([b[0], b[1]])
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, Error> {
+        let b = self.bytes(offset, 4)?;
+        let array = [b[0], b[1], b[2], b[3]];
+        Ok(if self.big_endian {
+            u32::from_be_bytes(array)
+        } else {
+            u32::from_le_bytes(array)
+        })
+    }
+
+    fn u64(&self, offset: usize) -> Result<u64, Error> {
+        let (a, b) = (self.u32(offset)? as u64, self.u32(offset + 4)? as u64);
+        Ok(if self.big_endian {
+            (a << 32) | b
+        } else {
+            (b << 32) | a
+        })
+    }
+
+    /// Reads an offset or a count, which are 64-bit in BigTIFF.
+    fn offset(&self, offset: usize) -> Result<usize, Error> {
+        if self.big_tiff {
+            usize::try_from(self.u64(offset)?).map_err(|_| Error::OutOfBounds)
+        } else {
+            Ok(self.u32(offset)? as usize)
+        }
+    }
+}
+
+struct Entry {
+    ty: u16,
+    count: usize,
+    /// Offset of the values, which may be inside the entry itself.
+    offset: usize,
+}
+
+struct Directory<'a> {
+    source: &'a Source<'a>,
+    entries: HashMap<u16, Entry>,
+}
+
+impl Directory<'_> {
+    fn values(&self, tag: u16) -> Result<Option<Vec<u64>>, Error> {
+        let entry = match self.entries.get(&tag) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        (0..entry.count)
+            .map(|i| match entry.ty {
+                TY_BYTE | TY_UNDEFINED => Ok(self.source.bytes(entry.offset + i, 1)?[0] as u64),
+                TY_SHORT => Ok(self.source.u16(entry.offset + i * 2)? as u64),
+                TY_LONG => Ok(self.source.u32(entry.offset + i * 4)? as u64),
+                TY_LONG8 => self.source.u64(entry.offset + i * 8),
+                other => Err(Error::Unsupported("field type", other as u32)),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Some)
+    }
+
+    fn value(&self, tag: u16) -> Result<Option<u32>, Error> {
+        match self.values(tag)?.and_then(|v| v.first().cloned()) {
+            Some(value) => u32::try_from(value)
+                .map(Some)
+                .map_err(|_| Error::Unsupported("tag value", tag as u32)),
+            None => Ok(None),
+        }
+    }
+
+    fn required(&self, tag: u16) -> Result<u32, Error> {
+        self.value(tag)?.ok_or(Error::MissingTag(tag))
+    }
+
+    fn ascii(&self, tag: u16) -> Result<Option<String>, Error> {
+        match self.entries.get(&tag) {
+            Some(entry) if entry.ty == TY_ASCII => {
+                let bytes = self.source.bytes(entry.offset, entry.count)?;
+                let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+                Ok(Some(String::from_utf8_lossy(&bytes[..end]).into_owned()))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+fn type_size(ty: u16) -> usize {
+    match ty {
+        3 | 8 => 2,
+        4 | 9 | 11 | 13 => 4,
+        5 | 10 | 12 | 16 | 17 | 18 => 8,
+        _ => 1,
+    }
+}
+
+fn unpack_bits(input: &[u8], output: &mut Vec<u8>) -> Result<(), Error> {
+    let mut i = 0;
+    while let Some(&header) = input.get(i) {
+        let n = header as i8;
+        i += 1;
+        match n {
+            -128 => {}
+            0..=127 => {
+                let count = n as usize + 1;
+                let run = input.get(i..i + count).ok_or(Error::OutOfBounds)?;
+                output.extend_from_slice(run);
+                i += count;
+            }
+            _ => {
+                let byte = *input.get(i).ok_or(Error::OutOfBounds)?;
+                output.extend((0..1 - n as isize).map(|_| byte));
+                i += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_image(dir: &Directory<'_>) -> Result<LoadedImage, Error> {
+    if dir.entries.contains_key(&TAG_TILE_WIDTH) {
+        return Err(Error::Unsupported("tiled layout", 0));
+    }
+    let width = dir.required(TAG_IMAGE_WIDTH)?;
+    let height = dir.required(TAG_IMAGE_LENGTH)?;
+    let bits = dir.value(TAG_BITS_PER_SAMPLE)?.unwrap_or(1);
+    let samples = dir.value(TAG_SAMPLES_PER_PIXEL)?.unwrap_or(1);
+    let compression = dir.value(TAG_COMPRESSION)?.unwrap_or(COMPRESSION_NONE);
+    let photometric = dir.value(TAG_PHOTOMETRIC)?;
+    let predictor = dir.value(TAG_PREDICTOR)?.unwrap_or(1);
+    if dir.value(TAG_PLANAR_CONFIGURATION)?.unwrap_or(1) != 1 && samples > 1 {
+        return Err(Error::Unsupported("planar configuration", 2));
+    }
+    match bits {
+        1 | 2 | 4 | 8 | 16 => {}
+        other => return Err(Error::Unsupported("bits per sample", other)),
+    }
+    if samples > 1 && bits < 8 {
+        return Err(Error::Unsupported("bits per multi-sample", bits));
+    }
+
+    let offsets = dir
+        .values(TAG_STRIP_OFFSETS)?
+        .ok_or(Error::MissingTag(TAG_STRIP_OFFSETS))?;
+    let counts = dir
+        .values(TAG_STRIP_BYTE_COUNTS)?
+        .ok_or(Error::MissingTag(TAG_STRIP_BYTE_COUNTS))?;
+    let mut raw = Vec::new();
+    for (&offset, &count) in offsets.iter().zip(counts.iter()) {
+        let strip = dir.source.bytes(offset as usize, count as usize)?;
+        match compression {
+            COMPRESSION_NONE => raw.extend_from_slice(strip),
+            COMPRESSION_DEFLATE | COMPRESSION_DEFLATE_OLD => {
+                let data = miniz_oxide::inflate::decompress_to_vec_zlib(strip)
+                    .map_err(|_| Error::BadDeflate)?;
+                raw.extend_from_slice(&data);
+            }
+            COMPRESSION_LZW => super::lzw::decode(strip, &mut raw).map_err(Error::BadLzwCode)?,
+            COMPRESSION_PACK_BITS => unpack_bits(strip, &mut raw)?,
+            other => return Err(Error::Unsupported("compression", other)),
+        }
+    }
+
+    let stride = (width as usize * samples as usize * bits as usize).div_ceil(8);
+    let expected = stride * height as usize;
+    if raw.len() < expected {
+        return Err(Error::ShortData {
+            expected,
+            actual: raw.len(),
+        });
+    }
+    raw.truncate(expected);
+
+    let sample_bytes = bits as usize / 8;
+    // 16-bit samples are converted to little-endian up front
+    if bits == 16 && dir.source.big_endian {
+        for pair in raw.chunks_mut(2) {
+            pair.swap(0, 1);
+        }
+    }
+    if predictor == PREDICTOR_HORIZONTAL {
+        let pixel = samples as usize;
+        for row in raw.chunks_mut(stride) {
+            match bits {
+                8 => {
+                    for i in pixel..row.len() {
+                        row[i] = row[i].wrapping_add(row[i - pixel]);
+                    }
+                }
+                16 => {
+                    for i in pixel..row.len() / 2 {
+                        let prev =
+                            u16::from_le_bytes([row[2 * (i - pixel)], row[2 * (i - pixel) + 1]]);
+                        let cur = u16::from_le_bytes([row[2 * i], row[2 * i + 1]]);
+                        row[2 * i..2 * i + 2]
+                            .copy_from_slice(&cur.wrapping_add(prev).to_le_bytes());
+                    }
+                }
+                other => return Err(Error::Unsupported("predictor bits", other)),
+            }
+        }
+    } else if predictor != 1 {
+        return Err(Error::Unsupported("predictor", predictor));
+    }
+
+    // only the first sample of each pixel is kept
+    let mut data = if samples > 1 {
+        raw.chunks(sample_bytes * samples as usize)
+            .flat_map(|pixel| pixel[..sample_bytes].iter().cloned())
+            .collect()
+    } else {
+        raw
+    };
+    if photometric == Some(PHOTOMETRIC_WHITE_IS_ZERO) {
+        if bits == 16 {
+            for pair in data.chunks_mut(2) {
+                let value = !u16::from_le_bytes([pair[0], pair[1]]);
+                pair.copy_from_slice(&value.to_le_bytes());
+            }
+        } else {
+            let mask = ((1u16 << bits) - 1) as u8;
+            for byte in data.iter_mut() {
+                // flips all the packed samples at once
+                *byte ^= if bits == 8 {
+                    0xFF
+                } else {
+                    mask.wrapping_mul(0xFF / mask)
+                };
+            }
+        }
+    }
+
+    let palette = match dir.values(TAG_COLOR_MAP)? {
+        Some(ref map) if photometric == Some(PHOTOMETRIC_PALETTE) => {
+            let count = map.len() / 3;
+            Some(
+                (0..count)
+                    .map(|i| {
+                        let channel = |c: usize| (map[c * count + i] >> 8) as u8;
+                        [channel(0), channel(1), channel(2)]
+                    })
+                    .collect(),
+            )
+        }
+        _ => None,
+    };
+
+    Ok(LoadedImage {
+        width,
+        height,
+        bpp: bits as u16,
+        data,
+        palette,
+    })
+}
+
+/// Loads all the images of a file, keyed by their description.
+/// Images without a description are keyed by their index in the file.
+pub fn load<R: io::Read>(mut input: R) -> Result<HashMap<String, LoadedImage>, Error> {
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    let mut source = Source {
+        big_endian: match data.get(..2) {
+            Some(b"II") => false,
+            Some(b"MM") => true,
+            _ => return Err(Error::BadHeader),
+        },
+        big_tiff: false,
+        data: &data,
+    };
+    match source.u16(2) {
+        Ok(42) => {}
+        Ok(43) if source.u16(4)? == 8 => source.big_tiff = true,
+        _ => return Err(Error::BadHeader),
+    }
+
+    // sizes of the IFD entry parts, different in BigTIFF
+    let (count_size, field_size) = if source.big_tiff { (8, 8) } else { (2, 4) };
+    let entry_size = 4 + 2 * field_size;
+
+    let mut images = HashMap::new();
+    let mut index = 0;
+    let mut ifd_offset = source.offset(if source.big_tiff { 8 } else { 4 })?;
+    while ifd_offset != 0 {
+        if index >= MAX_IMAGES {
+            return Err(Error::Unsupported("number of images", MAX_IMAGES as u32));
+        }
+        let num_entries = if source.big_tiff {
+            source.offset(ifd_offset)?
+        } else {
+            source.u16(ifd_offset)? as usize
+        };
+        if num_entries > data.len() / entry_size {
+            return Err(Error::OutOfBounds);
+        }
+        let mut entries = HashMap::new();
+        for i in 0..num_entries {
+            let base = ifd_offset + count_size + i * entry_size;
+            let tag = source.u16(base)?;
+            let ty = source.u16(base + 2)?;
+            let count = source.offset(base + 4)?;
+            let size = count.checked_mul(type_size(ty)).ok_or(Error::OutOfBounds)?;
+            let offset = if size <= field_size {
+                base + 4 + field_size
+            } else {
+                source.offset(base + 4 + field_size)?
+            };
+            entries.insert(tag, Entry { ty, count, offset });
+        }
+
+        let dir = Directory {
+            source: &source,
+            entries,
+        };
+        let name = match dir.ascii(TAG_IMAGE_DESCRIPTION)? {
+            Some(name) => name,
+            None => index.to_string(),
+        };
+        index += 1;
+        images.insert(name, read_image(&dir)?);
+        ifd_offset = source.offset(ifd_offset + count_size + num_entries * entry_size)?;
+    }
+
+    Ok(images)
+}
@@ -0,0 +1,142 @@
+//! LZW flavor of TIFF: codes are packed MSB first,
+//! and their width grows one code earlier than strictly necessary.
+
+use std::collections::HashMap;
+
+const CLEAR: u16 = 256;
+const END: u16 = 257;
+const FIRST: u16 = 258;
+const MIN_WIDTH: u32 = 9;
+const MAX_WIDTH: u32 = 12;
+/// The encoder resets the table before reaching the last code.
+const TABLE_FULL: u16 = (1 << MAX_WIDTH) - 2;
+
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn put(&mut self, code: u16, width: u32) {
+        self.acc = (self.acc << width) | code as u32;
+        self.count += width;
+        while self.count >= 8 {
+            self.count -= 8;
+            self.out.push((self.acc >> self.count) as u8);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count != 0 {
+            let pad = 8 - self.count;
+            self.put(0, pad);
+        }
+        self.out
+    }
+}
+
+pub fn encode(input: &[u8]) -> Vec<u8> {
+    let mut bits = BitWriter {
+        out: Vec::with_capacity(input.len() / 2),
+        acc: 0,
+        count: 0,
+    };
+    let mut table = HashMap::<(u16, u8), u16>::new();
+    let mut next = FIRST;
+    let mut width = MIN_WIDTH;
+    bits.put(CLEAR, width);
+
+    let mut bytes = input.iter();
+    let mut word = match bytes.next() {
+        Some(&byte) => byte as u16,
+        None => {
+            bits.put(END, width);
+            return bits.finish();
+        }
+    };
+    for &byte in bytes {
+        if let Some(&code) = table.get(&(word, byte)) {
+            word = code;
+            continue;
+        }
+        bits.put(word, width);
+        table.insert((word, byte), next);
+        next += 1;
+        if next == TABLE_FULL {
+            bits.put(CLEAR, width);
+            table.clear();
+            next = FIRST;
+            width = MIN_WIDTH;
+        } else if next >= 1 << width {
+            width += 1;
+        }
+        word = byte as u16;
+    }
+    bits.put(word, width);
+    // the decoder is one entry behind, and may switch the width before this code
+    if next + 1 >= 1 << width && width < MAX_WIDTH {
+        width += 1;
+    }
+    bits.put(END, width);
+    bits.finish()
+}
+
+/// Decodes a strip, returning the offending code on error.
+pub fn decode(input: &[u8], output: &mut Vec<u8>) -> Result<(), u16> {
+    let mut table: Vec<Vec<u8>> = Vec::with_capacity(1 << MAX_WIDTH);
+    let reset = |table: &mut Vec<Vec<u8>>| {
+        table.clear();
+        table.extend((0..=255u8).map(|b| vec![b]));
+        // placeholders for the clear and end codes
+        table.push(Vec::new());
+        table.push(Vec::new());
+    };
+    reset(&mut table);
+
+    let mut width = MIN_WIDTH as usize;
+    let mut bit_pos = 0;
+    let mut prev: Option<u16> = None;
+    while bit_pos + width <= input.len() * 8 {
+        let mut code = 0u16;
+        for _ in 0..width {
+            let bit = (input[bit_pos / 8] >> (7 - bit_pos % 8)) & 1;
+            code = (code << 1) | bit as u16;
+            bit_pos += 1;
+        }
+
+        match code {
+            CLEAR => {
+                reset(&mut table);
+                width = MIN_WIDTH as usize;
+                prev = None;
+                continue;
+            }
+            END => break,
+            _ => {}
+        }
+
+        let entry = match (table.get(code as usize), prev) {
+            (Some(entry), _) => entry.clone(),
+            (None, Some(p)) if code as usize == table.len() => {
+                let mut entry = table[p as usize].clone();
+                entry.push(entry[0]);
+                entry
+            }
+            _ => return Err(code),
+        };
+        output.extend_from_slice(&entry);
+        if let Some(p) = prev {
+            if table.len() < 1 << MAX_WIDTH {
+                let mut new = table[p as usize].clone();
+                new.push(entry[0]);
+                table.push(new);
+            }
+        }
+        prev = Some(code);
+        if table.len() + 1 >= 1 << width && width < MAX_WIDTH as usize {
+            width += 1;
+        }
+    }
+    Ok(())
+}
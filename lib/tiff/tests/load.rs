@@ -0,0 +1,341 @@
+use std::{collections::HashMap, io::Cursor};
+
+/// Minimal TIFF LZW encoder, with the code width growing one code early.
+fn lzw_encode(input: &[u8]) -> Vec<u8> {
+    struct Bits {
+        out: Vec<u8>,
+        acc: u32,
+        count: u32,
+    }
+    impl Bits {
+        fn put(&mut self, code: u16, width: u32) {
+            self.acc = (self.acc << width) | code as u32;
+            self.count += width;
+            while self.count >= 8 {
+                self.count -= 8;
+                self.out.push((self.acc >> self.count) as u8);
+            }
+        }
+    }
+
+    let mut bits = Bits {
+        out: Vec::new(),
+        acc: 0,
+        count: 0,
+    };
+    let mut table = HashMap::<Vec<u8>, u16>::new();
+    let mut next = 258u16;
+    let mut width = 9;
+    bits.put(256, width);
+    let mut word = Vec::new();
+    for &byte in input {
+        let mut longer = word.clone();
+        longer.push(byte);
+        if longer.len() == 1 || table.contains_key(&longer) {
+            word = longer;
+            continue;
+        }
+        let code = if word.len() == 1 {
+            word[0] as u16
+        } else {
+            table[&word]
+        };
+        bits.put(code, width);
+        table.insert(longer, next);
+        next += 1;
+        if next >= 1 << width {
+            width += 1;
+        }
+        if next == 4094 {
+            bits.put(256, width);
+            table.clear();
+            next = 258;
+            width = 9;
+        }
+        word = vec![byte];
+    }
+    if !word.is_empty() {
+        let code = if word.len() == 1 {
+            word[0] as u16
+        } else {
+            table[&word]
+        };
+        bits.put(code, width);
+    }
+    bits.put(257, width);
+    if bits.count != 0 {
+        let pad = 8 - bits.count;
+        bits.put(0, pad);
+    }
+    bits.out
+}
+
+fn pack_bits(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for chunk in input.chunks(0x80) {
+        if chunk.iter().all(|&b| b == chunk[0]) && chunk.len() > 1 {
+            out.push((1 - chunk.len() as i32) as u8);
+            out.push(chunk[0]);
+        } else {
+            out.push(chunk.len() as u8 - 1);
+            out.extend_from_slice(chunk);
+        }
+    }
+    out
+}
+
+/// Builds a single-image file with the given tags,
+/// where the strips are stored right after the header.
+fn build(big_endian: bool, strips: &[Vec<u8>], tags: &[(u16, u16, Vec<u32>)]) -> Vec<u8> {
+    let u16b = |v: u16| {
+        if big_endian {
+            v.to_be_bytes()
+        } else {
+            v.to_le_bytes()
+        }
+    };
+    let u32b = |v: u32| {
+        if big_endian {
+            v.to_be_bytes()
+        } else {
+            v.to_le_bytes()
+        }
+    };
+
+    let mut out = Vec::new();
+    out.extend_from_slice(if big_endian { b"MM" } else { b"II" });
+    out.extend_from_slice(&u16b(42));
+    out.extend_from_slice(&[0; 4]);
+    let mut offsets = Vec::new();
+    for strip in strips {
+        offsets.push(out.len() as u32);
+        out.extend_from_slice(strip);
+    }
+    let mut tags = tags.to_vec();
+    tags.push((0x111, 4, offsets));
+    tags.push((0x117, 4, strips.iter().map(|s| s.len() as u32).collect()));
+    tags.sort_by_key(|t| t.0);
+
+    // out-of-line values go before the directory
+    let mut values = Vec::new();
+    for &(_, ty, ref list) in &tags {
+        let bytes = list
+            .iter()
+            .flat_map(|&v| match ty {
+                3 => u16b(v as u16).to_vec(),
+                _ => u32b(v).to_vec(),
+            })
+            .collect::<Vec<_>>();
+        if bytes.len() > 4 {
+            values.push(Some(out.len() as u32));
+            out.extend_from_slice(&bytes);
+        } else {
+            values.push(None);
+        }
+    }
+    let ifd = out.len() as u32;
+    out[4..8].copy_from_slice(&u32b(ifd));
+    out.extend_from_slice(&u16b(tags.len() as u16));
+    for (&(tag, ty, ref list), offset) in tags.iter().zip(values) {
+        out.extend_from_slice(&u16b(tag));
+        out.extend_from_slice(&u16b(ty));
+        out.extend_from_slice(&u32b(list.len() as u32));
+        match offset {
+            Some(offset) => out.extend_from_slice(&u32b(offset)),
+            None => {
+                let mut inline = match ty {
+                    3 => list.iter().flat_map(|&v| u16b(v as u16)).collect(),
+                    _ => list.iter().flat_map(|&v| u32b(v)).collect::<Vec<_>>(),
+                };
+                inline.resize(4, 0);
+                out.extend_from_slice(&inline);
+            }
+        }
+    }
+    out.extend_from_slice(&[0; 4]);
+    out
+}
+
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| ((i * 7) ^ (i >> 5)) as u8).collect()
+}
+
+#[test]
+fn roundtrip() {
+    let heights = pattern(16 * 8);
+    let materials = pattern(16 * 8 / 2);
+    let colors = (0..16u8).map(|i| [i * 16, 0xFF - i, i]).collect::<Vec<_>>();
+    let images = [
+        tiff::Image {
+            width: 16,
+            height: 8,
+            bpp: 8,
+            name: "height of the lower layer",
+            data: &heights,
+            palette: None,
+        },
+        tiff::Image {
+            width: 16,
+            height: 8,
+            bpp: 4,
+            name: "m0",
+            data: &materials,
+            palette: Some(&colors),
+        },
+    ];
+
+    for &compression in &[
+        tiff::Compression::None,
+        tiff::Compression::Lzw,
+        tiff::Compression::Deflate,
+    ] {
+        for &big_tiff in &[false, true] {
+            let options = tiff::Options {
+                compression,
+                big_tiff,
+            };
+            let mut file = Cursor::new(Vec::new());
+            tiff::save_with(&mut file, &images, &options).unwrap();
+
+            let loaded = tiff::load(Cursor::new(file.into_inner())).unwrap();
+            assert_eq!(loaded.len(), 2);
+            for image in &images {
+                let other = loaded[image.name].as_image(image.name);
+                assert_eq!((other.width, other.height), (image.width, image.height));
+                assert_eq!(other.bpp, image.bpp);
+                assert_eq!(other.data, image.data, "{:?}", options);
+                assert_eq!(other.palette, image.palette);
+            }
+        }
+    }
+}
+
+#[test]
+fn lzw_long_stream() {
+    // enough codes to hit the table reset
+    let data = (0..0x10000u32)
+        .map(|i| (i.wrapping_mul(2654435761) >> 29) as u8)
+        .collect::<Vec<_>>();
+    let file = build(
+        false,
+        &[lzw_encode(&data)],
+        &[
+            (0x100, 4, vec![0x100]),
+            (0x101, 4, vec![0x100]),
+            (0x102, 3, vec![8]),
+            (0x103, 3, vec![5]),
+        ],
+    );
+    let loaded = tiff::load(Cursor::new(file)).unwrap();
+    assert_eq!(loaded["0"].data, data);
+}
+
+#[test]
+fn lzw_long_stream_saved() {
+    // enough codes to hit the table reset, followed by a long run
+    let data = (0..0x10000u32)
+        .map(|i| match i {
+            0..=0xBFFF => (i.wrapping_mul(2654435761) >> 29) as u8,
+            _ => 0x11,
+        })
+        .collect::<Vec<_>>();
+    let image = tiff::Image {
+        width: 0x100,
+        height: 0x100,
+        bpp: 8,
+        name: "noise",
+        data: &data,
+        palette: None,
+    };
+    let options = tiff::Options {
+        compression: tiff::Compression::Lzw,
+        big_tiff: false,
+    };
+    let mut file = Cursor::new(Vec::new());
+    tiff::save_with(&mut file, &[image], &options).unwrap();
+    let loaded = tiff::load(Cursor::new(file.into_inner())).unwrap();
+    assert_eq!(loaded["noise"].data, data);
+}
+
+#[test]
+fn compressed_strips() {
+    let (width, height) = (64u32, 48u32);
+    let data = (0..width * height)
+        .map(|i| if i % 300 < 200 { 0x11 } else { (i / 3) as u8 })
+        .collect::<Vec<_>>();
+    let rows_per_strip = 16;
+    let stride = width as usize * rows_per_strip;
+    let tags = |compression: u32| {
+        vec![
+            (0x100, 4, vec![width]),
+            (0x101, 4, vec![height]),
+            (0x102, 3, vec![8]),
+            (0x103, 3, vec![compression]),
+            (0x116, 4, vec![rows_per_strip as u32]),
+        ]
+    };
+
+    for &(compression, big_endian) in &[
+        (1, true),
+        (5, false),
+        (5, true),
+        (32773, false),
+        (32773, true),
+    ] {
+        let strips = data
+            .chunks(stride)
+            .map(|strip| match compression {
+                5 => lzw_encode(strip),
+                32773 => pack_bits(strip),
+                _ => strip.to_vec(),
+            })
+            .collect::<Vec<_>>();
+        let file = build(big_endian, &strips, &tags(compression));
+        let loaded = tiff::load(Cursor::new(file)).unwrap();
+        assert_eq!(loaded["0"].data, data, "compression {}", compression);
+    }
+}
+
+#[test]
+fn wide_samples() {
+    let values = [0x0102u16, 0xFFFE, 0x8000, 0x0001];
+    let raw = values
+        .iter()
+        .flat_map(|v| v.to_be_bytes())
+        .collect::<Vec<_>>();
+    let file = build(
+        true,
+        &[raw],
+        &[
+            (0x100, 4, vec![2]),
+            (0x101, 4, vec![2]),
+            (0x102, 3, vec![16]),
+            (0x106, 3, vec![1]),
+        ],
+    );
+    let loaded = tiff::load(Cursor::new(file)).unwrap();
+    let image = &loaded["0"];
+    assert_eq!(image.bpp, 16);
+    let expected = values
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect::<Vec<_>>();
+    assert_eq!(image.data, expected);
+}
+
+#[test]
+fn bad_input() {
+    assert!(matches!(
+        tiff::load(Cursor::new(b"PK\x03\x04".to_vec())),
+        Err(tiff::Error::BadHeader)
+    ));
+    let mut file = build(
+        false,
+        &[vec![0; 4]],
+        &[(0x100, 4, vec![2]), (0x101, 4, vec![2])],
+    );
+    // point the strip outside of the file
+    let len = file.len();
+    file.truncate(len - 40);
+    assert!(tiff::load(Cursor::new(file)).is_err());
+}
@@ -1,8 +1,6 @@
-use crate::config::text::Reader;
+use crate::config::text::{ErrorKind, ParseError, Reader};
 
-use serde_scan;
-
-use std::fs::File;
+use std::path::Path;
 
 #[derive(Debug, Deserialize)]
 pub struct Cycle {
@@ -20,25 +18,32 @@ pub struct Bunch {
     pub cycles: Vec<Cycle>,
 }
 
-pub fn load(file: File) -> Vec<Bunch> {
+pub fn load(path: &Path) -> Result<Vec<Bunch>, ParseError> {
     let mut bunches = Vec::new();
-    let mut fi = Reader::new(file);
-    fi.advance();
-    assert_eq!(fi.cur(), "uniVang-ParametersFile_Ver_1");
+    let mut fi = Reader::open(path)?;
+    fi.expect_line("uniVang-ParametersFile_Ver_1")?;
 
     while fi.advance() {
-        let (escave, bios, count): (String, String, usize) = fi.scan();
+        let (escave, bios, count): (String, String, usize) = fi.scan()?;
         let mut cycles = Vec::with_capacity(count);
         info!("Escave {} has {} cycles", escave, count);
         for _ in 0..count {
-            fi.advance();
+            fi.next_line()?;
             let cycle = {
                 let mut elems = fi.cur().split('"');
-                assert!(elems.next().unwrap().is_empty());
-                let name = elems.next().unwrap().to_string();
-                let leftover = elems.next().unwrap();
-                let (cirt_max, radiance_time, price, palette_path) =
-                    serde_scan::from_str(leftover).unwrap();
+                let (name, leftover) = match (elems.next(), elems.next(), elems.next()) {
+                    (Some(""), Some(name), Some(leftover)) => (name.to_string(), leftover),
+                    _ => {
+                        return Err(fi.error(
+                            1,
+                            ErrorKind::InvalidValue {
+                                value: fi.cur().to_string(),
+                                reason: "expected a quoted cycle name".to_string(),
+                            },
+                        ))
+                    }
+                };
+                let (cirt_max, radiance_time, price, palette_path) = fi.scan_part(leftover)?;
                 Cycle {
                     name,
                     cirt_max,
@@ -56,5 +61,5 @@ pub fn load(file: File) -> Vec<Bunch> {
             cycles,
         });
     }
-    bunches
+    Ok(bunches)
 }
@@ -1,24 +1,30 @@
 use crate::{
-    config::text::Reader, config::Settings, model, render::object::Context as ObjectContext,
+    config::text::{self, ErrorKind, ParseError, Reader},
+    config::Settings,
+    model,
+    render::object::Context as ObjectContext,
 };
 
 use wgpu;
 
-use std::{collections::HashMap, fs::File};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 pub type BoxSize = u8;
 pub type Price = u32;
 pub type Time = u16;
 pub type Shield = u16;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
 pub enum Kind {
     Main,
     Ruffa,
     Constructor,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize)]
 pub struct CarStats {
     pub class: u8,
     pub price_buy: Price,
@@ -39,6 +45,8 @@ pub struct CarStats {
 }
 
 impl CarStats {
+    const NUM_VALUES: usize = 19;
+
     fn new(d: &[u32]) -> Self {
         CarStats {
             class: d[0] as u8,
@@ -64,6 +72,30 @@ impl CarStats {
             max_teleport: d[18] as u8,
         }
     }
+
+    fn values(&self) -> [u32; Self::NUM_VALUES] {
+        [
+            self.class as u32,
+            self.price_buy,
+            self.price_sell,
+            self.size[0] as u32,
+            self.size[1] as u32,
+            self.size[2] as u32,
+            self.size[3] as u32,
+            self.max_speed as u32,
+            self.max_armor as u32,
+            self.shield_max as u32,
+            self.shield_regen as u32,
+            self.shield_drop as u32,
+            self.drop_time as u32,
+            self.max_fire as u32,
+            self.max_water as u32,
+            self.max_oxygen as u32,
+            self.max_fly as u32,
+            self.max_damage as u32,
+            self.max_teleport as u32,
+        ]
+    }
 }
 
 #[repr(u8)]
@@ -78,7 +110,7 @@ pub enum _Side {
 
 pub const NUM_SIDES: usize = 5;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct CarPhysics {
     pub name: String,
     // base
@@ -105,108 +137,266 @@ pub struct CarPhysics {
 }
 
 impl CarPhysics {
-    fn load(file: File) -> Self {
-        let mut fi = Reader::new(file);
-        fi.advance();
-        CarPhysics {
-            name: fi.cur().split_whitespace().nth(1).unwrap().to_owned(),
-            scale_size: fi.next_key_value("scale_size:"),
-            scale_bound: fi.next_key_value("scale_bound:"),
-            scale_box: fi.next_key_value("scale_box:"),
-            z_offset_of_mass_center: fi.next_key_value("z_offset_of_mass_center:"),
-            speed_factor: fi.next_key_value("speed_factor:"),
-            mobility_factor: fi.next_key_value("mobility_factor:"),
-            water_speed_factor: fi.next_key_value("water_speed_factor:"),
-            air_speed_factor: fi.next_key_value("air_speed_factor:"),
-            underground_speed_factor: fi.next_key_value("underground_speed_factor:"),
-            k_archimedean: fi.next_key_value("k_archimedean:"),
-            k_water_traction: fi.next_key_value("k_water_traction:"),
-            k_water_rudder: fi.next_key_value("k_water_rudder:"),
+    pub fn load(path: &Path) -> Result<Self, ParseError> {
+        let mut fi = Reader::open(path)?;
+        fi.next_line()?;
+        Ok(CarPhysics {
+            name: fi.value(1)?,
+            scale_size: fi.next_key_value("scale_size:")?,
+            scale_bound: fi.next_key_value("scale_bound:")?,
+            scale_box: fi.next_key_value("scale_box:")?,
+            z_offset_of_mass_center: fi.next_key_value("z_offset_of_mass_center:")?,
+            speed_factor: fi.next_key_value("speed_factor:")?,
+            mobility_factor: fi.next_key_value("mobility_factor:")?,
+            water_speed_factor: fi.next_key_value("water_speed_factor:")?,
+            air_speed_factor: fi.next_key_value("air_speed_factor:")?,
+            underground_speed_factor: fi.next_key_value("underground_speed_factor:")?,
+            k_archimedean: fi.next_key_value("k_archimedean:")?,
+            k_water_traction: fi.next_key_value("k_water_traction:")?,
+            k_water_rudder: fi.next_key_value("k_water_rudder:")?,
             terra_mover_sx: [
-                fi.next_key_value("TerraMoverSx:"),
-                fi.next_key_value("TerraMoverSy:"),
-                fi.next_key_value("TerraMoverSz:"),
+                fi.next_key_value("TerraMoverSx:")?,
+                fi.next_key_value("TerraMoverSy:")?,
+                fi.next_key_value("TerraMoverSz:")?,
             ],
             defence: [
-                fi.next_key_value("FrontDefense:"),
-                fi.next_key_value("BackDefense:"),
-                fi.next_key_value("SideDefense:"),
-                fi.next_key_value("UpperDefense:"),
-                fi.next_key_value("LowerDefense:"),
+                fi.next_key_value("FrontDefense:")?,
+                fi.next_key_value("BackDefense:")?,
+                fi.next_key_value("SideDefense:")?,
+                fi.next_key_value("UpperDefense:")?,
+                fi.next_key_value("LowerDefense:")?,
             ],
             ram_power: [
-                fi.next_key_value("FrontRamPower:"),
-                fi.next_key_value("BackRamPower:"),
-                fi.next_key_value("SideRamPower:"),
-                fi.next_key_value("UpperRamPower:"),
-                fi.next_key_value("LowerRamPower:"),
+                fi.next_key_value("FrontRamPower:")?,
+                fi.next_key_value("BackRamPower:")?,
+                fi.next_key_value("SideRamPower:")?,
+                fi.next_key_value("UpperRamPower:")?,
+                fi.next_key_value("LowerRamPower:")?,
             ],
+        })
+    }
+
+    /// Writes the parameters over the contents of an existing vehicle file.
+    pub fn save(&self, template: &[u8], file: &str) -> Result<Vec<u8>, ParseError> {
+        text::rewrite(template, file, |fo| {
+            fo.next_line()?;
+            fo.set_value(1, &self.name)?;
+            fo.next_key_value("scale_size:", &self.scale_size)?;
+            fo.next_key_value("scale_bound:", &self.scale_bound)?;
+            fo.next_key_value("scale_box:", &self.scale_box)?;
+            fo.next_key_value("z_offset_of_mass_center:", &self.z_offset_of_mass_center)?;
+            fo.next_key_value("speed_factor:", &self.speed_factor)?;
+            fo.next_key_value("mobility_factor:", &self.mobility_factor)?;
+            fo.next_key_value("water_speed_factor:", &self.water_speed_factor)?;
+            fo.next_key_value("air_speed_factor:", &self.air_speed_factor)?;
+            fo.next_key_value("underground_speed_factor:", &self.underground_speed_factor)?;
+            fo.next_key_value("k_archimedean:", &self.k_archimedean)?;
+            fo.next_key_value("k_water_traction:", &self.k_water_traction)?;
+            fo.next_key_value("k_water_rudder:", &self.k_water_rudder)?;
+            fo.next_key_value("TerraMoverSx:", &self.terra_mover_sx[0])?;
+            fo.next_key_value("TerraMoverSy:", &self.terra_mover_sx[1])?;
+            fo.next_key_value("TerraMoverSz:", &self.terra_mover_sx[2])?;
+            fo.next_key_value("FrontDefense:", &self.defence[0])?;
+            fo.next_key_value("BackDefense:", &self.defence[1])?;
+            fo.next_key_value("SideDefense:", &self.defence[2])?;
+            fo.next_key_value("UpperDefense:", &self.defence[3])?;
+            fo.next_key_value("LowerDefense:", &self.defence[4])?;
+            fo.next_key_value("FrontRamPower:", &self.ram_power[0])?;
+            fo.next_key_value("BackRamPower:", &self.ram_power[1])?;
+            fo.next_key_value("SideRamPower:", &self.ram_power[2])?;
+            fo.next_key_value("UpperRamPower:", &self.ram_power[3])?;
+            fo.next_key_value("LowerRamPower:", &self.ram_power[4])?;
+            Ok(())
+        })
+    }
+}
+
+/// Vehicle listed in `car.prm`.
+#[derive(Clone, Debug, Serialize)]
+pub struct CarEntry {
+    pub name: String,
+    pub kind: Kind,
+    pub stats: CarStats,
+}
+
+const CAR_KINDS: [Kind; 3] = [Kind::Main, Kind::Ruffa, Kind::Constructor];
+
+pub fn load_list(path: &Path) -> Result<Vec<CarEntry>, ParseError> {
+    read_list(Reader::open(path)?)
+}
+
+fn read_list(mut fi: Reader) -> Result<Vec<CarEntry>, ParseError> {
+    fi.expect_line("uniVang-ParametersFile_Ver_1")?;
+
+    let num_main: u8 = fi.next_value()?;
+    let num_ruffa: u8 = fi.next_value()?;
+    let num_const: u8 = fi.next_value()?;
+    info!(
+        "Reading {} main vehicles, {} ruffas, and {} constructors",
+        num_main, num_ruffa, num_const
+    );
+
+    let mut list = Vec::new();
+    for (&kind, count) in CAR_KINDS.iter().zip([num_main, num_ruffa, num_const]) {
+        for _ in 0..count {
+            let (name, data): (_, Vec<u32>) = fi.next_entry()?;
+            if data.len() < CarStats::NUM_VALUES {
+                return Err(fi.error(0, ErrorKind::MissingValue));
+            }
+            list.push(CarEntry {
+                name: name.to_string(),
+                kind,
+                stats: CarStats::new(&data),
+            });
         }
     }
+    Ok(list)
 }
 
+/// Writes the vehicle list over the contents of an existing `car.prm`.
+/// The file groups the vehicles by kind, so they are written in the order
+/// of `CAR_KINDS`, keeping the list order within each kind.
+///
+/// Only the stats can be changed: the list has to have the same vehicles
+/// of each kind, in the same order, as the file. Otherwise an error names
+/// the first vehicle that differs.
+pub fn save_list(list: &[CarEntry], template: &[u8], file: &str) -> Result<Vec<u8>, ParseError> {
+    let current = read_list(Reader::new(template, file)?)?;
+    for kind in CAR_KINDS {
+        let names = |entries: &[CarEntry]| {
+            entries
+                .iter()
+                .filter(|entry| entry.kind == kind)
+                .map(|entry| entry.name.clone())
+                .collect::<Vec<_>>()
+        };
+        let (new, old) = (names(list), names(&current));
+        let (value, reason) = if let Some(name) = new.iter().find(|n| !old.contains(n)) {
+            (name, "added, or moved from another kind")
+        } else if let Some(name) = old.iter().find(|n| !new.contains(n)) {
+            (name, "removed, or moved to another kind")
+        } else if let Some((name, _)) = new.iter().zip(&old).find(|(a, b)| a != b) {
+            (name, "moved within its kind")
+        } else {
+            continue;
+        };
+        return Err(ParseError {
+            file: file.to_string(),
+            line: 0,
+            column: 0,
+            kind: ErrorKind::InvalidValue {
+                value: value.clone(),
+                reason: format!(
+                    "{:?} vehicle is {}, only the stats of the listed vehicles can be changed",
+                    kind, reason
+                ),
+            },
+        });
+    }
+
+    text::rewrite(template, file, |fo| {
+        fo.expect_line("uniVang-ParametersFile_Ver_1")?;
+        for kind in CAR_KINDS {
+            let count = list.iter().filter(|entry| entry.kind == kind).count() as u8;
+            fo.next_value(&count)?;
+        }
+        for kind in CAR_KINDS {
+            for entry in list.iter().filter(|entry| entry.kind == kind) {
+                fo.next_entry(&entry.name, &entry.stats.values())?;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Name of the parameter file shared by the vehicles without their own.
+const DEFAULT_PHYSICS: &str = "default";
+
 #[derive(Clone)]
 pub struct CarInfo {
     pub kind: Kind,
     pub stats: CarStats,
     pub physics: CarPhysics,
+    /// Where the physics came from, either the vehicle file or the defaults.
+    pub physics_path: PathBuf,
     pub model: model::VisualModel,
     pub scale: f32,
 }
 
+impl CarInfo {
+    /// Replaces the physics, along with the scale, unless they are the defaults
+    /// that leave the scale to the model.
+    pub fn set_physics(&mut self, physics: CarPhysics) {
+        if self.physics_path.file_name() != Some(DEFAULT_PHYSICS.as_ref()) {
+            self.scale = physics.scale_size;
+        }
+        self.physics = physics;
+    }
+}
+
 pub fn load_registry(
     settings: &Settings,
     reg: &super::game::Registry,
     device: &wgpu::Device,
     object: &ObjectContext,
-) -> HashMap<String, CarInfo> {
+) -> Result<HashMap<String, CarInfo>, ParseError> {
     let mut map = HashMap::new();
-    let mut fi = Reader::new(settings.open_relative("car.prm"));
-    fi.advance();
-    assert_eq!(fi.cur(), "uniVang-ParametersFile_Ver_1");
+    let list_path = settings.data_path.join("car.prm");
 
-    let num_main: u8 = fi.next_value();
-    let num_ruffa: u8 = fi.next_value();
-    let num_const: u8 = fi.next_value();
-    info!(
-        "Reading {} main vehicles, {} ruffas, and {} constructors",
-        num_main, num_ruffa, num_const
-    );
-
-    for i in 0..num_main + num_ruffa + num_const {
-        let (name, data) = fi.next_entry();
-        let mi = &reg.model_infos[name];
+    for entry in load_list(&list_path)? {
+        let name = entry.name.as_str();
+        let mi = match reg.model_infos.get(name) {
+            Some(mi) => mi,
+            None => {
+                return Err(ParseError {
+                    file: list_path.display().to_string(),
+                    line: 0,
+                    column: 0,
+                    kind: ErrorKind::InvalidValue {
+                        value: entry.name.clone(),
+                        reason: "unknown model".to_string(),
+                    },
+                })
+            }
+        };
         let mut prm_path = settings.data_path.join(&mi.path).with_extension("prm");
         let is_default = !prm_path.exists();
         if is_default {
             warn!("Vehicle {} doesn't have parameters, using defaults", name);
-            prm_path.set_file_name("default");
+            prm_path.set_file_name(DEFAULT_PHYSICS);
         }
-        let physics = CarPhysics::load(File::open(prm_path).unwrap());
+        let physics = CarPhysics::load(&prm_path)?;
         let scale = if is_default {
             mi.scale
         } else {
             physics.scale_size
         };
         let file = settings.open_relative(&mi.path);
-        let model = model::load_m3d(file, device, object, settings.game.physics.shape_sampling);
+        let model = match model::load_m3d(
+            file,
+            device,
+            object,
+            settings.game.physics.shape_sampling,
+            &settings.render.lod,
+        ) {
+            Ok(model) => model,
+            Err(e) => {
+                error!("Vehicle {} model {:?} is invalid: {}", name, mi.path, e);
+                continue;
+            }
+        };
         map.insert(
             name.to_owned(),
             CarInfo {
-                kind: if i < num_main {
-                    Kind::Main
-                } else if i < num_main + num_ruffa {
-                    Kind::Ruffa
-                } else {
-                    Kind::Constructor
-                },
-                stats: CarStats::new(&data),
+                kind: entry.kind,
+                stats: entry.stats,
                 physics,
+                physics_path: prm_path,
                 model,
                 scale,
             },
         );
     }
 
-    map
+    Ok(map)
 }
@@ -1,14 +1,18 @@
-use crate::config::text::Reader;
+use crate::config::text::{self, ParseError, Reader, Writer};
 
-use std::fs::File;
+use std::path::Path;
 
 // see `src/runtime.h` for original defines
 pub const MAIN_LOOP_TIME: f32 = 0.05;
 
+const TRACTION_SCALE: f32 = 1.0 / 64.0;
+const PI_BITS: usize = 11;
+const ANGLE_SCALE: f32 = std::f32::consts::PI / (1 << PI_BITS) as f32;
+
 pub type Traction = f32;
 pub type Angle = f32;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize)]
 pub struct VelocityPair {
     pub v: f32, //linear
     pub w: f32, //angular
@@ -20,7 +24,7 @@ impl VelocityPair {
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize)]
 pub struct Nature {
     pub gravity: f32,
     pub density: f32,
@@ -30,7 +34,7 @@ pub struct Nature {
     pub movement_detection_threshold: u8,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize)]
 pub struct Impulse {
     pub elastic_restriction: f32,
     pub elastic_time_scale_factor: f32,
@@ -41,7 +45,7 @@ pub struct Impulse {
     pub k_friction: f32,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize)]
 pub struct Car {
     pub rudder_step: Angle,
     pub rudder_max: Angle,
@@ -50,7 +54,7 @@ pub struct Car {
     pub traction_decr: Traction,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize)]
 pub struct Global {
     pub speed_factor: f32,
     pub mobility_factor: f32,
@@ -61,7 +65,7 @@ pub struct Global {
     pub f_brake_max: f32,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize)]
 pub struct Helicopter {
     pub max_height: u16,
     pub height_incr: u16,
@@ -80,7 +84,7 @@ pub struct Helicopter {
     pub circle_dphi: u16,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize)]
 pub struct Drag {
     pub speed: VelocityPair,
     pub wheel_speed: f32,
@@ -99,13 +103,13 @@ pub struct Drag {
     pub abs_min: VelocityPair,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize)]
 pub struct Terrain {
     pub dz_max: f32,
     pub min_wall_delta: f32,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize)]
 pub struct Mole {
     pub k_elastic_mole: f32,
     pub k_mole: f32,
@@ -114,7 +118,7 @@ pub struct Mole {
     pub mole_submerging_fz: f32,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize)]
 pub struct Contact {
     pub k_elastic_wheel: f32,
     pub k_elastic_spring: f32,
@@ -130,7 +134,7 @@ pub struct Contact {
     pub k_friction_spring: f32,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize)]
 pub struct Force {
     pub f_spring_impulse: f32,
     pub k_spring_impulse: f32,
@@ -142,13 +146,13 @@ pub struct Force {
     pub side_impulse_duration: u8,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize)]
 pub struct Speed {
     pub standard_frame_rate: u32,
     pub speed_correction_tau: f32,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize)]
 pub struct Common {
     pub nature: Nature,
     pub impulse: Impulse,
@@ -163,149 +167,299 @@ pub struct Common {
     pub speed: Speed,
 }
 
-fn get_pair(reader: &mut Reader<File>, name: &str) -> VelocityPair {
+fn get_pair(reader: &mut Reader, name: &str) -> Result<VelocityPair, ParseError> {
     let sv = format!("V_{}:", name);
     let sw = format!("W_{}:", name);
-    VelocityPair {
-        v: reader.next_key_value(&sv),
-        w: reader.next_key_value(&sw),
-    }
+    Ok(VelocityPair {
+        v: reader.next_key_value(&sv)?,
+        w: reader.next_key_value(&sw)?,
+    })
 }
 
-pub fn load(file: File) -> Common {
-    let mut fi = Reader::new(file);
-    fi.advance();
-    assert_eq!(fi.cur(), "COMMON:\t\t2");
-    let traction_scale = 1.0 / 64.0;
-    let angle_scale = {
-        use std::f32::consts::PI;
-        const PI_BITS: usize = 11;
-        PI / (1 << PI_BITS) as f32
-    };
-    Common {
+fn put_pair(writer: &mut Writer<'_>, name: &str, pair: &VelocityPair) -> Result<(), ParseError> {
+    writer.next_key_value(&format!("V_{}:", name), &pair.v)?;
+    writer.next_key_value(&format!("W_{}:", name), &pair.w)
+}
+
+pub fn load(path: &Path) -> Result<Common, ParseError> {
+    let mut fi = Reader::open(path)?;
+    fi.expect_line("COMMON:\t\t2")?;
+    Ok(Common {
         nature: Nature {
-            gravity: fi.next_key_value("g:"),
-            density: fi.next_key_value("density:"),
-            time_delta0: fi.next_key_value("dt0:"),
-            scale_general: fi.next_key_value("scale_general:"),
-            num_calls_analysis: fi.next_key_value("num_calls_analysis:"),
+            gravity: fi.next_key_value("g:")?,
+            density: fi.next_key_value("density:")?,
+            time_delta0: fi.next_key_value("dt0:")?,
+            scale_general: fi.next_key_value("scale_general:")?,
+            num_calls_analysis: fi.next_key_value("num_calls_analysis:")?,
             movement_detection_threshold: {
-                let mdt = fi.next_key_value("movement_detection_threshould:");
-                fi.advance(); //num_skip_updates
-                fi.advance(); //wheel_analyze
-                fi.advance(); //analysis_off
+                let mdt = fi.next_key_value("movement_detection_threshould:")?;
+                fi.next_line()?; //num_skip_updates
+                fi.next_line()?; //wheel_analyze
+                fi.next_line()?; //analysis_off
                 mdt
             },
         },
         impulse: Impulse {
-            elastic_restriction: fi.next_key_value("elastic_restriction:"),
-            elastic_time_scale_factor: fi.next_key_value("elastic_time_scale_factor:"),
-            rolling_scale: fi.next_key_value("rolling_scale:"),
-            normal_threshold: fi.next_key_value("normal_threshould:"),
-            k_wheel: fi.next_key_value("k_wheel:"),
+            elastic_restriction: fi.next_key_value("elastic_restriction:")?,
+            elastic_time_scale_factor: fi.next_key_value("elastic_time_scale_factor:")?,
+            rolling_scale: fi.next_key_value("rolling_scale:")?,
+            normal_threshold: fi.next_key_value("normal_threshould:")?,
+            k_wheel: fi.next_key_value("k_wheel:")?,
             factors: [
-                fi.next_key_value("horizontal_impulse_factor:"),
-                fi.next_key_value("vertical_impulse_factor:"),
+                fi.next_key_value("horizontal_impulse_factor:")?,
+                fi.next_key_value("vertical_impulse_factor:")?,
             ],
-            k_friction: fi.next_key_value("k_friction_impulse:"),
+            k_friction: fi.next_key_value("k_friction_impulse:")?,
         },
         car: Car {
-            rudder_step: fi.next_key_value::<u16>("rudder_step:") as f32 * angle_scale,
-            rudder_max: fi.next_key_value::<u16>("rudder_max:") as f32 * angle_scale,
-            rudder_k_decr: fi.next_key_value("rudder_k_decr:"),
-            traction_incr: fi.next_key_value::<u16>("traction_increment:") as f32 * traction_scale,
-            traction_decr: fi.next_key_value::<u16>("traction_decrement:") as f32 * traction_scale,
+            rudder_step: fi.next_key_value::<u16>("rudder_step:")? as f32 * ANGLE_SCALE,
+            rudder_max: fi.next_key_value::<u16>("rudder_max:")? as f32 * ANGLE_SCALE,
+            rudder_k_decr: fi.next_key_value("rudder_k_decr:")?,
+            traction_incr: fi.next_key_value::<u16>("traction_increment:")? as f32 * TRACTION_SCALE,
+            traction_decr: fi.next_key_value::<u16>("traction_decrement:")? as f32 * TRACTION_SCALE,
         },
         global: Global {
-            speed_factor: fi.next_key_value("global_speed_factor:"),
-            mobility_factor: fi.next_key_value("global_mobility_factor:"),
-            water_speed_factor: fi.next_key_value("global_water_speed_factor:"),
-            air_speed_factor: fi.next_key_value("global_air_speed_factor:"),
-            underground_speed_factor: fi.next_key_value("global_underground_speed_factor:"),
-            k_traction_turbo: fi.next_key_value("k_traction_turbo:"),
-            f_brake_max: fi.next_key_value("f_brake_max:"),
+            speed_factor: fi.next_key_value("global_speed_factor:")?,
+            mobility_factor: fi.next_key_value("global_mobility_factor:")?,
+            water_speed_factor: fi.next_key_value("global_water_speed_factor:")?,
+            air_speed_factor: fi.next_key_value("global_air_speed_factor:")?,
+            underground_speed_factor: fi.next_key_value("global_underground_speed_factor:")?,
+            k_traction_turbo: fi.next_key_value("k_traction_turbo:")?,
+            f_brake_max: fi.next_key_value("f_brake_max:")?,
         },
         heli: Helicopter {
-            max_height: fi.next_key_value("max_helicopter_height:"),
-            height_incr: fi.next_key_value("helicopter_height_incr:"),
-            height_decr: fi.next_key_value("helicopter_height_decr:"),
-            k_thrust: fi.next_key_value("k_helicopter_thrust:"),
-            k_rotate: fi.next_key_value("k_helicopter_rotate:"),
-            k_strife: fi.next_key_value("k_helicopter_strife:"),
-            max_time: fi.next_key_value("max_helicopter_time:"),
+            max_height: fi.next_key_value("max_helicopter_height:")?,
+            height_incr: fi.next_key_value("helicopter_height_incr:")?,
+            height_decr: fi.next_key_value("helicopter_height_decr:")?,
+            k_thrust: fi.next_key_value("k_helicopter_thrust:")?,
+            k_rotate: fi.next_key_value("k_helicopter_rotate:")?,
+            k_strife: fi.next_key_value("k_helicopter_strife:")?,
+            max_time: fi.next_key_value("max_helicopter_time:")?,
             convert: [
-                fi.next_key_value("heli_x_convert:"),
-                fi.next_key_value("heli_y_convert:"),
+                fi.next_key_value("heli_x_convert:")?,
+                fi.next_key_value("heli_y_convert:")?,
             ],
-            rudder_decr: fi.next_key_value("heli_rudder_decr:"),
-            traction_decr: fi.next_key_value("heli_traction_decr:"),
-            z_offset: fi.next_key_value("heli_z_offset:"),
-            ampl: fi.next_key_value("helicopter_ampl:"),
-            dphi: fi.next_key_value("helicopter_dphi:"),
+            rudder_decr: fi.next_key_value("heli_rudder_decr:")?,
+            traction_decr: fi.next_key_value("heli_traction_decr:")?,
+            z_offset: fi.next_key_value("heli_z_offset:")?,
+            ampl: fi.next_key_value("helicopter_ampl:")?,
+            dphi: fi.next_key_value("helicopter_dphi:")?,
             circle_radius: [
-                fi.next_key_value("helicopter_circle_radius_x:"),
-                fi.next_key_value("helicopter_circle_radius_y:"),
+                fi.next_key_value("helicopter_circle_radius_x:")?,
+                fi.next_key_value("helicopter_circle_radius_y:")?,
             ],
-            circle_dphi: fi.next_key_value("helicopter_circle_dphi:"),
+            circle_dphi: fi.next_key_value("helicopter_circle_dphi:")?,
         },
         drag: Drag {
-            speed: get_pair(&mut fi, "drag_speed"),
-            wheel_speed: fi.next_key_value("V_drag_wheel_speed:"),
-            z: fi.next_key_value("V_drag_z:"),
-            free: get_pair(&mut fi, "drag_free"),
-            wheel: get_pair(&mut fi, "drag_wheel"),
-            spring: get_pair(&mut fi, "drag_spring"),
-            coll: get_pair(&mut fi, "drag_coll"),
-            helicopter: get_pair(&mut fi, "drag_helicopter"),
-            float: get_pair(&mut fi, "drag_float"),
-            friction: get_pair(&mut fi, "drag_friction"),
-            abs_stop: get_pair(&mut fi, "abs_stop"),
-            stuff: fi.next_key_value("V_drag_stuff:"),
-            swamp: fi.next_key_value("V_drag_swamp:"),
-            mole: fi.next_key_value("V_drag_mole:"),
-            abs_min: get_pair(&mut fi, "abs_min"),
+            speed: get_pair(&mut fi, "drag_speed")?,
+            wheel_speed: fi.next_key_value("V_drag_wheel_speed:")?,
+            z: fi.next_key_value("V_drag_z:")?,
+            free: get_pair(&mut fi, "drag_free")?,
+            wheel: get_pair(&mut fi, "drag_wheel")?,
+            spring: get_pair(&mut fi, "drag_spring")?,
+            coll: get_pair(&mut fi, "drag_coll")?,
+            helicopter: get_pair(&mut fi, "drag_helicopter")?,
+            float: get_pair(&mut fi, "drag_float")?,
+            friction: get_pair(&mut fi, "drag_friction")?,
+            abs_stop: get_pair(&mut fi, "abs_stop")?,
+            stuff: fi.next_key_value("V_drag_stuff:")?,
+            swamp: fi.next_key_value("V_drag_swamp:")?,
+            mole: fi.next_key_value("V_drag_mole:")?,
+            abs_min: get_pair(&mut fi, "abs_min")?,
         },
         terrain: Terrain {
-            dz_max: fi.next_key_value("dZ_max:"),
-            min_wall_delta: fi.next_key_value("MIN_WALL_DELTA:"),
+            dz_max: fi.next_key_value("dZ_max:")?,
+            min_wall_delta: fi.next_key_value("MIN_WALL_DELTA:")?,
         },
         mole: Mole {
-            k_elastic_mole: fi.next_key_value("k_elastic_mole:"),
-            k_mole: fi.next_key_value("K_mole:"),
-            k_mole_rudder: fi.next_key_value("k_mole_rudder:"),
-            mole_emerging_fz: fi.next_key_value("mole_emerging_fz:"),
-            mole_submerging_fz: fi.next_key_value("mole_submerging_fz:"),
+            k_elastic_mole: fi.next_key_value("k_elastic_mole:")?,
+            k_mole: fi.next_key_value("K_mole:")?,
+            k_mole_rudder: fi.next_key_value("k_mole_rudder:")?,
+            mole_emerging_fz: fi.next_key_value("mole_emerging_fz:")?,
+            mole_submerging_fz: fi.next_key_value("mole_submerging_fz:")?,
         },
         contact: Contact {
-            k_elastic_wheel: fi.next_key_value("k_elastic_wheel:"),
-            k_elastic_spring: fi.next_key_value("k_elastic_spring:"),
-            k_elastic_xy: fi.next_key_value("k_elastic_xy:"),
-            k_elastic_db_coll: fi.next_key_value("k_elastic_db_coll:"),
-            k_destroy_level: fi.next_key_value("k_destroy_level:"),
+            k_elastic_wheel: fi.next_key_value("k_elastic_wheel:")?,
+            k_elastic_spring: fi.next_key_value("k_elastic_spring:")?,
+            k_elastic_xy: fi.next_key_value("k_elastic_xy:")?,
+            k_elastic_db_coll: fi.next_key_value("k_elastic_db_coll:")?,
+            k_destroy_level: fi.next_key_value("k_destroy_level:")?,
             strong_ground_collision_threshold: fi
-                .next_key_value("strong_ground_collision_threshould:"),
+                .next_key_value("strong_ground_collision_threshould:")?,
             strong_double_collision_threshold: fi
-                .next_key_value("strong_double_collision_threshould:"),
-            k_friction_wheel_x: fi.next_key_value("k_friction_wheel_x:"),
-            k_friction_wheel_x_back: fi.next_key_value("k_friction_wheel_x_back:"),
-            k_friction_wheel_y: fi.next_key_value("k_friction_wheel_y:"),
-            k_friction_wheel_z: fi.next_key_value("k_friction_wheel_z:"),
-            k_friction_spring: fi.next_key_value("k_friction_spring:"),
+                .next_key_value("strong_double_collision_threshould:")?,
+            k_friction_wheel_x: fi.next_key_value("k_friction_wheel_x:")?,
+            k_friction_wheel_x_back: fi.next_key_value("k_friction_wheel_x_back:")?,
+            k_friction_wheel_y: fi.next_key_value("k_friction_wheel_y:")?,
+            k_friction_wheel_z: fi.next_key_value("k_friction_wheel_z:")?,
+            k_friction_spring: fi.next_key_value("k_friction_spring:")?,
         },
         force: Force {
-            f_spring_impulse: fi.next_key_value("f_spring_impulse:"),
-            k_spring_impulse: fi.next_key_value("K_spring_impulse:"),
-            f_traction_impulse: fi.next_key_value("f_traction_impulse:"),
-            k_distance_to_force: fi.next_key_value("k_distance_to_force:"),
-            explosion: get_pair(&mut fi, "explosion"),
-            max_jump_power: fi.next_key_value("max_jump_power:"),
-            side_impulse_delay: fi.next_key_value("side_impulse_delay:"),
-            side_impulse_duration: fi.next_key_value("side_impulse_duration:"),
+            f_spring_impulse: fi.next_key_value("f_spring_impulse:")?,
+            k_spring_impulse: fi.next_key_value("K_spring_impulse:")?,
+            f_traction_impulse: fi.next_key_value("f_traction_impulse:")?,
+            k_distance_to_force: fi.next_key_value("k_distance_to_force:")?,
+            explosion: get_pair(&mut fi, "explosion")?,
+            max_jump_power: fi.next_key_value("max_jump_power:")?,
+            side_impulse_delay: fi.next_key_value("side_impulse_delay:")?,
+            side_impulse_duration: fi.next_key_value("side_impulse_duration:")?,
         },
         //TODO: actually read from the config
         speed: Speed {
             standard_frame_rate: 14,
             speed_correction_tau: 1.6e-2,
         },
-    }
+    })
+}
+
+/// Writes the parameters over the contents of an existing `common.prm`.
+pub fn save(common: &Common, template: &[u8], file: &str) -> Result<Vec<u8>, ParseError> {
+    text::rewrite(template, file, |fo| {
+        fo.expect_line("COMMON:\t\t2")?;
+
+        // nature
+        fo.next_key_value("g:", &common.nature.gravity)?;
+        fo.next_key_value("density:", &common.nature.density)?;
+        fo.next_key_value("dt0:", &common.nature.time_delta0)?;
+        fo.next_key_value("scale_general:", &common.nature.scale_general)?;
+        fo.next_key_value("num_calls_analysis:", &common.nature.num_calls_analysis)?;
+        fo.next_key_value(
+            "movement_detection_threshould:",
+            &common.nature.movement_detection_threshold,
+        )?;
+        fo.next_line()?; //num_skip_updates
+        fo.next_line()?; //wheel_analyze
+        fo.next_line()?; //analysis_off
+
+        // impulse
+        fo.next_key_value("elastic_restriction:", &common.impulse.elastic_restriction)?;
+        fo.next_key_value(
+            "elastic_time_scale_factor:",
+            &common.impulse.elastic_time_scale_factor,
+        )?;
+        fo.next_key_value("rolling_scale:", &common.impulse.rolling_scale)?;
+        fo.next_key_value("normal_threshould:", &common.impulse.normal_threshold)?;
+        fo.next_key_value("k_wheel:", &common.impulse.k_wheel)?;
+        fo.next_key_value("horizontal_impulse_factor:", &common.impulse.factors[0])?;
+        fo.next_key_value("vertical_impulse_factor:", &common.impulse.factors[1])?;
+        fo.next_key_value("k_friction_impulse:", &common.impulse.k_friction)?;
+
+        // car
+        fo.next_key_value(
+            "rudder_step:",
+            &((common.car.rudder_step / ANGLE_SCALE).round() as u16),
+        )?;
+        fo.next_key_value(
+            "rudder_max:",
+            &((common.car.rudder_max / ANGLE_SCALE).round() as u16),
+        )?;
+        fo.next_key_value("rudder_k_decr:", &common.car.rudder_k_decr)?;
+        fo.next_key_value(
+            "traction_increment:",
+            &((common.car.traction_incr / TRACTION_SCALE).round() as u16),
+        )?;
+        fo.next_key_value(
+            "traction_decrement:",
+            &((common.car.traction_decr / TRACTION_SCALE).round() as u16),
+        )?;
+
+        // global
+        fo.next_key_value("global_speed_factor:", &common.global.speed_factor)?;
+        fo.next_key_value("global_mobility_factor:", &common.global.mobility_factor)?;
+        fo.next_key_value(
+            "global_water_speed_factor:",
+            &common.global.water_speed_factor,
+        )?;
+        fo.next_key_value("global_air_speed_factor:", &common.global.air_speed_factor)?;
+        fo.next_key_value(
+            "global_underground_speed_factor:",
+            &common.global.underground_speed_factor,
+        )?;
+        fo.next_key_value("k_traction_turbo:", &common.global.k_traction_turbo)?;
+        fo.next_key_value("f_brake_max:", &common.global.f_brake_max)?;
+
+        // heli
+        fo.next_key_value("max_helicopter_height:", &common.heli.max_height)?;
+        fo.next_key_value("helicopter_height_incr:", &common.heli.height_incr)?;
+        fo.next_key_value("helicopter_height_decr:", &common.heli.height_decr)?;
+        fo.next_key_value("k_helicopter_thrust:", &common.heli.k_thrust)?;
+        fo.next_key_value("k_helicopter_rotate:", &common.heli.k_rotate)?;
+        fo.next_key_value("k_helicopter_strife:", &common.heli.k_strife)?;
+        fo.next_key_value("max_helicopter_time:", &common.heli.max_time)?;
+        fo.next_key_value("heli_x_convert:", &common.heli.convert[0])?;
+        fo.next_key_value("heli_y_convert:", &common.heli.convert[1])?;
+        fo.next_key_value("heli_rudder_decr:", &common.heli.rudder_decr)?;
+        fo.next_key_value("heli_traction_decr:", &common.heli.traction_decr)?;
+        fo.next_key_value("heli_z_offset:", &common.heli.z_offset)?;
+        fo.next_key_value("helicopter_ampl:", &common.heli.ampl)?;
+        fo.next_key_value("helicopter_dphi:", &common.heli.dphi)?;
+        fo.next_key_value("helicopter_circle_radius_x:", &common.heli.circle_radius[0])?;
+        fo.next_key_value("helicopter_circle_radius_y:", &common.heli.circle_radius[1])?;
+        fo.next_key_value("helicopter_circle_dphi:", &common.heli.circle_dphi)?;
+
+        // drag
+        put_pair(fo, "drag_speed", &common.drag.speed)?;
+        fo.next_key_value("V_drag_wheel_speed:", &common.drag.wheel_speed)?;
+        fo.next_key_value("V_drag_z:", &common.drag.z)?;
+        put_pair(fo, "drag_free", &common.drag.free)?;
+        put_pair(fo, "drag_wheel", &common.drag.wheel)?;
+        put_pair(fo, "drag_spring", &common.drag.spring)?;
+        put_pair(fo, "drag_coll", &common.drag.coll)?;
+        put_pair(fo, "drag_helicopter", &common.drag.helicopter)?;
+        put_pair(fo, "drag_float", &common.drag.float)?;
+        put_pair(fo, "drag_friction", &common.drag.friction)?;
+        put_pair(fo, "abs_stop", &common.drag.abs_stop)?;
+        fo.next_key_value("V_drag_stuff:", &common.drag.stuff)?;
+        fo.next_key_value("V_drag_swamp:", &common.drag.swamp)?;
+        fo.next_key_value("V_drag_mole:", &common.drag.mole)?;
+        put_pair(fo, "abs_min", &common.drag.abs_min)?;
+
+        // terrain
+        fo.next_key_value("dZ_max:", &common.terrain.dz_max)?;
+        fo.next_key_value("MIN_WALL_DELTA:", &common.terrain.min_wall_delta)?;
+
+        // mole
+        fo.next_key_value("k_elastic_mole:", &common.mole.k_elastic_mole)?;
+        fo.next_key_value("K_mole:", &common.mole.k_mole)?;
+        fo.next_key_value("k_mole_rudder:", &common.mole.k_mole_rudder)?;
+        fo.next_key_value("mole_emerging_fz:", &common.mole.mole_emerging_fz)?;
+        fo.next_key_value("mole_submerging_fz:", &common.mole.mole_submerging_fz)?;
+
+        // contact
+        fo.next_key_value("k_elastic_wheel:", &common.contact.k_elastic_wheel)?;
+        fo.next_key_value("k_elastic_spring:", &common.contact.k_elastic_spring)?;
+        fo.next_key_value("k_elastic_xy:", &common.contact.k_elastic_xy)?;
+        fo.next_key_value("k_elastic_db_coll:", &common.contact.k_elastic_db_coll)?;
+        fo.next_key_value("k_destroy_level:", &common.contact.k_destroy_level)?;
+        fo.next_key_value(
+            "strong_ground_collision_threshould:",
+            &common.contact.strong_ground_collision_threshold,
+        )?;
+        fo.next_key_value(
+            "strong_double_collision_threshould:",
+            &common.contact.strong_double_collision_threshold,
+        )?;
+        fo.next_key_value("k_friction_wheel_x:", &common.contact.k_friction_wheel_x)?;
+        fo.next_key_value(
+            "k_friction_wheel_x_back:",
+            &common.contact.k_friction_wheel_x_back,
+        )?;
+        fo.next_key_value("k_friction_wheel_y:", &common.contact.k_friction_wheel_y)?;
+        fo.next_key_value("k_friction_wheel_z:", &common.contact.k_friction_wheel_z)?;
+        fo.next_key_value("k_friction_spring:", &common.contact.k_friction_spring)?;
+
+        // force
+        fo.next_key_value("f_spring_impulse:", &common.force.f_spring_impulse)?;
+        fo.next_key_value("K_spring_impulse:", &common.force.k_spring_impulse)?;
+        fo.next_key_value("f_traction_impulse:", &common.force.f_traction_impulse)?;
+        fo.next_key_value("k_distance_to_force:", &common.force.k_distance_to_force)?;
+        put_pair(fo, "explosion", &common.force.explosion)?;
+        fo.next_key_value("max_jump_power:", &common.force.max_jump_power)?;
+        fo.next_key_value("side_impulse_delay:", &common.force.side_impulse_delay)?;
+        fo.next_key_value(
+            "side_impulse_duration:",
+            &common.force.side_impulse_duration,
+        )?;
+        Ok(())
+    })
 }
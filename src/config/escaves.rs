@@ -1,6 +1,6 @@
-use crate::config::text::Reader;
+use crate::config::text::{ParseError, Reader};
 
-use std::fs::File;
+use std::path::Path;
 
 #[derive(Debug, Deserialize)]
 pub struct ItemSource {
@@ -17,22 +17,17 @@ pub struct Escave {
     pub need_items: Vec<ItemSource>,
 }
 
-pub fn load(file: File) -> Vec<Escave> {
+pub fn load(path: &Path) -> Result<Vec<Escave>, ParseError> {
     let mut escaves = Vec::new();
-    let mut fi = Reader::new(file);
-    fi.advance();
-    assert_eq!(fi.cur(), "uniVang-ParametersFile_Ver_1");
+    let mut fi = Reader::open(path)?;
+    fi.expect_line("uniVang-ParametersFile_Ver_1")?;
 
     while fi.advance() {
-        let (name, world, x, y, special_item): (String, String, i32, i32, String) = fi.scan();
+        let (name, world, x, y, special_item): (String, String, i32, i32, String) = fi.scan()?;
         info!("Escave {} in {} at {}x{}", name, world, x, y);
         let mut need_items = Vec::new();
-        while {
-            fi.advance();
-            fi.cur() != "none"
-        } {
-            let item = fi.scan();
-            need_items.push(item);
+        while fi.next_line()? != "none" {
+            need_items.push(fi.scan()?);
         }
         escaves.push(Escave {
             name,
@@ -42,5 +37,5 @@ pub fn load(file: File) -> Vec<Escave> {
             need_items,
         });
     }
-    escaves
+    Ok(escaves)
 }
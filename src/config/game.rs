@@ -1,4 +1,7 @@
-use crate::{config::text::Reader, config::Settings};
+use crate::config::{
+    text::{ErrorKind, ParseError, Reader},
+    Settings,
+};
 
 use std::collections::HashMap;
 
@@ -12,24 +15,32 @@ pub struct Registry {
 }
 
 impl Registry {
-    pub fn load(settings: &Settings) -> Registry {
+    pub fn load(settings: &Settings) -> Result<Registry, ParseError> {
         let mut reg = Registry {
             model_infos: HashMap::new(),
         };
-        let mut fi = Reader::new(settings.open_relative("game.lst"));
+        let mut fi = Reader::open(&settings.data_path.join("game.lst"))?;
 
         while !fi.cur().starts_with("NumModel") {
-            fi.advance();
+            fi.next_line()?;
         }
-        let count: u32 = fi.cur().split_whitespace().nth(1).unwrap().parse().unwrap();
-        let max_size: u8 = fi.next_key_value("MaxSize");
+        let count: u32 = fi.value(1)?;
+        let max_size: u8 = fi.next_key_value("MaxSize")?;
 
         for i in 0..count {
-            let num = fi.next_key_value("ModelNum");
-            assert_eq!(i, num);
-            let name: String = fi.next_key_value("Name");
-            let size: u8 = fi.next_key_value("Size");
-            let key: String = fi.next_key_value("NameID");
+            let num: u32 = fi.next_key_value("ModelNum")?;
+            if num != i {
+                return Err(fi.error(
+                    0,
+                    ErrorKind::InvalidValue {
+                        value: num.to_string(),
+                        reason: format!("expected model number {}", i),
+                    },
+                ));
+            }
+            let name: String = fi.next_key_value("Name")?;
+            let size: u8 = fi.next_key_value("Size")?;
+            let key: String = fi.next_key_value("NameID")?;
             reg.model_infos.insert(
                 key,
                 ModelInfo {
@@ -39,6 +50,6 @@ impl Registry {
             );
         }
 
-        reg
+        Ok(reg)
     }
 }
@@ -0,0 +1,596 @@
+//! Settings stacked from several layers, each overriding the previous ones:
+//! the template, the user files, the environment, and the command line.
+//!
+//! The layers are read with `ron` and kept as a JSON tree, because the RON values lose
+//! the enum names. The names are read where the layers below have an enum, starting
+//! with the template, which is parsed as its settings type.
+//! Unit variants become strings and the other variants become single-key objects,
+//! `None` is null and `Some(x)` is just `x`, which is what `serde_json` expects.
+
+use serde::{
+    de::{
+        DeserializeOwned, DeserializeSeed, Deserializer, EnumAccess, MapAccess, SeqAccess,
+        VariantAccess, Visitor,
+    },
+    Serialize,
+};
+use serde_json::{Map, Number, Value};
+
+use std::{
+    collections::BTreeMap,
+    error::Error as StdError,
+    fmt, fs, io, mem,
+    path::{Path, PathBuf},
+};
+
+/// Prefix of the environment variables that override the settings.
+/// The rest of the name is the key, with `__` separating the levels,
+/// e.g. `VANGERS_GAME__LEVEL` for `game.level`.
+pub const ENV_PREFIX: &str = "VANGERS_";
+const ENV_SEPARATOR: &str = "__";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    Template,
+    File(PathBuf),
+    Env(String),
+    CommandLine,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Source::Template => write!(f, "template"),
+            Source::File(ref path) => write!(f, "{}", path.display()),
+            Source::Env(ref name) => write!(f, "${}", name),
+            Source::CommandLine => write!(f, "--set"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Syntax {
+        source: Source,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    Assignment(String),
+    UnknownKey {
+        key: String,
+        source: Source,
+    },
+    Deserialize {
+        /// Dotted key of the setting that failed, empty if it's about the whole tree.
+        path: String,
+        error: serde_json::Error,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::Io {
+                ref path,
+                ref error,
+            } => write!(f, "{}: {}", path.display(), error),
+            Error::Syntax {
+                ref source,
+                line,
+                column,
+                ref message,
+            } => write!(f, "{}:{}:{}: {}", source, line, column, message),
+            Error::Assignment(ref text) => {
+                write!(f, "expected `key=value`, found `{}`", text)
+            }
+            Error::UnknownKey {
+                ref key,
+                ref source,
+            } => write!(f, "{}: unknown setting `{}`", source, key),
+            Error::Deserialize {
+                ref path,
+                ref error,
+            } if path.is_empty() => write!(f, "{}", error),
+            Error::Deserialize {
+                ref path,
+                ref error,
+            } => write!(f, "{}: {}", path, error),
+        }
+    }
+}
+
+impl StdError for Error {}
+
+/// Problem with a setting, found by the checks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Issue {
+    /// Dotted key of the setting, empty if it's about the whole tree.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+static NULL: Value = Value::Null;
+static UNIT: ron::Value = ron::Value::Unit;
+
+/// Reads a layer with `ron`, producing the JSON tree.
+/// `shape` is the same layer parsed into a `ron::Value` before, which tells if an enum variant
+/// has any contents, and `schema` is the tree parsed before, which tells where the enums are.
+#[derive(Clone, Copy)]
+struct Guided<'a> {
+    schema: &'a Value,
+    shape: &'a ron::Value,
+}
+
+impl<'a> Guided<'a> {
+    fn child(schema: Option<&'a Value>, shape: Option<&'a ron::Value>) -> Self {
+        Guided {
+            schema: schema.unwrap_or(&NULL),
+            shape: shape.unwrap_or(&UNIT),
+        }
+    }
+
+    /// Checks if the value starts with a name that has to be kept: an enum variant,
+    /// or a bare word in place of a string.
+    fn is_named(&self) -> bool {
+        match *self.shape {
+            ron::Value::Unit => self.schema.is_string() || is_variant(self.schema),
+            ron::Value::Map(_) | ron::Value::Seq(_) => is_variant(self.schema),
+            _ => false,
+        }
+    }
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for Guided<'a> {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        if self.is_named() {
+            deserializer.deserialize_enum("", &[], self)
+        } else {
+            deserializer.deserialize_any(self)
+        }
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for Guided<'a> {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a setting")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Value, E> {
+        Ok(Number::from_f64(v).map_or(Value::Null, Value::Number))
+    }
+
+    fn visit_char<E>(self, v: char) -> Result<Value, E> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Value, E> {
+        Ok(Value::String(v))
+    }
+
+    // unit and the empty structs
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Object(Map::new()))
+    }
+
+    fn visit_none<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        let shape = match *self.shape {
+            ron::Value::Option(Some(ref inner)) => Some(&**inner),
+            _ => None,
+        };
+        Guided::child(Some(self.schema), shape).deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut values = Vec::new();
+        loop {
+            let i = values.len();
+            let schema = match *self.schema {
+                Value::Array(ref s) => s.get(i).or_else(|| s.first()),
+                _ => None,
+            };
+            let shape = match *self.shape {
+                ron::Value::Seq(ref s) => s.get(i),
+                _ => None,
+            };
+            match seq.next_element_seed(Guided::child(schema, shape))? {
+                Some(value) => values.push(value),
+                None => return Ok(Value::Array(values)),
+            }
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Value, A::Error> {
+        let mut map = Map::new();
+        while let Some(key) = access.next_key::<Value>()? {
+            let key = match key {
+                Value::String(key) => key,
+                other => other.to_string(),
+            };
+            let shape = match *self.shape {
+                ron::Value::Map(ref m) => m.get(&ron::Value::String(key.clone())),
+                _ => None,
+            };
+            let value = access.next_value_seed(Guided::child(self.schema.get(&key), shape))?;
+            map.insert(key, value);
+        }
+        Ok(Value::Object(map))
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Value, A::Error> {
+        let (name, variant) = data.variant_seed(VariantName)?;
+        let schema = self.schema.get(&name);
+        let value = match *self.shape {
+            ron::Value::Unit => {
+                variant.unit_variant()?;
+                return Ok(Value::String(name));
+            }
+            // RON doesn't tell a newtype from a tuple of one element
+            ron::Value::Seq(ref s) if s.len() == 1 => {
+                variant.newtype_variant_seed(Guided::child(schema, s.first()))?
+            }
+            ron::Value::Seq(ref s) => {
+                variant.tuple_variant(s.len(), Guided::child(schema, Some(self.shape)))?
+            }
+            _ => variant.struct_variant(&[], Guided::child(schema, Some(self.shape)))?,
+        };
+        let mut map = Map::new();
+        map.insert(name, value);
+        Ok(Value::Object(map))
+    }
+}
+
+struct VariantName;
+
+impl<'de> DeserializeSeed<'de> for VariantName {
+    type Value = String;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<String, D::Error> {
+        deserializer.deserialize_identifier(self)
+    }
+}
+
+impl<'de> Visitor<'de> for VariantName {
+    type Value = String;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a variant name")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<String, E> {
+        Ok(v.to_string())
+    }
+}
+
+/// Parses a layer, using the tree of the layers below it to find the enums.
+/// The RON values lose the variant names, so the text is read twice:
+/// into a `ron::Value` to learn the shape, then into the JSON tree.
+fn parse(text: &str, schema: &Value, source: &Source) -> Result<Value, Error> {
+    let result = ron::de::from_str::<ron::Value>(text).and_then(|shape| {
+        let mut deserializer = ron::de::Deserializer::from_str(text)?;
+        let value = Guided {
+            schema,
+            shape: &shape,
+        }
+        .deserialize(&mut deserializer)?;
+        deserializer.end()?;
+        Ok(value)
+    });
+    result.map_err(|error| syntax_error(error, source))
+}
+
+fn syntax_error(error: ron::de::Error, source: &Source) -> Error {
+    let (line, column) = match error {
+        ron::de::Error::Parser(_, position) => (position.line, position.col),
+        _ => (0, 0),
+    };
+    let message = error.to_string();
+    let prefix = format!("{}:{}: ", line, column);
+    Error::Syntax {
+        source: source.clone(),
+        line,
+        column,
+        message: message
+            .strip_prefix(&prefix)
+            .unwrap_or(&message)
+            .to_string(),
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// Merges a layer into the base value, remembering the source of the changed leaves.
+fn merge(
+    base: &mut Value,
+    layer: Value,
+    path: &str,
+    source: &Source,
+    sources: &mut BTreeMap<String, Source>,
+) {
+    if let (Value::Object(base_map), Value::Object(layer_map)) = (&mut *base, &layer) {
+        // a single-key object that differs from the base one switches the enum variant
+        let switches_variant =
+            base_map.len() == 1 && layer_map.len() == 1 && layer_map.keys().ne(base_map.keys());
+        if !switches_variant && !layer_map.is_empty() {
+            if let Value::Object(layer_map) = layer {
+                for (key, value) in layer_map {
+                    let child = base_map.entry(key.clone()).or_insert(Value::Null);
+                    merge(child, value, &join(path, &key), source, sources);
+                }
+            }
+            return;
+        }
+    }
+
+    let prefix = format!("{}.", path);
+    sources.retain(|key, _| key != path && !key.starts_with(&prefix));
+    record_leaves(&layer, path, source, sources);
+    *base = layer;
+}
+
+fn record_leaves(
+    value: &Value,
+    path: &str,
+    source: &Source,
+    sources: &mut BTreeMap<String, Source>,
+) {
+    match *value {
+        Value::Object(ref map) if !map.is_empty() => {
+            for (key, child) in map {
+                record_leaves(child, &join(path, key), source, sources);
+            }
+        }
+        _ => {
+            sources.insert(path.to_string(), source.clone());
+        }
+    }
+}
+
+fn kind(value: &Value) -> &'static str {
+    match *value {
+        Value::Null => "nothing",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "a list",
+        Value::Object(_) => "a structure",
+    }
+}
+
+/// Checks if the value looks like an enum variant, which can be a string or an object.
+fn is_variant(value: &Value) -> bool {
+    let name = match *value {
+        Value::String(ref name) => name,
+        Value::Object(ref map) if map.len() == 1 => map.keys().next().unwrap(),
+        _ => return false,
+    };
+    name.starts_with(|c: char| c.is_ascii_uppercase())
+}
+
+fn check_kind(schema: &Value, value: &Value, path: &str, issues: &mut Vec<Issue>) {
+    match (schema, value) {
+        // optional values are `None` in the template, so there is nothing to compare with
+        (Value::Null, _) => {}
+        (Value::Object(s), Value::Object(v)) if !is_variant(schema) => {
+            for (key, child) in v {
+                let path = join(path, key);
+                match s.get(key) {
+                    Some(schema_child) => check_kind(schema_child, child, &path, issues),
+                    None => issues.push(Issue {
+                        path,
+                        message: "unknown setting".to_string(),
+                    }),
+                }
+            }
+        }
+        _ if is_variant(schema) && is_variant(value) => {}
+        (Value::Array(s), Value::Array(v)) => {
+            if let Some(first) = s.first() {
+                for (i, child) in v.iter().enumerate() {
+                    check_kind(first, child, &format!("{}[{}]", path, i), issues);
+                }
+            }
+        }
+        _ if mem::discriminant(schema) == mem::discriminant(value) => {}
+        _ => issues.push(Issue {
+            path: path.to_string(),
+            message: format!("expected {}, found `{}`", kind(schema), value),
+        }),
+    }
+}
+
+/// Settings tree with the source of each value.
+pub struct Layers {
+    root: Value,
+    sources: BTreeMap<String, Source>,
+}
+
+impl Layers {
+    /// Starts with the template, which is also read as `T` to learn where the enums are.
+    pub fn new<T: Serialize + DeserializeOwned>(template: &str) -> Result<Self, Error> {
+        let typed = ron::de::from_str::<T>(template)
+            .map_err(|error| syntax_error(error, &Source::Template))?;
+        let schema = serde_json::to_value(typed).map_err(|error| Error::Deserialize {
+            path: String::new(),
+            error,
+        })?;
+        let mut layers = Layers {
+            root: Value::Object(Map::new()),
+            sources: BTreeMap::new(),
+        };
+        let layer = parse(template, &schema, &Source::Template)?;
+        merge(
+            &mut layers.root,
+            layer,
+            "",
+            &Source::Template,
+            &mut layers.sources,
+        );
+        Ok(layers)
+    }
+
+    pub fn merge_str(&mut self, text: &str, source: Source) -> Result<(), Error> {
+        let layer = parse(text, &self.root, &source)?;
+        merge(&mut self.root, layer, "", &source, &mut self.sources);
+        Ok(())
+    }
+
+    pub fn merge_file(&mut self, path: &Path) -> Result<(), Error> {
+        let text = fs::read_to_string(path).map_err(|error| Error::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        self.merge_str(&text, Source::File(path.to_path_buf()))
+    }
+
+    /// Overrides a single setting, given by the dotted key.
+    /// The value is parsed as RON, or taken as a plain string if that fails.
+    pub fn set(&mut self, key: &str, value: &str, source: Source) -> Result<(), Error> {
+        let unknown = || Error::UnknownKey {
+            key: key.to_string(),
+            source: source.clone(),
+        };
+        let mut node = &mut self.root;
+        for name in key.split('.') {
+            if node.is_null() {
+                // filling an optional setting that was `None`
+                *node = Value::Object(Map::new());
+                node.as_object_mut()
+                    .unwrap()
+                    .insert(name.to_string(), Value::Null);
+            }
+            node = node
+                .as_object_mut()
+                .and_then(|map| map.get_mut(name))
+                .ok_or_else(unknown)?;
+        }
+        let value =
+            parse(value, node, &source).unwrap_or_else(|_| Value::String(value.to_string()));
+        merge(node, value, key, &source, &mut self.sources);
+        Ok(())
+    }
+
+    /// Applies a `key=value` assignment from the command line.
+    pub fn assign(&mut self, assignment: &str) -> Result<(), Error> {
+        match assignment.split_once('=') {
+            Some((key, value)) => self.set(key.trim(), value.trim(), Source::CommandLine),
+            None => Err(Error::Assignment(assignment.to_string())),
+        }
+    }
+
+    /// Applies the variables starting with `ENV_PREFIX`. The unknown ones are skipped.
+    pub fn merge_env(
+        &mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<(), Error> {
+        for (name, value) in vars {
+            let key = match name.strip_prefix(ENV_PREFIX) {
+                Some(rest) => rest
+                    .split(ENV_SEPARATOR)
+                    .map(|part| part.to_lowercase())
+                    .collect::<Vec<_>>()
+                    .join("."),
+                None => continue,
+            };
+            match self.set(&key, &value, Source::Env(name.clone())) {
+                Err(Error::UnknownKey { .. }) => {
+                    warn!("Ignoring ${}: unknown setting `{}`", name, key)
+                }
+                other => other?,
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        key.split('.')
+            .try_fold(&self.root, |node, name| node.as_object()?.get(name))
+    }
+
+    /// Returns the layer that the setting, or its part, came from.
+    pub fn source(&self, key: &str) -> Option<&Source> {
+        let prefix = format!("{}.", key);
+        self.sources.get(key).or_else(|| {
+            self.sources
+                .range(prefix.clone()..)
+                .next()
+                .filter(|(path, _)| path.starts_with(&prefix))
+                .map(|(_, source)| source)
+        })
+    }
+
+    /// Compares the tree with the template one, reporting the unknown settings
+    /// and the values that can't be of the right type.
+    pub fn check_schema(&self, template: &Layers) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        check_kind(&template.root, &self.root, "", &mut issues);
+        issues
+    }
+
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        serde_path_to_error::deserialize(&self.root).map_err(|e| {
+            let path = e.path().to_string();
+            Error::Deserialize {
+                // the root is shown as "."
+                path: if path == "." { String::new() } else { path },
+                error: e.into_inner(),
+            }
+        })
+    }
+}
+
+/// Lists the resolved settings, one value per line along with its source.
+impl fmt::Display for Layers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, source) in self.sources.iter() {
+            if let Some(value) = self.get(key) {
+                writeln!(f, "{} = {} // {}", key, value, source)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -3,8 +3,10 @@ pub mod car;
 pub mod common;
 pub mod escaves;
 pub mod game;
+pub mod layers;
 pub mod settings;
-mod text;
+pub mod text;
 pub mod worlds;
 
 pub use self::settings::Settings;
+pub use self::text::ParseError;
@@ -1,9 +1,15 @@
+use super::{
+    bunches, car,
+    game::Registry,
+    layers::{self, Issue, Layers},
+    worlds,
+};
 use crate::render::object::BodyColor;
 
 use std::fs::File;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct Car {
     pub id: String,
     pub color: BodyColor,
@@ -11,13 +17,13 @@ pub struct Car {
     pub pos: Option<(i32, i32)>,
 }
 
-#[derive(Copy, Clone, Deserialize)]
+#[derive(Copy, Clone, Deserialize, Serialize)]
 pub enum View {
     Flat,
     Perspective,
 }
 
-#[derive(Copy, Clone, Deserialize)]
+#[derive(Copy, Clone, Deserialize, Serialize)]
 pub struct Camera {
     pub angle: u8,
     pub height: f32,
@@ -26,33 +32,36 @@ pub struct Camera {
     pub depth_range: (f32, f32),
 }
 
-#[derive(Copy, Clone, Deserialize)]
+#[derive(Copy, Clone, Deserialize, Serialize)]
 pub enum SpawnAt {
     Player,
     Random,
 }
 
-#[derive(Copy, Clone, Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct Other {
     pub count: usize,
     pub spawn_at: SpawnAt,
+    /// Weapons of the NPC vangers, same as `Car::slots`.
+    #[serde(default)]
+    pub slots: Vec<String>,
 }
 
-#[derive(Copy, Clone, Deserialize)]
+#[derive(Copy, Clone, Deserialize, Serialize)]
 pub struct GpuCollision {
     pub max_objects: usize,
     pub max_polygons_total: usize,
     pub max_raster_size: (u32, u32),
 }
 
-#[derive(Copy, Clone, Deserialize)]
+#[derive(Copy, Clone, Deserialize, Serialize)]
 pub struct Physics {
     pub max_quant: f32,
     pub shape_sampling: u8,
     pub gpu_collision: Option<GpuCollision>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct Game {
     pub level: String,
     pub cycle: String,
@@ -62,14 +71,14 @@ pub struct Game {
     pub physics: Physics,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct Window {
     pub title: String,
     pub size: [u32; 2],
     pub reload_on_focus: bool,
 }
 
-#[derive(Copy, Clone, Deserialize)]
+#[derive(Copy, Clone, Deserialize, Serialize)]
 pub enum Backend {
     Auto,
     Metal,
@@ -92,7 +101,7 @@ impl Backend {
     }
 }
 
-#[derive(Copy, Clone, Default, Deserialize)]
+#[derive(Copy, Clone, Default, Deserialize, Serialize)]
 pub struct DebugRender {
     pub max_vertices: usize,
     pub collision_shapes: bool,
@@ -100,25 +109,25 @@ pub struct DebugRender {
     pub impulses: bool,
 }
 
-#[derive(Copy, Clone, Deserialize)]
+#[derive(Copy, Clone, Deserialize, Serialize)]
 pub enum ShadowTerrain {
     RayTraced,
 }
 
-#[derive(Copy, Clone, Deserialize)]
+#[derive(Copy, Clone, Deserialize, Serialize)]
 pub struct Shadow {
     pub size: u32,
     pub terrain: ShadowTerrain,
 }
 
-#[derive(Copy, Clone, Deserialize)]
+#[derive(Copy, Clone, Deserialize, Serialize)]
 pub struct Light {
     pub pos: [f32; 4],
     pub color: [f32; 4],
     pub shadow: Shadow,
 }
 
-#[derive(Copy, Clone, Deserialize)]
+#[derive(Copy, Clone, Deserialize, Serialize)]
 pub enum Terrain {
     RayTraced,
     RayMipTraced {
@@ -134,26 +143,55 @@ pub enum Terrain {
     },
 }
 
-#[derive(Copy, Clone, Deserialize)]
+#[derive(Copy, Clone, Deserialize, Serialize)]
 pub struct Water {}
 
-#[derive(Copy, Clone, Deserialize)]
+#[derive(Copy, Clone, Deserialize, Serialize)]
 pub struct Fog {
     pub color: [f32; 4],
     pub depth: f32,
 }
 
-#[derive(Clone, Deserialize)]
+/// Simplified meshes, used for the distant objects.
+#[derive(Copy, Clone, Deserialize, Serialize)]
+pub struct Lod {
+    /// Number of the simplified meshes per model, zero to disable.
+    pub levels: u8,
+    /// Part of the triangles kept at each level.
+    pub ratio: f32,
+    /// Distance, in the model radii, at which the first level is used.
+    /// It doubles with each following level.
+    pub distance: f32,
+    /// Largest deviation of the first level from the full mesh, in the model radii.
+    /// It doubles with each following level, as the distance does.
+    pub error: f32,
+}
+
+impl Default for Lod {
+    /// Disabled, for the settings that predate it.
+    fn default() -> Self {
+        Lod {
+            levels: 0,
+            ratio: 0.5,
+            distance: 20.0,
+            error: 0.02,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
 pub struct Render {
     pub wgpu_trace_path: String,
     pub light: Light,
     pub terrain: Terrain,
     pub water: Water,
     pub fog: Fog,
+    #[serde(default)]
+    pub lod: Lod,
     pub debug: DebugRender,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct Settings {
     pub data_path: PathBuf,
     pub car: Car,
@@ -163,35 +201,354 @@ pub struct Settings {
     pub render: Render,
 }
 
+/// Settings layered over the template and the user file.
+#[derive(Default)]
+pub struct Overrides {
+    /// Extra file, applied over the user one.
+    pub config: Option<PathBuf>,
+    /// Assignments like `game.level=Fostral`, applied last.
+    pub assignments: Vec<String>,
+}
+
 impl Settings {
-    pub fn load(path: &str) -> Self {
-        use std::io::Read;
-
-        const TEMPLATE: &str = "config/settings.template.ron";
-        const PATH: &str = "config/settings.ron";
-        let mut string = String::new();
-        File::open(path)
-            .unwrap_or_else(|e| panic!("Unable to open the settings file: {:?}.\nPlease copy '{}' to '{}' and adjust 'data_path'",
-                e, TEMPLATE, PATH))
-            .read_to_string(&mut string)
-            .unwrap();
-        let set: Settings = match ron::de::from_str(&string) {
+    pub const TEMPLATE: &'static str = include_str!("../../config/settings.template.ron");
+    const TEMPLATE_PATH: &'static str = "config/settings.template.ron";
+    const USER_PATH: &'static str = "config/settings.ron";
+
+    /// Stacks the layers: the template, the user file if there is one,
+    /// the overriding file, `VANGERS_*` environment variables, and the assignments.
+    pub fn resolve(overrides: &Overrides) -> Result<Layers, layers::Error> {
+        let mut layers = Layers::new::<Self>(Self::TEMPLATE)?;
+        let user_path = Path::new(Self::USER_PATH);
+        if user_path.exists() {
+            layers.merge_file(user_path)?;
+        } else {
+            warn!(
+                "{} is not found, using the defaults from {}",
+                Self::USER_PATH,
+                Self::TEMPLATE_PATH
+            );
+        }
+        if let Some(ref path) = overrides.config {
+            layers.merge_file(path)?;
+        }
+        layers.merge_env(std::env::vars())?;
+        for assignment in overrides.assignments.iter() {
+            layers.assign(assignment)?;
+        }
+        Ok(layers)
+    }
+
+    fn deserialize_layers(layers: &Layers) -> Result<Self, Vec<Issue>> {
+        layers.deserialize().map_err(|e| match e {
+            layers::Error::Deserialize { path, error } => vec![Issue {
+                path,
+                message: error.to_string(),
+            }],
+            other => vec![Issue {
+                path: String::new(),
+                message: other.to_string(),
+            }],
+        })
+    }
+
+    /// Builds the settings for a launch, failing only on the values that can't work.
+    /// The unknown settings and the missing game data are logged as warnings,
+    /// and the rest of the game data is left to the code that loads it.
+    pub fn from_layers(layers: &Layers) -> Result<Self, Vec<Issue>> {
+        let template = Layers::new::<Self>(Self::TEMPLATE).expect("Invalid settings template");
+        for issue in layers.check_schema(&template) {
+            warn!("Settings: {}", issue);
+        }
+        let set = Self::deserialize_layers(layers)?;
+        let issues = set.validate_values();
+        if !issues.is_empty() {
+            return Err(issues);
+        }
+        if let Some(issue) = set.check_data_path() {
+            warn!("Settings: {}", issue);
+        }
+        Ok(set)
+    }
+
+    /// Builds the settings, reporting all the problems with the tree and its meaning,
+    /// including the ones found in the game data.
+    pub fn check_layers(layers: &Layers) -> Result<Self, Vec<Issue>> {
+        let template = Layers::new::<Self>(Self::TEMPLATE).expect("Invalid settings template");
+        let issues = layers.check_schema(&template);
+        if !issues.is_empty() {
+            return Err(issues);
+        }
+        let set = Self::deserialize_layers(layers)?;
+        let issues = set.validate();
+        if issues.is_empty() {
+            Ok(set)
+        } else {
+            Err(issues)
+        }
+    }
+
+    pub fn load(overrides: &Overrides) -> Self {
+        let layers = Self::resolve(overrides)
+            .unwrap_or_else(|e| panic!("Unable to load the settings: {}", e));
+        match Self::from_layers(&layers) {
             Ok(set) => set,
-            Err(e) => panic!(
-                "Unable to parse settings RON: {:?}.\nPlease check if `{}` has changed and your local config needs to be adjusted.",
-                e,
-                TEMPLATE,
+            Err(issues) => panic!(
+                "Invalid settings, please check if `{}` has changed and your local config needs to be adjusted:\n{}",
+                Self::TEMPLATE_PATH,
+                issues
+                    .iter()
+                    .map(|issue| issue.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
             ),
+        }
+    }
+
+    /// Checks the settings against each other, the enabled features, and the game data.
+    pub fn validate(&self) -> Vec<Issue> {
+        let mut issues = self.validate_values();
+        issues.extend(self.validate_data());
+        issues
+    }
+
+    /// Checks the settings against each other and the enabled features.
+    pub fn validate_values(&self) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        let mut report = |path: &str, message: String| {
+            issues.push(Issue {
+                path: path.to_string(),
+                message,
+            })
         };
 
-        if !set.check_path("options.dat") {
-            panic!(
-                "Can't find the resources of the original Vangers game at {:?}, please check your `{}`",
-                set.data_path, PATH,
+        if self.window.size.contains(&0) {
+            report("window.size", "must not be zero".to_string());
+        }
+        let (near, far) = self.game.camera.depth_range;
+        if !(near > 0.0 && near < far) {
+            report(
+                "game.camera.depth_range",
+                format!("expected `0 < near < far`, found ({}, {})", near, far),
             );
         }
+        if self.game.physics.max_quant <= 0.0 {
+            report("game.physics.max_quant", "must be positive".to_string());
+        }
+        if let Some(ref gc) = self.game.physics.gpu_collision {
+            if !cfg!(feature = "glsl") {
+                report(
+                    "game.physics.gpu_collision",
+                    "needs the `glsl` feature, please set it to `None` or rebuild with `--features glsl`"
+                        .to_string(),
+                );
+            }
+            if gc.max_objects == 0 {
+                report(
+                    "game.physics.gpu_collision.max_objects",
+                    "must be positive".to_string(),
+                );
+            }
+            if gc.max_polygons_total == 0 {
+                report(
+                    "game.physics.gpu_collision.max_polygons_total",
+                    "must be positive".to_string(),
+                );
+            }
+            if gc.max_raster_size.0 == 0 || gc.max_raster_size.1 == 0 {
+                report(
+                    "game.physics.gpu_collision.max_raster_size",
+                    "must not be zero".to_string(),
+                );
+            }
+        }
+
+        match self.render.light.shadow.terrain {
+            ShadowTerrain::RayTraced if self.render.light.shadow.size == 0 => report(
+                "render.light.shadow.size",
+                "must be positive for the `RayTraced` shadows".to_string(),
+            ),
+            ShadowTerrain::RayTraced => {}
+        }
+        match self.render.terrain {
+            Terrain::RayMipTraced {
+                mip_count,
+                max_steps,
+                ..
+            } if mip_count == 0 || max_steps == 0 => report(
+                "render.terrain",
+                "`mip_count` and `max_steps` of `RayMipTraced` must be positive".to_string(),
+            ),
+            Terrain::Scattered { density } if density.contains(&0) => report(
+                "render.terrain",
+                "`density` of `Scattered` must not be zero".to_string(),
+            ),
+            _ => {}
+        }
+        let lod = &self.render.lod;
+        if lod.levels != 0 {
+            if !(lod.ratio > 0.0 && lod.ratio < 1.0) {
+                report(
+                    "render.lod.ratio",
+                    format!("expected a part between 0 and 1, found {}", lod.ratio),
+                );
+            }
+            if lod.distance <= 0.0 {
+                report("render.lod.distance", "must be positive".to_string());
+            }
+            if lod.error <= 0.0 {
+                report("render.lod.error", "must be positive".to_string());
+            }
+        }
+
+        issues
+    }
+
+    fn check_data_path(&self) -> Option<Issue> {
+        if self.check_path("options.dat") {
+            None
+        } else {
+            Some(Issue {
+                path: "data_path".to_string(),
+                message: format!(
+                    "can't find the resources of the original Vangers game at {:?}",
+                    self.data_path
+                ),
+            })
+        }
+    }
+
+    /// Checks the settings against the game data, parsing the files they refer to.
+    pub fn validate_data(&self) -> Vec<Issue> {
+        if let Some(issue) = self.check_data_path() {
+            return vec![issue];
+        }
+        let mut issues = Vec::new();
+        let mut report = |path: &str, message: String| {
+            issues.push(Issue {
+                path: path.to_string(),
+                message,
+            })
+        };
 
-        set
+        if self.game.level.ends_with(".ini") {
+            let ini_path = self.data_path.join(&self.game.level);
+            if !ini_path.is_file() {
+                report(
+                    "game.level",
+                    format!("level file {} is not found", ini_path.display()),
+                );
+            }
+        } else if !self.game.level.is_empty() {
+            match worlds::load(&self.data_path.join("wrlds.dat")) {
+                Ok(worlds) if !worlds.contains_key(&self.game.level) => {
+                    let mut names = worlds.keys().collect::<Vec<_>>();
+                    names.sort();
+                    report(
+                        "game.level",
+                        format!(
+                            "unknown level `{}`, expected one of {:?}",
+                            self.game.level, names
+                        ),
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => report("data_path", e.to_string()),
+            }
+        }
+        if !self.game.cycle.is_empty() {
+            match bunches::load(&self.data_path.join("bunches.prm")) {
+                Ok(bunches) => {
+                    let mut names = bunches
+                        .iter()
+                        .flat_map(|bunch| bunch.cycles.iter().map(|cycle| cycle.name.as_str()))
+                        .collect::<Vec<_>>();
+                    if !names.contains(&self.game.cycle.as_str()) {
+                        names.sort_unstable();
+                        names.dedup();
+                        report(
+                            "game.cycle",
+                            format!(
+                                "unknown cycle `{}`, expected one of {:?}",
+                                self.game.cycle, names
+                            ),
+                        );
+                    }
+                }
+                Err(e) => report("data_path", e.to_string()),
+            }
+        }
+
+        let registry = match Registry::load(self) {
+            Ok(registry) => registry,
+            Err(e) => {
+                report("data_path", e.to_string());
+                return issues;
+            }
+        };
+        match car::load_list(&self.data_path.join("car.prm")) {
+            Ok(list) => {
+                let known = list.iter().any(|entry| entry.name == self.car.id)
+                    && registry.model_infos.contains_key(&self.car.id);
+                if !known {
+                    let names = list
+                        .iter()
+                        .map(|entry| entry.name.as_str())
+                        .filter(|name| registry.model_infos.contains_key(*name))
+                        .collect::<Vec<_>>();
+                    report(
+                        "car.id",
+                        format!(
+                            "unknown vehicle `{}`, expected one of {:?}",
+                            self.car.id, names
+                        ),
+                    );
+                }
+            }
+            Err(e) => report("data_path", e.to_string()),
+        }
+        for (path, slots) in [
+            ("car.slots", &self.car.slots),
+            ("game.other.slots", &self.game.other.slots),
+        ] {
+            for (i, name) in slots.iter().enumerate() {
+                if !registry.model_infos.contains_key(name) {
+                    report(
+                        &format!("{}[{}]", path, i),
+                        format!("unknown model `{}`", name),
+                    );
+                }
+            }
+        }
+
+        issues
+    }
+
+    /// Checks the settings against the capabilities of the adapter.
+    pub fn validate_adapter(&self, downlevel: &wgpu::DownlevelCapabilities) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        let compute = downlevel
+            .flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS);
+        if let Terrain::Scattered { .. } = self.render.terrain {
+            if !compute
+                || !downlevel
+                    .flags
+                    .contains(wgpu::DownlevelFlags::FRAGMENT_WRITABLE_STORAGE)
+            {
+                issues.push(Issue {
+                    path: "render.terrain".to_string(),
+                    message: "`Scattered` needs compute shaders and writable storage in fragment shaders, which the adapter doesn't support".to_string(),
+                });
+            }
+        }
+        if self.game.physics.gpu_collision.is_some() && !compute {
+            issues.push(Issue {
+                path: "game.physics.gpu_collision".to_string(),
+                message: "needs compute shaders, which the adapter doesn't support".to_string(),
+            });
+        }
+        issues
     }
 
     pub fn open_relative(&self, path: &str) -> File {
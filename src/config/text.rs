@@ -1,20 +1,180 @@
 use serde::Deserialize;
 use serde_scan;
 
-use std::fmt::Debug;
-use std::io::{BufRead, BufReader, Read};
-use std::str::FromStr;
+use std::{
+    error::Error as StdError,
+    fmt,
+    fs::File,
+    io::{self, Read},
+    ops::Range,
+    path::Path,
+    str::FromStr,
+};
 
-pub struct Reader<I> {
-    input: BufReader<I>,
+/// Upper halves of the code pages that the original data is written in.
+const CP866_HIGH: &str = "АБВГДЕЖЗИЙКЛМНОПРСТУФХЦЧШЩЪЫЬЭЮЯабвгдежзийклмноп\
+    ░▒▓│┤╡╢╖╕╣║╗╝╜╛┐└┴┬├─┼╞╟╚╔╩╦╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀\
+    рстуфхцчшщъыьэюяЁёЄєЇїЎў°∙·√№¤■\u{a0}";
+const CP1251_HIGH: &str = "ЂЃ‚ѓ„…†‡€‰Љ‹ЊЌЋЏђ‘’“”•–—\u{98}™љ›њќћџ\
+    \u{a0}ЎўЈ¤Ґ¦§Ё©Є«¬\u{ad}®Ї°±Ііґµ¶·ё№є»јЅѕї\
+    АБВГДЕЖЗИЙКЛМНОПРСТУФХЦЧШЩЪЫЬЭЮЯабвгдежзийклмнопрстуфхцчшщъыьэюя";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Cp866,
+    Cp1251,
+}
+
+impl Encoding {
+    /// Guesses the encoding of the text. The Russian comments are mostly
+    /// lowercase, which takes different ranges in the two code pages.
+    pub fn detect(data: &[u8]) -> Self {
+        if std::str::from_utf8(data).is_ok() {
+            return Encoding::Utf8;
+        }
+        let (mut cp866, mut cp1251) = (0, 0);
+        for &b in data {
+            match b {
+                0x80..=0xAF => cp866 += 1,
+                0xC0..=0xDF | 0xF0..=0xFF => cp1251 += 1,
+                _ => {}
+            }
+        }
+        if cp1251 > cp866 {
+            Encoding::Cp1251
+        } else {
+            Encoding::Cp866
+        }
+    }
+
+    pub fn encode(self, text: &str) -> Vec<u8> {
+        let table = match self {
+            Encoding::Utf8 => return text.as_bytes().to_vec(),
+            Encoding::Cp866 => CP866_HIGH,
+            Encoding::Cp1251 => CP1251_HIGH,
+        };
+        text.chars()
+            .map(|c| {
+                if c.is_ascii() {
+                    c as u8
+                } else {
+                    match table.chars().position(|t| t == c) {
+                        Some(index) => 0x80 + index as u8,
+                        None => b'?',
+                    }
+                }
+            })
+            .collect()
+    }
+
+    pub fn decode(self, data: &[u8]) -> String {
+        let table = match self {
+            Encoding::Utf8 => return String::from_utf8_lossy(data).into_owned(),
+            Encoding::Cp866 => CP866_HIGH,
+            Encoding::Cp1251 => CP1251_HIGH,
+        };
+        let high = table.chars().collect::<Vec<_>>();
+        data.iter()
+            .map(|&b| match b {
+                0..=0x7F => b as char,
+                _ => high[b as usize - 0x80],
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    Io(io::Error),
+    UnexpectedEnd,
+    UnexpectedLine { expected: String, found: String },
+    UnexpectedKey { expected: String, found: String },
+    MissingValue,
+    InvalidValue { value: String, reason: String },
+    Scan(String),
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ErrorKind::Io(ref e) => write!(f, "{}", e),
+            ErrorKind::UnexpectedEnd => write!(f, "unexpected end of file"),
+            ErrorKind::UnexpectedLine {
+                ref expected,
+                ref found,
+            } => write!(f, "expected `{}`, found `{}`", expected, found),
+            ErrorKind::UnexpectedKey {
+                ref expected,
+                ref found,
+            } => write!(f, "expected key `{}`, found `{}`", expected, found),
+            ErrorKind::MissingValue => write!(f, "missing value"),
+            ErrorKind::InvalidValue {
+                ref value,
+                ref reason,
+            } => write!(f, "invalid value `{}`: {}", value, reason),
+            ErrorKind::Scan(ref e) => write!(f, "unable to scan the line: {}", e),
+        }
+    }
+}
+
+/// Error in a text file, with the position that caused it.
+/// Lines and columns start from 1, and zero means the whole file or line.
+#[derive(Debug)]
+pub struct ParseError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub kind: ErrorKind,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file)?;
+        if self.line != 0 {
+            write!(f, ":{}", self.line)?;
+        }
+        if self.column != 0 {
+            write!(f, ":{}", self.column)?;
+        }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+impl StdError for ParseError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self.kind {
+            ErrorKind::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Walks over the lines of a text file, skipping the comments.
+/// Reading and rewriting share it, so that they see the same lines with data.
+pub struct Cursor<I> {
+    file: String,
+    lines: I,
     line: String,
+    line_number: usize,
+    /// All the passed lines, when rewriting the file.
+    output: Option<String>,
 }
 
-impl<I: Read> Reader<I> {
-    pub fn new(input: I) -> Self {
-        Reader {
-            input: BufReader::new(input),
-            line: String::new(),
+pub type Reader = Cursor<std::vec::IntoIter<String>>;
+
+impl<I> Cursor<I>
+where
+    I: Iterator,
+    I::Item: AsRef<str>,
+{
+    /// Makes an error at the given column of the current line.
+    pub fn error(&self, column: usize, kind: ErrorKind) -> ParseError {
+        ParseError {
+            file: self.file.clone(),
+            line: self.line_number,
+            column,
+            kind,
         }
     }
 
@@ -22,59 +182,286 @@ impl<I: Read> Reader<I> {
         self.line.trim_end()
     }
 
+    fn next_raw(&mut self) -> bool {
+        if let Some(ref mut output) = self.output {
+            output.push_str(&self.line);
+        }
+        self.line.clear();
+        match self.lines.next() {
+            Some(line) => {
+                self.line.push_str(line.as_ref());
+                self.line_number += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves to the next line with data, skipping the comments.
+    /// Returns `false` at the end of the file.
     pub fn advance(&mut self) -> bool {
-        loop {
-            self.line.clear();
-            match self.input.read_line(&mut self.line) {
-                Ok(0) => return false,
-                Ok(_) => (),
-                Err(_) => continue, // non-UTF8 (rus)
-            };
+        while self.next_raw() {
             if self.line.starts_with("/*") {
                 while !self.cur().ends_with("*/") {
-                    self.advance();
+                    if !self.next_raw() {
+                        return false;
+                    }
                 }
             } else if !self.cur().is_empty() && !self.line.starts_with("//") {
                 return true;
             }
         }
+        false
+    }
+
+    /// Moves to the next line with data, which has to be there.
+    pub fn next_line(&mut self) -> Result<&str, ParseError> {
+        if self.advance() {
+            Ok(self.cur())
+        } else {
+            Err(self.error(0, ErrorKind::UnexpectedEnd))
+        }
+    }
+
+    /// Checks that the next line with data is exactly the expected one.
+    pub fn expect_line(&mut self, expected: &str) -> Result<(), ParseError> {
+        if self.next_line()? == expected {
+            Ok(())
+        } else {
+            Err(self.error(
+                1,
+                ErrorKind::UnexpectedLine {
+                    expected: expected.to_string(),
+                    found: self.cur().to_string(),
+                },
+            ))
+        }
+    }
+}
+
+impl Reader {
+    /// Reads the whole input, naming it `file` in the errors.
+    pub fn new<I: Read>(mut input: I, file: impl Into<String>) -> Result<Self, ParseError> {
+        let file = file.into();
+        let mut data = Vec::new();
+        if let Err(e) = input.read_to_end(&mut data) {
+            return Err(ParseError {
+                file,
+                line: 0,
+                column: 0,
+                kind: ErrorKind::Io(e),
+            });
+        }
+        let text = Encoding::detect(&data).decode(&data);
+        Ok(Reader {
+            file,
+            lines: text
+                .lines()
+                .map(str::to_owned)
+                .collect::<Vec<_>>()
+                .into_iter(),
+            line: String::new(),
+            line_number: 0,
+            output: None,
+        })
+    }
+
+    pub fn open(path: &Path) -> Result<Self, ParseError> {
+        let name = path.display().to_string();
+        match File::open(path) {
+            Ok(file) => Self::new(file, name),
+            Err(e) => Err(ParseError {
+                file: name,
+                line: 0,
+                column: 0,
+                kind: ErrorKind::Io(e),
+            }),
+        }
+    }
+
+    /// Column of a token, which has to be a part of the current line.
+    fn column_of(&self, token: &str) -> usize {
+        let offset = token.as_ptr() as usize - self.line.as_ptr() as usize;
+        self.line[..offset].chars().count() + 1
+    }
+
+    fn end_column(&self) -> usize {
+        self.cur().chars().count() + 1
     }
 
-    pub fn next_value<T>(&mut self) -> T
+    fn parse_token<T>(&self, token: &str) -> Result<T, ParseError>
     where
         T: FromStr,
-        T::Err: Debug,
+        T::Err: fmt::Display,
     {
-        self.advance();
-        self.cur().parse().unwrap()
+        token.parse().map_err(|e: T::Err| {
+            self.error(
+                self.column_of(token),
+                ErrorKind::InvalidValue {
+                    value: token.to_string(),
+                    reason: e.to_string(),
+                },
+            )
+        })
     }
 
-    pub fn next_key_value<T>(&mut self, key: &str) -> T
+    /// Parses a whitespace-separated token of the current line.
+    pub fn value<T>(&self, index: usize) -> Result<T, ParseError>
     where
         T: FromStr,
-        T::Err: Debug,
+        T::Err: fmt::Display,
     {
-        self.advance();
-        let mut tokens = self.line.split_whitespace();
-        let name = tokens.next().unwrap();
-        assert_eq!(name, key);
-        tokens.next().unwrap().parse().unwrap()
+        match self.line.split_whitespace().nth(index) {
+            Some(token) => self.parse_token(token),
+            None => Err(self.error(self.end_column(), ErrorKind::MissingValue)),
+        }
     }
 
-    pub fn next_entry<T>(&mut self) -> (&str, Vec<T>)
+    pub fn next_value<T>(&mut self) -> Result<T, ParseError>
     where
         T: FromStr,
-        T::Err: Debug,
+        T::Err: fmt::Display,
     {
-        self.advance();
+        self.next_line()?;
+        self.parse_token(self.line.trim())
+    }
+
+    pub fn next_key_value<T>(&mut self, key: &str) -> Result<T, ParseError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        self.next_line()?;
+        let name = self.line.split_whitespace().next().unwrap_or_default();
+        if name != key {
+            return Err(self.error(
+                self.column_of(name),
+                ErrorKind::UnexpectedKey {
+                    expected: key.to_string(),
+                    found: name.to_string(),
+                },
+            ));
+        }
+        self.value(1)
+    }
+
+    pub fn next_entry<T>(&mut self) -> Result<(&str, Vec<T>), ParseError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        self.next_line()?;
         let mut tokens = self.line.split_whitespace();
-        let name = tokens.next().unwrap();
-        let data = tokens.map(|t| t.parse().unwrap()).collect();
-        (name, data)
+        let name = tokens.next().unwrap_or_default();
+        let data = tokens
+            .map(|t| self.parse_token(t))
+            .collect::<Result<_, _>>()?;
+        Ok((name, data))
+    }
+
+    /// Scans a part of the current line.
+    pub fn scan_part<'a, T: Deserialize<'a>>(&self, part: &'a str) -> Result<T, ParseError> {
+        serde_scan::from_str(part)
+            .map_err(|e| self.error(self.column_of(part), ErrorKind::Scan(format!("{:?}", e))))
+    }
+
+    pub fn scan<'a, T: Deserialize<'a>>(&'a self) -> Result<T, ParseError> {
+        self.scan_part(&self.line)
+    }
+}
+
+/// Writes the values over the text of an existing file,
+/// in the same order as `Reader` reads them.
+/// Everything else, including the comments, stays intact.
+pub type Writer<'a> = Cursor<std::str::SplitInclusive<'a, char>>;
+
+impl Writer<'_> {
+    fn token_range(&self, index: usize) -> Option<Range<usize>> {
+        let token = self.line.split_whitespace().nth(index)?;
+        let start = token.as_ptr() as usize - self.line.as_ptr() as usize;
+        Some(start..start + token.len())
     }
 
-    pub fn scan<'a, T: Deserialize<'a>>(&'a mut self) -> T {
-        serde_scan::from_str(&self.line)
-            .unwrap_or_else(|_| panic!("Unable to scan line: {}", self.line))
+    /// Replaces a whitespace-separated token of the current line,
+    /// unless it already holds the value.
+    pub fn set_value<T>(&mut self, index: usize, value: &T) -> Result<(), ParseError>
+    where
+        T: FromStr + fmt::Display + PartialEq,
+    {
+        let range = match self.token_range(index) {
+            Some(range) => range,
+            None => {
+                let column = self.cur().chars().count() + 1;
+                return Err(self.error(column, ErrorKind::MissingValue));
+            }
+        };
+        if self.line[range.clone()].parse::<T>().ok().as_ref() != Some(value) {
+            self.line.replace_range(range, &value.to_string());
+        }
+        Ok(())
     }
+
+    pub fn next_value<T>(&mut self, value: &T) -> Result<(), ParseError>
+    where
+        T: FromStr + fmt::Display + PartialEq,
+    {
+        self.next_line()?;
+        self.set_value(0, value)
+    }
+
+    fn check_key(&self, key: &str) -> Result<(), ParseError> {
+        let name = self.line.split_whitespace().next().unwrap_or_default();
+        if name == key {
+            Ok(())
+        } else {
+            Err(self.error(
+                1,
+                ErrorKind::UnexpectedKey {
+                    expected: key.to_string(),
+                    found: name.to_string(),
+                },
+            ))
+        }
+    }
+
+    pub fn next_key_value<T>(&mut self, key: &str, value: &T) -> Result<(), ParseError>
+    where
+        T: FromStr + fmt::Display + PartialEq,
+    {
+        self.next_line()?;
+        self.check_key(key)?;
+        self.set_value(1, value)
+    }
+
+    pub fn next_entry<T>(&mut self, name: &str, values: &[T]) -> Result<(), ParseError>
+    where
+        T: FromStr + fmt::Display + PartialEq,
+    {
+        self.next_line()?;
+        self.check_key(name)?;
+        for (i, value) in values.iter().enumerate() {
+            self.set_value(i + 1, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes new values over the contents of a file in the original encoding.
+pub fn rewrite(
+    data: &[u8],
+    file: &str,
+    fun: impl FnOnce(&mut Writer<'_>) -> Result<(), ParseError>,
+) -> Result<Vec<u8>, ParseError> {
+    let encoding = Encoding::detect(data);
+    let text = encoding.decode(data);
+    let mut writer = Writer {
+        file: file.to_string(),
+        lines: text.split_inclusive('\n'),
+        line: String::new(),
+        line_number: 0,
+        output: Some(String::with_capacity(text.len())),
+    };
+    fun(&mut writer)?;
+    while writer.next_raw() {}
+    Ok(encoding.encode(&writer.output.unwrap_or_default()))
 }
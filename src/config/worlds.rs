@@ -1,16 +1,16 @@
-use crate::config::text::Reader;
+use crate::config::text::{ParseError, Reader};
 
 use std::collections::HashMap;
-use std::fs::File;
+use std::path::Path;
 
 pub type Worlds = HashMap<String, String>;
 
-pub fn load(file: File) -> Worlds {
-    let mut fi = Reader::new(file);
-    let count = fi.next_value::<usize>();
+pub fn load(path: &Path) -> Result<Worlds, ParseError> {
+    let mut fi = Reader::open(path)?;
+    let count = fi.next_value::<usize>()?;
     (0..count)
         .map(|_| {
-            fi.advance();
+            fi.next_line()?;
             fi.scan()
         })
         .collect()
@@ -1,6 +1,11 @@
-use ini::Ini;
+use super::LevelError;
+
+use ini::{Ini, Properties};
+use std::fs;
+use std::io::{ErrorKind, Result as IoResult};
 use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 #[derive(Copy, Clone)]
 pub struct Power(pub i32);
@@ -32,61 +37,295 @@ pub struct LevelConfig {
     pub terrains: Box<[TerrainConfig]>,
 }
 
+struct Section<'a> {
+    name: &'static str,
+    props: &'a Properties,
+}
+
+impl<'a> Section<'a> {
+    fn new(ini: &'a Ini, name: &'static str) -> Result<Self, LevelError> {
+        match ini.section(Some(name)) {
+            Some(props) => Ok(Section { name, props }),
+            None => Err(LevelError::MissingSection(name)),
+        }
+    }
+
+    /// Returns the value without the inline comment, if any.
+    fn get(&self, key: &'static str) -> Result<&'a str, LevelError> {
+        match self.props.get(key) {
+            Some(value) => Ok(value.split([';', '#']).next().unwrap_or("").trim_end()),
+            None => Err(LevelError::MissingKey {
+                section: self.name,
+                key,
+            }),
+        }
+    }
+
+    fn parse<T: FromStr>(&self, key: &'static str) -> Result<T, LevelError> {
+        parse_value(key, self.get(key)?)
+    }
+
+    fn parse_list<T: FromStr>(&self, key: &'static str) -> Result<Vec<T>, LevelError> {
+        self.get(key)?
+            .split_whitespace()
+            .map(|value| parse_value(key, value))
+            .collect()
+    }
+}
+
+fn parse_value<T: FromStr>(key: &'static str, value: &str) -> Result<T, LevelError> {
+    value.trim().parse().map_err(|_| LevelError::BadNumber {
+        key,
+        value: value.to_string(),
+    })
+}
+
 impl LevelConfig {
     pub fn load(ini_path: &Path) -> Self {
-        let ini = Ini::load_from_file(ini_path).unwrap_or_else(|_| {
-            panic!("Unable to read the level's INI description: {:?}", ini_path)
-        });
-        let global = &ini["Global Parameters"];
-        let storage = &ini["Storage"];
-        let render = &ini["Rendering Parameters"];
-
-        let terra_count = render
-            .get("Terrain Max")
-            .map_or(8, |value| value.parse::<usize>().unwrap());
+        Self::try_load(ini_path).unwrap_or_else(|e| {
+            panic!(
+                "Unable to read the level's INI description {:?}: {}",
+                ini_path, e
+            )
+        })
+    }
+
+    pub fn try_load(ini_path: &Path) -> Result<Self, LevelError> {
+        let ini = Ini::load_from_file(ini_path).map_err(|error| LevelError::Ini {
+            path: ini_path.to_path_buf(),
+            error,
+        })?;
+        let global = Section::new(&ini, "Global Parameters")?;
+        let storage = Section::new(&ini, "Storage")?;
+        let render = Section::new(&ini, "Rendering Parameters")?;
+
+        let terra_count = match render.props.get("Terrain Max") {
+            Some(_) => render.parse::<usize>("Terrain Max")?,
+            None => 8,
+        };
         let mut terrains = (0..terra_count)
             .map(|_| TerrainConfig::default())
             .collect::<Box<[_]>>();
 
         for (t, val) in terrains
             .iter_mut()
-            .zip(render["Shadow Offsets"].split_whitespace())
+            .zip(render.parse_list("Shadow Offsets")?)
         {
-            t.shadow_offset = val.parse().unwrap();
+            t.shadow_offset = val;
         }
-        for (t, val) in terrains
-            .iter_mut()
-            .zip(render["Height Shifts"].split_whitespace())
-        {
-            t.height_shift = val.parse().unwrap();
+        for (t, val) in terrains.iter_mut().zip(render.parse_list("Height Shifts")?) {
+            t.height_shift = val;
         }
-        for (t, val) in terrains
-            .iter_mut()
-            .zip(render["Begin Colors"].split_whitespace())
-        {
-            t.colors.start = val.parse().unwrap();
+        for (t, val) in terrains.iter_mut().zip(render.parse_list("Begin Colors")?) {
+            t.colors.start = val;
         }
-        for (t, val) in terrains
-            .iter_mut()
-            .zip(render["End Colors"].split_whitespace())
-        {
-            t.colors.end = val.parse().unwrap();
+        for (t, val) in terrains.iter_mut().zip(render.parse_list("End Colors")?) {
+            t.colors.end = val;
         }
 
-        let path_data = ini_path.with_file_name(&storage["File Name"]);
-        LevelConfig {
+        let path_data = ini_path.with_file_name(storage.get("File Name")?);
+        Ok(LevelConfig {
             path_data,
-            path_palette: ini_path.with_file_name(&storage["Palette File"]),
-            is_compressed: &storage["Compressed Format Using"] != "0",
+            path_palette: ini_path.with_file_name(storage.get("Palette File")?),
+            is_compressed: storage.get("Compressed Format Using")? != "0",
             //name: self.game.level.clone(),
             size: (
-                Power(global["Map Power X"].parse().unwrap()),
-                Power(global["Map Power Y"].parse().unwrap()),
+                Power(global.parse("Map Power X")?),
+                Power(global.parse("Map Power Y")?),
             ),
-            geo: Power(global["GeoNet Power"].parse().unwrap()),
-            section: Power(global["Section Size Power"].parse().unwrap()),
-            min_square: Power(global["Minimal Square Power"].parse().unwrap()),
+            geo: Power(global.parse("GeoNet Power")?),
+            section: Power(global.parse("Section Size Power")?),
+            min_square: Power(global.parse("Minimal Square Power")?),
             terrains,
+        })
+    }
+}
+
+type IniEntries = Vec<(&'static str, Vec<(&'static str, String)>)>;
+
+fn relative_name(ini_path: &Path, path: &Path) -> String {
+    let dir = ini_path.parent().unwrap_or_else(|| Path::new(""));
+    path.strip_prefix(dir)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn join<T: ToString>(values: impl Iterator<Item = T>) -> String {
+    values.map(|v| v.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+impl LevelConfig {
+    fn ini_entries(&self, ini_path: &Path) -> IniEntries {
+        let render = vec![
+            ("Terrain Max", self.terrains.len().to_string()),
+            (
+                "Shadow Offsets",
+                join(self.terrains.iter().map(|t| t.shadow_offset)),
+            ),
+            (
+                "Height Shifts",
+                join(self.terrains.iter().map(|t| t.height_shift)),
+            ),
+            (
+                "Begin Colors",
+                join(self.terrains.iter().map(|t| t.colors.start)),
+            ),
+            (
+                "End Colors",
+                join(self.terrains.iter().map(|t| t.colors.end)),
+            ),
+        ];
+
+        vec![
+            (
+                "Global Parameters",
+                vec![
+                    ("Map Power X", self.size.0.as_power().to_string()),
+                    ("Map Power Y", self.size.1.as_power().to_string()),
+                    ("GeoNet Power", self.geo.as_power().to_string()),
+                    ("Section Size Power", self.section.as_power().to_string()),
+                    (
+                        "Minimal Square Power",
+                        self.min_square.as_power().to_string(),
+                    ),
+                ],
+            ),
+            (
+                "Storage",
+                vec![
+                    ("File Name", relative_name(ini_path, &self.path_data)),
+                    ("Palette File", relative_name(ini_path, &self.path_palette)),
+                    (
+                        "Compressed Format Using",
+                        (self.is_compressed as u8).to_string(),
+                    ),
+                ],
+            ),
+            ("Rendering Parameters", render),
+        ]
+    }
+
+    /// Writes the INI description, updating the existing file in place if there is one.
+    pub fn save(&self, ini_path: &Path) -> IoResult<()> {
+        let source = match fs::read(ini_path) {
+            Ok(source) => source,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        fs::write(ini_path, self.patch_ini(ini_path, &source))
+    }
+
+    /// Produces the INI contents based on the `source` one.
+    /// Comments, including the ones after the replaced values, unknown keys,
+    /// and line endings of the source are preserved, missing keys and sections are appended.
+    pub fn patch_ini(&self, ini_path: &Path, source: &[u8]) -> Vec<u8> {
+        let newline: &[u8] = if source.windows(2).any(|w| w == b"\r\n") {
+            b"\r\n"
+        } else {
+            b"\n"
+        };
+        let mut entries = self
+            .ini_entries(ini_path)
+            .into_iter()
+            .map(|(section, keys)| (section, keys.into_iter().map(Some).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        let mut output = Vec::with_capacity(source.len());
+        // section index and the output offset to insert missing keys at
+        let mut current: Option<(usize, usize)> = None;
+
+        let flush_section =
+            |output: &mut Vec<u8>, keys: &mut Vec<Option<(&str, String)>>, insert_at: usize| {
+                let mut missing = Vec::new();
+                for (key, value) in keys.drain(..).flatten() {
+                    missing.extend_from_slice(format!("{} = {}", key, value).as_bytes());
+                    missing.extend_from_slice(newline);
+                }
+                output.splice(insert_at..insert_at, missing);
+            };
+
+        let mut lines = source.split(|&b| b == b'\n').collect::<Vec<_>>();
+        if source.ends_with(b"\n") {
+            lines.pop();
+        }
+        for line in lines {
+            let text = String::from_utf8_lossy(line);
+            let trimmed = text.trim();
+            if trimmed.starts_with('[') {
+                if let Some((index, insert_at)) = current.take() {
+                    flush_section(&mut output, &mut entries[index].1, insert_at);
+                }
+                let name = trimmed.trim_start_matches('[').trim_end_matches(']').trim();
+                if let Some(index) = entries.iter().position(|&(section, _)| section == name) {
+                    output.extend_from_slice(line);
+                    output.push(b'\n');
+                    current = Some((index, output.len()));
+                    continue;
+                }
+            } else if let (Some((index, ref mut insert_at)), Some(eq)) =
+                (current.as_mut(), line.iter().position(|&b| b == b'='))
+            {
+                let key = String::from_utf8_lossy(&line[..eq]);
+                let found = entries[*index]
+                    .1
+                    .iter_mut()
+                    .find(|entry| entry.as_ref().map(|e| e.0) == Some(key.trim()));
+                if let Some(entry) = found {
+                    if !trimmed.starts_with(';') && !trimmed.starts_with('#') {
+                        let (_, value) = entry.take().unwrap();
+                        let spaces = line[eq + 1..]
+                            .iter()
+                            .take_while(|&&b| b == b' ' || b == b'\t')
+                            .count();
+                        output.extend_from_slice(&line[..eq + 1 + spaces]);
+                        output.extend_from_slice(value.as_bytes());
+                        let end = line.len() - line.ends_with(b"\r") as usize;
+                        let rest = &line[eq + 1..end];
+                        if let Some(comment) = rest.iter().position(|&b| b == b';' || b == b'#') {
+                            // keep the gap between the old value and its comment
+                            let gap = rest[..comment]
+                                .iter()
+                                .rev()
+                                .take_while(|&&b| b == b' ' || b == b'\t')
+                                .count();
+                            if gap == 0 {
+                                output.push(b' ');
+                            }
+                            output.extend_from_slice(&rest[comment - gap..]);
+                        }
+                        output.extend_from_slice(&line[end..]);
+                        output.push(b'\n');
+                        *insert_at = output.len();
+                        continue;
+                    }
+                }
+            }
+            output.extend_from_slice(line);
+            output.push(b'\n');
+            if let Some((_, ref mut insert_at)) = current {
+                // trailing comments are likely to describe the next section
+                if !trimmed.is_empty() && !trimmed.starts_with(';') && !trimmed.starts_with('#') {
+                    *insert_at = output.len();
+                }
+            }
+        }
+        if let Some((index, insert_at)) = current {
+            flush_section(&mut output, &mut entries[index].1, insert_at);
         }
+
+        for (section, keys) in entries.iter_mut() {
+            if keys.iter().all(Option::is_none) {
+                continue;
+            }
+            if !output.is_empty() {
+                output.extend_from_slice(newline);
+            }
+            output.extend_from_slice(format!("[{}]", section).as_bytes());
+            output.extend_from_slice(newline);
+            let insert_at = output.len();
+            flush_section(&mut output, keys, insert_at);
+        }
+
+        output
     }
 }
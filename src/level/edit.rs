@@ -0,0 +1,218 @@
+use super::{
+    read_texel, Altitude, Level, Point, TerrainBits, TerrainType, Texel, DELTA_MASK, DELTA_SHIFT0,
+    DELTA_SHIFT1, DOUBLE_LEVEL,
+};
+
+/// Rectangle of texels. May extend past the level bounds,
+/// in which case it's wrapped around by `DirtyTracker`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Region {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+}
+
+impl Region {
+    fn touches(&self, other: &Region) -> bool {
+        self.x <= other.x + other.w
+            && other.x <= self.x + self.w
+            && self.y <= other.y + other.h
+            && other.y <= self.y + self.h
+    }
+
+    fn union(&self, other: &Region) -> Region {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Region {
+            x,
+            y,
+            w: (self.x + self.w).max(other.x + other.w) - x,
+            h: (self.y + self.h).max(other.y + other.h) - y,
+        }
+    }
+}
+
+/// Accumulates modified areas of a level, merging the ones that touch.
+pub struct DirtyTracker {
+    size: (i32, i32),
+    regions: Vec<Region>,
+}
+
+impl DirtyTracker {
+    pub fn new(size: (i32, i32)) -> Self {
+        DirtyTracker {
+            size,
+            regions: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    pub fn add(&mut self, region: Region) {
+        if region.w <= 0 || region.h <= 0 {
+            return;
+        }
+        // split the region along the level edges
+        let x0 = region.x.rem_euclid(self.size.0);
+        let y0 = region.y.rem_euclid(self.size.1);
+        let w = region.w.min(self.size.0);
+        let h = region.h.min(self.size.1);
+        let w0 = w.min(self.size.0 - x0);
+        let h0 = h.min(self.size.1 - y0);
+        for &(x, w) in [(x0, w0), (0, w - w0)].iter() {
+            for &(y, h) in [(y0, h0), (0, h - h0)].iter() {
+                if w > 0 && h > 0 {
+                    self.add_wrapped(Region { x, y, w, h });
+                }
+            }
+        }
+    }
+
+    fn add_wrapped(&mut self, mut region: Region) {
+        while let Some(index) = self.regions.iter().position(|r| r.touches(&region)) {
+            region = region.union(&self.regions.swap_remove(index));
+        }
+        self.regions.push(region);
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = Region> + '_ {
+        self.regions.drain(..)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum BrushOp {
+    Raise(Altitude),
+    Lower(Altitude),
+    Flatten(Altitude),
+    Paint(TerrainType),
+}
+
+impl BrushOp {
+    fn apply(&self, point: &mut Point, floor: Altitude) {
+        match *self {
+            BrushOp::Raise(amount) => point.0 = point.0.saturating_add(amount),
+            BrushOp::Lower(amount) => point.0 = point.0.saturating_sub(amount).max(floor),
+            BrushOp::Flatten(altitude) => point.0 = altitude.max(floor),
+            BrushOp::Paint(terrain) => point.1 = terrain,
+        }
+    }
+}
+
+/// Circular brush, affecting the top layer of the texels within `radius`.
+#[derive(Copy, Clone, Debug)]
+pub struct Brush {
+    pub center: (i32, i32),
+    pub radius: i32,
+}
+
+/// Writes an element, preserving the meta bits that don't describe it.
+fn write_point(
+    height: &mut [u8],
+    meta: &mut [u8],
+    i: usize,
+    point: Point,
+    flags: u8,
+    bits: TerrainBits,
+) {
+    let keep = !(DOUBLE_LEVEL | (bits.mask << bits.shift) | DELTA_MASK);
+    debug_assert!(point.1 <= bits.mask, "Terrain {} is out of range", point.1);
+    height[i] = point.0;
+    meta[i] = (meta[i] & keep) | bits.write(point.1 & bits.mask) | flags;
+}
+
+/// Writes a texel at element `i`, see `Level::set`.
+pub(super) fn write_texel(
+    height: &mut [u8],
+    meta: &mut [u8],
+    i: usize,
+    texel: Texel,
+    bits: TerrainBits,
+) {
+    match texel {
+        Texel::Single(point) => {
+            if meta[i] & DOUBLE_LEVEL != 0 {
+                if let Texel::Dual { high, .. } = read_texel(height, meta, i, bits) {
+                    write_point(height, meta, i ^ 1, high, 0, bits);
+                }
+            }
+            write_point(height, meta, i, point, 0, bits);
+        }
+        Texel::Dual { low, high, delta } => {
+            let d0 = (delta >> DELTA_SHIFT0) & DELTA_MASK;
+            let d1 = (delta >> DELTA_SHIFT1) & DELTA_MASK;
+            let high = Point(high.0.max(low.0), high.1);
+            write_point(height, meta, i & !1, low, DOUBLE_LEVEL | d0, bits);
+            write_point(height, meta, i | 1, high, DOUBLE_LEVEL | d1, bits);
+        }
+    }
+}
+
+impl Level {
+    fn index(&self, coord: (i32, i32)) -> usize {
+        let x = coord.0.rem_euclid(self.size.0);
+        let y = coord.1.rem_euclid(self.size.1);
+        (y * self.size.0 + x) as usize
+    }
+
+    /// Writes a texel, keeping the pair of elements it shares consistent.
+    ///
+    /// Dual texels always cover the whole even/odd pair. Their delta is rounded
+    /// down to the representable precision, and the upper layer is never below the lower one.
+    /// Writing a single texel into a dual pair turns its partner into a single texel
+    /// with the altitude and terrain of the former upper layer.
+    pub fn set(&mut self, coord: (i32, i32), texel: Texel) {
+        let i = self.index(coord);
+        let bits = self.terrain_bits();
+        write_texel(&mut self.height, &mut self.meta, i, texel, bits);
+    }
+
+    /// Applies an operation to all the texels under the brush,
+    /// returning the region that needs to be refreshed.
+    pub fn apply_brush(&mut self, brush: &Brush, op: BrushOp) -> Region {
+        let r = brush.radius.max(0);
+        // align to the pairs of elements
+        let x0 = (brush.center.0 - r) & !1;
+        let x1 = (brush.center.0 + r) | 1;
+        let region = Region {
+            x: x0,
+            y: brush.center.1 - r,
+            w: x1 + 1 - x0,
+            h: 2 * r + 1,
+        };
+        let inside = |x: i32, y: i32| {
+            let (dx, dy) = (x - brush.center.0, y - brush.center.1);
+            dx * dx + dy * dy <= r * r
+        };
+
+        for y in region.y..region.y + region.h {
+            for x in (region.x..region.x + region.w).step_by(2) {
+                match self.get((x, y)) {
+                    Texel::Single(_) => {
+                        for x in (x..x + 2).filter(|&x| inside(x, y)) {
+                            if let Texel::Single(mut point) = self.get((x, y)) {
+                                op.apply(&mut point, 0);
+                                self.set((x, y), Texel::Single(point));
+                            }
+                        }
+                    }
+                    Texel::Dual {
+                        low,
+                        mut high,
+                        delta,
+                    } => {
+                        if inside(x, y) || inside(x + 1, y) {
+                            op.apply(&mut high, low.0);
+                            self.set((x, y), Texel::Dual { low, high, delta });
+                        }
+                    }
+                }
+            }
+        }
+
+        region
+    }
+}
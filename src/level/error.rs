@@ -0,0 +1,157 @@
+use super::Region;
+
+use std::{error::Error, fmt, io, ops::Range, path::PathBuf};
+
+#[derive(Debug)]
+pub enum LevelError {
+    /// A file can't be opened.
+    Open {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// Reading from an opened file failed, typically due to truncation.
+    Read {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// The palette data is truncated or unreadable.
+    Palette(io::Error),
+    /// The INI description can't be parsed.
+    Ini {
+        path: PathBuf,
+        error: ini::Error,
+    },
+    MissingSection(&'static str),
+    MissingKey {
+        section: &'static str,
+        key: &'static str,
+    },
+    BadNumber {
+        key: &'static str,
+        value: String,
+    },
+    /// The file size doesn't match the one derived from the level config.
+    SizeMismatch {
+        path: PathBuf,
+        expected: u64,
+        actual: u64,
+    },
+    /// A compressed row of the VMC can't be expanded.
+    CorruptSplay {
+        row: usize,
+        error: splay::Error,
+    },
+    /// The VPR file length doesn't match the level config.
+    BadVprLength {
+        expected: u64,
+        actual: u64,
+    },
+    /// The requested rows are outside of the level.
+    RowsOutOfBounds {
+        rows: Range<i32>,
+        height: i32,
+    },
+    /// The region splits the element pairs of dual texels.
+    UnalignedRegion(Region),
+    /// The crop region dimensions aren't powers of two.
+    RegionNotPowerOfTwo(Region),
+    /// There are no tiles to stitch.
+    NoTiles,
+    /// A row of tiles has a different length than the first one.
+    TileRowLength {
+        row: usize,
+        length: usize,
+        expected: usize,
+    },
+    /// A tile doesn't match the width of its column or the height of its row.
+    TileSize {
+        row: usize,
+        column: usize,
+        size: (i32, i32),
+        expected: (i32, i32),
+    },
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            LevelError::Open {
+                ref path,
+                ref error,
+            } => write!(f, "unable to open {:?}: {}", path, error),
+            LevelError::Read {
+                ref path,
+                ref error,
+            } => write!(f, "unable to read {:?}: {}", path, error),
+            LevelError::Palette(ref error) => write!(f, "unable to read the palette: {}", error),
+            LevelError::Ini {
+                ref path,
+                ref error,
+            } => write!(f, "unable to parse INI {:?}: {}", path, error),
+            LevelError::MissingSection(section) => write!(f, "missing INI section [{}]", section),
+            LevelError::MissingKey { section, key } => {
+                write!(f, "missing INI key '{}' in [{}]", key, section)
+            }
+            LevelError::BadNumber { key, ref value } => {
+                write!(f, "bad number '{}' for INI key '{}'", value, key)
+            }
+            LevelError::SizeMismatch {
+                ref path,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "file {:?} has size {}, expected {}",
+                path, actual, expected
+            ),
+            LevelError::CorruptSplay { row, ref error } => {
+                write!(f, "corrupt compressed row {}: {}", row, error)
+            }
+            LevelError::BadVprLength { expected, actual } => {
+                write!(f, "VPR file has length {}, expected {}", actual, expected)
+            }
+            LevelError::RowsOutOfBounds { ref rows, height } => {
+                write!(f, "rows {:?} are out of the level height {}", rows, height)
+            }
+            LevelError::UnalignedRegion(region) => {
+                write!(f, "region {:?} splits texel pairs", region)
+            }
+            LevelError::RegionNotPowerOfTwo(region) => {
+                write!(f, "region {:?} is not a power of two", region)
+            }
+            LevelError::NoTiles => write!(f, "no tiles to stitch"),
+            LevelError::TileRowLength {
+                row,
+                length,
+                expected,
+            } => write!(
+                f,
+                "tile row {} has {} tiles, expected {}",
+                row, length, expected
+            ),
+            LevelError::TileSize {
+                row,
+                column,
+                size,
+                expected,
+            } => write!(
+                f,
+                "tile {} of row {} has size {:?}, expected {:?}",
+                column, row, size, expected
+            ),
+        }
+    }
+}
+
+impl Error for LevelError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            LevelError::Open { ref error, .. }
+            | LevelError::Read { ref error, .. }
+            | LevelError::Palette(ref error) => Some(error),
+            LevelError::Ini { ref error, .. } => Some(error),
+            LevelError::CorruptSplay { ref error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
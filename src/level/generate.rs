@@ -0,0 +1,312 @@
+//! Seeded procedural levels, for testing without the original game data.
+
+use super::{
+    palette, vpr::Vpr, Altitude, Level, LevelConfig, LevelData, Point, Power, TerrainConfig,
+    TerrainType, Texel,
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use std::{fs, io::Result as IoResult, path::Path};
+
+const NUM_TERRAINS: u8 = 8;
+/// Palette entries per terrain, leaving the top 32 for the interface.
+const COLORS_PER_TERRAIN: u8 = 28;
+/// Base colors of the terrains in the 6-bit palette, water first.
+const TERRAIN_COLORS: [[u8; 3]; NUM_TERRAINS as usize] = [
+    [10, 24, 48],
+    [40, 34, 20],
+    [26, 40, 16],
+    [44, 40, 30],
+    [36, 22, 14],
+    [30, 30, 34],
+    [48, 46, 40],
+    [56, 56, 60],
+];
+
+pub struct GeneratorParams {
+    pub seed: u64,
+    pub size: (Power, Power),
+    /// Amplitude ratio between the consecutive octaves of the noise.
+    pub roughness: f32,
+    /// Everything below is flooded.
+    pub water_level: Altitude,
+    pub num_tunnels: usize,
+    pub num_overhangs: usize,
+    pub is_compressed: bool,
+}
+
+impl Default for GeneratorParams {
+    fn default() -> Self {
+        GeneratorParams {
+            seed: 0,
+            size: (Power(10), Power(10)),
+            roughness: 0.55,
+            water_level: 48,
+            num_tunnels: 8,
+            num_overhangs: 16,
+            is_compressed: false,
+        }
+    }
+}
+
+pub struct Generated {
+    pub data: LevelData,
+    pub config: LevelConfig,
+    /// Water altitude of each section.
+    pub flood_map: Box<[u8]>,
+    /// Raw 6-bit palette, as stored in the palette file.
+    pub palette: palette::Palette,
+}
+
+/// Wrapping value noise, summed over the octaves and normalized to [0, 1].
+fn fractal_noise(rng: &mut StdRng, size: (usize, usize), roughness: f32) -> Vec<f32> {
+    use rayon::prelude::*;
+
+    let mut values = vec![0f32; size.0 * size.1];
+    let mut cell = (size.0.min(size.1) / 4).max(1);
+    let mut amplitude = 1.0;
+    loop {
+        let lattice_size = (size.0 / cell, size.1 / cell);
+        let lattice = (0..lattice_size.0 * lattice_size.1)
+            .map(|_| rng.gen::<f32>())
+            .collect::<Vec<_>>();
+        let lattice_at = |x: usize, y: usize| {
+            lattice[(y % lattice_size.1) * lattice_size.0 + x % lattice_size.0]
+        };
+        values
+            .par_chunks_mut(size.0)
+            .enumerate()
+            .for_each(|(y, row)| {
+                let (gy, fy) = (y / cell, (y % cell) as f32 / cell as f32);
+                let ty = fy * fy * (3.0 - 2.0 * fy);
+                for (x, value) in row.iter_mut().enumerate() {
+                    let (gx, fx) = (x / cell, (x % cell) as f32 / cell as f32);
+                    let tx = fx * fx * (3.0 - 2.0 * fx);
+                    let top = lattice_at(gx, gy) * (1.0 - tx) + lattice_at(gx + 1, gy) * tx;
+                    let bottom =
+                        lattice_at(gx, gy + 1) * (1.0 - tx) + lattice_at(gx + 1, gy + 1) * tx;
+                    *value += amplitude * (top * (1.0 - ty) + bottom * ty);
+                }
+            });
+        if cell == 1 {
+            break;
+        }
+        cell /= 2;
+        amplitude *= roughness;
+    }
+
+    let (min, max) = values
+        .iter()
+        .fold((f32::MAX, f32::MIN), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+    let scale = if max > min { 1.0 / (max - min) } else { 0.0 };
+    for v in values.iter_mut() {
+        *v = (*v - min) * scale;
+    }
+    values
+}
+
+/// Terrain type of a texel, banded by altitude with water at the bottom.
+fn terrain_at(altitude: Altitude, water_level: Altitude) -> TerrainType {
+    if altitude <= water_level {
+        0
+    } else {
+        let bands = (NUM_TERRAINS - 1) as u32;
+        let above = (altitude - water_level) as u32;
+        1 + (above * bands / (0x100 - water_level as u32)).min(bands - 1) as TerrainType
+    }
+}
+
+fn new_palette(terrains: &[TerrainConfig]) -> palette::Palette {
+    let mut data = [[0; 4]; 0x100];
+    for (tc, base) in terrains.iter().zip(TERRAIN_COLORS.iter()) {
+        let count = (tc.colors.end - tc.colors.start) as u32;
+        for (i, color) in data[tc.colors.start as usize..=tc.colors.end as usize]
+            .iter_mut()
+            .enumerate()
+        {
+            // from a third of the base brightness up to the base color
+            let shade = count + 2 * i as u32;
+            for (c, &b) in color.iter_mut().zip(base.iter()) {
+                *c = (b as u32 * shade / (3 * count)).min(63) as u8;
+            }
+        }
+    }
+    for (i, color) in data[0xE0..].iter_mut().enumerate() {
+        let grey = (i * 2) as u8;
+        *color = [grey, grey, grey, 0];
+    }
+    data
+}
+
+impl Level {
+    /// Turns the texel pairs under a circle into dual texels,
+    /// with the layers provided by `layers` given the current altitude.
+    fn carve<F>(&mut self, center: (i32, i32), radius: i32, mut layers: F)
+    where
+        F: FnMut(Altitude) -> Option<(Altitude, Altitude, Altitude)>,
+    {
+        for y in center.1 - radius..=center.1 + radius {
+            for x in ((center.0 - radius) & !1..=center.0 + radius).step_by(2) {
+                let (dx, dy) = (x - center.0, y - center.1);
+                if dx * dx + dy * dy > radius * radius {
+                    continue;
+                }
+                let top = match self.get((x, y)) {
+                    Texel::Single(point) => point,
+                    Texel::Dual { .. } => continue,
+                };
+                if let Some((low, delta, high)) = layers(top.0) {
+                    self.set(
+                        (x, y),
+                        Texel::Dual {
+                            low: Point(low, top.1),
+                            high: Point(high, top.1),
+                            delta,
+                        },
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Generates a level, to be stored next to `ini_path`.
+pub fn generate(params: &GeneratorParams, ini_path: &Path) -> Generated {
+    let mut rng = StdRng::seed_from_u64(params.seed);
+    let size = (params.size.0.as_value(), params.size.1.as_value());
+    let terrains = (0..NUM_TERRAINS)
+        .map(|i| TerrainConfig {
+            shadow_offset: 0,
+            height_shift: 0,
+            colors: i * COLORS_PER_TERRAIN..(i + 1) * COLORS_PER_TERRAIN - 1,
+        })
+        .collect::<Box<[_]>>();
+    let config = LevelConfig {
+        path_palette: ini_path.with_extension("pal"),
+        path_data: ini_path.with_extension(""),
+        is_compressed: params.is_compressed,
+        size: params.size,
+        geo: Power(5),
+        section: Power(7),
+        min_square: Power(2),
+        terrains,
+    };
+
+    let noise = fractal_noise(
+        &mut rng,
+        (size.0 as usize, size.1 as usize),
+        params.roughness,
+    );
+    let water = params.water_level;
+    let mut level = Level {
+        size,
+        flood_map: vec![water; (size.1 >> config.section.as_power()) as usize].into_boxed_slice(),
+        flood_section_power: config.section.as_power() as usize,
+        height: noise
+            .iter()
+            .map(|&v| ((v * 255.0) as Altitude).max(water))
+            .collect(),
+        meta: vec![0; noise.len()].into_boxed_slice(),
+        palette: [[0; 4]; 0x100],
+        terrains: config.terrains.clone(),
+    };
+    let bits = level.terrain_bits();
+    for (meta, &altitude) in level.meta.iter_mut().zip(level.height.iter()) {
+        *meta = bits.write(terrain_at(altitude, water));
+    }
+
+    // tunnels meandering through the hills at a constant floor
+    for _ in 0..params.num_tunnels {
+        let mut pos = (
+            rng.gen_range(0..size.0) as f32,
+            rng.gen_range(0..size.1) as f32,
+        );
+        let mut angle = rng.gen_range(0.0..std::f32::consts::TAU);
+        let radius = rng.gen_range(3..7);
+        let floor = water.saturating_add(rng.gen_range(8..64));
+        let delta = 0x40;
+        for _ in 0..rng.gen_range(64..256) {
+            level.carve((pos.0 as i32, pos.1 as i32), radius, |top| {
+                if top as u32 >= floor as u32 + delta as u32 + 16 {
+                    Some((floor, delta, top))
+                } else {
+                    None
+                }
+            });
+            pos.0 += angle.cos();
+            pos.1 += angle.sin();
+            angle += rng.gen_range(-0.3..0.3);
+        }
+    }
+
+    // arches hanging over the ground
+    for _ in 0..params.num_overhangs {
+        let center = (rng.gen_range(0..size.0), rng.gen_range(0..size.1));
+        let radius = rng.gen_range(4..10);
+        let delta = rng.gen_range(4..12) * 8;
+        let thickness = rng.gen_range(16..48);
+        level.carve(center, radius, |top| {
+            let high = top as u32 + delta as u32 + thickness;
+            if top > water && high <= 0xFF {
+                Some((top, delta, high as Altitude))
+            } else {
+                None
+            }
+        });
+    }
+
+    let data = LevelData::from(level);
+    // The same flood map as the one saved in the VPR. It comes from the
+    // estimate in `vpr`, which isn't checked against the game, so a generated
+    // level floods the way the estimate says rather than the way the game would.
+    let flood_map = Vpr::from_level(&data, &config, None)
+        .flood
+        .iter()
+        .map(|&level| level as u8)
+        .collect();
+    Generated {
+        flood_map,
+        palette: new_palette(&config.terrains),
+        data,
+        config,
+    }
+}
+
+impl Generated {
+    /// Assembles a level ready for rendering.
+    pub fn to_level(&self) -> Level {
+        let mut palette = self.palette;
+        palette::prepare(&mut palette, &self.config.terrains);
+        palette::scale(&mut palette);
+        Level {
+            size: self.data.size,
+            flood_map: self.flood_map.clone(),
+            flood_section_power: self.config.section.as_power() as usize,
+            height: self.data.height.clone(),
+            meta: self.data.meta.clone(),
+            palette,
+            terrains: self.config.terrains.clone(),
+        }
+    }
+
+    /// Writes the level data, VPR, palette, and the INI description.
+    pub fn save(&self, ini_path: &Path) -> IoResult<()> {
+        let raw_palette = self
+            .palette
+            .iter()
+            .flat_map(|color| color[..3].iter().cloned())
+            .collect::<Vec<_>>();
+        fs::write(&self.config.path_palette, raw_palette)?;
+        if self.config.is_compressed {
+            self.data
+                .save_vmc(&self.config.path_data.with_extension("vmc"))?;
+        } else {
+            self.data
+                .save_vmp(&self.config.path_data.with_extension("vmp"));
+        }
+        Vpr::from_level(&self.data, &self.config, None)
+            .save(&self.config.path_data.with_extension("vpr"))?;
+        self.config.save(ini_path)
+    }
+}
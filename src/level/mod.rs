@@ -1,14 +1,25 @@
-use byteorder::{LittleEndian as E, ReadBytesExt, WriteBytesExt};
+use byteorder::{LittleEndian as E, WriteBytesExt};
 
 use std::{
     fs::File,
-    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    io::{BufReader, BufWriter, Error as IoError, ErrorKind, Read, Seek, SeekFrom, Write},
     path::Path,
 };
 
 mod config;
-
-pub use self::config::{LevelConfig, TerrainConfig};
+mod edit;
+mod error;
+pub mod generate;
+mod ops;
+pub mod palette;
+pub mod query;
+mod vmc;
+pub mod vpr;
+
+pub use self::config::{LevelConfig, Power, TerrainConfig};
+pub use self::edit::{Brush, BrushOp, DirtyTracker, Region};
+pub use self::error::LevelError;
+pub use self::vmc::VmcReader;
 
 pub type TerrainType = u8;
 
@@ -82,6 +93,22 @@ impl Texel {
     }
 }
 
+fn read_texel(height: &[u8], meta: &[u8], i: usize, bits: TerrainBits) -> Texel {
+    if meta[i] & DOUBLE_LEVEL != 0 {
+        let meta0 = meta[i & !1];
+        let meta1 = meta[i | 1];
+        let d0 = (meta0 & DELTA_MASK) << DELTA_SHIFT0;
+        let d1 = (meta1 & DELTA_MASK) << DELTA_SHIFT1;
+        Texel::Dual {
+            low: Point(height[i & !1], bits.read(meta0)),
+            high: Point(height[i | 1], bits.read(meta1)),
+            delta: d0 + d1,
+        }
+    } else {
+        Texel::Single(Point(height[i], bits.read(meta[i])))
+    }
+}
+
 impl Level {
     pub fn new_test() -> Self {
         let tc = TerrainConfig {
@@ -113,20 +140,7 @@ impl Level {
             coord.1 += self.size.1;
         }
         let i = ((coord.1 % self.size.1) * self.size.0 + (coord.0 % self.size.0)) as usize;
-        let meta = self.meta[i];
-        if meta & DOUBLE_LEVEL != 0 {
-            let meta0 = self.meta[i & !1];
-            let meta1 = self.meta[i | 1];
-            let d0 = (meta0 & DELTA_MASK) << DELTA_SHIFT0;
-            let d1 = (meta1 & DELTA_MASK) << DELTA_SHIFT1;
-            Texel::Dual {
-                low: Point(self.height[i & !1], bits.read(meta0)),
-                high: Point(self.height[i | 1], bits.read(meta1)),
-                delta: d0 + d1,
-            }
-        } else {
-            Texel::Single(Point(self.height[i], bits.read(meta)))
-        }
+        read_texel(&self.height, &self.meta, i, bits)
     }
 
     pub fn export(&self) -> Vec<u8> {
@@ -160,79 +174,42 @@ impl Level {
     }
 }
 
-#[allow(unused)]
-fn print_palette(data: &[[u8; 4]], info: &str) {
-    print!("Palette - {}:", info);
-    for i in 0..3 {
-        print!("\n\t");
-        for j in 0..0x100 {
-            print!("{:02X}", data[j][i]);
-        }
-    }
-    println!();
+fn open(path: &Path) -> Result<File, LevelError> {
+    File::open(path).map_err(|error| LevelError::Open {
+        path: path.to_path_buf(),
+        error,
+    })
 }
 
-pub fn read_palette(input: File, config: Option<&[TerrainConfig]>) -> [[u8; 4]; 0x100] {
-    let mut file = BufReader::new(input);
-    let mut data = [[0; 4]; 0x100];
-    for p in data.iter_mut() {
-        file.read_exact(&mut p[..3]).unwrap();
-        //p[0] <<= 2; p[1] <<= 2; p[2] <<= 2;
+fn read_error(path: &Path) -> impl Fn(IoError) -> LevelError + '_ {
+    move |error| LevelError::Read {
+        path: path.to_path_buf(),
+        error,
     }
-    //print_palette(&data, "read from file");
-    if let Some(terrains) = config {
-        // see `PalettePrepare` of the original
-        data[0] = [0; 4];
-
-        for tc in terrains {
-            for c in &mut data[tc.colors.start as usize][..3] {
-                *c >>= 1;
-            }
-        }
+}
 
-        for i in 0..16 {
-            let mut value = [(i * 4) as u8; 4];
-            value[3] = 0;
-            data[224 + i] = value;
-        }
+pub fn read_palette(input: File, config: Option<&[TerrainConfig]>) -> [[u8; 4]; 0x100] {
+    try_read_palette(input, config).unwrap()
+}
 
-        //print_palette(&data, "corrected");
-    }
-    // see `XGR_Screen::setpal` of the original
-    for p in data.iter_mut() {
-        p[0] <<= 2;
-        p[1] <<= 2;
-        p[2] <<= 2;
-    }
-    //print_palette(&data, "scale");
-    //TODO: there is quite a bit of logic missing here,
-    // see `GeneralTableOpen` and `PalettePrepare` of the original.
-    data
+pub fn try_read_palette(
+    input: File,
+    config: Option<&[TerrainConfig]>,
+) -> Result<[[u8; 4]; 0x100], LevelError> {
+    palette::load(BufReader::new(input), config)
 }
 
 pub fn load_flood(config: &LevelConfig) -> Box<[u8]> {
-    profiling::scope!("Flood Map");
-    let size = (config.size.0.as_value(), config.size.1.as_value());
-    let flood_size = size.1 >> config.section.as_power();
-
-    let vpr_file = match File::open(&config.path_data.with_extension("vpr")) {
-        Ok(file) => file,
-        Err(_) => return vec![0; flood_size as usize].into_boxed_slice(),
-    };
+    try_load_flood(config).unwrap()
+}
 
+pub fn try_load_flood(config: &LevelConfig) -> Result<Box<[u8]>, LevelError> {
+    profiling::scope!("Flood Map");
     info!("Loading flood map...");
-    let geo_pow = config.geo.as_power();
-    let net_size = (size.0 * size.1) >> (2 * geo_pow);
-    let flood_offset =
-        (2 * 4 + (1 + 4 + 4) * 4 + 2 * net_size + 2 * geo_pow * 4 + 2 * flood_size * geo_pow * 4)
-            as u64;
-    let expected_file_size = flood_offset + (flood_size * 4) as u64;
-    assert_eq!(vpr_file.metadata().unwrap().len(), expected_file_size,);
-    let mut vpr = BufReader::new(vpr_file);
-    vpr.seek(SeekFrom::Start(flood_offset)).unwrap();
-    (0..flood_size)
-        .map(|_| vpr.read_u32::<E>().unwrap() as u8)
-        .collect()
+    Ok(match vpr::Vpr::load_existing(config)? {
+        Some(vpr) => vpr.flood.iter().map(|&level| level as u8).collect(),
+        None => vec![0; vpr::Dimensions::new(config).num_sections].into_boxed_slice(),
+    })
 }
 
 pub struct LevelData {
@@ -256,6 +233,15 @@ fn avg(a: u8, b: u8) -> u8 {
 }
 
 impl LevelData {
+    pub fn new(size: (i32, i32)) -> Self {
+        let total = (size.0 * size.1) as usize;
+        LevelData {
+            height: vec![0u8; total].into_boxed_slice(),
+            meta: vec![0u8; total].into_boxed_slice(),
+            size,
+        }
+    }
+
     pub fn save_vmp(&self, path: &Path) {
         let mut vmp = BufWriter::new(File::create(path).unwrap());
         self.height
@@ -267,26 +253,54 @@ impl LevelData {
             });
     }
 
-    pub fn save_vmc(&self, path: &Path) {
-        use splay::Splay;
-        let mut vmc = BufWriter::new(File::create(path).unwrap());
+    /// Writes the compressed level. Fails if a row doesn't compress into
+    /// the 15 bits of size that the row table has for it.
+    pub fn save_vmc(&self, path: &Path) -> Result<(), IoError> {
+        use rayon::prelude::*;
+        use splay::{Compressor, Splay};
 
-        let base_offset = self.size.1 as u64 * (2 + 4) + Splay::tree_size();
-        for i in 0..self.size.1 {
-            vmc.write_i32::<E>(base_offset as i32 + i * self.size.0 * 2)
-                .unwrap();
-            vmc.write_i16::<E>(self.size.0 as i16 * 2).unwrap();
+        let rows = self
+            .height
+            .chunks(self.size.0 as _)
+            .zip(self.meta.chunks(self.size.0 as _))
+            .collect::<Vec<_>>();
+        let compressor = Compressor::new(rows.iter().cloned());
+        let compressed = rows
+            .par_iter()
+            .map(|&(h_row, m_row)| {
+                let mut data = Vec::new();
+                compressor.compress(h_row, m_row, &mut data);
+                data
+            })
+            .collect::<Vec<_>>();
+        if let Some((row, data)) = compressed
+            .iter()
+            .enumerate()
+            .find(|(_, data)| data.len() > i16::MAX as usize)
+        {
+            return Err(IoError::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "row {} compresses to {} bytes, more than a VMC row can hold",
+                    row,
+                    data.len()
+                ),
+            ));
         }
 
-        Splay::write_trivial(&mut vmc);
-        assert_eq!(vmc.seek(SeekFrom::Current(0)).unwrap(), base_offset);
+        let mut vmc = BufWriter::new(File::create(path)?);
+        let mut offset = self.size.1 as u64 * (2 + 4) + Splay::tree_size();
+        for data in compressed.iter() {
+            vmc.write_i32::<E>(offset as i32)?;
+            vmc.write_i16::<E>(data.len() as i16)?;
+            offset += data.len() as u64;
+        }
 
-        self.height
-            .chunks(self.size.0 as _)
-            .zip(self.meta.chunks(self.size.0 as _))
-            .for_each(|(h_row, m_row)| {
-                Splay::compress_trivial(h_row, m_row, &mut vmc);
-            });
+        compressor.splay().write(&mut vmc)?;
+        for data in compressed.iter() {
+            vmc.write_all(data)?;
+        }
+        Ok(())
     }
 
     pub fn import(data: &[u8], size: (i32, i32), terrain_shift: u8) -> Self {
@@ -328,61 +342,43 @@ impl LevelData {
 }
 
 pub fn load_vmc(path: &Path, size: (i32, i32)) -> LevelData {
-    use rayon::prelude::*;
-    use splay::Splay;
+    try_load_vmc(path, size).unwrap()
+}
 
+pub fn try_load_vmc(path: &Path, size: (i32, i32)) -> Result<LevelData, LevelError> {
     info!("Loading height map...");
-    let total = (size.0 * size.1) as usize;
-    let mut level = LevelData {
-        height: vec![0u8; total].into_boxed_slice(),
-        meta: vec![0u8; total].into_boxed_slice(),
-        size,
-    };
-
-    let (splay, st_table, sz_table) = {
-        profiling::scope!("Prepare");
-        let mut vmc_base = BufReader::new(File::open(path).expect("Unable to open VMC"));
-
-        info!("\tLoading compression tables...");
-        let mut st_table = Vec::<i32>::with_capacity(size.1 as usize);
-        let mut sz_table = Vec::<i16>::with_capacity(size.1 as usize);
-        for _ in 0..size.1 {
-            st_table.push(vmc_base.read_i32::<E>().unwrap());
-            sz_table.push(vmc_base.read_i16::<E>().unwrap());
-        }
-
-        info!("\tDecompressing level data...");
-        let splay = Splay::new(&mut vmc_base);
-        (splay, st_table, sz_table)
-    };
-
-    level
-        .height
-        .chunks_mut(size.0 as _)
-        .zip(level.meta.chunks_mut(size.0 as _))
-        .zip(st_table.iter().zip(&sz_table))
-        .collect::<Vec<_>>()
-        .par_chunks_mut(64)
-        .for_each(|source_group| {
-            //Note: a separate file per group is required
-            let mut vmc = File::open(path).unwrap();
-            let data_size: i16 = source_group
-                .iter()
-                .map(|(_, (_, &size))| size)
-                .max()
-                .unwrap();
-            let mut data = vec![0u8; data_size as usize];
-            for &mut ((ref mut h_row, ref mut m_row), (offset, &size)) in source_group {
-                vmc.seek(SeekFrom::Start(*offset as u64)).unwrap();
-                vmc.read_exact(&mut data[..size as usize]).unwrap();
-                splay.expand(&data[..size as usize], h_row, m_row);
-            }
-        });
+    let reader = VmcReader::open(path, size)?;
+    info!("\tDecompressing level data...");
+    reader.load_rows(0..size.1)
+}
 
-    level
+/// Loads a rectangle of the level, wrapping around the edges.
+/// Only the rows covered by the region are decompressed.
+pub fn try_load_region(config: &LevelConfig, region: Region) -> Result<LevelData, LevelError> {
+    let size = (config.size.0.as_value(), config.size.1.as_value());
+    if config.is_compressed {
+        VmcReader::open(&config.path_data.with_extension("vmc"), size)?.load_region(region)
+    } else {
+        let path = config.path_data.with_extension("vmp");
+        let mut vmp = open(&path)?;
+        let width = size.0 as usize;
+        let mut row = vec![0u8; width * 2];
+        vmc::crop_rows(size, region, |y, height, meta| {
+            vmp.seek(SeekFrom::Start((y * width * 2) as u64))
+                .map_err(read_error(&path))?;
+            vmp.read_exact(&mut row).map_err(read_error(&path))?;
+            height.copy_from_slice(&row[..width]);
+            meta.copy_from_slice(&row[width..]);
+            Ok(())
+        })
+    }
 }
 
 pub fn load_vmp(path: &Path, size: (i32, i32)) -> LevelData {
+    try_load_vmp(path, size).unwrap()
+}
+
+pub fn try_load_vmp(path: &Path, size: (i32, i32)) -> Result<LevelData, LevelError> {
     let total = (size.0 * size.1) as usize;
     let mut level = LevelData {
         height: vec![0u8; total].into_boxed_slice(),
@@ -390,40 +386,54 @@ pub fn load_vmp(path: &Path, size: (i32, i32)) -> LevelData {
         size,
     };
 
-    let mut vmp = BufReader::new(File::open(path).expect("Unable to open VMP"));
-    level
+    let file = open(path)?;
+    let file_size = file.metadata().map_err(read_error(path))?.len();
+    if file_size != total as u64 * 2 {
+        return Err(LevelError::SizeMismatch {
+            path: path.to_path_buf(),
+            expected: total as u64 * 2,
+            actual: file_size,
+        });
+    }
+
+    let mut vmp = BufReader::new(file);
+    for (h_row, m_row) in level
         .height
         .chunks_mut(size.0 as _)
         .zip(level.meta.chunks_mut(size.0 as _))
-        .for_each(|(h_row, m_row)| {
-            vmp.read_exact(h_row).unwrap();
-            vmp.read_exact(m_row).unwrap();
-        });
+    {
+        vmp.read_exact(h_row).map_err(read_error(path))?;
+        vmp.read_exact(m_row).map_err(read_error(path))?;
+    }
 
-    level
+    Ok(level)
 }
 
 pub fn load(config: &LevelConfig) -> Level {
+    try_load(config).unwrap_or_else(|e| panic!("Unable to load the level: {}", e))
+}
+
+pub fn try_load(config: &LevelConfig) -> Result<Level, LevelError> {
     profiling::scope!("Load Level");
     info!("Loading data map...");
     let size = (config.size.0.as_value(), config.size.1.as_value());
     let LevelData { height, meta, size } = if config.is_compressed {
-        load_vmc(&config.path_data.with_extension("vmc"), size)
+        try_load_vmc(&config.path_data.with_extension("vmc"), size)?
     } else {
-        load_vmp(&config.path_data.with_extension("vmp"), size)
+        try_load_vmp(&config.path_data.with_extension("vmp"), size)?
     };
 
     info!("Loading flood map...");
-    let flood_map = load_flood(config);
-    let palette = File::open(&config.path_palette).expect("Unable to open the palette file");
+    let flood_map = try_load_flood(config)?;
+    let palette = open(&config.path_palette)?;
 
-    Level {
+    Ok(Level {
         size,
         flood_map,
         flood_section_power: config.section.as_power() as usize,
         height,
         meta,
-        palette: read_palette(palette, Some(&config.terrains)),
+        palette: try_read_palette(palette, Some(&config.terrains))?,
         terrains: config.terrains.clone(),
-    }
+    })
 }
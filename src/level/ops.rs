@@ -0,0 +1,249 @@
+//! Whole-map operations on the level data: cropping, resampling, and stitching.
+//!
+//! All of them work on the pairs of elements, so that dual texels stay consistent.
+
+use super::{
+    edit::write_texel, read_texel, vmc::crop_rows, Altitude, LevelData, LevelError, Point, Region,
+    TerrainBits, TerrainType, Texel, DOUBLE_LEVEL,
+};
+
+fn average(values: impl Iterator<Item = u32>) -> Altitude {
+    let (sum, count) = values.fold((0, 0), |(sum, count), v| (sum + v, count + 1));
+    (sum / count.max(1)) as Altitude
+}
+
+/// Most frequent terrain type, preferring the lower types on ties.
+fn dominant(types: impl Iterator<Item = TerrainType>) -> TerrainType {
+    let mut counts = [0u32; 0x10];
+    for ty in types {
+        counts[ty as usize & 0xF] += 1;
+    }
+    let max = counts.iter().cloned().max().unwrap_or(0);
+    counts.iter().position(|&c| c == max).unwrap_or(0) as TerrainType
+}
+
+impl LevelData {
+    fn index(&self, x: i32, y: i32) -> usize {
+        (y.rem_euclid(self.size.1) * self.size.0 + x.rem_euclid(self.size.0)) as usize
+    }
+
+    fn texel(&self, x: i32, y: i32, bits: TerrainBits) -> Texel {
+        read_texel(&self.height, &self.meta, self.index(x, y), bits)
+    }
+
+    fn set_texel(&mut self, x: i32, y: i32, texel: Texel, bits: TerrainBits) {
+        let i = self.index(x, y);
+        write_texel(&mut self.height, &mut self.meta, i, texel, bits);
+    }
+
+    /// Cuts out a power-of-two sub-map, wrapping around the edges.
+    /// The horizontal origin has to be even to keep the texel pairs intact.
+    pub fn crop(&self, region: Region) -> Result<LevelData, LevelError> {
+        if !(region.w as u32).is_power_of_two() || !(region.h as u32).is_power_of_two() {
+            return Err(LevelError::RegionNotPowerOfTwo(region));
+        }
+        let width = self.size.0 as usize;
+        crop_rows(self.size, region, |y, h_row, m_row| {
+            h_row.copy_from_slice(&self.height[y * width..(y + 1) * width]);
+            m_row.copy_from_slice(&self.meta[y * width..(y + 1) * width]);
+            Ok(())
+        })
+    }
+
+    /// Shrinks the map by `1 << power` in both dimensions.
+    ///
+    /// A block of texels becomes dual if most of its pairs are dual,
+    /// with the layers averaged over them. Otherwise, the top altitudes are averaged.
+    /// The terrain is the dominant one of the block.
+    pub fn downsample(&self, power: u32, bits: TerrainBits) -> LevelData {
+        let factor = 1 << power;
+        let size = (self.size.0 / factor, self.size.1 / factor);
+        assert!(
+            size.0 >= 2 && size.1 >= 1,
+            "Level {:?} is too small to downsample by {}",
+            self.size,
+            factor
+        );
+        let mut result = LevelData::new(size);
+
+        for y in 0..size.1 {
+            let rows = y * factor..(y + 1) * factor;
+            for x in (0..size.0).step_by(2) {
+                let pairs = rows
+                    .clone()
+                    .flat_map(|sy| {
+                        (x * factor..(x + 2) * factor)
+                            .step_by(2)
+                            .map(move |sx| (sx, sy))
+                    })
+                    .map(|(sx, sy)| self.texel(sx, sy, bits))
+                    .collect::<Vec<_>>();
+                let duals = pairs
+                    .iter()
+                    .filter_map(|t| match *t {
+                        Texel::Dual { low, high, delta } => Some((low, high, delta)),
+                        Texel::Single(_) => None,
+                    })
+                    .collect::<Vec<_>>();
+
+                if duals.len() * 2 > pairs.len() {
+                    let texel = Texel::Dual {
+                        low: Point(
+                            average(duals.iter().map(|d| d.0 .0 as u32)),
+                            dominant(duals.iter().map(|d| d.0 .1)),
+                        ),
+                        high: Point(
+                            average(duals.iter().map(|d| d.1 .0 as u32)),
+                            dominant(duals.iter().map(|d| d.1 .1)),
+                        ),
+                        delta: average(duals.iter().map(|d| d.2 as u32)),
+                    };
+                    result.set_texel(x, y, texel, bits);
+                } else {
+                    for e in 0..2 {
+                        let block = rows.clone().flat_map(|sy| {
+                            ((x + e) * factor..(x + e + 1) * factor).map(move |sx| (sx, sy))
+                        });
+                        let points = block
+                            .map(|(sx, sy)| match self.texel(sx, sy, bits) {
+                                Texel::Single(point) => point,
+                                Texel::Dual { high, .. } => high,
+                            })
+                            .collect::<Vec<_>>();
+                        let point = Point(
+                            average(points.iter().map(|p| p.0 as u32)),
+                            dominant(points.iter().map(|p| p.1)),
+                        );
+                        result.set_texel(x + e, y, Texel::Single(point), bits);
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Enlarges the map by `1 << power` in both dimensions, replicating the texels.
+    pub fn upsample(&self, power: u32, bits: TerrainBits) -> LevelData {
+        let factor = 1 << power;
+        let size = (self.size.0 * factor, self.size.1 * factor);
+        let mut result = LevelData::new(size);
+
+        for y in 0..size.1 {
+            for x in (0..size.0).step_by(2) {
+                match self.texel(x / factor, y / factor, bits) {
+                    texel @ Texel::Dual { .. } => result.set_texel(x, y, texel, bits),
+                    Texel::Single(_) => {
+                        for e in 0..2 {
+                            let texel = self.texel((x + e) / factor, y / factor, bits);
+                            let point = match texel {
+                                Texel::Single(point) => point,
+                                Texel::Dual { high, .. } => high,
+                            };
+                            result.set_texel(x + e, y, Texel::Single(point), bits);
+                        }
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Joins a grid of maps, given as rows of tiles, into one.
+    ///
+    /// All the tiles of a row have to be of the same height, and all the tiles
+    /// of a column of the same width, or an error is returned. Single texels within `blend` elements
+    /// of a seam, including the ones at the edges, are blended towards the seam.
+    pub fn stitch(tiles: &[Vec<LevelData>], blend: i32) -> Result<LevelData, LevelError> {
+        let widths = match tiles.first() {
+            Some(row) if !row.is_empty() => row.iter().map(|t| t.size.0).collect::<Vec<_>>(),
+            _ => return Err(LevelError::NoTiles),
+        };
+        for (r, row) in tiles.iter().enumerate() {
+            if row.len() != widths.len() {
+                return Err(LevelError::TileRowLength {
+                    row: r,
+                    length: row.len(),
+                    expected: widths.len(),
+                });
+            }
+            let h = row[0].size.1;
+            for (c, (tile, &w)) in row.iter().zip(widths.iter()).enumerate() {
+                if tile.size != (w, h) {
+                    return Err(LevelError::TileSize {
+                        row: r,
+                        column: c,
+                        size: tile.size,
+                        expected: (w, h),
+                    });
+                }
+            }
+        }
+        let heights = tiles.iter().map(|row| row[0].size.1).collect::<Vec<_>>();
+        let size = (widths.iter().sum(), heights.iter().sum());
+        let mut result = LevelData::new(size);
+
+        let mut y0 = 0;
+        for (row, &h) in tiles.iter().zip(heights.iter()) {
+            let mut x0 = 0;
+            for (tile, &w) in row.iter().zip(widths.iter()) {
+                for y in 0..h {
+                    let src = (y * w) as usize..((y + 1) * w) as usize;
+                    let dst = result.index(x0, y0 + y);
+                    result.height[dst..dst + w as usize].copy_from_slice(&tile.height[src.clone()]);
+                    result.meta[dst..dst + w as usize].copy_from_slice(&tile.meta[src]);
+                }
+                x0 += w;
+            }
+            y0 += h;
+        }
+
+        if blend > 0 {
+            let seams_x = widths.iter().scan(0, |x, &w| {
+                *x += w;
+                Some(*x)
+            });
+            for seam in seams_x.collect::<Vec<_>>() {
+                for y in 0..size.1 {
+                    result.blend_seam(blend, |d| (seam + d, y));
+                }
+            }
+            let seams_y = heights.iter().scan(0, |y, &h| {
+                *y += h;
+                Some(*y)
+            });
+            for seam in seams_y.collect::<Vec<_>>() {
+                for x in 0..size.0 {
+                    result.blend_seam(blend, |d| (x, seam + d));
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Blends the single elements across a seam, where `coord(0)` is the first one after it.
+    fn blend_seam(&mut self, blend: i32, coord: impl Fn(i32) -> (i32, i32)) {
+        let indices = (-blend..blend)
+            .map(|d| {
+                let (x, y) = coord(d);
+                self.index(x, y)
+            })
+            .collect::<Vec<_>>();
+        let (before, after) = (indices[blend as usize - 1], indices[blend as usize]);
+        if (self.meta[before] | self.meta[after]) & DOUBLE_LEVEL != 0 {
+            return;
+        }
+        let middle = (self.height[before] as f32 + self.height[after] as f32) * 0.5;
+        for (d, &i) in (-blend..blend).zip(indices.iter()) {
+            if self.meta[i] & DOUBLE_LEVEL != 0 {
+                continue;
+            }
+            let distance = if d < 0 { -d - 1 } else { d } as f32;
+            let weight = 1.0 - distance / blend as f32;
+            let h = self.height[i] as f32;
+            self.height[i] = (h + (middle - h) * weight).round() as Altitude;
+        }
+    }
+}
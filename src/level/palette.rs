@@ -0,0 +1,166 @@
+//! Palette and color tables of the terrain.
+//!
+//! The palette follows the known part of `PalettePrepare` of the original.
+//! The lighting and shading tables follow the formulas of the terrain shaders,
+//! which evaluate them on the GPU. Neither is checked against the tables
+//! of the original game yet.
+
+use super::{Altitude, LevelError, TerrainConfig, TerrainType};
+
+use std::io::Read;
+
+pub type Palette = [[u8; 4]; 0x100];
+
+/// First of the 16 grey entries used by the interface.
+const GREY_START: usize = 224;
+/// Horizon correction, called `H_CORRECTION` in the original.
+pub const HORIZON_FACTOR: f32 = 0.5;
+const DIFFUSE_SCALE: f32 = 8.0;
+const SHADOW_DEPTH_SCALE: f32 = 0.6;
+
+/// Lighting coefficients of a terrain type, called "dx", "sd" and "jj" in the original.
+/// The values are the ones of the terrain shaders: the water is lit differently
+/// from the rest of the terrain types.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Material {
+    pub diffuse: f32,
+    pub shadow_depth: f32,
+    pub slope: f32,
+}
+
+impl Material {
+    pub fn new(terrain: TerrainType) -> Self {
+        if terrain == 0 {
+            // water
+            Material {
+                diffuse: 5.0,
+                shadow_depth: 1.25,
+                slope: 0.5,
+            }
+        } else {
+            Material {
+                diffuse: 1.0,
+                shadow_depth: 1.0,
+                slope: 1.0,
+            }
+        }
+    }
+
+    /// Lightness in [0, 1] of a surface rising by `height_diff` (normalized altitude)
+    /// over two texels towards the light.
+    pub fn light(&self, height_diff: f32) -> f32 {
+        let dx = self.diffuse * DIFFUSE_SCALE;
+        let sd = self.shadow_depth * SHADOW_DEPTH_SCALE;
+        let jj = self.slope * height_diff * 256.0;
+        let v = (dx * sd - jj) / ((1.0 + sd * sd) * (dx * dx + jj * jj)).sqrt();
+        v.clamp(0.0, 1.0)
+    }
+}
+
+/// Reads the raw 6-bit VGA palette.
+pub fn read<I: Read>(mut input: I) -> Result<Palette, LevelError> {
+    let mut data = [[0; 4]; 0x100];
+    for p in data.iter_mut() {
+        input.read_exact(&mut p[..3]).map_err(LevelError::Palette)?;
+    }
+    Ok(data)
+}
+
+/// Adjusts the raw palette for the terrain, see `PalettePrepare` of the original.
+pub fn prepare(data: &mut Palette, terrains: &[TerrainConfig]) {
+    data[0] = [0; 4];
+
+    // the first color of each terrain is used for the darkest shade
+    for tc in terrains {
+        for c in &mut data[tc.colors.start as usize][..3] {
+            *c >>= 1;
+        }
+    }
+
+    for (i, value) in data[GREY_START..GREY_START + 16].iter_mut().enumerate() {
+        let grey = (i * 4) as u8;
+        *value = [grey, grey, grey, 0];
+    }
+    //TODO: there is quite a bit of logic missing here,
+    // see `GeneralTableOpen` and `PalettePrepare` of the original.
+}
+
+/// Expands the 6-bit components to 8 bits, see `XGR_Screen::setpal` of the original.
+pub fn scale(data: &mut Palette) {
+    for p in data.iter_mut() {
+        p[0] <<= 2;
+        p[1] <<= 2;
+        p[2] <<= 2;
+    }
+}
+
+/// Runs the full pipeline of the original on a palette file.
+/// Without the terrain configuration, the palette is only scaled, like for the objects.
+pub fn load<I: Read>(input: I, terrains: Option<&[TerrainConfig]>) -> Result<Palette, LevelError> {
+    let mut data = read(input)?;
+    if let Some(terrains) = terrains {
+        prepare(&mut data, terrains);
+    }
+    scale(&mut data);
+    Ok(data)
+}
+
+/// Offset of the zero height difference in `ColorTables::light`.
+const LIGHT_CENTER: usize = 0x100;
+
+/// Per-terrain lookup tables of the original renderer.
+pub struct ColorTables {
+    /// Lightness of a texel, indexed by the altitude difference
+    /// with the texel two steps towards the light, offset by 0x100.
+    /// Called `lightCLR` in the original.
+    pub light: Box<[[u8; 0x200]]>,
+    /// Palette index, indexed by the lightness.
+    /// Called `palCLR` in the original.
+    pub colors: Box<[[u8; 0x100]]>,
+}
+
+impl ColorTables {
+    pub fn new(terrains: &[TerrainConfig]) -> Self {
+        let light = (0..terrains.len())
+            .map(|terrain| {
+                let material = Material::new(terrain as TerrainType);
+                let mut table = [0; 0x200];
+                for (i, value) in table.iter_mut().enumerate() {
+                    let diff = (i as f32 - LIGHT_CENTER as f32) / 255.0;
+                    *value = (material.light(diff) * 255.0).round() as u8;
+                }
+                table
+            })
+            .collect();
+        let colors = terrains
+            .iter()
+            .map(|tc| {
+                let (start, end) = (tc.colors.start as f32, tc.colors.end as f32);
+                let mut table = [0; 0x100];
+                for (i, value) in table.iter_mut().enumerate() {
+                    let t = i as f32 / 255.0;
+                    *value = (start + (end - start) * t + 0.5) as u8;
+                }
+                table
+            })
+            .collect();
+        ColorTables { light, colors }
+    }
+
+    /// Palette index of a texel, the same as `evaluate_color_id` of the terrain shaders.
+    ///
+    /// `height_diff` is the altitude difference with the texel two steps towards the light,
+    /// and `lit_factor` is the amount of light not blocked by the shadows.
+    pub fn color_id(
+        &self,
+        terrain: TerrainType,
+        altitude: Altitude,
+        height_diff: i32,
+        lit_factor: f32,
+    ) -> u8 {
+        let index = (LIGHT_CENTER as i32 + height_diff).clamp(0, 0x1FF) as usize;
+        let light = self.light[terrain as usize][index] as f32 / 255.0;
+        let value = lit_factor * (light - HORIZON_FACTOR * (1.0 - altitude as f32 / 255.0));
+        self.colors[terrain as usize][(value.clamp(0.0, 1.0) * 255.0).round() as usize]
+    }
+}
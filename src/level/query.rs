@@ -0,0 +1,158 @@
+//! Geometric queries against the level height field.
+//!
+//! Horizontal coordinates are in texels, wrapping around the level edges.
+//! Vertical coordinates are in world units, see `get_height`.
+
+use super::{Altitude, Level, Texel, HEIGHT_SCALE};
+
+use cgmath::{InnerSpace as _, Point3, Vector3};
+
+use std::ops::Range;
+
+/// Step of the ray marching, in texels.
+const RAY_STEP: f32 = 0.5;
+/// Number of bisection steps refining a ray hit.
+const RAY_REFINE_STEPS: usize = 8;
+
+pub fn get_height(altitude: Altitude) -> f32 {
+    altitude as f32 * (HEIGHT_SCALE as f32) / 255.0
+}
+
+// see `GET_MIDDLE_HIGHT` macro
+pub fn get_middle(low: Altitude, high: Altitude) -> f32 {
+    let extra_room = if high.saturating_sub(low) > 130 {
+        110
+    } else {
+        48
+    };
+    get_height(low.saturating_add(extra_room))
+}
+
+fn texel_at(level: &Level, x: f32, y: f32) -> Texel {
+    level.get((x.floor() as i32, y.floor() as i32))
+}
+
+/// Height of the surface supporting something at height `z`.
+/// For dual texels, it's the upper layer if `z` is above the middle of the cave.
+pub fn floor_height(texel: Texel, z: f32) -> f32 {
+    match texel {
+        Texel::Single(point) => get_height(point.0),
+        Texel::Dual { low, high, .. } => {
+            if z > get_middle(low.0, high.0) {
+                get_height(high.0)
+            } else {
+                get_height(low.0)
+            }
+        }
+    }
+}
+
+/// Vertical distance from the point to the surface under it, negative if below.
+pub fn get_distance_to_terrain(level: &Level, point: Point3<f32>) -> f32 {
+    point.z - floor_height(texel_at(level, point.x, point.y), point.z)
+}
+
+/// Checks if the point is inside the ground, including the upper layer of dual texels.
+pub fn is_solid(level: &Level, point: Point3<f32>) -> bool {
+    match texel_at(level, point.x, point.y) {
+        Texel::Single(p) => point.z < get_height(p.0),
+        Texel::Dual { low, high, delta } => {
+            point.z < get_height(low.0)
+                || (point.z < get_height(high.0)
+                    && point.z >= get_height(low.0.saturating_add(delta)))
+        }
+    }
+}
+
+/// Floor height, bilinearly interpolated between the centers of the neighboring texels.
+pub fn sample_height(level: &Level, point: Point3<f32>) -> f32 {
+    let (x, y) = (point.x - 0.5, point.y - 0.5);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (x - x0, y - y0);
+    let h = |dx: f32, dy: f32| floor_height(texel_at(level, x0 + dx, y0 + dy), point.z);
+    let top = h(0.0, 0.0) * (1.0 - tx) + h(1.0, 0.0) * tx;
+    let bottom = h(0.0, 1.0) * (1.0 - tx) + h(1.0, 1.0) * tx;
+    top * (1.0 - ty) + bottom * ty
+}
+
+/// Surface normal, estimated from the central differences of `sample_height`.
+pub fn sample_normal(level: &Level, point: Point3<f32>) -> Vector3<f32> {
+    let h = |dx: f32, dy: f32| sample_height(level, point + Vector3::new(dx, dy, 0.0));
+    let dx = h(1.0, 0.0) - h(-1.0, 0.0);
+    let dy = h(0.0, 1.0) - h(0.0, -1.0);
+    Vector3::new(-dx, -dy, 2.0).normalize()
+}
+
+/// Point where the ray crosses the horizontal plane at `height`,
+/// with the ray parameter clamped to `range`.
+pub fn intersect_plane(
+    origin: Point3<f32>,
+    dir: Vector3<f32>,
+    height: f32,
+    range: Range<f32>,
+) -> Point3<f32> {
+    let t_raw = (height - origin.z) / dir.z;
+    let t = range.start.max(t_raw).min(range.end);
+    origin + t * dir
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RayHit {
+    /// Hit position, not wrapped around the level edges.
+    pub pos: Point3<f32>,
+    /// Distance from the ray origin.
+    pub distance: f32,
+}
+
+/// Marches the ray through the height field, passing through the caves
+/// under dual texels, and refines the first hit by bisection.
+pub fn cast_ray(
+    level: &Level,
+    origin: Point3<f32>,
+    dir: Vector3<f32>,
+    max_distance: f32,
+) -> Option<RayHit> {
+    let dir = dir.normalize();
+    let top = HEIGHT_SCALE as f32;
+    // nothing is above the highest altitude, skip straight to it
+    let mut t = if origin.z > top {
+        if dir.z >= 0.0 {
+            return None;
+        }
+        (top - origin.z) / dir.z
+    } else {
+        0.0
+    };
+    if is_solid(level, origin + t * dir) {
+        return Some(RayHit {
+            pos: origin + t * dir,
+            distance: t,
+        });
+    }
+
+    while t < max_distance {
+        let t_next = (t + RAY_STEP).min(max_distance);
+        let pos = origin + t_next * dir;
+        if pos.z > top && dir.z >= 0.0 {
+            return None;
+        }
+        if is_solid(level, pos) {
+            let (mut t_free, mut t_solid) = (t, t_next);
+            for _ in 0..RAY_REFINE_STEPS {
+                let t_mid = 0.5 * (t_free + t_solid);
+                if is_solid(level, origin + t_mid * dir) {
+                    t_solid = t_mid;
+                } else {
+                    t_free = t_mid;
+                }
+            }
+            return Some(RayHit {
+                pos: origin + t_solid * dir,
+                distance: t_solid,
+            });
+        }
+        t = t_next;
+    }
+
+    None
+}
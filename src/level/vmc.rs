@@ -0,0 +1,199 @@
+//! Random access to the compressed level data (VMC).
+//!
+//! The row table at the start of the file makes every row independently
+//! addressable, so parts of the level can be decompressed without touching the rest.
+
+use super::{open, read_error, LevelData, LevelError, Region};
+
+use byteorder::{LittleEndian as E, ReadBytesExt};
+use splay::Splay;
+
+use std::{
+    io::{BufReader, Read, Seek, SeekFrom},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+/// Number of rows decompressed together, sharing a file handle.
+const ROW_GROUP: usize = 64;
+
+#[derive(Copy, Clone)]
+struct RowEntry {
+    offset: u32,
+    size: u16,
+}
+
+/// Row table and decoding trees of a VMC file, with the rows left compressed.
+pub struct VmcReader {
+    path: PathBuf,
+    size: (i32, i32),
+    splay: Splay,
+    rows: Box<[RowEntry]>,
+}
+
+impl VmcReader {
+    pub fn open(path: &Path, size: (i32, i32)) -> Result<Self, LevelError> {
+        profiling::scope!("Prepare");
+        let file = open(path)?;
+        let file_size = file.metadata().map_err(read_error(path))?.len();
+        let mut vmc_base = BufReader::new(file);
+
+        info!("\tLoading compression tables...");
+        let mut rows = Vec::with_capacity(size.1 as usize);
+        for _ in 0..size.1 {
+            let offset = vmc_base.read_i32::<E>().map_err(read_error(path))?;
+            let size = vmc_base.read_i16::<E>().map_err(read_error(path))?;
+            let end = offset as i64 + size as i64;
+            if offset < 0 || size < 0 || end as u64 > file_size {
+                return Err(LevelError::SizeMismatch {
+                    path: path.to_path_buf(),
+                    expected: end.max(0) as u64,
+                    actual: file_size,
+                });
+            }
+            rows.push(RowEntry {
+                offset: offset as u32,
+                size: size as u16,
+            });
+        }
+
+        let splay = Splay::try_new(&mut vmc_base).map_err(read_error(path))?;
+        Ok(VmcReader {
+            path: path.to_path_buf(),
+            size,
+            splay,
+            rows: rows.into_boxed_slice(),
+        })
+    }
+
+    pub fn size(&self) -> (i32, i32) {
+        self.size
+    }
+
+    /// Decompresses the listed rows into consecutive rows of `height` and `meta`.
+    fn expand_rows(
+        &self,
+        rows: &[usize],
+        height: &mut [u8],
+        meta: &mut [u8],
+    ) -> Result<(), LevelError> {
+        use rayon::prelude::*;
+
+        let width = self.size.0 as usize;
+        height
+            .chunks_mut(width)
+            .zip(meta.chunks_mut(width))
+            .zip(rows)
+            .collect::<Vec<_>>()
+            .par_chunks_mut(ROW_GROUP)
+            .try_for_each(|source_group| {
+                //Note: a separate file per group is required
+                let mut vmc = open(&self.path)?;
+                let data_size = source_group
+                    .iter()
+                    .map(|&(_, row)| self.rows[*row].size)
+                    .max()
+                    .unwrap();
+                let mut data = vec![0u8; data_size as usize];
+                for &mut ((ref mut h_row, ref mut m_row), &row) in source_group {
+                    let entry = self.rows[row];
+                    let data = &mut data[..entry.size as usize];
+                    vmc.seek(SeekFrom::Start(entry.offset as u64))
+                        .map_err(read_error(&self.path))?;
+                    vmc.read_exact(data).map_err(read_error(&self.path))?;
+                    self.splay
+                        .try_expand(data, h_row, m_row)
+                        .map_err(|error| LevelError::CorruptSplay { row, error })?;
+                }
+                Ok(())
+            })
+    }
+
+    /// Decompresses a range of full rows.
+    pub fn load_rows(&self, rows: Range<i32>) -> Result<LevelData, LevelError> {
+        if rows.start < 0 || rows.start > rows.end || rows.end > self.size.1 {
+            return Err(LevelError::RowsOutOfBounds {
+                rows,
+                height: self.size.1,
+            });
+        }
+        let rows = (rows.start as usize..rows.end as usize).collect::<Vec<_>>();
+        let total = self.size.0 as usize * rows.len();
+        let mut level = LevelData {
+            height: vec![0u8; total].into_boxed_slice(),
+            meta: vec![0u8; total].into_boxed_slice(),
+            size: (self.size.0, rows.len() as i32),
+        };
+        self.expand_rows(&rows, &mut level.height, &mut level.meta)?;
+        Ok(level)
+    }
+
+    /// Decompresses only the rows covered by the region, and crops them.
+    /// See `crop_rows` for the region requirements.
+    pub fn load_region(&self, region: Region) -> Result<LevelData, LevelError> {
+        check_region(region)?;
+        let rows = (region.y..region.y + region.h.min(self.size.1))
+            .map(|y| y.rem_euclid(self.size.1) as usize)
+            .collect::<Vec<_>>();
+        let width = self.size.0 as usize;
+        let mut height = vec![0u8; width * rows.len()];
+        let mut meta = vec![0u8; width * rows.len()];
+        self.expand_rows(&rows, &mut height, &mut meta)?;
+
+        let mut index = 0;
+        crop_rows(self.size, region, |_, h_row, m_row| {
+            h_row.copy_from_slice(&height[index * width..(index + 1) * width]);
+            m_row.copy_from_slice(&meta[index * width..(index + 1) * width]);
+            index += 1;
+            Ok(())
+        })
+    }
+}
+
+fn check_region(region: Region) -> Result<(), LevelError> {
+    if region.x & 1 == 0 && region.w & 1 == 0 {
+        Ok(())
+    } else {
+        Err(LevelError::UnalignedRegion(region))
+    }
+}
+
+/// Builds the level data of a region, wrapping around the edges,
+/// given a way to fetch the full rows in order.
+///
+/// The horizontal bounds of the region have to be even, so that the pairs
+/// of elements representing dual texels are kept intact.
+pub(super) fn crop_rows<F>(
+    size: (i32, i32),
+    region: Region,
+    mut fetch: F,
+) -> Result<LevelData, LevelError>
+where
+    F: FnMut(usize, &mut [u8], &mut [u8]) -> Result<(), LevelError>,
+{
+    check_region(region)?;
+    let w = region.w.clamp(0, size.0) as usize;
+    let h = region.h.clamp(0, size.1) as usize;
+    let mut level = LevelData {
+        height: vec![0u8; w * h].into_boxed_slice(),
+        meta: vec![0u8; w * h].into_boxed_slice(),
+        size: (w as i32, h as i32),
+    };
+
+    let width = size.0 as usize;
+    let mut h_row = vec![0u8; width];
+    let mut m_row = vec![0u8; width];
+    let x0 = region.x.rem_euclid(size.0) as usize;
+    // the part until the right edge, followed by the wrapped part
+    let w0 = w.min(width - x0);
+    for (i, y) in (region.y..region.y + h as i32).enumerate() {
+        fetch(y.rem_euclid(size.1) as usize, &mut h_row, &mut m_row)?;
+        for (dst, src) in [(&mut level.height, &h_row), (&mut level.meta, &m_row)] {
+            let dst = &mut dst[i * w..(i + 1) * w];
+            dst[..w0].copy_from_slice(&src[x0..x0 + w0]);
+            dst[w0..].copy_from_slice(&src[..w - w0]);
+        }
+    }
+
+    Ok(level)
+}
@@ -0,0 +1,221 @@
+//! Pre-rendered level data (VPR), produced by the original editor alongside the VMC.
+//!
+//! The layout is, in order:
+//!   - 2 header words
+//!   - 1 + 4 + 4 parameter words
+//!   - GeoNet grid, 2 bytes per cell of `(1 << geo) x (1 << geo)` texels
+//!   - 2 tables of `geo` words
+//!   - 2 tables of `sections x geo` words
+//!   - flood table, a word per section of `1 << section` rows
+//!
+//! Only the flood table is consumed by the engine today.
+//! A loaded VPR is kept as-is, so that it writes back byte-identically.
+//! For a level without a VPR, the GeoNet grid and the flood table are estimated
+//! from the level data, and the rest is zeroed, since its meaning is not known.
+//! The estimate hasn't been checked against the VPR files of the game.
+
+use super::{open, read_error, LevelConfig, LevelData, LevelError, TerrainBits, DOUBLE_LEVEL};
+
+use byteorder::{LittleEndian as E, ReadBytesExt, WriteBytesExt};
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Result as IoResult, Write},
+    path::Path,
+};
+
+/// Table sizes derived from the level configuration.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Dimensions {
+    pub net_size: usize,
+    pub geo_power: usize,
+    pub num_sections: usize,
+}
+
+impl Dimensions {
+    pub fn new(config: &LevelConfig) -> Self {
+        let size = (config.size.0.as_value(), config.size.1.as_value());
+        let geo_power = config.geo.as_power();
+        Dimensions {
+            net_size: ((size.0 * size.1) >> (2 * geo_power)) as usize,
+            geo_power: geo_power as usize,
+            num_sections: (size.1 >> config.section.as_power()) as usize,
+        }
+    }
+
+    /// Offset of the flood table in the file.
+    pub fn flood_offset(&self) -> u64 {
+        (2 * 4
+            + (1 + 4 + 4) * 4
+            + 2 * self.net_size
+            + 2 * self.geo_power * 4
+            + 2 * self.num_sections * self.geo_power * 4) as u64
+    }
+
+    pub fn file_size(&self) -> u64 {
+        self.flood_offset() + self.num_sections as u64 * 4
+    }
+}
+
+pub struct Vpr {
+    pub header: [i32; 2],
+    pub params: [i32; 1 + 4 + 4],
+    /// Lowest and highest top altitude of each GeoNet cell.
+    pub geo_net: Box<[[u8; 2]]>,
+    pub geo_tables: [Box<[i32]>; 2],
+    pub section_tables: [Box<[i32]>; 2],
+    /// Water altitude of each section.
+    pub flood: Box<[u32]>,
+}
+
+fn read_words<I: ReadBytesExt>(input: &mut I, count: usize) -> IoResult<Box<[i32]>> {
+    let mut words = vec![0; count];
+    input.read_i32_into::<E>(&mut words)?;
+    Ok(words.into_boxed_slice())
+}
+
+impl Vpr {
+    /// Creates a VPR with all the tables zeroed.
+    pub fn new(dim: Dimensions) -> Self {
+        let zeros = || vec![0i32; dim.geo_power].into_boxed_slice();
+        let section_zeros = || vec![0i32; dim.num_sections * dim.geo_power].into_boxed_slice();
+        Vpr {
+            header: [0; 2],
+            params: [0; 1 + 4 + 4],
+            geo_net: vec![[0; 2]; dim.net_size].into_boxed_slice(),
+            geo_tables: [zeros(), zeros()],
+            section_tables: [section_zeros(), section_zeros()],
+            flood: vec![0; dim.num_sections].into_boxed_slice(),
+        }
+    }
+
+    pub fn dimensions(&self) -> Dimensions {
+        Dimensions {
+            net_size: self.geo_net.len(),
+            geo_power: self.geo_tables[0].len(),
+            num_sections: self.flood.len(),
+        }
+    }
+
+    pub fn load(path: &Path, dim: Dimensions) -> Result<Self, LevelError> {
+        let file = open(path)?;
+        let actual = file.metadata().map_err(read_error(path))?.len();
+        if actual != dim.file_size() {
+            return Err(LevelError::BadVprLength {
+                expected: dim.file_size(),
+                actual,
+            });
+        }
+        Self::read(&mut BufReader::new(file), dim).map_err(read_error(path))
+    }
+
+    pub fn read<I: ReadBytesExt>(input: &mut I, dim: Dimensions) -> IoResult<Self> {
+        let mut vpr = Vpr::new(dim);
+        input.read_i32_into::<E>(&mut vpr.header)?;
+        input.read_i32_into::<E>(&mut vpr.params)?;
+        for cell in vpr.geo_net.iter_mut() {
+            input.read_exact(cell)?;
+        }
+        for table in vpr.geo_tables.iter_mut() {
+            *table = read_words(input, dim.geo_power)?;
+        }
+        for table in vpr.section_tables.iter_mut() {
+            *table = read_words(input, dim.num_sections * dim.geo_power)?;
+        }
+        input.read_u32_into::<E>(&mut vpr.flood)?;
+        Ok(vpr)
+    }
+
+    pub fn save(&self, path: &Path) -> IoResult<()> {
+        let mut output = BufWriter::new(File::create(path)?);
+        self.write(&mut output)?;
+        output.flush()
+    }
+
+    pub fn write<O: WriteBytesExt>(&self, output: &mut O) -> IoResult<()> {
+        for &word in self.header.iter().chain(self.params.iter()) {
+            output.write_i32::<E>(word)?;
+        }
+        for cell in self.geo_net.iter() {
+            output.write_all(cell)?;
+        }
+        for table in self.geo_tables.iter().chain(self.section_tables.iter()) {
+            for &word in table.iter() {
+                output.write_i32::<E>(word)?;
+            }
+        }
+        for &level in self.flood.iter() {
+            output.write_u32::<E>(level)?;
+        }
+        Ok(())
+    }
+
+    /// Estimates the GeoNet grid and the flood table from the level data.
+    /// The flood level of a section is taken as the highest altitude of its water texels.
+    pub fn estimate(&mut self, data: &LevelData, terrain_bits: TerrainBits) {
+        let dim = self.dimensions();
+        let width = data.size.0 as usize;
+        let net_width = width >> dim.geo_power;
+        let section_rows = data.size.1 as usize / dim.num_sections.max(1);
+
+        for cell in self.geo_net.iter_mut() {
+            *cell = [!0, 0];
+        }
+        for level in self.flood.iter_mut() {
+            *level = 0;
+        }
+
+        for (y, (h_row, m_row)) in data
+            .height
+            .chunks(width)
+            .zip(data.meta.chunks(width))
+            .enumerate()
+        {
+            let net_row = (y >> dim.geo_power) * net_width;
+            let section = y / section_rows.max(1);
+            for x in 0..width {
+                // the upper layer of a dual texel is stored in the odd element
+                let i = if m_row[x] & DOUBLE_LEVEL != 0 {
+                    x | 1
+                } else {
+                    x
+                };
+                let (altitude, terrain) = (h_row[i], terrain_bits.read(m_row[i]));
+                if let Some(cell) = self.geo_net.get_mut(net_row + (x >> dim.geo_power)) {
+                    cell[0] = cell[0].min(altitude);
+                    cell[1] = cell[1].max(altitude);
+                }
+                if terrain == 0 {
+                    if let Some(level) = self.flood.get_mut(section) {
+                        *level = (*level).max(altitude as u32);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Picks the VPR of a level: `base`, which is normally the VPR the level
+    /// had before editing, is kept unchanged if it fits. Otherwise, the tables
+    /// are estimated from the level data.
+    pub fn from_level(data: &LevelData, config: &LevelConfig, base: Option<Vpr>) -> Self {
+        let dim = Dimensions::new(config);
+        match base {
+            Some(vpr) if vpr.dimensions() == dim => return vpr,
+            Some(_) => warn!("The previous VPR doesn't fit the level, its tables are estimated"),
+            None => info!("The level has no VPR, its tables are estimated"),
+        }
+        let mut vpr = Vpr::new(dim);
+        vpr.estimate(data, TerrainBits::new(config.terrains.len() as u8));
+        vpr
+    }
+
+    /// Loads the VPR that sits next to the level data, if there is one.
+    pub fn load_existing(config: &LevelConfig) -> Result<Option<Self>, LevelError> {
+        let path = config.path_data.with_extension("vpr");
+        if path.exists() {
+            Self::load(&path, Dimensions::new(config)).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
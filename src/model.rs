@@ -1,12 +1,18 @@
-use crate::render::{
-    debug::Position as DebugPos,
-    object::{Context as ObjectContext, Vertex as ObjectVertex},
-    ShapePolygon, VertexStorageNotSupported,
+use crate::{
+    config::{
+        game::Registry,
+        settings::{Lod, Settings},
+    },
+    render::{
+        debug::Position as DebugPos,
+        object::{Context as ObjectContext, Vertex as ObjectVertex},
+        ShapePolygon, VertexStorageNotSupported,
+    },
 };
 use m3d;
 use wgpu::util::DeviceExt as _;
 
-use std::{fs::File, mem, ops::Range, slice, sync::Arc};
+use std::{collections::HashMap, fs::File, mem, ops::Range, slice, sync::Arc};
 
 #[derive(Copy, Clone)]
 pub struct BoundingBox {
@@ -21,6 +27,8 @@ pub struct Mesh {
     pub offset: [f32; 3],
     pub bbox: BoundingBox,
     pub physics: m3d::Physics,
+    /// Simplified versions of the mesh, from the finest to the coarsest.
+    pub lods: Vec<Arc<Mesh>>,
 }
 
 #[derive(Clone, Debug)]
@@ -121,53 +129,110 @@ fn vec_i2f(v: [i32; 3]) -> [f32; 3] {
     [v[0] as f32, v[1] as f32, v[2] as f32]
 }
 
-pub fn load_c3d(
-    raw: m3d::Mesh<m3d::Geometry<m3d::DrawTriangle>>,
+fn c3d_vertices(geometry: &m3d::Geometry<m3d::DrawTriangle>) -> Vec<ObjectVertex> {
+    let mut vertices = Vec::with_capacity(geometry.polygons.len() * 3);
+    for tri in &geometry.polygons {
+        vertices.extend(tri.vertices.iter().map(|v| {
+            let p = geometry.positions[v.pos as usize];
+            let n = geometry.normals[v.normal as usize];
+            ObjectVertex {
+                pos: [p[0], p[1], p[2], 1],
+                color: tri.material[0],
+                normal: [n[0], n[1], n[2], 0],
+            }
+        }));
+    }
+    vertices
+}
+
+fn create_mesh(
+    vertices: &[ObjectVertex],
+    offset: [f32; 3],
+    bbox: BoundingBox,
+    physics: m3d::Physics,
+    lods: Vec<Arc<Mesh>>,
     device: &wgpu::Device,
 ) -> Arc<Mesh> {
-    let num_vertices = raw.geometry.polygons.len() * 3;
-    debug!("\tGot {} GPU vertices...", num_vertices);
-    let vertex_size = mem::size_of::<ObjectVertex>();
-    let vertex_buf = device.create_buffer(&wgpu::BufferDescriptor {
+    debug!("\tGot {} GPU vertices...", vertices.len());
+    let vertex_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: Some("C3D"),
-        size: (num_vertices * vertex_size) as wgpu::BufferAddress,
+        contents: bytemuck::cast_slice(vertices),
         usage: wgpu::BufferUsages::VERTEX,
-        mapped_at_creation: true,
     });
-    {
-        let mut mapping = vertex_buf.slice(..).get_mapped_range_mut();
-        for (chunk, tri) in mapping
-            .chunks_mut(3 * vertex_size)
-            .zip(&raw.geometry.polygons)
-        {
-            let out_vertices =
-                unsafe { slice::from_raw_parts_mut(chunk.as_mut_ptr() as *mut ObjectVertex, 3) };
-            for (vo, v) in out_vertices.iter_mut().zip(&tri.vertices) {
-                let p = raw.geometry.positions[v.pos as usize];
-                let n = raw.geometry.normals[v.normal as usize];
-                *vo = ObjectVertex {
-                    pos: [p[0], p[1], p[2], 1],
-                    color: tri.material[0],
-                    normal: [n[0], n[1], n[2], 0],
-                };
-            }
-        }
-    }
-    vertex_buf.unmap();
 
     Arc::new(Mesh {
-        num_vertices,
+        num_vertices: vertices.len(),
         vertex_buf,
-        offset: vec_i2f(raw.parent_off),
-        bbox: BoundingBox {
-            min: vec_i2f(raw.bounds.coord_min),
-            max: vec_i2f(raw.bounds.coord_max),
-            radius: raw.max_radius as f32,
-        },
-        physics: raw.physics,
+        offset,
+        bbox,
+        physics,
+        lods,
     })
 }
 
+fn c3d_bbox<G>(raw: &m3d::Mesh<G>) -> BoundingBox {
+    BoundingBox {
+        min: vec_i2f(raw.bounds.coord_min),
+        max: vec_i2f(raw.bounds.coord_max),
+        radius: raw.max_radius as f32,
+    }
+}
+
+fn load_c3d_parts<G>(
+    vertices: &[ObjectVertex],
+    raw: &m3d::Mesh<G>,
+    lods: Vec<Arc<Mesh>>,
+    device: &wgpu::Device,
+) -> Arc<Mesh> {
+    create_mesh(
+        vertices,
+        vec_i2f(raw.parent_off),
+        c3d_bbox(raw),
+        raw.physics,
+        lods,
+        device,
+    )
+}
+
+pub fn load_c3d(
+    raw: m3d::Mesh<m3d::Geometry<m3d::DrawTriangle>>,
+    device: &wgpu::Device,
+) -> Arc<Mesh> {
+    load_c3d_parts(&c3d_vertices(&raw.geometry), &raw, Vec::new(), device)
+}
+
+/// Loads the mesh together with its simplified versions.
+pub fn load_c3d_with_lods(
+    raw: m3d::Mesh<m3d::Geometry<m3d::DrawTriangle>>,
+    lod: &Lod,
+    device: &wgpu::Device,
+) -> Arc<Mesh> {
+    let mut lods = Vec::new();
+    let mut num_triangles = raw.geometry.polygons.len();
+    // the radius of the vertices the simplification works on
+    let radius = raw
+        .geometry
+        .positions
+        .iter()
+        .map(|p| p.iter().map(|&v| v as f32 * v as f32).sum::<f32>().sqrt())
+        .fold(1.0, f32::max);
+    let mut max_error = lod.error * radius;
+    for _ in 0..lod.levels {
+        let target = (num_triangles as f32 * lod.ratio) as usize;
+        let simplified = m3d::simplify(&raw.geometry, target, max_error);
+        max_error *= 2.0;
+        let count = simplified.geometry.polygons.len();
+        if count >= num_triangles {
+            break;
+        }
+        debug!("\tLOD with {} triangles, error {}", count, simplified.error);
+        let vertices = c3d_vertices(&simplified.geometry);
+        lods.push(load_c3d_parts(&vertices, &raw, Vec::new(), device));
+        num_triangles = count;
+    }
+    load_c3d_parts(&c3d_vertices(&raw.geometry), &raw, lods, device)
+}
+
 pub fn load_c3d_shape(
     raw: m3d::Mesh<m3d::Geometry<m3d::CollisionQuad>>,
     device: &wgpu::Device,
@@ -307,18 +372,19 @@ pub fn load_m3d(
     device: &wgpu::Device,
     object: &ObjectContext,
     shape_sampling: u8,
-) -> VisualModel {
-    let raw = m3d::FullModel::load(file);
+    lod: &Lod,
+) -> Result<VisualModel, m3d::Error> {
+    let raw = m3d::FullModel::load(file)?;
 
-    VisualModel {
-        body: load_c3d(raw.body, device),
+    Ok(VisualModel {
+        body: load_c3d_with_lods(raw.body, lod, device),
         shape: load_c3d_shape(raw.shape, device, shape_sampling, true, object),
         bound: raw.bound,
         color: raw.color,
         wheels: raw
             .wheels
             .into_iter()
-            .map(|wheel| wheel.map(|mesh| load_c3d(mesh, device)))
+            .map(|wheel| wheel.map(|mesh| load_c3d_with_lods(mesh, lod, device)))
             .collect(),
         debris: raw
             .debris
@@ -328,6 +394,244 @@ pub fn load_m3d(
                 shape: load_c3d_shape(debrie.shape, device, 0, false, object),
             })
             .collect(),
-        slots: m3d::Slot::map_all(raw.slots, |_, _| unreachable!()),
+        slots: m3d::Slot::map_all(raw.slots, |mesh, _| load_c3d(mesh, device)),
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum Playback {
+    /// Stops at the last frame.
+    Once,
+    /// Goes from the last frame back to the first.
+    Loop,
+    /// Goes back and forth.
+    PingPong,
+}
+
+impl Playback {
+    /// Returns the position within the key frames, given the time in frames.
+    pub fn frame_position(self, time: f32, num_frames: usize) -> f32 {
+        let last = num_frames.saturating_sub(1) as f32;
+        match self {
+            Playback::Once => time.clamp(0.0, last),
+            Playback::Loop => time.rem_euclid(num_frames.max(1) as f32),
+            Playback::PingPong if last == 0.0 => 0.0,
+            Playback::PingPong => {
+                let phase = time.rem_euclid(2.0 * last);
+                if phase <= last {
+                    phase
+                } else {
+                    2.0 * last - phase
+                }
+            }
+        }
+    }
+
+    /// Returns the pair of key frames to blend at the time in frames,
+    /// and the weight of the second one.
+    pub fn key_frames(self, time: f32, num_frames: usize) -> (usize, usize, f32) {
+        let position = self.frame_position(time, num_frames);
+        let index = (position.floor() as usize).min(num_frames.saturating_sub(1));
+        let next = match self {
+            Playback::Loop => (index + 1) % num_frames.max(1),
+            Playback::Once | Playback::PingPong => (index + 1).min(num_frames.saturating_sub(1)),
+        };
+        (index, next, position - index as f32)
+    }
+}
+
+/// Key frame of the animation, with the vertices kept for blending.
+struct KeyFrame {
+    mesh: Arc<Mesh>,
+    vertices: Vec<ObjectVertex>,
+}
+
+/// Model of the A3D animation, which keeps its own playback time,
+/// while sharing the key frames between the clones.
+///
+/// Only the model viewer plays them so far: `road` and `level` don't know
+/// where the animated world objects are placed, since that data isn't parsed.
+#[derive(Clone)]
+pub struct AnimatedModel {
+    keys: Arc<[KeyFrame]>,
+    /// Frame blended at the time of the last `prepare`.
+    current: Arc<Mesh>,
+    pub bound: m3d::UpperBound,
+    pub color: m3d::BodyColor,
+    /// Seconds per key frame.
+    pub frame_duration: f32,
+    pub playback: Playback,
+    time: f32,
+}
+
+/// Blends the vertices of the frames that share the topology.
+fn lerp_vertices(a: &[ObjectVertex], b: &[ObjectVertex], t: f32) -> Option<Vec<ObjectVertex>> {
+    if a.len() != b.len() || a.iter().zip(b).any(|(va, vb)| va.color != vb.color) {
+        return None;
+    }
+    let lerp = |x: i8, y: i8| (x as f32 + (y as f32 - x as f32) * t).round() as i8;
+    Some(
+        a.iter()
+            .zip(b)
+            .map(|(va, vb)| {
+                let n = [0, 1, 2].map(|i| lerp(va.normal[i], vb.normal[i]) as f32);
+                let len = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+                let scale = if len > 0.0 {
+                    m3d::NORMALIZER / len
+                } else {
+                    0.0
+                };
+                ObjectVertex {
+                    pos: [
+                        lerp(va.pos[0], vb.pos[0]),
+                        lerp(va.pos[1], vb.pos[1]),
+                        lerp(va.pos[2], vb.pos[2]),
+                        1,
+                    ],
+                    color: va.color,
+                    normal: [
+                        (n[0] * scale) as i8,
+                        (n[1] * scale) as i8,
+                        (n[2] * scale) as i8,
+                        0,
+                    ],
+                }
+            })
+            .collect(),
+    )
+}
+
+impl AnimatedModel {
+    pub fn new(
+        raw: m3d::AnimatedMesh<m3d::Geometry<m3d::DrawTriangle>>,
+        device: &wgpu::Device,
+    ) -> Result<Self, m3d::Error> {
+        if raw.meshes.is_empty() {
+            return Err(m3d::Error::NoFrames);
+        }
+        let keys = raw
+            .meshes
+            .iter()
+            .map(|mesh| {
+                let vertices = c3d_vertices(&mesh.geometry);
+                KeyFrame {
+                    mesh: load_c3d_parts(&vertices, mesh, Vec::new(), device),
+                    vertices,
+                }
+            })
+            .collect::<Arc<[_]>>();
+
+        Ok(AnimatedModel {
+            current: Arc::clone(&keys[0].mesh),
+            keys,
+            bound: raw.bound,
+            color: raw.color,
+            frame_duration: 0.1,
+            playback: Playback::Loop,
+            time: 0.0,
+        })
+    }
+
+    pub fn num_key_frames(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn advance(&mut self, delta: f32) {
+        self.time += delta;
+    }
+
+    pub fn restart(&mut self) {
+        self.time = 0.0;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.playback == Playback::Once
+            && self.time >= self.frame_duration * self.num_key_frames().saturating_sub(1) as f32
+    }
+
+    /// Blends the frame at the current time between the key frames around it.
+    /// Frames of a different topology are switched to the nearest one instead.
+    pub fn prepare(&mut self, device: &wgpu::Device) {
+        let time = if self.frame_duration > 0.0 {
+            self.time / self.frame_duration
+        } else {
+            0.0
+        };
+        let (index, next, t) = self.playback.key_frames(time, self.keys.len());
+        let (a, b) = (&self.keys[index], &self.keys[next]);
+        if t <= 0.0 || index == next {
+            self.current = Arc::clone(&a.mesh);
+            return;
+        }
+        self.current = match lerp_vertices(&a.vertices, &b.vertices, t) {
+            Some(blended) => {
+                let offset = [0, 1, 2].map(|j| {
+                    let (oa, ob) = (a.mesh.offset[j], b.mesh.offset[j]);
+                    oa + (ob - oa) * t
+                });
+                create_mesh(
+                    &blended,
+                    offset,
+                    a.mesh.bbox,
+                    a.mesh.physics,
+                    Vec::new(),
+                    device,
+                )
+            }
+            None if t < 0.5 => Arc::clone(&a.mesh),
+            None => Arc::clone(&b.mesh),
+        };
+    }
+
+    /// Returns the frame blended by the last `prepare`.
+    pub fn current(&self) -> &Arc<Mesh> {
+        &self.current
+    }
+}
+
+pub fn load_a3d(file: File, device: &wgpu::Device) -> Result<AnimatedModel, m3d::Error> {
+    let raw = m3d::AnimatedMesh::load(file)?;
+    AnimatedModel::new(raw, device)
+}
+
+/// Weapon meshes of the game registry, loaded once and shared between the models.
+#[derive(Default)]
+pub struct SlotMeshes {
+    meshes: HashMap<String, Option<(Arc<Mesh>, f32)>>,
+}
+
+impl SlotMeshes {
+    /// Puts the named weapons into the slots of the model, in order.
+    /// Unknown and invalid weapons leave their slots empty.
+    pub fn attach(
+        &mut self,
+        model: &mut VisualModel,
+        names: &[String],
+        registry: &Registry,
+        settings: &Settings,
+        device: &wgpu::Device,
+    ) {
+        for (slot, name) in model.slots.iter_mut().zip(names) {
+            let entry = self.meshes.entry(name.clone()).or_insert_with(|| {
+                let info = match registry.model_infos.get(name) {
+                    Some(info) => info,
+                    None => {
+                        error!("Unknown slot model {:?}", name);
+                        return None;
+                    }
+                };
+                match m3d::Mesh::load(&mut settings.open_relative(&info.path)) {
+                    Ok(raw) => Some((load_c3d(raw, device), info.scale)),
+                    Err(e) => {
+                        error!("Slot model {:?} is invalid: {}", info.path, e);
+                        None
+                    }
+                }
+            });
+            if let Some((ref mesh, scale)) = *entry {
+                slot.mesh = Some(Arc::clone(mesh));
+                slot.scale = scale;
+            }
+        }
     }
 }
@@ -39,6 +39,31 @@ struct Physics {
 unsafe impl Pod for Physics {}
 unsafe impl Zeroable for Physics {}
 
+impl Physics {
+    fn new(car_physics: &CarPhysics) -> Self {
+        Physics {
+            scale: [
+                car_physics.scale_size,
+                car_physics.scale_bound,
+                car_physics.scale_box,
+                car_physics.z_offset_of_mass_center,
+            ],
+            mobility_ship: [
+                car_physics.mobility_factor,
+                car_physics.k_archimedean,
+                car_physics.k_water_traction,
+                car_physics.k_water_rudder,
+            ],
+            speed: [
+                car_physics.speed_factor,
+                car_physics.water_speed_factor,
+                car_physics.air_speed_factor,
+                car_physics.underground_speed_factor,
+            ],
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
 struct Model {
@@ -87,6 +112,9 @@ impl Data {
         },
         wheels: [[0.0; 4]; MAX_WHEELS],
     };
+    const PHYSICS_OFFSET: usize = mem::offset_of!(Data, physics);
+    /// Offset of the scale, which is the last component of `pos_scale`.
+    const SCALE_OFFSET: usize = mem::offset_of!(Data, pos_scale) + 3 * mem::size_of::<f32>();
 }
 
 #[repr(C)]
@@ -128,6 +156,68 @@ struct Constants {
 unsafe impl Pod for Constants {}
 unsafe impl Zeroable for Constants {}
 
+impl Constants {
+    fn new(common: &Common) -> Self {
+        Constants {
+            nature: [
+                common.nature.time_delta0,
+                common.nature.density,
+                common.nature.gravity,
+                0.0,
+            ],
+            global_speed: [
+                common.global.speed_factor,
+                common.global.water_speed_factor,
+                common.global.air_speed_factor,
+                common.global.underground_speed_factor,
+            ],
+            global_mobility: [common.global.mobility_factor, 0.0, 0.0, 0.0],
+            car_rudder: [
+                common.car.rudder_step,
+                common.car.rudder_max,
+                common.car.rudder_k_decr,
+                0.0,
+            ],
+            car_traction: [common.car.traction_incr, common.car.traction_decr, 0.0, 0.0],
+            impulse_elastic: [
+                common.impulse.elastic_restriction,
+                common.impulse.elastic_time_scale_factor,
+                0.0,
+                0.0,
+            ],
+            impulse_factors: [
+                common.impulse.factors[0],
+                common.impulse.factors[1],
+                0.0,
+                0.0,
+            ],
+            impulse: [
+                common.impulse.rolling_scale,
+                common.impulse.normal_threshold,
+                common.impulse.k_wheel,
+                common.impulse.k_friction,
+            ],
+            drag: DragConstants {
+                free: common.drag.free.to_array(),
+                speed: common.drag.speed.to_array(),
+                spring: common.drag.spring.to_array(),
+                abs_min: common.drag.abs_min.to_array(),
+                abs_stop: common.drag.abs_stop.to_array(),
+                coll: common.drag.coll.to_array(),
+                other: [common.drag.wheel_speed, common.drag.z],
+                _pad: [0.0; 2],
+            },
+            contact_elastic: [
+                common.contact.k_elastic_wheel,
+                common.contact.k_elastic_spring,
+                common.contact.k_elastic_xy,
+                common.contact.k_elastic_db_coll,
+            ],
+            force: [common.force.k_distance_to_force, 0.0, 0.0, 0.0],
+        }
+    }
+}
+
 pub type GpuBody = ListId<Data>;
 
 struct Pipelines {
@@ -240,6 +330,8 @@ impl GpuStoreInit {
 enum Update {
     InitData { index: usize },
     SetControl { index: usize },
+    SetPhysics { index: usize },
+    SetScale { index: usize },
 }
 
 struct GpuResult {
@@ -265,6 +357,7 @@ pub struct GpuStore {
     pipelines: Pipelines,
     buf_data: wgpu::Buffer,
     buf_uniforms: wgpu::Buffer,
+    buf_constants: wgpu::Buffer,
     buf_ranges: wgpu::Buffer,
     buf_pushes: wgpu::Buffer,
     capacity: usize,
@@ -275,6 +368,9 @@ pub struct GpuStore {
     updates: Vec<(usize, Update)>,
     update_data: Vec<Data>,
     update_control: Vec<GpuControl>,
+    update_physics: Vec<Physics>,
+    update_scale: Vec<f32>,
+    update_constants: Option<Constants>,
     pending_pushes: Vec<GpuPush>,
     gpu_result: Option<GpuResult>,
     cpu_mirror: Arc<Mutex<GpuStoreMirror>>,
@@ -417,67 +513,10 @@ impl GpuStore {
         };
         let buf_pushes = device.create_buffer(&desc_pushes);
 
-        let constants = Constants {
-            nature: [
-                common.nature.time_delta0,
-                common.nature.density,
-                common.nature.gravity,
-                0.0,
-            ],
-            global_speed: [
-                common.global.speed_factor,
-                common.global.water_speed_factor,
-                common.global.air_speed_factor,
-                common.global.underground_speed_factor,
-            ],
-            global_mobility: [common.global.mobility_factor, 0.0, 0.0, 0.0],
-            car_rudder: [
-                common.car.rudder_step,
-                common.car.rudder_max,
-                common.car.rudder_k_decr,
-                0.0,
-            ],
-            car_traction: [common.car.traction_incr, common.car.traction_decr, 0.0, 0.0],
-            impulse_elastic: [
-                common.impulse.elastic_restriction,
-                common.impulse.elastic_time_scale_factor,
-                0.0,
-                0.0,
-            ],
-            impulse_factors: [
-                common.impulse.factors[0],
-                common.impulse.factors[1],
-                0.0,
-                0.0,
-            ],
-            impulse: [
-                common.impulse.rolling_scale,
-                common.impulse.normal_threshold,
-                common.impulse.k_wheel,
-                common.impulse.k_friction,
-            ],
-            drag: DragConstants {
-                free: common.drag.free.to_array(),
-                speed: common.drag.speed.to_array(),
-                spring: common.drag.spring.to_array(),
-                abs_min: common.drag.abs_min.to_array(),
-                abs_stop: common.drag.abs_stop.to_array(),
-                coll: common.drag.coll.to_array(),
-                other: [common.drag.wheel_speed, common.drag.z],
-                _pad: [0.0; 2],
-            },
-            contact_elastic: [
-                common.contact.k_elastic_wheel,
-                common.contact.k_elastic_spring,
-                common.contact.k_elastic_xy,
-                common.contact.k_elastic_db_coll,
-            ],
-            force: [common.force.k_distance_to_force, 0.0, 0.0, 0.0],
-        };
         let buf_constants = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("body-constants"),
-            contents: bytemuck::bytes_of(&constants),
-            usage: wgpu::BufferUsages::UNIFORM,
+            contents: bytemuck::bytes_of(&Constants::new(common)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -528,6 +567,7 @@ impl GpuStore {
             pipelines,
             buf_data: init.buffer,
             buf_uniforms,
+            buf_constants,
             buf_ranges,
             buf_pushes,
             capacity: init.capacity,
@@ -538,6 +578,9 @@ impl GpuStore {
             updates: Vec::new(),
             update_data: Vec::new(),
             update_control: Vec::new(),
+            update_physics: Vec::new(),
+            update_scale: Vec::new(),
+            update_constants: None,
             pending_pushes: Vec::with_capacity(WORK_GROUP_WIDTH as usize),
             gpu_result: None,
             cpu_mirror: Arc::new(Mutex::new(GpuStoreMirror {
@@ -556,6 +599,33 @@ impl GpuStore {
         self.update_control.push(control);
     }
 
+    /// Replaces the vehicle parameters of a live body.
+    pub fn update_physics(&mut self, body: &GpuBody, car_physics: &CarPhysics) {
+        self.updates.push((
+            body.index(),
+            Update::SetPhysics {
+                index: self.update_physics.len(),
+            },
+        ));
+        self.update_physics.push(Physics::new(car_physics));
+    }
+
+    /// Replaces the scale of a body, keeping the rest of its state.
+    pub fn update_scale(&mut self, body: &GpuBody, scale: f32) {
+        self.updates.push((
+            body.index(),
+            Update::SetScale {
+                index: self.update_scale.len(),
+            },
+        ));
+        self.update_scale.push(scale);
+    }
+
+    /// Replaces the global parameters shared by all the bodies.
+    pub fn update_common(&mut self, common: &Common) {
+        self.update_constants = Some(Constants::new(common));
+    }
+
     pub fn add_push(&mut self, body: &GpuBody, vec: cgmath::Vector3<f32>) {
         self.pending_pushes.push(GpuPush {
             dir_id: [vec.x, vec.y, vec.z, body.index() as f32],
@@ -607,26 +677,7 @@ impl GpuStore {
                 jacobi1: [ji[3], ji[4], ji[5], model.body.bbox.radius],
                 jacobi2: [ji[6], ji[7], ji[8], 0.0],
             },
-            physics: Physics {
-                scale: [
-                    car_physics.scale_size,
-                    car_physics.scale_bound,
-                    car_physics.scale_box,
-                    car_physics.z_offset_of_mass_center,
-                ],
-                mobility_ship: [
-                    car_physics.mobility_factor,
-                    car_physics.k_archimedean,
-                    car_physics.k_water_traction,
-                    car_physics.k_water_rudder,
-                ],
-                speed: [
-                    car_physics.speed_factor,
-                    car_physics.water_speed_factor,
-                    car_physics.air_speed_factor,
-                    car_physics.underground_speed_factor,
-                ],
-            },
+            physics: Physics::new(car_physics),
             wheels,
         };
 
@@ -667,6 +718,42 @@ impl GpuStore {
             self.update_control.clear();
             Some(buf)
         };
+        let buf_set_physics = if self.update_physics.is_empty() {
+            None
+        } else {
+            let buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("temp-physics"),
+                contents: bytemuck::cast_slice(&self.update_physics),
+                usage: wgpu::BufferUsages::COPY_SRC,
+            });
+            self.update_physics.clear();
+            Some(buf)
+        };
+        let buf_set_scale = if self.update_scale.is_empty() {
+            None
+        } else {
+            let buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("temp-scale"),
+                contents: bytemuck::cast_slice(&self.update_scale),
+                usage: wgpu::BufferUsages::COPY_SRC,
+            });
+            self.update_scale.clear();
+            Some(buf)
+        };
+        if let Some(constants) = self.update_constants.take() {
+            let temp = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("temp-constants"),
+                contents: bytemuck::bytes_of(&constants),
+                usage: wgpu::BufferUsages::COPY_SRC,
+            });
+            encoder.copy_buffer_to_buffer(
+                &temp,
+                0,
+                &self.buf_constants,
+                0,
+                mem::size_of::<Constants>() as wgpu::BufferAddress,
+            );
+        }
 
         for (body_id, update) in self.updates.drain(..) {
             let data_size = mem::size_of::<Data>();
@@ -690,6 +777,26 @@ impl GpuStore {
                         size as wgpu::BufferAddress,
                     );
                 }
+                Update::SetPhysics { index } => {
+                    let size = mem::size_of::<Physics>();
+                    encoder.copy_buffer_to_buffer(
+                        buf_set_physics.as_ref().unwrap(),
+                        (index * size) as wgpu::BufferAddress,
+                        &self.buf_data,
+                        (body_id * data_size + Data::PHYSICS_OFFSET) as wgpu::BufferAddress,
+                        size as wgpu::BufferAddress,
+                    );
+                }
+                Update::SetScale { index } => {
+                    let size = mem::size_of::<f32>();
+                    encoder.copy_buffer_to_buffer(
+                        buf_set_scale.as_ref().unwrap(),
+                        (index * size) as wgpu::BufferAddress,
+                        &self.buf_data,
+                        (body_id * data_size + Data::SCALE_OFFSET) as wgpu::BufferAddress,
+                        size as wgpu::BufferAddress,
+                    );
+                }
             }
         }
     }
@@ -388,6 +388,7 @@ pub struct Batcher {
     instances: HashMap<*const model::Mesh, InstanceArray>,
     debug_shapes: Vec<Arc<model::Shape>>,
     debug_instances: Vec<object::Instance>,
+    lod_view: Option<(cgmath::Vector3<f32>, settings::Lod)>,
 }
 
 impl Batcher {
@@ -396,6 +397,34 @@ impl Batcher {
             instances: HashMap::new(),
             debug_shapes: Vec::new(),
             debug_instances: Vec::new(),
+            lod_view: None,
+        }
+    }
+
+    /// Sets the point the distances are measured from when picking
+    /// the simplified meshes. Only the full meshes are used without it.
+    pub fn set_lod_view(&mut self, origin: Option<cgmath::Vector3<f32>>, lod: &settings::Lod) {
+        self.lod_view = origin.map(|origin| (origin, *lod));
+    }
+
+    fn select_lod<'a>(
+        &self,
+        mesh: &'a Arc<model::Mesh>,
+        transform: &Transform,
+    ) -> &'a Arc<model::Mesh> {
+        use cgmath::InnerSpace as _;
+
+        let (origin, ref lod) = match self.lod_view {
+            Some(view) if !mesh.lods.is_empty() => view,
+            _ => return mesh,
+        };
+        let size = (mesh.bbox.radius * transform.scale).max(1.0);
+        let ratio = (transform.disp - origin).magnitude() / size / lod.distance;
+        if ratio < 1.0 {
+            mesh
+        } else {
+            let level = (ratio.log2() as usize + 1).min(mesh.lods.len());
+            &mesh.lods[level - 1]
         }
     }
 
@@ -422,8 +451,9 @@ impl Batcher {
         use cgmath::{One as _, Rotation3 as _, Transform as _};
 
         // body
+        let body = self.select_lod(&model.body, base_transform);
         self.add_mesh(
-            &model.body,
+            body,
             object::Instance::new(base_transform, 0.0, gpu_body, color),
         );
         if let Some(shape_scale) = debug_shape_scale {
@@ -444,6 +474,7 @@ impl Batcher {
                     rot: cgmath::Quaternion::one(),
                     scale: 1.0,
                 });
+                let mesh = self.select_lod(mesh, &transform);
                 self.add_mesh(
                     mesh,
                     object::Instance::new(&transform, 0.0, gpu_body, color),
@@ -469,6 +500,20 @@ impl Batcher {
         }
     }
 
+    /// Adds the current frame of the animation.
+    pub fn add_animated_model(
+        &mut self,
+        model: &model::AnimatedModel,
+        transform: &Transform,
+        gpu_body: &body::GpuBody,
+        color: object::BodyColor,
+    ) {
+        self.add_mesh(
+            model.current(),
+            object::Instance::new(transform, 0.0, gpu_body, color),
+        );
+    }
+
     pub fn prepare(&mut self, device: &wgpu::Device) {
         for array in self.instances.values_mut() {
             if !array.data.is_empty() {
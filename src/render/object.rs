@@ -39,7 +39,7 @@ const COLOR_TABLE: [[u8; 2]; NUM_COLOR_IDS as usize] = [
 ];
 
 #[repr(u32)]
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
 pub enum BodyColor {
     Dummy = 1,
     Green = 21,
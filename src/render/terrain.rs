@@ -1026,6 +1026,17 @@ impl Context {
         }
     }
 
+    /// Takes over the regions modified by level editing.
+    pub fn mark_dirty(&mut self, tracker: &mut level::DirtyTracker) {
+        self.dirty_rects
+            .extend(tracker.drain().map(|region| super::Rect {
+                x: region.x as u16,
+                y: region.y as u16,
+                w: region.w as u16,
+                h: region.h as u16,
+            }));
+    }
+
     pub fn update_dirty(
         &mut self,
         encoder: &mut wgpu::CommandEncoder,
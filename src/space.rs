@@ -1,3 +1,5 @@
+use crate::level::query;
+
 use cgmath::{EuclideanSpace as _, InnerSpace as _, Rotation as _, Rotation3 as _, Transform as _};
 use std::ops::Range;
 
@@ -128,10 +130,12 @@ impl Camera {
     }
 
     fn intersect_ray_height(&self, dir: cgmath::Vector3<f32>, height: f32) -> cgmath::Point3<f32> {
-        let t_raw = (height - self.loc.z) / dir.z;
-        let range = self.depth_range();
-        let t = range.start.max(t_raw).min(range.end);
-        cgmath::Point3::from_vec(self.loc) + t * dir
+        query::intersect_plane(
+            cgmath::Point3::from_vec(self.loc),
+            dir,
+            height,
+            self.depth_range(),
+        )
     }
 
     pub fn intersect_height(&self, height: f32) -> cgmath::Point3<f32> {
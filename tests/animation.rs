@@ -0,0 +1,34 @@
+use vangers::model::Playback;
+
+#[test]
+fn playback_modes() {
+    let frames = 4;
+    let at = |playback: Playback, time: f32| playback.frame_position(time, frames);
+
+    assert_eq!(at(Playback::Once, 1.5), 1.5);
+    assert_eq!(at(Playback::Once, 10.0), 3.0);
+    assert_eq!(at(Playback::Once, -1.0), 0.0);
+
+    assert_eq!(at(Playback::Loop, 3.5), 3.5);
+    assert_eq!(at(Playback::Loop, 4.0), 0.0);
+    assert_eq!(at(Playback::Loop, 9.25), 1.25);
+
+    assert_eq!(at(Playback::PingPong, 2.0), 2.0);
+    assert_eq!(at(Playback::PingPong, 4.0), 2.0);
+    assert_eq!(at(Playback::PingPong, 6.0), 0.0);
+    assert_eq!(at(Playback::PingPong, 7.5), 1.5);
+
+    assert_eq!(Playback::PingPong.frame_position(5.0, 1), 0.0);
+    assert_eq!(Playback::Loop.frame_position(5.0, 1), 0.0);
+}
+
+#[test]
+fn key_frame_blending() {
+    let frames = 4;
+    assert_eq!(Playback::Loop.key_frames(1.25, frames), (1, 2, 0.25));
+    // the last frame blends back into the first one
+    assert_eq!(Playback::Loop.key_frames(3.5, frames), (3, 0, 0.5));
+    assert_eq!(Playback::Once.key_frames(10.0, frames), (3, 3, 0.0));
+    assert_eq!(Playback::PingPong.key_frames(4.5, frames), (1, 2, 0.5));
+    assert_eq!(Playback::Loop.key_frames(0.5, 1), (0, 0, 0.5));
+}
@@ -0,0 +1,314 @@
+//! The converter is a binary, so its modules are compiled into the test directly.
+
+#[allow(dead_code)]
+#[path = "../bin/convert/model_obj.rs"]
+mod model_obj;
+
+#[allow(dead_code)]
+#[path = "../bin/convert/model_gltf.rs"]
+mod model_gltf;
+
+#[path = "../bin/convert/shape.rs"]
+mod shape;
+
+use m3d::{
+    BodyColor, Bounds, CollisionMesh, CollisionQuad, ColorId, Debrie, DrawMesh, DrawTriangle,
+    FullModel, Geometry, Mesh, Physics, Slot, UpperBound, Vertex, Wheel,
+};
+
+use std::fs::{self, File};
+
+fn tetrahedron(offset: [i32; 3], rotation: [i32; 3]) -> DrawMesh {
+    let vertex = |pos, normal| Vertex { pos, normal };
+    Mesh {
+        geometry: Geometry {
+            positions: vec![[0, 0, 0], [10, 0, 0], [0, 10, 0], [0, 0, 10]],
+            normals: vec![[0, 0, -124], [-124, 0, 0]],
+            polygons: vec![
+                DrawTriangle {
+                    vertices: [vertex(0, 0), vertex(2, 0), vertex(1, 0)],
+                    flat_normal: [0, 0, -124],
+                    material: [ColorId::Body as u32, 0],
+                },
+                DrawTriangle {
+                    vertices: [vertex(0, 1), vertex(3, 1), vertex(2, 1)],
+                    flat_normal: [-124, 0, 0],
+                    material: [ColorId::Window as u32, 3],
+                },
+            ],
+        },
+        bounds: Bounds {
+            coord_min: [0; 3],
+            coord_max: [10, 10, 10],
+        },
+        parent_off: offset,
+        parent_rot: rotation,
+        max_radius: 10,
+        physics: Physics {
+            volume: 1.5,
+            rcm: [0.5; 3],
+            jacobi: [[1.0, 0.0, 0.0], [0.0, 2.0, 0.0], [0.0, 0.0, 3.0]],
+        },
+    }
+}
+
+fn shape(mesh: &DrawMesh) -> CollisionMesh {
+    Mesh {
+        geometry: Geometry {
+            positions: vec![[0, 0, 0], [10, 0, 0], [10, 10, 0], [0, 10, 0]],
+            normals: Vec::new(),
+            polygons: vec![CollisionQuad {
+                vertices: [0, 1, 2, 3],
+                middle: [5, 5, 0],
+                flat_normal: [0, 0, 124],
+            }],
+        },
+        bounds: mesh.bounds,
+        parent_off: mesh.parent_off,
+        parent_rot: mesh.parent_rot,
+        max_radius: 8,
+        physics: mesh.physics,
+    }
+}
+
+/// Corner positions and materials of the triangles, in a canonical order.
+fn triangles(mesh: &DrawMesh) -> Vec<([[i8; 3]; 3], [u32; 2])> {
+    let geo = &mesh.geometry;
+    let mut list = geo
+        .polygons
+        .iter()
+        .map(|tri| {
+            let corners = tri.vertices.map(|v| geo.positions[v.pos as usize]);
+            // the winding is kept, but the first corner may change
+            let first = (0..3).min_by_key(|&i| corners[i]).unwrap();
+            let rotated = [0, 1, 2].map(|i| corners[(first + i) % 3]);
+            (rotated, tri.material)
+        })
+        .collect::<Vec<_>>();
+    list.sort();
+    list
+}
+
+fn assert_mesh_eq(a: &DrawMesh, b: &DrawMesh) {
+    assert_eq!(triangles(a), triangles(b));
+    assert_eq!(a.parent_off, b.parent_off);
+    assert_eq!(a.parent_rot, b.parent_rot);
+    assert_eq!(a.max_radius, b.max_radius);
+    assert_eq!(a.bounds.coord_max, b.bounds.coord_max);
+    assert_eq!(a.physics.jacobi, b.physics.jacobi);
+}
+
+fn assert_shape_eq(a: &CollisionMesh, b: &CollisionMesh) {
+    let positions = |mesh: &CollisionMesh| {
+        mesh.geometry
+            .polygons
+            .iter()
+            .map(|quad| quad.vertices.map(|v| mesh.geometry.positions[v as usize]))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(positions(a), positions(b));
+    assert_eq!(a.parent_off, b.parent_off);
+    assert_eq!(a.max_radius, b.max_radius);
+}
+
+fn full_model() -> FullModel {
+    let body = tetrahedron([0; 3], [0; 3]);
+    FullModel {
+        shape: shape(&body),
+        body,
+        bound: UpperBound {
+            dimensions: [20, 30, 10],
+            radius: 25,
+        },
+        color: BodyColor {
+            offset: 128,
+            shift: 3,
+        },
+        wheels: vec![
+            Wheel {
+                mesh: Some(tetrahedron([8, 10, -4], [0; 3])),
+                steer: 1,
+                pos: [8.0, 10.0, -4.0],
+                width: 4,
+                radius: 5,
+                bound_index: 0,
+            },
+            Wheel {
+                mesh: None,
+                steer: 0,
+                pos: [-8.0, -10.0, -4.0],
+                width: 4,
+                radius: 5,
+                bound_index: 0,
+            },
+        ],
+        debris: {
+            let mesh = tetrahedron([2, 3, 4], [90, 0, 45]);
+            vec![Debrie {
+                shape: shape(&mesh),
+                mesh,
+            }]
+        },
+        slots: [
+            Slot {
+                mesh: Some(tetrahedron([1, 2, 3], [0; 3])),
+                scale: 1.0,
+                pos: [3, 4, 5],
+                angle: 90,
+            },
+            Slot::EMPTY,
+            Slot::EMPTY,
+        ],
+    }
+}
+
+#[test]
+fn gltf_roundtrip() {
+    let model = full_model();
+
+    let dir = std::env::temp_dir().join(format!("vangers-gltf-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let gltf_path = dir.join("model.gltf");
+    model_gltf::export_m3d(full_model(), &gltf_path);
+    let json: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&gltf_path).unwrap()).unwrap();
+    let mut imported = model_gltf::import_m3d(&gltf_path).unwrap();
+    // the slot meshes are not a part of M3D
+    let slot_meshes = imported.slots.each_mut().map(|slot| slot.mesh.take());
+    let m3d_path = dir.join("model.m3d");
+    imported.save(File::create(&m3d_path).unwrap());
+    let back = FullModel::load(File::open(&m3d_path).unwrap()).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_mesh_eq(&back.body, &model.body);
+    assert_shape_eq(&back.shape, &model.shape);
+    assert_eq!(back.bound.dimensions, model.bound.dimensions);
+    assert_eq!(back.color.offset, model.color.offset);
+    assert_eq!(back.color.shift, model.color.shift);
+
+    assert_eq!(back.wheels.len(), 2);
+    assert_mesh_eq(
+        back.wheels[0].mesh.as_ref().unwrap(),
+        model.wheels[0].mesh.as_ref().unwrap(),
+    );
+    assert!(back.wheels[1].mesh.is_none());
+    for (a, b) in back.wheels.iter().zip(model.wheels.iter()) {
+        assert_eq!(a.pos, b.pos);
+        assert_eq!(a.steer, b.steer);
+        assert_eq!(a.width, b.width);
+    }
+
+    assert_eq!(back.debris.len(), 1);
+    assert_mesh_eq(&back.debris[0].mesh, &model.debris[0].mesh);
+    assert_shape_eq(&back.debris[0].shape, &model.debris[0].shape);
+
+    assert_mesh_eq(
+        slot_meshes[0].as_ref().unwrap(),
+        model.slots[0].mesh.as_ref().unwrap(),
+    );
+    assert_eq!(back.slots[0].pos, model.slots[0].pos);
+    assert_eq!(back.slots[0].angle, model.slots[0].angle);
+    assert!(slot_meshes[1].is_none() && slot_meshes[2].is_none());
+
+    // the rotation of the debris is visible to the glTF tools
+    let node = json["nodes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|node| node["name"] == "debrie0")
+        .unwrap();
+    let rotation = node["rotation"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| v.as_f64().unwrap() as f32)
+        .collect::<Vec<_>>();
+    // 90 degrees around X, followed by 45 degrees around Z
+    assert_eq!(rotation.len(), 4);
+    let expected = [0.65328, 0.27060, 0.27060, 0.65328];
+    for (a, b) in rotation.iter().zip(expected.iter()) {
+        assert!((a - b).abs() < 1e-5, "{:?} != {:?}", rotation, expected);
+    }
+}
+
+#[test]
+fn gltf_without_extras() {
+    // close the body, so that it has a volume
+    let mut model = full_model();
+    let vertex = |pos| Vertex { pos, normal: 0 };
+    for [a, b, c] in [[0, 1, 3], [1, 2, 3]] {
+        model.body.geometry.polygons.push(DrawTriangle {
+            vertices: [vertex(a), vertex(b), vertex(c)],
+            flat_normal: [0; 3],
+            material: [ColorId::Body as u32, 0],
+        });
+    }
+
+    let dir = std::env::temp_dir().join(format!("vangers-gltf-bare-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let gltf_path = dir.join("model.gltf");
+    model_gltf::export_m3d(model, &gltf_path);
+    let mut json: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&gltf_path).unwrap()).unwrap();
+    // the mesh extras are lost by the tools that don't keep custom properties
+    for node in json["nodes"].as_array_mut().unwrap() {
+        if let Some(extras) = node["extras"].as_object_mut() {
+            extras.remove("mesh");
+        }
+    }
+    fs::write(&gltf_path, json.to_string()).unwrap();
+    let imported = model_gltf::import_m3d(&gltf_path);
+
+    json["materials"][ColorId::Body as usize]["name"] = "Chrome".into();
+    fs::write(&gltf_path, json.to_string()).unwrap();
+    let unknown_material = model_gltf::import_m3d(&gltf_path).err();
+
+    let glb_path = dir.join("model.glb");
+    let mut glb = b"glTF".to_vec();
+    for value in [2u32, 40, 100, 0x4E4F_534A] {
+        glb.extend_from_slice(&value.to_le_bytes());
+    }
+    glb.extend_from_slice(b"{}");
+    fs::write(&glb_path, &glb).unwrap();
+    let truncated = model_gltf::import_m3d(&glb_path).err();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let body = imported.unwrap().body;
+    assert_eq!(body.bounds.coord_min, [0; 3]);
+    assert_eq!(body.bounds.coord_max, [10; 3]);
+    assert_eq!(body.max_radius, 10);
+    // the tetrahedron with the legs of 10
+    assert!((body.physics.volume - 1000.0 / 6.0).abs() < 1e-3);
+    for rcm in body.physics.rcm {
+        assert!((rcm - 2.5).abs() < 1e-5);
+    }
+
+    let message = unknown_material.unwrap();
+    assert!(message.starts_with("node "), "{}", message);
+    assert!(message.contains("material Chrome"), "{}", message);
+    let message = truncated.unwrap();
+    assert!(message.contains("truncated"), "{}", message);
+}
+
+#[test]
+fn hull_keeps_wheel_bounds() {
+    let mut model = full_model();
+    // bound to a polygon the old shape doesn't have
+    model.wheels[1].bound_index = 5;
+    shape::update_model(&mut model, Some(8));
+
+    let geometry = &model.shape.geometry;
+    let corners = |index: u32| {
+        let quad = &geometry.polygons[index as usize];
+        quad.vertices.map(|v| geometry.positions[v as usize])
+    };
+    // the old polygon was on the bottom
+    assert!(corners(model.wheels[0].bound_index)
+        .iter()
+        .all(|p| p[2] == 0));
+    // the wheel is at negative X and Y, closest to the side facing -Y
+    assert!(corners(model.wheels[1].bound_index)
+        .iter()
+        .all(|p| p[1] == 0));
+    model.validate().unwrap();
+}
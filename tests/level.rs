@@ -0,0 +1,496 @@
+use vangers::level::{load_vmc, LevelData, LevelError, Region, VmcReader};
+
+/// Creates a scratch directory for a single test, so parallel tests
+/// and concurrent runs don't step on each other's files.
+fn test_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("vangers-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn vmc_roundtrip() {
+    let size = (64, 32);
+    let total = (size.0 * size.1) as usize;
+    let level = LevelData {
+        height: (0..total).map(|i| (i / 3 + i % 7) as u8).collect(),
+        meta: (0..total).map(|i| ((i / 16) & 0x38) as u8).collect(),
+        size,
+    };
+    let dir = test_dir("roundtrip");
+    let path = dir.join("world.vmc");
+    level.save_vmc(&path).unwrap();
+    let loaded = load_vmc(&path, size);
+    let reader = VmcReader::open(&path, size).unwrap();
+    let region = reader
+        .load_region(Region {
+            x: 60,
+            y: -3,
+            w: 8,
+            h: 5,
+        })
+        .unwrap();
+    let out_of_bounds = reader.load_rows(30..33).map(|_| ());
+    let unaligned = reader
+        .load_region(Region {
+            x: 1,
+            y: 0,
+            w: 8,
+            h: 2,
+        })
+        .map(|_| ());
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(loaded.height == level.height);
+    assert!(loaded.meta == level.meta);
+
+    assert_eq!(region.size, (8, 5));
+    assert_eq!(region.height[0], level.height[29 * 64 + 60]);
+    assert_eq!(region.height[5], level.height[29 * 64 + 1]);
+    assert_eq!(region.meta[4 * 8 + 7], level.meta[64 + 3]);
+    assert!(matches!(
+        out_of_bounds,
+        Err(LevelError::RowsOutOfBounds { height: 32, .. })
+    ));
+    assert!(matches!(unaligned, Err(LevelError::UnalignedRegion(_))));
+}
+
+#[test]
+fn vmc_incompressible_row() {
+    // a single row of noise, too wide to fit the row table
+    let size = (1 << 15, 1);
+    let mut state = 0x2545_f491u32;
+    let mut noise = || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as u8
+    };
+    let level = LevelData {
+        height: (0..size.0).map(|_| noise()).collect(),
+        meta: (0..size.0).map(|_| noise()).collect(),
+        size,
+    };
+    let dir = test_dir("incompressible");
+    let path = dir.join("world.vmc");
+    let error = level.save_vmc(&path).unwrap_err();
+    let exists = path.exists();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert!(!exists);
+}
+
+#[test]
+fn vpr_roundtrip() {
+    use vangers::level::vpr::{Dimensions, Vpr};
+
+    let dim = Dimensions {
+        net_size: 16,
+        geo_power: 3,
+        num_sections: 4,
+    };
+    let bytes = (0..dim.file_size())
+        .map(|i| (i * 7) as u8)
+        .collect::<Vec<_>>();
+    let vpr = Vpr::read(&mut &bytes[..], dim).unwrap();
+    assert_eq!(vpr.dimensions(), dim);
+    let mut output = Vec::new();
+    vpr.write(&mut output).unwrap();
+    assert!(output == bytes);
+}
+
+#[test]
+fn vpr_estimate() {
+    use vangers::level::{vpr::Vpr, LevelConfig};
+
+    let dir = test_dir("vpr");
+    let path = dir.join("world.ini");
+    let source = "[Global Parameters]\n\
+        Map Power X = 4\n\
+        Map Power Y = 4\n\
+        GeoNet Power = 2\n\
+        Section Size Power = 3\n\
+        Minimal Square Power = 1\n\
+        [Storage]\n\
+        File Name = output\n\
+        Palette File = harmony.pal\n\
+        Compressed Format Using = 0\n\
+        [Rendering Parameters]\n\
+        Shadow Offsets = 0 1 2 3 4 5 6 7\n\
+        Height Shifts = 0 0 0 0 0 0 0 0\n\
+        Begin Colors = 0 8 16 24 32 40 48 56\n\
+        End Colors = 7 15 23 31 39 47 55 63\n";
+    std::fs::write(&path, source).unwrap();
+    let config = LevelConfig::try_load(&path).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // water (terrain 0) only in the lower half
+    let level = LevelData {
+        height: (0..256).map(|i| (i % 16 + i / 16) as u8).collect(),
+        meta: (0..256).map(|i| if i < 128 { 1 << 3 } else { 0 }).collect(),
+        size: (16, 16),
+    };
+    let dim = vangers::level::vpr::Dimensions::new(&config);
+    let bytes = (0..dim.file_size())
+        .map(|i| (i * 5) as u8)
+        .collect::<Vec<_>>();
+    let base = Vpr::read(&mut &bytes[..], dim).unwrap();
+    let vpr = Vpr::from_level(&level, &config, Some(base));
+    let mut output = Vec::new();
+    vpr.write(&mut output).unwrap();
+    // the existing tables are kept
+    assert!(output == bytes);
+
+    let fresh = Vpr::from_level(&level, &config, None);
+    assert_eq!(fresh.params, [0; 9]);
+    assert_eq!(fresh.geo_net[0], [0, 6]);
+    assert_eq!(fresh.geo_net[15], [24, 30]);
+    assert_eq!(&fresh.flood[..], &[0, 30]);
+}
+
+/// Estimates the VPR tables of every game level, and compares them to the original files.
+/// Needs `VANGERS_DATA_PATH` to point to the resources of the game.
+#[test]
+#[ignore = "needs the game data in VANGERS_DATA_PATH"]
+fn vpr_estimate_matches_game() {
+    use vangers::{
+        config::worlds,
+        level::{vpr::Vpr, LevelConfig},
+    };
+
+    let data_path = std::path::PathBuf::from(
+        std::env::var_os("VANGERS_DATA_PATH").expect("VANGERS_DATA_PATH is not set"),
+    );
+    let worlds = worlds::load(&data_path.join("wrlds.dat")).unwrap();
+    for (name, ini_name) in worlds {
+        let config = LevelConfig::try_load(&data_path.join(ini_name)).unwrap();
+        let original = match Vpr::load_existing(&config).unwrap() {
+            Some(vpr) => vpr,
+            None => continue,
+        };
+        let level = vangers::level::try_load(&config).unwrap();
+        let vpr = Vpr::from_level(&LevelData::from(level), &config, None);
+        assert!(
+            vpr.geo_net == original.geo_net,
+            "GeoNet of {} differs",
+            name
+        );
+        assert!(vpr.flood == original.flood, "flood of {} differs", name);
+    }
+}
+
+#[test]
+fn texel_editing() {
+    use vangers::level::{Brush, BrushOp, DirtyTracker, Level, Point, Texel};
+
+    let mut level = Level::new_test();
+    level.size = (8, 8);
+    level.height = vec![10; 64].into_boxed_slice();
+    level.meta = vec![0; 64].into_boxed_slice();
+
+    level.set(
+        (3, 2),
+        Texel::Dual {
+            low: Point(5, 1),
+            high: Point(40, 2),
+            delta: 0x18,
+        },
+    );
+    match level.get((2, 2)) {
+        Texel::Dual { low, high, delta } => {
+            assert_eq!((low.0, low.1, high.0, high.1, delta), (5, 1, 40, 2, 0x18));
+        }
+        Texel::Single(_) => panic!("Dual texel expected"),
+    }
+
+    level.set((2, 2), Texel::Single(Point(7, 3)));
+    match (level.get((2, 2)), level.get((3, 2))) {
+        (Texel::Single(a), Texel::Single(b)) => assert_eq!((a.0, a.1, b.0, b.1), (7, 3, 40, 2)),
+        _ => panic!("Single texels expected"),
+    }
+
+    let region = level.apply_brush(
+        &Brush {
+            center: (0, 0),
+            radius: 1,
+        },
+        BrushOp::Raise(5),
+    );
+    assert_eq!(level.get((-1, 0)).top(), 15);
+    assert_eq!(level.get((1, 1)).top(), 10);
+
+    let mut tracker = DirtyTracker::new(level.size);
+    tracker.add(region);
+    assert_eq!(tracker.drain().count(), 4);
+}
+
+#[test]
+fn ini_patching() {
+    use vangers::level::LevelConfig;
+
+    let dir = test_dir("ini");
+    let path = dir.join("world.ini");
+    let source = "; hand-written\r\n\
+        [Global Parameters]\r\n\
+        Map Power X = 11\r\n\
+        Map Power Y = 14  ; height\r\n\
+        GeoNet Power = 5\r\n\
+        Section Size Power = 7\r\n\
+        Minimal Square Power = 2\r\n\
+        Custom Key = 42\r\n\
+        \r\n\
+        [Storage]\r\n\
+        File Name = output\r\n\
+        Palette File = harmony.pal\r\n\
+        Compressed Format Using = 0\r\n\
+        \r\n\
+        [Rendering Parameters]\r\n\
+        Shadow Offsets = 0 1 2 3 4 5 6 7\r\n\
+        Height Shifts = 0 0 0 0 0 0 0 0\r\n\
+        Begin Colors = 0 8 16 24 32 40 48 56\r\n\
+        End Colors = 7 15 23 31 39 47 55 63\r\n\
+        \r\n\
+        [Unknown]\r\n\
+        Something = else\r\n";
+    std::fs::write(&path, source).unwrap();
+
+    let mut config = LevelConfig::try_load(&path).unwrap();
+    config.size.1 = vangers::level::Power(12);
+    config.is_compressed = true;
+    config.save(&path).unwrap();
+    let text = std::fs::read_to_string(&path).unwrap();
+    let reloaded = LevelConfig::try_load(&path).unwrap();
+    let partial = config.patch_ini(&path, b"[Storage]\nFile Name = x\n\n; tail\n");
+    let unreadable = config.save(&dir);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(unreadable.is_err());
+
+    assert!(text.starts_with("; hand-written\r\n"));
+    assert!(text.contains("Map Power Y = 12  ; height\r\n"));
+    assert!(text.contains("Terrain Max = 8\r\n"));
+    assert!(text.contains("Custom Key = 42\r\n"));
+    assert!(text.contains("Compressed Format Using = 1\r\n"));
+    assert!(text.contains("[Unknown]\r\nSomething = else\r\n"));
+    assert_eq!(reloaded.size.1.as_power(), 12);
+    assert!(reloaded.is_compressed);
+    assert_eq!(reloaded.terrains.len(), config.terrains.len());
+
+    let partial = String::from_utf8(partial).unwrap();
+    assert!(partial.starts_with(
+        "[Storage]\nFile Name = output\nPalette File = harmony.pal\nCompressed Format Using = 1\n\n; tail\n"
+    ));
+    assert!(partial.contains("\n[Global Parameters]\nMap Power X = 11\n"));
+}
+
+#[test]
+fn terrain_queries() {
+    use cgmath::{Point3, Vector3};
+    use vangers::level::{query, Level, Point, Texel};
+
+    let mut level = Level::new_test();
+    level.size = (8, 8);
+    level.height = vec![0; 64].into_boxed_slice();
+    level.meta = vec![0; 64].into_boxed_slice();
+    for x in 0..8 {
+        level.set((x, 4), Texel::Single(Point(255, 0)));
+    }
+    level.set(
+        (2, 2),
+        Texel::Dual {
+            low: Point(0, 0),
+            high: Point(200, 0),
+            delta: 0x60,
+        },
+    );
+
+    assert_eq!(
+        query::sample_height(&level, Point3::new(1.5, 1.5, 10.0)),
+        0.0
+    );
+    let slope = query::sample_height(&level, Point3::new(1.5, 4.0, 10.0));
+    assert!((slope - 64.0).abs() < 0.01);
+    let normal = query::sample_normal(&level, Point3::new(1.5, 3.5, 10.0));
+    assert!(normal.y < 0.0 && normal.z > 0.0);
+
+    // straight down onto the roof of the cave
+    let hit = query::cast_ray(
+        &level,
+        Point3::new(2.5, 2.5, 200.0),
+        Vector3::new(0.0, 0.0, -1.0),
+        300.0,
+    )
+    .unwrap();
+    assert!((hit.pos.z - query::get_height(200)).abs() < 0.1);
+    // horizontally through the cave, hitting the wall with a wrap-around
+    let hit = query::cast_ray(
+        &level,
+        Point3::new(2.5, 1.5, 10.0),
+        Vector3::new(0.0, -1.0, 0.0),
+        20.0,
+    )
+    .unwrap();
+    assert!((hit.pos.y + 3.0).abs() < 0.1);
+    assert!(query::is_solid(&level, Point3::new(2.5, 2.5, 80.0)));
+    assert!(!query::is_solid(&level, Point3::new(2.5, 2.5, 30.0)));
+
+    let dir = Vector3::new(1.0, 0.0, -1.0);
+    let pos = query::intersect_plane(Point3::new(0.0, 0.0, 100.0), dir, 40.0, 1.0..1000.0);
+    assert_eq!(pos, Point3::new(60.0, 0.0, 40.0));
+    let pos = query::intersect_plane(Point3::new(0.0, 0.0, 100.0), dir, 40.0, 1.0..10.0);
+    assert_eq!(pos, Point3::new(10.0, 0.0, 90.0));
+}
+
+#[test]
+fn palette_tables() {
+    use vangers::level::{palette, TerrainConfig};
+
+    let terrains = (0..8u8)
+        .map(|i| TerrainConfig {
+            shadow_offset: 0,
+            height_shift: 0,
+            colors: i * 28..i * 28 + 27,
+        })
+        .collect::<Vec<_>>();
+    let raw = (0..0x300).map(|i| (i % 64) as u8).collect::<Vec<_>>();
+    let data = palette::load(&raw[..], Some(&terrains)).unwrap();
+    assert_eq!(data[0], [0; 4]);
+    // start of the second terrain, halved and scaled
+    assert_eq!(
+        data[28],
+        [(20 >> 1) << 2, (21 >> 1) << 2, (22 >> 1) << 2, 0]
+    );
+    assert_eq!(data[29], [23 << 2, 24 << 2, 25 << 2, 0]);
+    assert_eq!(data[224 + 5], [80, 80, 80, 0]);
+    let objects = palette::load(&raw[..], None).unwrap();
+    assert_eq!(objects[0], [0, 4, 8, 0]);
+
+    // the water is lit differently from the other terrain types
+    let tables = palette::ColorTables::new(&terrains);
+    assert_eq!(tables.light[1][0x100], 131);
+    assert_eq!(tables.light[0][0x100], 153);
+    assert_eq!(tables.light[1][0x100 + 255], 0);
+    assert_eq!(tables.colors[2][0], 56);
+    assert_eq!(tables.colors[2][255], 83);
+    assert_eq!(tables.color_id(1, 255, 0, 1.0), 28 + 14);
+    assert_eq!(tables.color_id(1, 0, 0, 1.0), 28);
+}
+
+#[test]
+fn generated_level() {
+    use vangers::level::{
+        generate::{generate, GeneratorParams},
+        LevelConfig, Power, DOUBLE_LEVEL,
+    };
+
+    let dir = test_dir("generated");
+    let ini_path = dir.join("world.ini");
+    let params = GeneratorParams {
+        seed: 5,
+        size: (Power(8), Power(8)),
+        num_tunnels: 2,
+        num_overhangs: 4,
+        is_compressed: true,
+        ..GeneratorParams::default()
+    };
+    let generated = generate(&params, &ini_path);
+    generated.save(&ini_path).unwrap();
+    let config = LevelConfig::try_load(&ini_path).unwrap();
+    let level = vangers::level::try_load(&config).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(level.height == generated.data.height);
+    assert!(level.meta == generated.data.meta);
+    assert!(level.flood_map == generated.flood_map);
+    assert!(level.palette[..] == generated.to_level().palette[..]);
+    assert!(level.meta.iter().any(|&m| m & DOUBLE_LEVEL != 0));
+
+    let again = generate(&params, &ini_path);
+    assert!(again.data.height == generated.data.height);
+}
+
+#[test]
+fn crop_resample_stitch() {
+    use vangers::level::{Region, TerrainBits, DOUBLE_LEVEL};
+
+    let bits = TerrainBits::new(8);
+    let size = (16, 8);
+    let total = (size.0 * size.1) as usize;
+    let mut level = LevelData {
+        height: (0..total).map(|i| (i % 16 * 8) as u8).collect(),
+        meta: (0..total).map(|i| bits.write((i / 16 % 8) as u8)).collect(),
+        size,
+    };
+    // a dual pair near the left edge
+    level.meta[3 * 16 + 2] |= DOUBLE_LEVEL | 0x2;
+    level.meta[3 * 16 + 3] |= DOUBLE_LEVEL;
+
+    let crop = level
+        .crop(Region {
+            x: 12,
+            y: 2,
+            w: 8,
+            h: 2,
+        })
+        .unwrap();
+    assert_eq!(crop.size, (8, 2));
+    assert_eq!(crop.height[0], 12 * 8);
+    assert_eq!(crop.height[4], 0);
+    assert_eq!(crop.meta[8 + 6], bits.write(3) | DOUBLE_LEVEL | 0x2);
+
+    let up = level.upsample(1, bits);
+    assert_eq!(up.size, (32, 16));
+    for &(x, y) in &[(4, 6), (6, 7)] {
+        let i = y * 32 + x;
+        assert_eq!(up.meta[i] & DOUBLE_LEVEL, DOUBLE_LEVEL);
+        assert_eq!((up.height[i], up.height[i + 1]), (16, 24));
+    }
+    let down = up.downsample(1, bits);
+    assert!(down.height == level.height);
+    assert!(down.meta == level.meta);
+
+    let half = |x| {
+        level
+            .crop(Region {
+                x,
+                y: 0,
+                w: 8,
+                h: 8,
+            })
+            .unwrap()
+    };
+    let stitched = LevelData::stitch(&[vec![half(0), half(8)]], 2).unwrap();
+    assert_eq!(stitched.size, size);
+    // the seam in the middle is blended towards the average of its sides
+    assert_eq!(stitched.height[3 * 16 + 6..3 * 16 + 10], [54, 60, 60, 66]);
+    assert_eq!(stitched.meta[3 * 16 + 2], level.meta[3 * 16 + 2]);
+
+    let odd = level.crop(Region {
+        x: 0,
+        y: 0,
+        w: 6,
+        h: 8,
+    });
+    assert!(matches!(odd, Err(LevelError::RegionNotPowerOfTwo(_))));
+    assert!(matches!(
+        LevelData::stitch(&[], 2),
+        Err(LevelError::NoTiles)
+    ));
+    let ragged = [vec![half(0), half(8)], vec![half(0)]];
+    assert!(matches!(
+        LevelData::stitch(&ragged, 2),
+        Err(LevelError::TileRowLength {
+            row: 1,
+            length: 1,
+            expected: 2
+        })
+    ));
+    let mismatched = [vec![half(0), crop]];
+    assert!(matches!(
+        LevelData::stitch(&mismatched, 2),
+        Err(LevelError::TileSize {
+            row: 0,
+            column: 1,
+            size: (8, 2),
+            expected: (8, 8)
+        })
+    ));
+}
@@ -0,0 +1,288 @@
+use vangers::config::{
+    car, common,
+    text::{Encoding, ErrorKind},
+};
+
+use std::{fs, path::PathBuf};
+
+const COMMON_KEYS: &[&str] = &[
+    "g:",
+    "density:",
+    "dt0:",
+    "scale_general:",
+    "num_calls_analysis:",
+    "movement_detection_threshould:",
+    "num_skip_updates:",
+    "wheel_analyze:",
+    "analysis_off:",
+    "elastic_restriction:",
+    "elastic_time_scale_factor:",
+    "rolling_scale:",
+    "normal_threshould:",
+    "k_wheel:",
+    "horizontal_impulse_factor:",
+    "vertical_impulse_factor:",
+    "k_friction_impulse:",
+    "rudder_step:",
+    "rudder_max:",
+    "rudder_k_decr:",
+    "traction_increment:",
+    "traction_decrement:",
+    "global_speed_factor:",
+    "global_mobility_factor:",
+    "global_water_speed_factor:",
+    "global_air_speed_factor:",
+    "global_underground_speed_factor:",
+    "k_traction_turbo:",
+    "f_brake_max:",
+    "max_helicopter_height:",
+    "helicopter_height_incr:",
+    "helicopter_height_decr:",
+    "k_helicopter_thrust:",
+    "k_helicopter_rotate:",
+    "k_helicopter_strife:",
+    "max_helicopter_time:",
+    "heli_x_convert:",
+    "heli_y_convert:",
+    "heli_rudder_decr:",
+    "heli_traction_decr:",
+    "heli_z_offset:",
+    "helicopter_ampl:",
+    "helicopter_dphi:",
+    "helicopter_circle_radius_x:",
+    "helicopter_circle_radius_y:",
+    "helicopter_circle_dphi:",
+    "V_drag_speed:",
+    "W_drag_speed:",
+    "V_drag_wheel_speed:",
+    "V_drag_z:",
+    "V_drag_free:",
+    "W_drag_free:",
+    "V_drag_wheel:",
+    "W_drag_wheel:",
+    "V_drag_spring:",
+    "W_drag_spring:",
+    "V_drag_coll:",
+    "W_drag_coll:",
+    "V_drag_helicopter:",
+    "W_drag_helicopter:",
+    "V_drag_float:",
+    "W_drag_float:",
+    "V_drag_friction:",
+    "W_drag_friction:",
+    "V_abs_stop:",
+    "W_abs_stop:",
+    "V_drag_stuff:",
+    "V_drag_swamp:",
+    "V_drag_mole:",
+    "V_abs_min:",
+    "W_abs_min:",
+    "dZ_max:",
+    "MIN_WALL_DELTA:",
+    "k_elastic_mole:",
+    "K_mole:",
+    "k_mole_rudder:",
+    "mole_emerging_fz:",
+    "mole_submerging_fz:",
+    "k_elastic_wheel:",
+    "k_elastic_spring:",
+    "k_elastic_xy:",
+    "k_elastic_db_coll:",
+    "k_destroy_level:",
+    "strong_ground_collision_threshould:",
+    "strong_double_collision_threshould:",
+    "k_friction_wheel_x:",
+    "k_friction_wheel_x_back:",
+    "k_friction_wheel_y:",
+    "k_friction_wheel_z:",
+    "k_friction_spring:",
+    "f_spring_impulse:",
+    "K_spring_impulse:",
+    "f_traction_impulse:",
+    "k_distance_to_force:",
+    "V_explosion:",
+    "W_explosion:",
+    "max_jump_power:",
+    "side_impulse_delay:",
+    "side_impulse_duration:",
+];
+
+const VEHICLE_KEYS: &[&str] = &[
+    "scale_size:",
+    "scale_bound:",
+    "scale_box:",
+    "z_offset_of_mass_center:",
+    "speed_factor:",
+    "mobility_factor:",
+    "water_speed_factor:",
+    "air_speed_factor:",
+    "underground_speed_factor:",
+    "k_archimedean:",
+    "k_water_traction:",
+    "k_water_rudder:",
+    "TerraMoverSx:",
+    "TerraMoverSy:",
+    "TerraMoverSz:",
+    "FrontDefense:",
+    "BackDefense:",
+    "SideDefense:",
+    "UpperDefense:",
+    "LowerDefense:",
+    "FrontRamPower:",
+    "BackRamPower:",
+    "SideRamPower:",
+    "UpperRamPower:",
+    "LowerRamPower:",
+];
+
+/// Writes a fixture in the DOS style: CP866 comments and CRLF line endings.
+fn fixture(name: &str, text: &str) -> (PathBuf, Vec<u8>) {
+    let data = Encoding::Cp866.encode(&text.replace('\n', "\r\n"));
+    let path = std::env::temp_dir().join(format!("vangers-{}-{}", std::process::id(), name));
+    fs::write(&path, &data).unwrap();
+    (path, data)
+}
+
+/// Key-value lines with a comment in between, using the float syntax where it's allowed.
+fn key_values(keys: &[&str], is_float: impl Fn(&str) -> bool) -> String {
+    let mut text = String::new();
+    for (i, key) in keys.iter().enumerate() {
+        if i % 10 == 0 {
+            text += "// параметры\n";
+        }
+        let value = if is_float(key) {
+            format!("{}.50", i % 7)
+        } else {
+            format!("{}", i % 5 + 1)
+        };
+        text += &format!("{}\t{}\n", key, value);
+    }
+    text
+}
+
+/// Counts the lines that differ between the two files.
+fn changed_lines(a: &[u8], b: &[u8]) -> usize {
+    let (a, b) = (Encoding::Cp866.decode(a), Encoding::Cp866.decode(b));
+    assert_eq!(a.lines().count(), b.lines().count());
+    a.lines().zip(b.lines()).filter(|(x, y)| x != y).count()
+}
+
+#[test]
+fn common_roundtrip() {
+    let integers = [
+        "num_calls_analysis:",
+        "movement_detection_threshould:",
+        "rudder_step:",
+        "rudder_max:",
+        "traction_increment:",
+        "traction_decrement:",
+        "max_helicopter_height:",
+        "helicopter_height_incr:",
+        "helicopter_height_decr:",
+        "max_helicopter_time:",
+        "helicopter_dphi:",
+        "helicopter_circle_dphi:",
+        "side_impulse_delay:",
+        "side_impulse_duration:",
+    ];
+    let text = format!(
+        "/* Общие параметры\nмира */\nCOMMON:\t\t2\n\n{}",
+        key_values(COMMON_KEYS, |key| !integers.contains(&key))
+    );
+    let (path, data) = fixture("common.prm", &text);
+
+    let mut common = common::load(&path).unwrap();
+    let saved = common::save(&common, &data, "common.prm").unwrap();
+    assert_eq!(saved, data);
+
+    common.nature.gravity = 12.25;
+    common.car.rudder_max *= 2.0;
+    let saved = common::save(&common, &data, "common.prm").unwrap();
+    assert_eq!(changed_lines(&saved, &data), 2);
+    fs::write(&path, &saved).unwrap();
+    let loaded = common::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.nature.gravity, 12.25);
+    assert_eq!(loaded.car.rudder_max, common.car.rudder_max);
+
+    let mirror = ron::ser::to_string(&loaded).unwrap();
+    assert!(mirror.contains("gravity:12.25"), "{}", mirror);
+}
+
+#[test]
+fn vehicle_roundtrip() {
+    let text = format!(
+        "// Машинка\nName:  Test\n{}",
+        key_values(VEHICLE_KEYS, |key| !key.contains("Defense")
+            && !key.contains("RamPower"))
+    );
+    let (path, data) = fixture("vehicle.prm", &text);
+
+    let mut physics = car::CarPhysics::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(physics.name, "Test");
+    let saved = physics.save(&data, "vehicle.prm").unwrap();
+    assert_eq!(saved, data);
+
+    physics.speed_factor = 3.0;
+    physics.defence[2] = 77;
+    let saved = physics.save(&data, "vehicle.prm").unwrap();
+    assert_eq!(changed_lines(&saved, &data), 2);
+    assert!(Encoding::Cp866
+        .decode(&saved)
+        .contains("SideDefense:\t77\r\n"));
+}
+
+#[test]
+fn car_list_roundtrip() {
+    let mut text = "uniVang-ParametersFile_Ver_1\n// количество\n2\n1\n1\n".to_string();
+    for (i, name) in ["Mechos1", "Mechos2", "Ruffa", "Builder"]
+        .iter()
+        .enumerate()
+    {
+        let values = (0..19)
+            .map(|j| ((i * 19 + j) % 50).to_string())
+            .collect::<Vec<_>>();
+        text += &format!("{}\t\t{}\n", name, values.join(" "));
+    }
+    let (path, data) = fixture("car.prm", &text);
+
+    let mut list = car::load_list(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(list.len(), 4);
+    assert_eq!(list[2].kind, car::Kind::Ruffa);
+    let saved = car::save_list(&list, &data, "car.prm").unwrap();
+    assert_eq!(saved, data);
+
+    list[1].stats.price_buy = 1000;
+    let saved = car::save_list(&list, &data, "car.prm").unwrap();
+    assert_eq!(changed_lines(&saved, &data), 1);
+
+    // the entries are grouped by kind, whatever the list order
+    list.rotate_right(1);
+    assert_eq!(list[0].kind, car::Kind::Constructor);
+    let rotated = car::save_list(&list, &data, "car.prm").unwrap();
+    assert_eq!(rotated, saved);
+
+    // the vehicles themselves can't be changed
+    let mut added = list.clone();
+    added.push(car::CarEntry {
+        name: "Mechos3".to_string(),
+        ..list[1].clone()
+    });
+    let mut removed = list.clone();
+    removed.remove(1);
+    let mut swapped = list.clone();
+    swapped.swap(1, 2);
+    for (changed, name) in [
+        (added, "Mechos3"),
+        (removed, "Mechos1"),
+        (swapped, "Mechos2"),
+    ] {
+        let error = car::save_list(&changed, &data, "car.prm").unwrap_err();
+        match error.kind {
+            ErrorKind::InvalidValue { ref value, .. } => assert_eq!(value, name),
+            _ => panic!("unexpected error {}", error),
+        }
+    }
+}
@@ -9,3 +9,251 @@ fn load_ffi_config() {
     let file = std::fs::File::open("res/ffi-config.ron").unwrap();
     ron::de::from_reader::<_, vangers::config::settings::Render>(file).unwrap();
 }
+
+#[test]
+fn optional_slots() {
+    let other =
+        ron::de::from_str::<vangers::config::settings::Other>("(count: 1, spawn_at: Random)")
+            .unwrap();
+    assert!(other.slots.is_empty());
+}
+
+#[test]
+fn optional_lod() {
+    let text = std::fs::read_to_string("res/ffi-config.ron").unwrap();
+    let start = text.find("\tlod:").unwrap();
+    let end = start + text[start..].find("),").unwrap() + 2;
+    let old = format!("{}{}", &text[..start], &text[end..]);
+    let render = ron::de::from_str::<vangers::config::settings::Render>(&old).unwrap();
+    assert_eq!(render.lod.levels, 0);
+}
+
+mod layers {
+    use vangers::config::{
+        layers::{Error, Issue, Layers, Source},
+        settings::{Settings, Terrain, View},
+    };
+
+    use std::path::PathBuf;
+
+    fn vars(list: &[(&str, &str)]) -> Vec<(String, String)> {
+        list.iter()
+            .map(|&(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn stacking() {
+        let user = Source::File(PathBuf::from("user.ron"));
+        let mut layers = Layers::new::<Settings>(Settings::TEMPLATE).unwrap();
+        layers
+            .merge_str(
+                r#"(
+                    game: (level: "Boozeena", view: Flat),
+                    render: (terrain: RayMipTraced(mip_count: 10, max_jumps: 25, max_steps: 100, debug: false)),
+                )"#,
+                user.clone(),
+            )
+            .unwrap();
+        layers
+            .merge_env(vars(&[
+                ("VANGERS_GAME__OTHER__COUNT", "3"),
+                ("VANGERS_DATA_PATH", "/opt/vangers"),
+                ("VANGERS_NO_SUCH_THING", "1"),
+                ("PATH", "/bin"),
+            ]))
+            .unwrap();
+        layers.assign("game.level=Fostral").unwrap();
+        layers.assign("car.pos = Some((1300, 450))").unwrap();
+
+        let settings = layers.deserialize::<Settings>().unwrap();
+        assert_eq!(settings.game.level, "Fostral");
+        assert!(matches!(settings.game.view, View::Flat));
+        assert_eq!(settings.game.other.count, 3);
+        assert_eq!(settings.data_path, PathBuf::from("/opt/vangers"));
+        assert_eq!(settings.car.pos, Some((1300, 450)));
+        assert!(matches!(
+            settings.render.terrain,
+            Terrain::RayMipTraced { mip_count: 10, .. }
+        ));
+
+        assert_eq!(layers.source("game.level"), Some(&Source::CommandLine));
+        assert_eq!(layers.source("game.view"), Some(&user));
+        assert_eq!(
+            layers.source("game.other.count"),
+            Some(&Source::Env("VANGERS_GAME__OTHER__COUNT".to_string()))
+        );
+        assert_eq!(layers.source("render.terrain"), Some(&user));
+        assert_eq!(layers.source("window.title"), Some(&Source::Template));
+
+        let printed = layers.to_string();
+        assert!(printed.contains("game.level = \"Fostral\" // --set\n"));
+        assert!(printed.contains("window.size = [1280,800] // template\n"));
+    }
+
+    #[test]
+    fn variant_switch() {
+        let mut layers = Layers::new::<Settings>(Settings::TEMPLATE).unwrap();
+        layers
+            .assign("render.terrain=Scattered(density: (2, 2, 2))")
+            .unwrap();
+        layers.assign("render.terrain=Sliced").unwrap();
+        let settings = layers.deserialize::<Settings>().unwrap();
+        assert!(matches!(settings.render.terrain, Terrain::Sliced));
+        assert_eq!(layers.source("render.terrain"), Some(&Source::CommandLine));
+        assert_eq!(layers.get("render.terrain.Scattered"), None);
+    }
+
+    #[test]
+    fn errors() {
+        let mut layers = Layers::new::<Settings>(Settings::TEMPLATE).unwrap();
+        let e = layers
+            .merge_str(
+                "(\n  game: (\n    level: Fostral\"\n  ),\n)",
+                Source::CommandLine,
+            )
+            .unwrap_err();
+        assert!(
+            matches!(
+                e,
+                Error::Syntax {
+                    line: 3,
+                    column: 19,
+                    ..
+                }
+            ),
+            "{}",
+            e
+        );
+        assert!(matches!(
+            layers.assign("game.level"),
+            Err(Error::Assignment(_))
+        ));
+        assert!(matches!(
+            layers.assign("game.levle=Fostral"),
+            Err(Error::UnknownKey { .. })
+        ));
+        layers.assign("game.other.count=many").unwrap();
+        assert!(matches!(
+            layers.deserialize::<Settings>(),
+            Err(Error::Deserialize { ref path, .. }) if path == "game.other.count"
+        ));
+    }
+
+    #[test]
+    fn ron_syntax() {
+        let mut layers = Layers::new::<Settings>(Settings::TEMPLATE).unwrap();
+        layers
+            .merge_str(
+                "#![enable(implicit_some)]\n(game: (level: r\"Boozeena\", camera: Camera(angle: 45)))",
+                Source::CommandLine,
+            )
+            .unwrap();
+        layers.assign("render.light.shadow.size=0x400").unwrap();
+        layers.assign("window.title='R'").unwrap();
+        layers.assign("window.size=(1280)").unwrap();
+        layers.assign("data_path=vangers").unwrap();
+        assert_eq!(layers.get("game.level"), Some(&"Boozeena".into()));
+        assert_eq!(layers.get("game.camera.angle"), Some(&45.into()));
+        assert_eq!(layers.get("game.camera.height"), Some(&300.into()));
+        assert_eq!(layers.get("render.light.shadow.size"), Some(&1024.into()));
+        assert_eq!(layers.get("window.title"), Some(&"R".into()));
+        assert_eq!(
+            layers.get("window.size").unwrap().as_array().unwrap().len(),
+            1
+        );
+        assert_eq!(layers.get("data_path"), Some(&"vangers".into()));
+    }
+
+    #[test]
+    fn schema() {
+        let template = Layers::new::<Settings>(Settings::TEMPLATE).unwrap();
+        let mut layers = Layers::new::<Settings>(Settings::TEMPLATE).unwrap();
+        layers
+            .merge_str(
+                "(game: (camera: (fog_depth: 50), other: (count: \"many\")))",
+                Source::CommandLine,
+            )
+            .unwrap();
+        layers.assign("window.size=(1280, \"wide\")").unwrap();
+        layers.assign("render.terrain=Sliced").unwrap();
+        let paths = layers
+            .check_schema(&template)
+            .into_iter()
+            .map(|issue| issue.path)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                "game.camera.fog_depth",
+                "game.other.count",
+                "window.size[1]"
+            ]
+        );
+        assert!(matches!(
+            Settings::check_layers(&layers),
+            Err(ref issues) if issues.len() == 3
+        ));
+        // the unknown settings alone don't stop a launch
+        let mut layers = Layers::new::<Settings>(Settings::TEMPLATE).unwrap();
+        layers
+            .merge_str("(game: (camera: (fog_depth: 50)))", Source::CommandLine)
+            .unwrap();
+        layers.assign("data_path=\"/nonexistent/vangers\"").unwrap();
+        assert!(Settings::from_layers(&layers).is_ok());
+        assert!(matches!(
+            Settings::check_layers(&layers),
+            Err(ref issues) if issues.len() == 1
+        ));
+        // a misspelled variant passes the schema check, but still names the setting
+        let mut layers = Layers::new::<Settings>(Settings::TEMPLATE).unwrap();
+        layers.assign("render.terrain=RayTracedd").unwrap();
+        let issues = Settings::from_layers(&layers).err().unwrap();
+        assert_eq!(issues[0].path, "render.terrain");
+        assert!(issues[0].message.contains("RayTracedd"), "{}", issues[0]);
+    }
+
+    #[test]
+    fn validation() {
+        let mut layers = Layers::new::<Settings>(Settings::TEMPLATE).unwrap();
+        layers.assign("render.light.shadow.size=0").unwrap();
+        layers.assign("game.camera.depth_range=(100, 10)").unwrap();
+        layers.assign("data_path=\"/nonexistent/vangers\"").unwrap();
+        let settings = layers.deserialize::<Settings>().unwrap();
+        let issues = settings.validate();
+        let paths = issues
+            .iter()
+            .map(|issue| issue.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                "game.camera.depth_range",
+                "render.light.shadow.size",
+                "data_path"
+            ]
+        );
+        assert_eq!(
+            issues[1],
+            Issue {
+                path: "render.light.shadow.size".to_string(),
+                message: "must be positive for the `RayTraced` shadows".to_string(),
+            }
+        );
+
+        layers
+            .assign("game.physics.gpu_collision=Some((max_objects: 100, max_polygons_total: 0, max_raster_size: (100, 100)))")
+            .unwrap();
+        let settings = layers.deserialize::<Settings>().unwrap();
+        let issues = settings.validate();
+        assert_eq!(
+            issues
+                .iter()
+                .any(|issue| issue.path == "game.physics.gpu_collision"),
+            !cfg!(feature = "glsl")
+        );
+        assert!(issues
+            .iter()
+            .any(|issue| issue.path == "game.physics.gpu_collision.max_polygons_total"));
+    }
+}
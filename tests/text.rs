@@ -0,0 +1,51 @@
+use vangers::config::text::{Encoding, ErrorKind, Reader};
+
+fn reader(data: &[u8]) -> Reader {
+    Reader::new(data, "test.prm").unwrap()
+}
+
+#[test]
+fn key_values() {
+    let mut fi = reader(b"// comment\n\n/* multi\nline */\nscale: 1.5\nsize:  \t7\n");
+    assert_eq!(fi.next_key_value::<f32>("scale:").unwrap(), 1.5);
+    assert_eq!(fi.next_key_value::<u8>("size:").unwrap(), 7);
+    assert!(!fi.advance());
+}
+
+#[test]
+fn error_positions() {
+    let mut fi = reader(b"scale: 1.5\n  size: big\n");
+    let e = fi.next_key_value::<f32>("size:").unwrap_err();
+    assert_eq!((e.line, e.column), (1, 1));
+    assert!(matches!(e.kind, ErrorKind::UnexpectedKey { .. }));
+
+    let e = fi.next_key_value::<u8>("size:").unwrap_err();
+    assert_eq!((e.line, e.column), (2, 9));
+    assert!(matches!(e.kind, ErrorKind::InvalidValue { .. }));
+    assert!(e.to_string().starts_with("test.prm:2:9: invalid value `big`"));
+
+    let e = fi.next_value::<u8>().unwrap_err();
+    assert!(matches!(e.kind, ErrorKind::UnexpectedEnd));
+}
+
+#[test]
+fn unterminated_comment() {
+    let mut fi = reader(b"/* never\nclosed\n");
+    assert!(!fi.advance());
+    assert!(fi.next_line().is_err());
+}
+
+#[test]
+fn russian_comments() {
+    // "Привет" in both code pages
+    let cp866 = b"\x8f\xe0\xa8\xa2\xa5\xe2 1\n";
+    let cp1251 = b"\xcf\xf0\xe8\xe2\xe5\xf2 1\n";
+    assert_eq!(Encoding::detect(cp866), Encoding::Cp866);
+    assert_eq!(Encoding::detect(cp1251), Encoding::Cp1251);
+    assert_eq!(Encoding::detect("Привет".as_bytes()), Encoding::Utf8);
+
+    for data in [&cp866[..], &cp1251[..]] {
+        let mut fi = reader(data);
+        assert_eq!(fi.next_key_value::<u32>("Привет").unwrap(), 1);
+    }
+}